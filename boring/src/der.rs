@@ -0,0 +1,205 @@
+//! Minimal DER reading and writing, for structures BoringSSL does not expose.
+use std::ptr;
+
+use crate::error::ErrorStack;
+use crate::ffi;
+use crate::hash::MessageDigest;
+
+pub(crate) const INTEGER: u8 = 0x02;
+pub(crate) const OCTET_STRING: u8 = 0x04;
+pub(crate) const NULL: u8 = 0x05;
+pub(crate) const OBJECT_IDENTIFIER: u8 = 0x06;
+pub(crate) const BMP_STRING: u8 = 0x1e;
+pub(crate) const SEQUENCE: u8 = 0x30;
+pub(crate) const SET: u8 = 0x31;
+
+/// Returns the tag of a context-specific element.
+pub(crate) const fn context(number: u8, constructed: bool) -> u8 {
+    let constructed = if constructed { 0x20 } else { 0 };
+    0x80 | constructed | number
+}
+
+fn malformed() -> ErrorStack {
+    ErrorStack::internal_error_str("malformed DER")
+}
+
+/// Returns the digest identified by the contents of an OBJECT IDENTIFIER.
+pub(crate) fn digest_from_oid(oid: &[u8]) -> Result<MessageDigest, ErrorStack> {
+    match oid {
+        [0x2b, 0x0e, 0x03, 0x02, 0x1a] => Ok(MessageDigest::sha1()),
+        [0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01] => Ok(MessageDigest::sha256()),
+        [0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02] => Ok(MessageDigest::sha384()),
+        [0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03] => Ok(MessageDigest::sha512()),
+        _ => Err(ErrorStack::internal_error_str("unsupported digest")),
+    }
+}
+
+/// Converts BER, such as indefinite lengths and constructed strings, to DER.
+pub(crate) fn ber_to_der(ber: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+    unsafe {
+        let mut input = std::mem::zeroed::<ffi::CBS>();
+        ffi::CBS_init(&mut input, ber.as_ptr(), ber.len());
+        let mut output = std::mem::zeroed::<ffi::CBS>();
+        let mut storage = ptr::null_mut();
+        if ffi::CBS_asn1_ber_to_der(&mut input, &mut output, &mut storage) != 1 {
+            return Err(ErrorStack::get());
+        }
+        let der = if storage.is_null() {
+            ber.to_vec()
+        } else {
+            let der = std::slice::from_raw_parts(storage, ffi::CBS_len(&output)).to_vec();
+            ffi::OPENSSL_free(storage.cast());
+            der
+        };
+        Ok(der)
+    }
+}
+
+/// Reads DER elements one after the other.
+pub(crate) struct DerReader<'a>(&'a [u8]);
+
+impl<'a> DerReader<'a> {
+    pub(crate) fn new(der: &'a [u8]) -> DerReader<'a> {
+        DerReader(der)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn peek_tag(&self) -> Option<u8> {
+        self.0.first().copied()
+    }
+
+    /// Reads the next element, returning its tag and contents.
+    pub(crate) fn read_any(&mut self) -> Result<(u8, &'a [u8]), ErrorStack> {
+        let [tag, first, rest @ ..] = self.0 else {
+            return Err(malformed());
+        };
+        // Multi-byte tags are not used by the structures read here.
+        if tag & 0x1f == 0x1f {
+            return Err(malformed());
+        }
+        let (len, rest) = match *first {
+            len @ 0..=0x7f => (len as usize, rest),
+            0x81..=0x84 => {
+                let count = (*first & 0x7f) as usize;
+                if rest.len() < count || rest[0] == 0 {
+                    return Err(malformed());
+                }
+                let len = rest[..count]
+                    .iter()
+                    .fold(0, |len, &b| (len << 8) | b as usize);
+                if len < 0x80 {
+                    return Err(malformed());
+                }
+                (len, &rest[count..])
+            }
+            _ => return Err(malformed()),
+        };
+        if rest.len() < len {
+            return Err(malformed());
+        }
+        let (contents, rest) = rest.split_at(len);
+        self.0 = rest;
+        Ok((*tag, contents))
+    }
+
+    /// Reads the next element, failing unless it has the given tag.
+    pub(crate) fn read(&mut self, tag: u8) -> Result<&'a [u8], ErrorStack> {
+        match self.read_any()? {
+            (actual, contents) if actual == tag => Ok(contents),
+            _ => Err(malformed()),
+        }
+    }
+
+    /// Reads the next element if it has the given tag.
+    pub(crate) fn read_optional(&mut self, tag: u8) -> Result<Option<&'a [u8]>, ErrorStack> {
+        if self.peek_tag() == Some(tag) {
+            self.read(tag).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Reads a non-negative INTEGER that fits in a `u32`.
+    pub(crate) fn read_u32(&mut self) -> Result<u32, ErrorStack> {
+        let contents = self.read(INTEGER)?;
+        let digits = match contents {
+            [0, rest @ ..] if !rest.is_empty() => rest,
+            [first, ..] if first & 0x80 == 0 => contents,
+            _ => return Err(malformed()),
+        };
+        if digits.len() > 4 {
+            return Err(malformed());
+        }
+        Ok(digits
+            .iter()
+            .fold(0, |value, &b| (value << 8) | u32::from(b)))
+    }
+}
+
+/// Writes DER elements one after the other.
+#[derive(Default)]
+pub(crate) struct DerWriter(Vec<u8>);
+
+impl DerWriter {
+    pub(crate) fn new() -> DerWriter {
+        DerWriter::default()
+    }
+
+    /// Writes an element with the given tag and contents.
+    pub(crate) fn write(&mut self, tag: u8, contents: &[u8]) {
+        self.0.push(tag);
+        let len = contents.len();
+        if len < 0x80 {
+            self.0.push(len as u8);
+        } else {
+            let bytes = len.to_be_bytes();
+            let skip = bytes.iter().take_while(|&&b| b == 0).count();
+            self.0.push(0x80 | (bytes.len() - skip) as u8);
+            self.0.extend_from_slice(&bytes[skip..]);
+        }
+        self.0.extend_from_slice(contents);
+    }
+
+    /// Writes a constructed element whose contents are written by `f`.
+    pub(crate) fn write_nested(&mut self, tag: u8, f: impl FnOnce(&mut DerWriter)) {
+        let mut contents = DerWriter::new();
+        f(&mut contents);
+        self.write(tag, &contents.0);
+    }
+
+    /// Writes a SET OF the given encoded elements, sorted as DER requires.
+    pub(crate) fn write_set_of(&mut self, mut elements: Vec<Vec<u8>>) {
+        elements.sort();
+        self.write(SET, &elements.concat());
+    }
+
+    /// Writes a non-negative INTEGER.
+    pub(crate) fn write_u32(&mut self, value: u32) {
+        let bytes = value.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&b| b == 0).count().min(3);
+        let mut contents = bytes[skip..].to_vec();
+        if contents[0] & 0x80 != 0 {
+            contents.insert(0, 0);
+        }
+        self.write(INTEGER, &contents);
+    }
+
+    /// Writes already encoded elements.
+    pub(crate) fn write_raw(&mut self, der: &[u8]) {
+        self.0.extend_from_slice(der);
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+/// Returns the elements written by `f`.
+pub(crate) fn encode(f: impl FnOnce(&mut DerWriter)) -> Vec<u8> {
+    let mut writer = DerWriter::new();
+    f(&mut writer);
+    writer.finish()
+}
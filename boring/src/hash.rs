@@ -76,6 +76,13 @@ impl MessageDigest {
         unsafe { MessageDigest(ffi::EVP_sha512_256()) }
     }
 
+    /// No digest, for keys such as Ed25519 which sign the whole message themselves.
+    ///
+    /// Only valid as the digest of a signature: it has no size, block size or type.
+    pub(crate) fn null() -> MessageDigest {
+        MessageDigest(ptr::null())
+    }
+
     #[allow(clippy::trivially_copy_pass_by_ref)]
     #[must_use]
     pub fn as_ptr(&self) -> *const ffi::EVP_MD {
@@ -1,7 +1,77 @@
 use crate::error::ErrorStack;
 use crate::{cvt_0i, cvt_p, ffi};
 
-use foreign_types::ForeignType;
+use foreign_types::{ForeignType, ForeignTypeRef};
+use openssl_macros::corresponds;
+
+/// An HPKE KEM identifier.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct HpkeKem(u16);
+
+impl HpkeKem {
+    /// DHKEM(X25519, HKDF-SHA256).
+    pub const X25519_HKDF_SHA256: Self = Self(ffi::EVP_HPKE_DHKEM_X25519_HKDF_SHA256 as u16);
+
+    /// Constructs an `HpkeKem` from a raw identifier.
+    #[must_use]
+    pub fn from_raw(raw: u16) -> HpkeKem {
+        HpkeKem(raw)
+    }
+
+    /// Returns the raw identifier.
+    #[must_use]
+    pub fn as_raw(&self) -> u16 {
+        self.0
+    }
+}
+
+/// An HPKE KDF identifier.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct HpkeKdf(u16);
+
+impl HpkeKdf {
+    /// HKDF-SHA256.
+    pub const HKDF_SHA256: Self = Self(ffi::EVP_HPKE_HKDF_SHA256 as u16);
+
+    /// Constructs an `HpkeKdf` from a raw identifier.
+    #[must_use]
+    pub fn from_raw(raw: u16) -> HpkeKdf {
+        HpkeKdf(raw)
+    }
+
+    /// Returns the raw identifier.
+    #[must_use]
+    pub fn as_raw(&self) -> u16 {
+        self.0
+    }
+}
+
+/// An HPKE AEAD identifier.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct HpkeAead(u16);
+
+impl HpkeAead {
+    /// AES-128-GCM.
+    pub const AES_128_GCM: Self = Self(ffi::EVP_HPKE_AES_128_GCM as u16);
+
+    /// AES-256-GCM.
+    pub const AES_256_GCM: Self = Self(ffi::EVP_HPKE_AES_256_GCM as u16);
+
+    /// ChaCha20-Poly1305.
+    pub const CHACHA20_POLY1305: Self = Self(ffi::EVP_HPKE_CHACHA20_POLY1305 as u16);
+
+    /// Constructs an `HpkeAead` from a raw identifier.
+    #[must_use]
+    pub fn from_raw(raw: u16) -> HpkeAead {
+        HpkeAead(raw)
+    }
+
+    /// Returns the raw identifier.
+    #[must_use]
+    pub fn as_raw(&self) -> u16 {
+        self.0
+    }
+}
 
 foreign_type_and_impl_send_sync! {
     type CType = ffi::EVP_HPKE_KEY;
@@ -28,4 +98,63 @@ impl HpkeKey {
             Ok(hpke)
         }
     }
+
+    /// Generates a new key for the `DHKEM(X25519, HKDF-SHA256)` KEM.
+    #[corresponds(EVP_HPKE_KEY_generate)]
+    pub fn generate_x25519_hkdf_sha256() -> Result<HpkeKey, ErrorStack> {
+        unsafe {
+            ffi::init();
+            let hpke = cvt_p(ffi::EVP_HPKE_KEY_new()).map(|p| HpkeKey::from_ptr(p))?;
+
+            cvt_0i(ffi::EVP_HPKE_KEY_generate(
+                hpke.as_ptr(),
+                ffi::EVP_hpke_x25519_hkdf_sha256(),
+            ))?;
+
+            Ok(hpke)
+        }
+    }
+}
+
+impl HpkeKeyRef {
+    /// Returns the KEM of the key.
+    #[corresponds(EVP_HPKE_KEM_id)]
+    #[must_use]
+    pub fn kem(&self) -> HpkeKem {
+        unsafe { HpkeKem(ffi::EVP_HPKE_KEM_id(ffi::EVP_HPKE_KEY_kem(self.as_ptr()))) }
+    }
+
+    /// Returns the encoded public key.
+    #[corresponds(EVP_HPKE_KEY_public_key)]
+    pub fn public_key(&self) -> Result<Vec<u8>, ErrorStack> {
+        let mut key = vec![0; ffi::EVP_HPKE_MAX_PUBLIC_KEY_LENGTH as usize];
+        let mut len = 0;
+        unsafe {
+            cvt_0i(ffi::EVP_HPKE_KEY_public_key(
+                self.as_ptr(),
+                key.as_mut_ptr(),
+                &mut len,
+                key.len(),
+            ))?;
+        }
+        key.truncate(len);
+        Ok(key)
+    }
+
+    /// Returns the encoded private key.
+    #[corresponds(EVP_HPKE_KEY_private_key)]
+    pub fn private_key(&self) -> Result<Vec<u8>, ErrorStack> {
+        let mut key = vec![0; ffi::EVP_HPKE_MAX_PRIVATE_KEY_LENGTH as usize];
+        let mut len = 0;
+        unsafe {
+            cvt_0i(ffi::EVP_HPKE_KEY_private_key(
+                self.as_ptr(),
+                key.as_mut_ptr(),
+                &mut len,
+                key.len(),
+            ))?;
+        }
+        key.truncate(len);
+        Ok(key)
+    }
 }
@@ -116,6 +116,7 @@ use crate::error::ErrorStack;
 mod macros;
 
 mod bio;
+mod der;
 #[macro_use]
 mod util;
 pub mod aes;
@@ -138,6 +139,7 @@ pub mod memcmp;
 pub mod nid;
 pub mod pkcs12;
 pub mod pkcs5;
+pub mod pkcs7;
 pub mod pkey;
 pub mod rand;
 pub mod rsa;
@@ -1057,6 +1057,7 @@ impl Nid {
     pub const AUTH_ECDSA: Nid = Nid(ffi::NID_auth_ecdsa);
     pub const AUTH_PSK: Nid = Nid(ffi::NID_auth_psk);
     pub const AUTH_ANY: Nid = Nid(ffi::NID_auth_any);
+    pub const TLSFEATURE: Nid = Nid(ffi::NID_tlsfeature);
 }
 
 #[cfg(test)]
@@ -1,4 +1,10 @@
 //! PKCS #12 archives.
+//!
+//! [`Pkcs12Ref::parse`] and [`Pkcs12Builder`] are backed by BoringSSL. [`Pkcs12Ref::parse_bags`]
+//! and [`Pkcs12Pbes2Builder`] are implemented here instead: BoringSSL only returns the first
+//! private key of an archive, drops the attributes of key bags and rejects archives without a
+//! MAC, and it does not export the PKCS #12 key derivation function its parser uses. Like
+//! BoringSSL, the parser refuses iteration counts above 100 million before deriving any key.
 
 use crate::ffi;
 use foreign_types::{ForeignType, ForeignTypeRef};
@@ -7,15 +13,45 @@ use openssl_macros::corresponds;
 use std::ffi::CString;
 use std::ptr;
 
+use crate::der::{self, DerReader, DerWriter};
 use crate::error::ErrorStack;
+use crate::hash::{hash, MessageDigest};
+use crate::memcmp;
 use crate::nid::Nid;
+use crate::pkcs5::pbkdf2_hmac;
 use crate::pkey::{HasPrivate, PKey, PKeyRef, Private};
+use crate::rand::rand_bytes;
 use crate::stack::Stack;
+use crate::symm::{self, Cipher};
 use crate::x509::{X509Ref, X509};
 use crate::{cvt_0i, cvt_p};
 
 pub const PKCS12_DEFAULT_ITER: c_int = 2048;
 
+/// The largest iteration count accepted when parsing, the limit of BoringSSL.
+const MAX_ITERATIONS: u32 = 100_000_000;
+
+const OID_DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x01];
+const OID_ENCRYPTED_DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x06];
+const OID_KEY_BAG: &[u8] = &[
+    0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x0c, 0x0a, 0x01, 0x01,
+];
+const OID_SHROUDED_KEY_BAG: &[u8] = &[
+    0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x0c, 0x0a, 0x01, 0x02,
+];
+const OID_CERT_BAG: &[u8] = &[
+    0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x0c, 0x0a, 0x01, 0x03,
+];
+const OID_X509_CERTIFICATE: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x16, 0x01];
+const OID_FRIENDLY_NAME: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x14];
+const OID_LOCAL_KEY_ID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x15];
+const OID_PKCS12_PBE: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x0c, 0x01];
+const OID_PBES2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0d];
+const OID_PBKDF2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0c];
+const OID_HMAC_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x09];
+const OID_AES_256_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x2a];
+const OID_SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+
 foreign_type_and_impl_send_sync! {
     type CType = ffi::PKCS12;
     fn drop = ffi::PKCS12_free;
@@ -60,6 +96,72 @@ impl Pkcs12Ref {
             Ok(ParsedPkcs12 { pkey, cert, chain })
         }
     }
+
+    /// Extracts every key and certificate of the `Pkcs12`, with their attributes.
+    ///
+    /// Unlike [`parse`](Self::parse), this returns all key and certificate bags in the order they
+    /// appear in the archive. Bags of other types are skipped. Archives without a MAC are only
+    /// accepted with an empty password; use [`parse_bags_unauthenticated`] to read them with
+    /// another one.
+    ///
+    /// [`parse_bags_unauthenticated`]: Self::parse_bags_unauthenticated
+    pub fn parse_bags(&self, pass: &str) -> Result<Pkcs12Bags, ErrorStack> {
+        self.read_bags(pass, false)
+    }
+
+    /// Like [`parse_bags`](Self::parse_bags), but also accepts archives without a MAC whatever
+    /// the password.
+    ///
+    /// The integrity of such archives is not checked: their contents may have been altered.
+    pub fn parse_bags_unauthenticated(&self, pass: &str) -> Result<Pkcs12Bags, ErrorStack> {
+        self.read_bags(pass, true)
+    }
+
+    fn read_bags(&self, pass: &str, allow_missing_mac: bool) -> Result<Pkcs12Bags, ErrorStack> {
+        let der = der::ber_to_der(&self.to_der()?)?;
+        let mut pfx = DerReader::new(DerReader::new(&der).read(der::SEQUENCE)?);
+        if pfx.read_u32()? != 3 {
+            return Err(ErrorStack::internal_error_str(
+                "unsupported PKCS #12 version",
+            ));
+        }
+        let (content_type, auth_safe) = read_content_info(pfx.read(der::SEQUENCE)?)?;
+        if content_type != OID_DATA {
+            return Err(malformed());
+        }
+        let auth_safe = DerReader::new(auth_safe).read(der::OCTET_STRING)?;
+        let bmp = match pfx.read_optional(der::SEQUENCE)? {
+            Some(mac_data) => verify_mac(mac_data, pass, auth_safe)?,
+            None if pass.is_empty() || allow_missing_mac => bmp_password(pass),
+            None => {
+                return Err(ErrorStack::internal_error_str(
+                    "PKCS #12 archive has no MAC",
+                ))
+            }
+        };
+        let password = Password {
+            utf8: pass.as_bytes(),
+            bmp,
+        };
+
+        let mut bags = Pkcs12Bags {
+            keys: vec![],
+            certs: vec![],
+        };
+        let mut content_infos = DerReader::new(DerReader::new(auth_safe).read(der::SEQUENCE)?);
+        while !content_infos.is_empty() {
+            let (content_type, content) = read_content_info(content_infos.read(der::SEQUENCE)?)?;
+            let safe_contents = if content_type == OID_DATA {
+                DerReader::new(content).read(der::OCTET_STRING)?.to_vec()
+            } else if content_type == OID_ENCRYPTED_DATA {
+                decrypt_data(content, &password)?
+            } else {
+                continue;
+            };
+            read_safe_contents(&safe_contents, &password, &mut bags)?;
+        }
+        Ok(bags)
+    }
 }
 
 impl Pkcs12 {
@@ -92,6 +194,20 @@ impl Pkcs12 {
             ca: None,
         }
     }
+
+    /// Creates a new builder for archives encrypted with PBES2, using AES-256-CBC and
+    /// PBKDF2-HMAC-SHA256, and authenticated with an HMAC-SHA256 MAC.
+    ///
+    /// These are the defaults of OpenSSL 3, and are supported by current versions of Windows.
+    #[must_use]
+    pub fn pbes2_builder() -> Pkcs12Pbes2Builder {
+        Pkcs12Pbes2Builder {
+            keys: vec![],
+            certs: vec![],
+            iter: PKCS12_DEFAULT_ITER as u32,
+            mac_iter: PKCS12_DEFAULT_ITER as u32,
+        }
+    }
 }
 
 pub struct ParsedPkcs12 {
@@ -100,6 +216,21 @@ pub struct ParsedPkcs12 {
     pub chain: Option<Stack<X509>>,
 }
 
+/// A key or certificate of a PKCS #12 archive, with its attributes.
+pub struct Pkcs12Bag<T> {
+    pub value: T,
+    /// The `friendlyName` attribute, usually displayed to users.
+    pub friendly_name: Option<String>,
+    /// The `localKeyID` attribute, usually shared by a key and its certificate.
+    pub local_key_id: Option<Vec<u8>>,
+}
+
+/// The keys and certificates of a PKCS #12 archive, as returned by [`Pkcs12Ref::parse_bags`].
+pub struct Pkcs12Bags {
+    pub keys: Vec<Pkcs12Bag<PKey<Private>>>,
+    pub certs: Vec<Pkcs12Bag<X509>>,
+}
+
 pub struct Pkcs12Builder {
     nid_key: Nid,
     nid_cert: Nid,
@@ -196,6 +327,592 @@ impl Pkcs12Builder {
     }
 }
 
+/// A builder for PKCS #12 archives using PBES2, created by [`Pkcs12::pbes2_builder`].
+pub struct Pkcs12Pbes2Builder {
+    keys: Vec<(Vec<u8>, Vec<Vec<u8>>)>,
+    certs: Vec<(Vec<u8>, Vec<Vec<u8>>)>,
+    iter: u32,
+    mac_iter: u32,
+}
+
+impl Pkcs12Pbes2Builder {
+    /// Adds a private key, with optional `friendlyName` and `localKeyID` attributes.
+    pub fn add_key<T>(
+        &mut self,
+        pkey: &PKeyRef<T>,
+        friendly_name: Option<&str>,
+        local_key_id: Option<&[u8]>,
+    ) -> Result<(), ErrorStack>
+    where
+        T: HasPrivate,
+    {
+        let pkcs8 = pkey.private_key_to_der_pkcs8()?;
+        self.keys
+            .push((pkcs8, encode_attributes(friendly_name, local_key_id)));
+        Ok(())
+    }
+
+    /// Adds a certificate, with optional `friendlyName` and `localKeyID` attributes.
+    ///
+    /// A certificate is usually given the same local key ID as its private key.
+    pub fn add_cert(
+        &mut self,
+        cert: &X509Ref,
+        friendly_name: Option<&str>,
+        local_key_id: Option<&[u8]>,
+    ) -> Result<(), ErrorStack> {
+        let der = cert.to_der()?;
+        self.certs
+            .push((der, encode_attributes(friendly_name, local_key_id)));
+        Ok(())
+    }
+
+    /// PBKDF2 iteration count, default is 2048.
+    pub fn key_iter(&mut self, iter: u32) -> &mut Self {
+        self.iter = iter;
+        self
+    }
+
+    /// MAC iteration count, default is 2048.
+    pub fn mac_iter(&mut self, mac_iter: u32) -> &mut Self {
+        self.mac_iter = mac_iter;
+        self
+    }
+
+    /// Builds the PKCS #12 archive.
+    ///
+    /// With a password, certificates and keys are encrypted and the archive is authenticated with
+    /// a MAC. Without one, nothing is encrypted and no MAC is added, like
+    /// `openssl pkcs12 -export -nomac -keypbe NONE -certpbe NONE` does.
+    pub fn build(&self, password: Option<&str>) -> Result<Pkcs12, ErrorStack> {
+        let mut auth_safe = vec![];
+
+        if !self.certs.is_empty() {
+            let safe_contents = der::encode(|w| {
+                w.write_nested(der::SEQUENCE, |w| {
+                    for (cert, attributes) in &self.certs {
+                        let value = der::encode(|w| {
+                            w.write_nested(der::SEQUENCE, |w| {
+                                w.write(der::OBJECT_IDENTIFIER, OID_X509_CERTIFICATE);
+                                w.write_nested(der::context(0, true), |w| {
+                                    w.write(der::OCTET_STRING, cert);
+                                });
+                            });
+                        });
+                        write_safe_bag(w, OID_CERT_BAG, &value, attributes);
+                    }
+                });
+            });
+            auth_safe.push(match password {
+                Some(password) => {
+                    let (algorithm, ciphertext) =
+                        pbes2_encrypt(password, self.iter, &safe_contents)?;
+                    encode_encrypted_data(&algorithm, &ciphertext)
+                }
+                None => encode_data(&safe_contents),
+            });
+        }
+
+        if !self.keys.is_empty() {
+            let mut bags = vec![];
+            for (pkcs8, attributes) in &self.keys {
+                bags.push(match password {
+                    Some(password) => {
+                        let (algorithm, ciphertext) = pbes2_encrypt(password, self.iter, pkcs8)?;
+                        let value = der::encode(|w| {
+                            w.write_nested(der::SEQUENCE, |w| {
+                                w.write_raw(&algorithm);
+                                w.write(der::OCTET_STRING, &ciphertext);
+                            });
+                        });
+                        der::encode(|w| write_safe_bag(w, OID_SHROUDED_KEY_BAG, &value, attributes))
+                    }
+                    None => der::encode(|w| write_safe_bag(w, OID_KEY_BAG, pkcs8, attributes)),
+                });
+            }
+            let safe_contents = der::encode(|w| w.write(der::SEQUENCE, &bags.concat()));
+            auth_safe.push(encode_data(&safe_contents));
+        }
+
+        let auth_safe = der::encode(|w| w.write(der::SEQUENCE, &auth_safe.concat()));
+        let mac_data = password
+            .map(|password| encode_mac_data(password, self.mac_iter, &auth_safe))
+            .transpose()?;
+        let pfx = der::encode(|w| {
+            w.write_nested(der::SEQUENCE, |w| {
+                w.write_u32(3);
+                w.write_raw(&encode_data(&auth_safe));
+                if let Some(mac_data) = &mac_data {
+                    w.write_raw(mac_data);
+                }
+            });
+        });
+        Pkcs12::from_der(&pfx)
+    }
+}
+
+fn malformed() -> ErrorStack {
+    ErrorStack::internal_error_str("malformed PKCS #12 archive")
+}
+
+fn unsupported() -> ErrorStack {
+    ErrorStack::internal_error_str("unsupported PKCS #12 algorithm")
+}
+
+/// Reads an iteration count, failing if it is zero or above [`MAX_ITERATIONS`].
+fn read_iterations(reader: &mut DerReader<'_>) -> Result<u32, ErrorStack> {
+    match reader.read_u32()? {
+        iter @ 1..=MAX_ITERATIONS => Ok(iter),
+        _ => Err(ErrorStack::internal_error_str(
+            "unsupported PKCS #12 iteration count",
+        )),
+    }
+}
+
+/// A password, encoded for PBES2 and for the PKCS #12 key derivation function.
+struct Password<'a> {
+    utf8: &'a [u8],
+    bmp: Vec<u8>,
+}
+
+/// Encodes a password as a NUL-terminated BMPString, as the PKCS #12 key derivation expects.
+fn bmp_password(pass: &str) -> Vec<u8> {
+    pass.encode_utf16()
+        .chain([0])
+        .flat_map(u16::to_be_bytes)
+        .collect()
+}
+
+/// The PKCS #12 key derivation function of RFC 7292, appendix B.
+fn pkcs12_kdf(
+    md: MessageDigest,
+    pass: &[u8],
+    salt: &[u8],
+    id: u8,
+    iter: u32,
+    out: &mut [u8],
+) -> Result<(), ErrorStack> {
+    let v = unsafe { ffi::EVP_MD_block_size(md.as_ptr()) };
+    let repeat = |data: &[u8]| -> Vec<u8> {
+        let len = v * data.len().div_ceil(v);
+        data.iter().cycle().take(len).copied().collect()
+    };
+    let mut i = repeat(salt);
+    i.extend(repeat(pass));
+    let d = vec![id; v];
+
+    for chunk in out.chunks_mut(md.size()) {
+        let mut a = hash(md, &[&d[..], &i].concat())?;
+        for _ in 1..iter {
+            a = hash(md, &a)?;
+        }
+        chunk.copy_from_slice(&a[..chunk.len()]);
+
+        let b = a.iter().cycle().take(v).copied().collect::<Vec<_>>();
+        for block in i.chunks_mut(v) {
+            let mut carry = 1;
+            for (x, y) in block.iter_mut().zip(&b).rev() {
+                let sum = u16::from(*x) + u16::from(*y) + carry;
+                *x = sum as u8;
+                carry = sum >> 8;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn mac(
+    md: MessageDigest,
+    bmp_pass: &[u8],
+    salt: &[u8],
+    iter: u32,
+    data: &[u8],
+) -> Result<Vec<u8>, ErrorStack> {
+    let mut key = vec![0; md.size()];
+    pkcs12_kdf(md, bmp_pass, salt, 3, iter, &mut key)?;
+
+    let mut out = [0; ffi::EVP_MAX_MD_SIZE as usize];
+    let mut out_len = 0;
+    unsafe {
+        cvt_p(ffi::HMAC(
+            md.as_ptr(),
+            key.as_ptr().cast(),
+            key.len(),
+            data.as_ptr(),
+            data.len(),
+            out.as_mut_ptr(),
+            &mut out_len,
+        ))?;
+    }
+    Ok(out[..out_len as usize].to_vec())
+}
+
+fn encode_mac_data(pass: &str, iter: u32, auth_safe: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+    let mut salt = [0; 8];
+    rand_bytes(&mut salt)?;
+    let mac = mac(
+        MessageDigest::sha256(),
+        &bmp_password(pass),
+        &salt,
+        iter,
+        auth_safe,
+    )?;
+    Ok(der::encode(|w| {
+        w.write_nested(der::SEQUENCE, |w| {
+            w.write_nested(der::SEQUENCE, |w| {
+                w.write_nested(der::SEQUENCE, |w| {
+                    w.write(der::OBJECT_IDENTIFIER, OID_SHA256);
+                    w.write(der::NULL, &[]);
+                });
+                w.write(der::OCTET_STRING, &mac);
+            });
+            w.write(der::OCTET_STRING, &salt);
+            w.write_u32(iter);
+        });
+    }))
+}
+
+/// Checks the MAC of `auth_safe`, returning the encoding of the password that matched.
+fn verify_mac(mac_data: &[u8], pass: &str, auth_safe: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+    let mut mac_data = DerReader::new(mac_data);
+    let mut digest_info = DerReader::new(mac_data.read(der::SEQUENCE)?);
+    let mut algorithm = DerReader::new(digest_info.read(der::SEQUENCE)?);
+    let md = der::digest_from_oid(algorithm.read(der::OBJECT_IDENTIFIER)?)?;
+    let expected = digest_info.read(der::OCTET_STRING)?;
+    let salt = mac_data.read(der::OCTET_STRING)?;
+    let iter = if mac_data.is_empty() {
+        1
+    } else {
+        read_iterations(&mut mac_data)?
+    };
+
+    // Empty passwords are encoded either as an empty string or as a lone NUL.
+    let mut candidates = vec![bmp_password(pass)];
+    if pass.is_empty() {
+        candidates.push(vec![]);
+    }
+    for bmp in candidates {
+        let mac = mac(md, &bmp, salt, iter, auth_safe)?;
+        if mac.len() == expected.len() && memcmp::eq(&mac, expected) {
+            return Ok(bmp);
+        }
+    }
+    Err(ErrorStack::internal_error_str(
+        "PKCS #12 MAC verification failed",
+    ))
+}
+
+fn hmac_digest_from_oid(oid: &[u8]) -> Result<MessageDigest, ErrorStack> {
+    match oid {
+        [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x07] => Ok(MessageDigest::sha1()),
+        [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x09] => Ok(MessageDigest::sha256()),
+        [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x0a] => Ok(MessageDigest::sha384()),
+        [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x0b] => Ok(MessageDigest::sha512()),
+        _ => Err(unsupported()),
+    }
+}
+
+fn cipher_from_oid(oid: &[u8]) -> Result<Cipher, ErrorStack> {
+    match oid {
+        [0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x02] => Ok(Cipher::aes_128_cbc()),
+        [0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x16] => Ok(Cipher::aes_192_cbc()),
+        [0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x2a] => Ok(Cipher::aes_256_cbc()),
+        [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x03, 0x07] => Ok(Cipher::des_ede3_cbc()),
+        _ => Err(unsupported()),
+    }
+}
+
+/// Encrypts `plaintext`, returning the encoded AlgorithmIdentifier and the ciphertext.
+fn pbes2_encrypt(
+    pass: &str,
+    iter: u32,
+    plaintext: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), ErrorStack> {
+    let mut salt = [0; 16];
+    rand_bytes(&mut salt)?;
+    let mut iv = [0; 16];
+    rand_bytes(&mut iv)?;
+    let mut key = [0; 32];
+    pbkdf2_hmac(
+        pass.as_bytes(),
+        &salt,
+        iter as usize,
+        MessageDigest::sha256(),
+        &mut key,
+    )?;
+    let ciphertext = symm::encrypt(Cipher::aes_256_cbc(), &key, Some(&iv), plaintext)?;
+
+    let algorithm = der::encode(|w| {
+        w.write_nested(der::SEQUENCE, |w| {
+            w.write(der::OBJECT_IDENTIFIER, OID_PBES2);
+            w.write_nested(der::SEQUENCE, |w| {
+                w.write_nested(der::SEQUENCE, |w| {
+                    w.write(der::OBJECT_IDENTIFIER, OID_PBKDF2);
+                    w.write_nested(der::SEQUENCE, |w| {
+                        w.write(der::OCTET_STRING, &salt);
+                        w.write_u32(iter);
+                        w.write_nested(der::SEQUENCE, |w| {
+                            w.write(der::OBJECT_IDENTIFIER, OID_HMAC_WITH_SHA256);
+                            w.write(der::NULL, &[]);
+                        });
+                    });
+                });
+                w.write_nested(der::SEQUENCE, |w| {
+                    w.write(der::OBJECT_IDENTIFIER, OID_AES_256_CBC);
+                    w.write(der::OCTET_STRING, &iv);
+                });
+            });
+        });
+    });
+    Ok((algorithm, ciphertext))
+}
+
+/// Decrypts `ciphertext` with the algorithm whose AlgorithmIdentifier has the given contents.
+fn decrypt(
+    algorithm: &[u8],
+    password: &Password<'_>,
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, ErrorStack> {
+    let mut algorithm = DerReader::new(algorithm);
+    let oid = algorithm.read(der::OBJECT_IDENTIFIER)?;
+    let params = algorithm.read(der::SEQUENCE)?;
+    if oid == OID_PBES2 {
+        pbes2_decrypt(params, password.utf8, ciphertext)
+    } else if let Some(&[scheme]) = oid.strip_prefix(OID_PKCS12_PBE) {
+        pkcs12_pbe_decrypt(scheme, params, &password.bmp, ciphertext)
+    } else {
+        Err(unsupported())
+    }
+}
+
+fn pbes2_decrypt(params: &[u8], pass: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+    let mut params = DerReader::new(params);
+    let mut kdf = DerReader::new(params.read(der::SEQUENCE)?);
+    if kdf.read(der::OBJECT_IDENTIFIER)? != OID_PBKDF2 {
+        return Err(unsupported());
+    }
+    let mut kdf = DerReader::new(kdf.read(der::SEQUENCE)?);
+    let salt = kdf.read(der::OCTET_STRING)?;
+    let iter = read_iterations(&mut kdf)?;
+    let key_len = match kdf.peek_tag() {
+        Some(der::INTEGER) => Some(kdf.read_u32()?),
+        _ => None,
+    };
+    let prf = match kdf.read_optional(der::SEQUENCE)? {
+        Some(prf) => hmac_digest_from_oid(DerReader::new(prf).read(der::OBJECT_IDENTIFIER)?)?,
+        None => MessageDigest::sha1(),
+    };
+
+    let mut scheme = DerReader::new(params.read(der::SEQUENCE)?);
+    let cipher = cipher_from_oid(scheme.read(der::OBJECT_IDENTIFIER)?)?;
+    let iv = scheme.read(der::OCTET_STRING)?;
+    if key_len.is_some_and(|len| len as usize != cipher.key_len()) {
+        return Err(unsupported());
+    }
+
+    let mut key = vec![0; cipher.key_len()];
+    pbkdf2_hmac(pass, salt, iter as usize, prf, &mut key)?;
+    symm::decrypt(cipher, &key, Some(iv), ciphertext)
+}
+
+/// Decrypts with one of the legacy schemes of RFC 7292, appendix C.
+fn pkcs12_pbe_decrypt(
+    scheme: u8,
+    params: &[u8],
+    bmp_pass: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, ErrorStack> {
+    let (cipher, key_len) = unsafe {
+        match scheme {
+            3 => (Cipher::des_ede3_cbc(), 24),
+            4 => (Cipher::des_ede3_cbc(), 16),
+            5 => (Cipher::from_ptr(ffi::EVP_rc2_cbc()), 16),
+            6 => (Cipher::from_ptr(ffi::EVP_rc2_40_cbc()), 5),
+            _ => return Err(unsupported()),
+        }
+    };
+    let mut params = DerReader::new(params);
+    let salt = params.read(der::OCTET_STRING)?;
+    let iter = read_iterations(&mut params)?;
+
+    let md = MessageDigest::sha1();
+    let mut key = vec![0; key_len];
+    pkcs12_kdf(md, bmp_pass, salt, 1, iter, &mut key)?;
+    if scheme == 4 {
+        // Two-key triple DES reuses the first key.
+        key.extend_from_within(..8);
+    }
+    let mut iv = [0; 8];
+    pkcs12_kdf(md, bmp_pass, salt, 2, iter, &mut iv)?;
+    symm::decrypt(cipher, &key, Some(&iv), ciphertext)
+}
+
+fn encode_attributes(friendly_name: Option<&str>, local_key_id: Option<&[u8]>) -> Vec<Vec<u8>> {
+    let attribute = |oid: &[u8], tag: u8, value: &[u8]| {
+        der::encode(|w| {
+            w.write_nested(der::SEQUENCE, |w| {
+                w.write(der::OBJECT_IDENTIFIER, oid);
+                w.write_nested(der::SET, |w| w.write(tag, value));
+            });
+        })
+    };
+    let mut attributes = vec![];
+    if let Some(friendly_name) = friendly_name {
+        let bmp = friendly_name
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect::<Vec<_>>();
+        attributes.push(attribute(OID_FRIENDLY_NAME, der::BMP_STRING, &bmp));
+    }
+    if let Some(local_key_id) = local_key_id {
+        attributes.push(attribute(OID_LOCAL_KEY_ID, der::OCTET_STRING, local_key_id));
+    }
+    attributes
+}
+
+fn read_attributes(attributes: &[u8]) -> Result<(Option<String>, Option<Vec<u8>>), ErrorStack> {
+    let mut friendly_name = None;
+    let mut local_key_id = None;
+    let mut attributes = DerReader::new(attributes);
+    while !attributes.is_empty() {
+        let mut attribute = DerReader::new(attributes.read(der::SEQUENCE)?);
+        let oid = attribute.read(der::OBJECT_IDENTIFIER)?;
+        let mut values = DerReader::new(attribute.read(der::SET)?);
+        if oid == OID_FRIENDLY_NAME {
+            let bmp = values.read(der::BMP_STRING)?;
+            if bmp.len() % 2 != 0 {
+                return Err(malformed());
+            }
+            let units = bmp
+                .chunks_exact(2)
+                .map(|unit| u16::from_be_bytes([unit[0], unit[1]]));
+            friendly_name = Some(
+                char::decode_utf16(units)
+                    .collect::<Result<String, _>>()
+                    .map_err(ErrorStack::internal_error)?,
+            );
+        } else if oid == OID_LOCAL_KEY_ID {
+            local_key_id = Some(values.read(der::OCTET_STRING)?.to_vec());
+        }
+    }
+    Ok((friendly_name, local_key_id))
+}
+
+fn write_safe_bag(w: &mut DerWriter, bag_id: &[u8], value: &[u8], attributes: &[Vec<u8>]) {
+    w.write_nested(der::SEQUENCE, |w| {
+        w.write(der::OBJECT_IDENTIFIER, bag_id);
+        w.write(der::context(0, true), value);
+        if !attributes.is_empty() {
+            w.write_set_of(attributes.to_vec());
+        }
+    });
+}
+
+fn encode_data(contents: &[u8]) -> Vec<u8> {
+    der::encode(|w| {
+        w.write_nested(der::SEQUENCE, |w| {
+            w.write(der::OBJECT_IDENTIFIER, OID_DATA);
+            w.write_nested(der::context(0, true), |w| {
+                w.write(der::OCTET_STRING, contents);
+            });
+        });
+    })
+}
+
+fn encode_encrypted_data(algorithm: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+    der::encode(|w| {
+        w.write_nested(der::SEQUENCE, |w| {
+            w.write(der::OBJECT_IDENTIFIER, OID_ENCRYPTED_DATA);
+            w.write_nested(der::context(0, true), |w| {
+                w.write_nested(der::SEQUENCE, |w| {
+                    w.write_u32(0);
+                    w.write_nested(der::SEQUENCE, |w| {
+                        w.write(der::OBJECT_IDENTIFIER, OID_DATA);
+                        w.write_raw(algorithm);
+                        w.write(der::context(0, false), ciphertext);
+                    });
+                });
+            });
+        });
+    })
+}
+
+/// Returns the content type and the explicitly tagged content of a ContentInfo.
+fn read_content_info(content_info: &[u8]) -> Result<(&[u8], &[u8]), ErrorStack> {
+    let mut content_info = DerReader::new(content_info);
+    let content_type = content_info.read(der::OBJECT_IDENTIFIER)?;
+    let content = content_info.read(der::context(0, true))?;
+    Ok((content_type, content))
+}
+
+fn decrypt_data(content: &[u8], password: &Password<'_>) -> Result<Vec<u8>, ErrorStack> {
+    let mut encrypted_data = DerReader::new(DerReader::new(content).read(der::SEQUENCE)?);
+    encrypted_data.read_u32()?;
+    let mut info = DerReader::new(encrypted_data.read(der::SEQUENCE)?);
+    info.read(der::OBJECT_IDENTIFIER)?;
+    let algorithm = info.read(der::SEQUENCE)?;
+    // The ciphertext is an implicitly tagged OCTET STRING, which BER allows to be split.
+    let ciphertext = match info.read_any()? {
+        (tag, ciphertext) if tag == der::context(0, false) => ciphertext.to_vec(),
+        (tag, parts) if tag == der::context(0, true) => {
+            let mut parts = DerReader::new(parts);
+            let mut ciphertext = vec![];
+            while !parts.is_empty() {
+                ciphertext.extend_from_slice(parts.read(der::OCTET_STRING)?);
+            }
+            ciphertext
+        }
+        _ => return Err(malformed()),
+    };
+    decrypt(algorithm, password, &ciphertext)
+}
+
+fn read_safe_contents(
+    safe_contents: &[u8],
+    password: &Password<'_>,
+    bags: &mut Pkcs12Bags,
+) -> Result<(), ErrorStack> {
+    let mut safe_bags = DerReader::new(DerReader::new(safe_contents).read(der::SEQUENCE)?);
+    while !safe_bags.is_empty() {
+        let mut safe_bag = DerReader::new(safe_bags.read(der::SEQUENCE)?);
+        let bag_id = safe_bag.read(der::OBJECT_IDENTIFIER)?;
+        let value = safe_bag.read(der::context(0, true))?;
+        let (friendly_name, local_key_id) = match safe_bag.read_optional(der::SET)? {
+            Some(attributes) => read_attributes(attributes)?,
+            None => (None, None),
+        };
+
+        if bag_id == OID_KEY_BAG || bag_id == OID_SHROUDED_KEY_BAG {
+            let pkey = if bag_id == OID_KEY_BAG {
+                PKey::private_key_from_pkcs8(value)?
+            } else {
+                let mut info = DerReader::new(DerReader::new(value).read(der::SEQUENCE)?);
+                let algorithm = info.read(der::SEQUENCE)?;
+                let ciphertext = info.read(der::OCTET_STRING)?;
+                PKey::private_key_from_pkcs8(&decrypt(algorithm, password, ciphertext)?)?
+            };
+            bags.keys.push(Pkcs12Bag {
+                value: pkey,
+                friendly_name,
+                local_key_id,
+            });
+        } else if bag_id == OID_CERT_BAG {
+            let mut cert_bag = DerReader::new(DerReader::new(value).read(der::SEQUENCE)?);
+            if cert_bag.read(der::OBJECT_IDENTIFIER)? != OID_X509_CERTIFICATE {
+                continue;
+            }
+            let cert =
+                DerReader::new(cert_bag.read(der::context(0, true))?).read(der::OCTET_STRING)?;
+            bags.certs.push(Pkcs12Bag {
+                value: X509::from_der(cert)?,
+                friendly_name,
+                local_key_id,
+            });
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use crate::hash::MessageDigest;
@@ -280,4 +997,181 @@ mod test {
         );
         assert!(parsed.pkey.public_eq(&pkey));
     }
+
+    fn check_identity_bags(bags: &Pkcs12Bags) {
+        let leaf_sha1 = "59172d9313e84459bcff27f967e79e6e9217e584";
+
+        assert_eq!(bags.keys.len(), 1);
+        assert_eq!(bags.keys[0].friendly_name.as_deref(), Some("foobar.com"));
+        assert_eq!(
+            hex::encode(bags.keys[0].local_key_id.as_ref().unwrap()),
+            leaf_sha1
+        );
+
+        assert_eq!(bags.certs.len(), 2);
+        let leaf = &bags.certs[0];
+        assert_eq!(
+            hex::encode(leaf.value.digest(MessageDigest::sha1()).unwrap()),
+            leaf_sha1
+        );
+        assert_eq!(leaf.friendly_name.as_deref(), Some("foobar.com"));
+        assert_eq!(hex::encode(leaf.local_key_id.as_ref().unwrap()), leaf_sha1);
+        assert!(leaf
+            .value
+            .public_key()
+            .unwrap()
+            .public_eq(&bags.keys[0].value));
+
+        let ca = &bags.certs[1];
+        assert_eq!(
+            hex::encode(ca.value.digest(MessageDigest::sha1()).unwrap()),
+            "c0cbdf7cdd03c9773e5468e1f6d2da7d5cbb1875"
+        );
+        assert_eq!(ca.friendly_name, None);
+        assert_eq!(ca.local_key_id, None);
+    }
+
+    #[test]
+    fn parse_bags() {
+        // Created with the defaults of OpenSSL 3: PBES2, AES-256-CBC and SHA-256.
+        let der = include_bytes!("../test/identity-aes256.p12");
+        let pkcs12 = Pkcs12::from_der(der).unwrap();
+        check_identity_bags(&pkcs12.parse_bags("mypass").unwrap());
+        assert!(pkcs12.parse_bags("wrong").is_err());
+    }
+
+    #[test]
+    fn parse_bags_legacy() {
+        let der = include_bytes!("../test/identity.p12");
+        let pkcs12 = Pkcs12::from_der(der).unwrap();
+        check_identity_bags(&pkcs12.parse_bags("mypass").unwrap());
+    }
+
+    #[test]
+    fn parse_bags_no_mac() {
+        let der = include_bytes!("../test/identity-nomac.p12");
+        let pkcs12 = Pkcs12::from_der(der).unwrap();
+        check_identity_bags(&pkcs12.parse_bags("").unwrap());
+        assert!(pkcs12.parse_bags("ignored").is_err());
+        check_identity_bags(&pkcs12.parse_bags_unauthenticated("ignored").unwrap());
+    }
+
+    /// Returns an unencrypted archive of the identity, with a MAC claiming `iter` iterations.
+    fn with_mac_iterations(iter: u32) -> Pkcs12 {
+        let der = include_bytes!("../test/identity-aes256.p12");
+        let bags = Pkcs12::from_der(der).unwrap().parse_bags("mypass").unwrap();
+        let mut builder = Pkcs12::pbes2_builder();
+        builder.add_key(&bags.keys[0].value, None, None).unwrap();
+        let archive = builder.build(None).unwrap().to_der().unwrap();
+
+        let mut pfx = DerReader::new(DerReader::new(&archive).read(der::SEQUENCE).unwrap());
+        pfx.read_u32().unwrap();
+        let auth_safe = pfx.read(der::SEQUENCE).unwrap();
+        let pfx = der::encode(|w| {
+            w.write_nested(der::SEQUENCE, |w| {
+                w.write_u32(3);
+                w.write(der::SEQUENCE, auth_safe);
+                w.write_nested(der::SEQUENCE, |w| {
+                    w.write_nested(der::SEQUENCE, |w| {
+                        w.write_nested(der::SEQUENCE, |w| {
+                            w.write(der::OBJECT_IDENTIFIER, OID_SHA256);
+                            w.write(der::NULL, &[]);
+                        });
+                        w.write(der::OCTET_STRING, &[0; 32]);
+                    });
+                    w.write(der::OCTET_STRING, &[0; 8]);
+                    w.write_u32(iter);
+                });
+            });
+        });
+        Pkcs12::from_der(&pfx).unwrap()
+    }
+
+    #[test]
+    fn parse_bags_iteration_limits() {
+        for iter in [0, MAX_ITERATIONS + 1, u32::MAX] {
+            let err = with_mac_iterations(iter).parse_bags("").err().unwrap();
+            assert!(err.to_string().contains("iteration count"), "{err}");
+        }
+        let err = with_mac_iterations(1).parse_bags("").err().unwrap();
+        assert!(err.to_string().contains("MAC verification failed"), "{err}");
+    }
+
+    /// Parses truncated and corrupted copies of the test archives, which must fail or succeed
+    /// without panicking.
+    #[test]
+    fn parse_bags_mutated() {
+        let parse = |der: &[u8], pass: &str| {
+            if let Ok(pkcs12) = Pkcs12::from_der(der) {
+                let _ = pkcs12.parse_bags_unauthenticated(pass);
+            }
+        };
+        for (der, pass) in [
+            (&include_bytes!("../test/identity.p12")[..], "mypass"),
+            (include_bytes!("../test/identity-aes256.p12"), "mypass"),
+            (include_bytes!("../test/identity-nomac.p12"), ""),
+        ] {
+            for len in 0..der.len() {
+                parse(&der[..len], pass);
+            }
+        }
+
+        // Without a MAC, corrupted contents reach every part of the parser.
+        let mut der = include_bytes!("../test/identity-nomac.p12").to_vec();
+        for i in 0..der.len() {
+            for bit in [0x01, 0x80] {
+                der[i] ^= bit;
+                parse(&der, "");
+                der[i] ^= bit;
+            }
+        }
+    }
+
+    #[test]
+    fn create_pbes2() {
+        let der = include_bytes!("../test/identity-aes256.p12");
+        let bags = Pkcs12::from_der(der).unwrap().parse_bags("mypass").unwrap();
+        let key = &bags.keys[0].value;
+        let leaf = &bags.certs[0].value;
+        let ca = &bags.certs[1].value;
+
+        let mut builder = Pkcs12::pbes2_builder();
+        builder
+            .add_key(key, Some("clé ☃"), Some(b"key id"))
+            .unwrap();
+        builder
+            .add_cert(leaf, Some("clé ☃"), Some(b"key id"))
+            .unwrap();
+        builder.add_cert(ca, None, None).unwrap();
+
+        let pkcs12 = builder.build(Some("mypass")).unwrap();
+        let pkcs12 = Pkcs12::from_der(&pkcs12.to_der().unwrap()).unwrap();
+        let parsed = pkcs12.parse_bags("mypass").unwrap();
+        assert_eq!(parsed.keys.len(), 1);
+        assert!(parsed.keys[0].value.public_eq(key));
+        assert_eq!(parsed.keys[0].friendly_name.as_deref(), Some("clé ☃"));
+        assert_eq!(parsed.keys[0].local_key_id.as_deref(), Some(&b"key id"[..]));
+        assert_eq!(parsed.certs.len(), 2);
+        assert_eq!(
+            parsed.certs[0].value.to_der().unwrap(),
+            leaf.to_der().unwrap()
+        );
+        assert_eq!(parsed.certs[0].friendly_name.as_deref(), Some("clé ☃"));
+        assert_eq!(
+            parsed.certs[1].value.to_der().unwrap(),
+            ca.to_der().unwrap()
+        );
+        assert!(pkcs12.parse_bags("wrong").is_err());
+
+        // BoringSSL's own parser understands the archive too.
+        let parsed = pkcs12.parse("mypass").unwrap();
+        assert!(parsed.pkey.public_eq(key));
+        assert_eq!(parsed.cert.to_der().unwrap(), leaf.to_der().unwrap());
+        assert_eq!(parsed.chain.unwrap().len(), 1);
+
+        let pkcs12 = builder.build(None).unwrap();
+        let parsed = pkcs12.parse_bags("").unwrap();
+        assert!(parsed.keys[0].value.public_eq(key));
+        assert_eq!(parsed.certs.len(), 2);
+    }
 }
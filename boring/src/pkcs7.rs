@@ -0,0 +1,365 @@
+//! PKCS #7 certificate bundles and detached signatures.
+//!
+//! Certificate chains are often exchanged as degenerate SignedData structures, without content
+//! or signers, in files named `.p7b` or `.p7c`.
+
+use crate::ffi;
+use foreign_types::{ForeignType, ForeignTypeRef};
+use openssl_macros::corresponds;
+use std::ptr;
+
+use crate::bio::MemBioSlice;
+use crate::der::{self, DerReader};
+use crate::error::ErrorStack;
+use crate::hash::hash;
+use crate::sign::Verifier;
+use crate::stack::Stack;
+use crate::x509::store::X509StoreRef;
+use crate::x509::verify::VerifyOptions;
+use crate::x509::X509;
+use crate::{cvt, cvt_p};
+
+const OID_SIGNED_DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02];
+const OID_CONTENT_TYPE: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x03];
+const OID_MESSAGE_DIGEST: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x04];
+
+foreign_type_and_impl_send_sync! {
+    type CType = ffi::PKCS7;
+    fn drop = ffi::PKCS7_free;
+
+    /// A PKCS #7 structure.
+    pub struct Pkcs7;
+}
+
+impl Pkcs7 {
+    from_der! {
+        /// Deserializes a DER-encoded PKCS #7 structure.
+        #[corresponds(d2i_PKCS7)]
+        from_der,
+        Pkcs7,
+        ffi::d2i_PKCS7,
+        ::libc::size_t
+    }
+
+    from_pem! {
+        /// Deserializes a PEM-encoded PKCS #7 structure.
+        ///
+        /// The input should have a header of `-----BEGIN PKCS7-----`.
+        #[corresponds(PEM_read_bio_PKCS7)]
+        from_pem,
+        Pkcs7,
+        ffi::PEM_read_bio_PKCS7
+    }
+
+    /// Creates a certificate bundle holding `certs`.
+    #[corresponds(PKCS7_bundle_certificates)]
+    pub fn from_certificates(certs: &[X509]) -> Result<Pkcs7, ErrorStack> {
+        let mut stack = Stack::new()?;
+        for cert in certs {
+            stack.push(cert.clone())?;
+        }
+
+        unsafe {
+            let mut cbb = std::mem::zeroed::<ffi::CBB>();
+            cvt(ffi::CBB_init(&mut cbb, 0))?;
+            let mut out = ptr::null_mut();
+            let mut out_len = 0;
+            if ffi::PKCS7_bundle_certificates(&mut cbb, stack.as_ptr()) != 1
+                || ffi::CBB_finish(&mut cbb, &mut out, &mut out_len) != 1
+            {
+                ffi::CBB_cleanup(&mut cbb);
+                return Err(ErrorStack::get());
+            }
+            let pkcs7 = Pkcs7::from_der(std::slice::from_raw_parts(out, out_len));
+            ffi::OPENSSL_free(out.cast());
+            pkcs7
+        }
+    }
+
+    /// Returns the certificates of a DER-encoded PKCS #7 SignedData structure.
+    #[corresponds(PKCS7_get_certificates)]
+    pub fn certificates_from_der(der: &[u8]) -> Result<Vec<X509>, ErrorStack> {
+        let certs = Stack::new()?;
+        unsafe {
+            ffi::init();
+            let mut cbs = std::mem::zeroed::<ffi::CBS>();
+            ffi::CBS_init(&mut cbs, der.as_ptr(), der.len());
+            cvt(ffi::PKCS7_get_certificates(certs.as_ptr(), &mut cbs))?;
+        }
+        Ok(certs.into_iter().collect())
+    }
+
+    /// Returns the certificates of a PEM-encoded PKCS #7 SignedData structure.
+    #[corresponds(PKCS7_get_PEM_certificates)]
+    pub fn certificates_from_pem(pem: &[u8]) -> Result<Vec<X509>, ErrorStack> {
+        let certs = Stack::new()?;
+        unsafe {
+            ffi::init();
+            let bio = MemBioSlice::new(pem)?;
+            cvt(ffi::PKCS7_get_PEM_certificates(
+                certs.as_ptr(),
+                bio.as_ptr(),
+            ))?;
+        }
+        Ok(certs.into_iter().collect())
+    }
+}
+
+impl Pkcs7Ref {
+    to_der! {
+        /// Serializes the structure to its DER encoding.
+        #[corresponds(i2d_PKCS7)]
+        to_der,
+        ffi::i2d_PKCS7
+    }
+
+    to_pem! {
+        /// Serializes the structure to PEM, with a header of `-----BEGIN PKCS7-----`.
+        #[corresponds(PEM_write_bio_PKCS7)]
+        to_pem,
+        ffi::PEM_write_bio_PKCS7
+    }
+
+    /// Returns the certificates of the structure, which must be a SignedData.
+    pub fn certificates(&self) -> Result<Vec<X509>, ErrorStack> {
+        Pkcs7::certificates_from_der(&self.to_der()?)
+    }
+
+    /// Verifies that the structure is a valid detached signature of `data`, returning the
+    /// certificates of the signers.
+    ///
+    /// The structure must be a SignedData including the certificate of every signer. Each of them
+    /// is verified against `store` with `options`, using the other certificates of the structure
+    /// as intermediates, and all signatures must be valid. RSA PKCS #1 v1.5 and ECDSA signatures
+    /// are supported, with or without signed attributes.
+    pub fn verify_detached(
+        &self,
+        data: &[u8],
+        store: &X509StoreRef,
+        options: &VerifyOptions,
+    ) -> Result<Vec<X509>, ErrorStack> {
+        let der = der::ber_to_der(&self.to_der()?)?;
+        let certs = Pkcs7::certificates_from_der(&der)?;
+
+        let mut content_info = DerReader::new(DerReader::new(&der).read(der::SEQUENCE)?);
+        if content_info.read(der::OBJECT_IDENTIFIER)? != OID_SIGNED_DATA {
+            return Err(ErrorStack::internal_error_str("not a PKCS #7 SignedData"));
+        }
+        let signed_data =
+            DerReader::new(content_info.read(der::context(0, true))?).read(der::SEQUENCE)?;
+        let mut signed_data = DerReader::new(signed_data);
+        signed_data.read_u32()?;
+        signed_data.read(der::SET)?;
+        let content_type =
+            DerReader::new(signed_data.read(der::SEQUENCE)?).read(der::OBJECT_IDENTIFIER)?;
+        signed_data.read_optional(der::context(0, true))?;
+        signed_data.read_optional(der::context(1, true))?;
+
+        let mut signer_infos = DerReader::new(signed_data.read(der::SET)?);
+        if signer_infos.is_empty() {
+            return Err(ErrorStack::internal_error_str("no PKCS #7 signers"));
+        }
+        let mut signers = vec![];
+        while !signer_infos.is_empty() {
+            let signer_info = signer_infos.read(der::SEQUENCE)?;
+            let signer = verify_signer(signer_info, content_type, data, &certs)?;
+            if !store.verify_chain(&signer, &certs, options)?.is_valid() {
+                return Err(ErrorStack::internal_error_str("untrusted PKCS #7 signer"));
+            }
+            signers.push(signer);
+        }
+        Ok(signers)
+    }
+}
+
+/// Checks the signature of a SignerInfo, returning the certificate of the signer.
+fn verify_signer(
+    signer_info: &[u8],
+    content_type: &[u8],
+    data: &[u8],
+    certs: &[X509],
+) -> Result<X509, ErrorStack> {
+    let mut signer_info = DerReader::new(signer_info);
+    signer_info.read_u32()?;
+    let cert = match signer_info.read_any()? {
+        (der::SEQUENCE, issuer_and_serial) => {
+            let mut issuer_and_serial = DerReader::new(issuer_and_serial);
+            let issuer = issuer_and_serial.read(der::SEQUENCE)?;
+            let issuer = der::encode(|w| w.write(der::SEQUENCE, issuer));
+            let serial = issuer_and_serial.read(der::INTEGER)?;
+            find_cert(certs, |cert| {
+                Ok(cert.issuer_name().to_der()? == issuer
+                    && cert.serial_number().to_bn()?.to_vec()
+                        == serial.strip_prefix(&[0]).unwrap_or(serial))
+            })?
+        }
+        (tag, key_id) if tag == der::context(0, false) => find_cert(certs, |cert| {
+            Ok(cert.subject_key_id().map(|id| id.as_slice()) == Some(key_id))
+        })?,
+        _ => return Err(ErrorStack::internal_error_str("malformed PKCS #7 signer")),
+    };
+
+    let md = der::digest_from_oid(
+        DerReader::new(signer_info.read(der::SEQUENCE)?).read(der::OBJECT_IDENTIFIER)?,
+    )?;
+    let signed_attributes = signer_info.read_optional(der::context(0, true))?;
+    // The algorithm is implied by the key of the signer.
+    signer_info.read(der::SEQUENCE)?;
+    let signature = signer_info.read(der::OCTET_STRING)?;
+
+    let public_key = cert.public_key()?;
+    let mut verifier = Verifier::new(md, &public_key)?;
+    match signed_attributes {
+        Some(attributes) => {
+            check_signed_attributes(attributes, content_type, &hash(md, data)?)?;
+            // The signature covers the attributes encoded as a SET, not with their implicit tag.
+            verifier.update(&der::encode(|w| w.write(der::SET, attributes)))?;
+        }
+        None => verifier.update(data)?,
+    }
+    if !verifier.verify(signature)? {
+        return Err(ErrorStack::internal_error_str("invalid PKCS #7 signature"));
+    }
+    Ok(cert)
+}
+
+fn find_cert(
+    certs: &[X509],
+    mut matches: impl FnMut(&X509) -> Result<bool, ErrorStack>,
+) -> Result<X509, ErrorStack> {
+    for cert in certs {
+        if matches(cert)? {
+            return Ok(cert.clone());
+        }
+    }
+    Err(ErrorStack::internal_error_str(
+        "PKCS #7 signer certificate not found",
+    ))
+}
+
+fn check_signed_attributes(
+    attributes: &[u8],
+    content_type: &[u8],
+    digest: &[u8],
+) -> Result<(), ErrorStack> {
+    let mut signed_content_type = None;
+    let mut message_digest = None;
+    let mut attributes = DerReader::new(attributes);
+    while !attributes.is_empty() {
+        let mut attribute = DerReader::new(attributes.read(der::SEQUENCE)?);
+        let oid = attribute.read(der::OBJECT_IDENTIFIER)?;
+        let mut values = DerReader::new(attribute.read(der::SET)?);
+        if oid == OID_CONTENT_TYPE {
+            signed_content_type = Some(values.read(der::OBJECT_IDENTIFIER)?);
+        } else if oid == OID_MESSAGE_DIGEST {
+            message_digest = Some(values.read(der::OCTET_STRING)?);
+        }
+    }
+    if signed_content_type != Some(content_type) || message_digest != Some(digest) {
+        return Err(ErrorStack::internal_error_str(
+            "PKCS #7 signed attributes do not match the content",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use hex;
+
+    use super::*;
+    use crate::hash::MessageDigest;
+    use crate::x509::store::{X509Store, X509StoreBuilder};
+
+    fn check_bundle(certs: &[X509]) {
+        let digests = certs
+            .iter()
+            .map(|cert| hex::encode(cert.digest(MessageDigest::sha1()).unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            digests,
+            [
+                "59172d9313e84459bcff27f967e79e6e9217e584",
+                "c0cbdf7cdd03c9773e5468e1f6d2da7d5cbb1875",
+            ]
+        );
+    }
+
+    #[test]
+    fn certificates() {
+        check_bundle(&Pkcs7::certificates_from_der(include_bytes!("../test/certs.p7b")).unwrap());
+        check_bundle(
+            &Pkcs7::certificates_from_pem(include_bytes!("../test/certs-p7b.pem")).unwrap(),
+        );
+        let pkcs7 = Pkcs7::from_pem(include_bytes!("../test/certs-p7b.pem")).unwrap();
+        check_bundle(&pkcs7.certificates().unwrap());
+    }
+
+    #[test]
+    fn from_certificates() {
+        let certs = Pkcs7::certificates_from_der(include_bytes!("../test/certs.p7b")).unwrap();
+        let pkcs7 = Pkcs7::from_certificates(&certs).unwrap();
+
+        let der = pkcs7.to_der().unwrap();
+        check_bundle(&Pkcs7::certificates_from_der(&der).unwrap());
+        check_bundle(&Pkcs7::from_der(&der).unwrap().certificates().unwrap());
+        check_bundle(&Pkcs7::certificates_from_pem(&pkcs7.to_pem().unwrap()).unwrap());
+
+        let empty = Pkcs7::from_certificates(&[]).unwrap();
+        assert!(empty.certificates().unwrap().is_empty());
+    }
+
+    fn store() -> X509Store {
+        let ca = X509::from_pem(include_bytes!("../test/root-ca.pem")).unwrap();
+        let mut store = X509StoreBuilder::new().unwrap();
+        store.add_cert(ca).unwrap();
+        store.build()
+    }
+
+    fn options() -> VerifyOptions {
+        // The test certificates have expired since.
+        let mut options = VerifyOptions::new();
+        options.set_time(1_600_000_000);
+        options
+    }
+
+    #[test]
+    fn verify_detached() {
+        let data = include_bytes!("../test/manifest.txt");
+        let store = store();
+
+        // Signed with and without signed attributes.
+        for der in [
+            &include_bytes!("../test/manifest.p7s")[..],
+            &include_bytes!("../test/manifest-noattr.p7s")[..],
+        ] {
+            let pkcs7 = Pkcs7::from_der(der).unwrap();
+            let signers = pkcs7.verify_detached(data, &store, &options()).unwrap();
+            assert_eq!(signers.len(), 1);
+            assert_eq!(
+                hex::encode(signers[0].digest(MessageDigest::sha1()).unwrap()),
+                "59172d9313e84459bcff27f967e79e6e9217e584"
+            );
+
+            let mut tampered = data.to_vec();
+            tampered[0] ^= 1;
+            pkcs7
+                .verify_detached(&tampered, &store, &options())
+                .unwrap_err();
+        }
+    }
+
+    #[test]
+    fn verify_detached_untrusted() {
+        let data = include_bytes!("../test/manifest.txt");
+        let pkcs7 = Pkcs7::from_der(include_bytes!("../test/manifest.p7s")).unwrap();
+        let empty = X509StoreBuilder::new().unwrap().build();
+        pkcs7.verify_detached(data, &empty, &options()).unwrap_err();
+
+        // A bundle without signers proves nothing.
+        let bundle = Pkcs7::from_der(include_bytes!("../test/certs.p7b")).unwrap();
+        bundle
+            .verify_detached(data, &store(), &options())
+            .unwrap_err();
+    }
+}
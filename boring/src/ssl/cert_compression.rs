@@ -0,0 +1,255 @@
+//! Certificate compression algorithms of RFC 8879.
+//!
+//! Each algorithm is behind its own feature: `cert-compression-brotli`, `cert-compression-zlib`
+//! and `cert-compression-zstd`. Decompression stops with an error once the output exceeds 100 KiB,
+//! or the limit set on the compressor, so that a small compressed message cannot make the peer
+//! inflate a large certificate chain.
+#[cfg(any(
+    feature = "cert-compression-brotli",
+    feature = "cert-compression-zlib",
+    feature = "cert-compression-zstd"
+))]
+use std::io::{self, Read, Write};
+
+use crate::error::ErrorStack;
+use crate::ssl::SslContextBuilder;
+#[cfg(any(
+    feature = "cert-compression-brotli",
+    feature = "cert-compression-zlib",
+    feature = "cert-compression-zstd"
+))]
+use crate::ssl::{CertificateCompressionAlgorithm, CertificateCompressor};
+
+/// The default limit on the size of a decompressed certificate chain, which is also BoringSSL's
+/// default limit on the size of a certificate chain.
+#[cfg(any(
+    feature = "cert-compression-brotli",
+    feature = "cert-compression-zlib",
+    feature = "cert-compression-zstd"
+))]
+const DEFAULT_MAX_DECOMPRESSED_LEN: usize = 100 * 1024;
+
+/// Copies the output of `decoder` to `output`, failing if it exceeds `max_len` bytes.
+#[cfg(any(
+    feature = "cert-compression-brotli",
+    feature = "cert-compression-zlib",
+    feature = "cert-compression-zstd"
+))]
+fn decompress_bounded<R, W>(decoder: R, output: &mut W, max_len: usize) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    let limit = u64::try_from(max_len).unwrap_or(u64::MAX).saturating_add(1);
+    let len = io::copy(&mut decoder.take(limit), output)?;
+    if len >= limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "decompressed certificate exceeds the size limit",
+        ));
+    }
+    Ok(())
+}
+
+/// Brotli certificate compression.
+///
+/// Requires the `cert-compression-brotli` feature.
+#[cfg(feature = "cert-compression-brotli")]
+#[derive(Debug, Clone)]
+pub struct BrotliCertificateCompressor {
+    quality: u32,
+    window: u32,
+    max_decompressed_len: usize,
+}
+
+#[cfg(feature = "cert-compression-brotli")]
+impl Default for BrotliCertificateCompressor {
+    fn default() -> Self {
+        Self {
+            quality: 5,
+            window: 18,
+            max_decompressed_len: DEFAULT_MAX_DECOMPRESSED_LEN,
+        }
+    }
+}
+
+#[cfg(feature = "cert-compression-brotli")]
+impl BrotliCertificateCompressor {
+    /// Sets the compression quality, from 0 to 11. Defaults to 5.
+    ///
+    /// The certificate chain is compressed on every handshake, which at 11 costs an order of
+    /// magnitude more CPU time for a few percent smaller output.
+    pub fn set_quality(&mut self, quality: u32) {
+        self.quality = quality.min(11);
+    }
+
+    /// Sets the limit on the size of decompressed certificate chains. Defaults to 100 KiB.
+    pub fn set_max_decompressed_len(&mut self, len: usize) {
+        self.max_decompressed_len = len;
+    }
+}
+
+#[cfg(feature = "cert-compression-brotli")]
+impl CertificateCompressor for BrotliCertificateCompressor {
+    const ALGORITHM: CertificateCompressionAlgorithm = CertificateCompressionAlgorithm::BROTLI;
+
+    const CAN_COMPRESS: bool = true;
+
+    const CAN_DECOMPRESS: bool = true;
+
+    fn compress<W>(&self, input: &[u8], output: &mut W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let mut writer = brotli::CompressorWriter::new(output, 4096, self.quality, self.window);
+        writer.write_all(input)?;
+        writer.into_inner();
+        Ok(())
+    }
+
+    fn decompress<W>(&self, input: &[u8], output: &mut W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let decoder = brotli::Decompressor::new(input, 4096);
+        decompress_bounded(decoder, output, self.max_decompressed_len)
+    }
+}
+
+/// Zlib certificate compression.
+///
+/// Requires the `cert-compression-zlib` feature.
+#[cfg(feature = "cert-compression-zlib")]
+#[derive(Debug, Clone)]
+pub struct ZlibCertificateCompressor {
+    level: u32,
+    max_decompressed_len: usize,
+}
+
+#[cfg(feature = "cert-compression-zlib")]
+impl Default for ZlibCertificateCompressor {
+    fn default() -> Self {
+        Self {
+            level: 9,
+            max_decompressed_len: DEFAULT_MAX_DECOMPRESSED_LEN,
+        }
+    }
+}
+
+#[cfg(feature = "cert-compression-zlib")]
+impl ZlibCertificateCompressor {
+    /// Sets the compression level, from 0 to 9. Defaults to 9.
+    pub fn set_level(&mut self, level: u32) {
+        self.level = level.min(9);
+    }
+
+    /// Sets the limit on the size of decompressed certificate chains. Defaults to 100 KiB.
+    pub fn set_max_decompressed_len(&mut self, len: usize) {
+        self.max_decompressed_len = len;
+    }
+}
+
+#[cfg(feature = "cert-compression-zlib")]
+impl CertificateCompressor for ZlibCertificateCompressor {
+    const ALGORITHM: CertificateCompressionAlgorithm = CertificateCompressionAlgorithm::ZLIB;
+
+    const CAN_COMPRESS: bool = true;
+
+    const CAN_DECOMPRESS: bool = true;
+
+    fn compress<W>(&self, input: &[u8], output: &mut W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(output, flate2::Compression::new(self.level));
+        encoder.write_all(input)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    fn decompress<W>(&self, input: &[u8], output: &mut W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let decoder = flate2::read::ZlibDecoder::new(input);
+        decompress_bounded(decoder, output, self.max_decompressed_len)
+    }
+}
+
+/// Zstandard certificate compression.
+///
+/// Requires the `cert-compression-zstd` feature.
+#[cfg(feature = "cert-compression-zstd")]
+#[derive(Debug, Clone)]
+pub struct ZstdCertificateCompressor {
+    level: i32,
+    max_decompressed_len: usize,
+}
+
+#[cfg(feature = "cert-compression-zstd")]
+impl Default for ZstdCertificateCompressor {
+    fn default() -> Self {
+        Self {
+            level: 3,
+            max_decompressed_len: DEFAULT_MAX_DECOMPRESSED_LEN,
+        }
+    }
+}
+
+#[cfg(feature = "cert-compression-zstd")]
+impl ZstdCertificateCompressor {
+    /// Sets the compression level, from 1 to 22. Defaults to 3.
+    ///
+    /// The certificate chain is compressed on every handshake, so the higher levels, which are
+    /// meant for data compressed once, are rarely worth their CPU time.
+    pub fn set_level(&mut self, level: i32) {
+        self.level = level.clamp(1, 22);
+    }
+
+    /// Sets the limit on the size of decompressed certificate chains. Defaults to 100 KiB.
+    pub fn set_max_decompressed_len(&mut self, len: usize) {
+        self.max_decompressed_len = len;
+    }
+}
+
+#[cfg(feature = "cert-compression-zstd")]
+impl CertificateCompressor for ZstdCertificateCompressor {
+    const ALGORITHM: CertificateCompressionAlgorithm = CertificateCompressionAlgorithm::ZSTD;
+
+    const CAN_COMPRESS: bool = true;
+
+    const CAN_DECOMPRESS: bool = true;
+
+    fn compress<W>(&self, input: &[u8], output: &mut W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        output.write_all(&zstd::bulk::compress(input, self.level)?)
+    }
+
+    fn decompress<W>(&self, input: &[u8], output: &mut W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let decoder = zstd::stream::read::Decoder::new(input)?;
+        decompress_bounded(decoder, output, self.max_decompressed_len)
+    }
+}
+
+impl SslContextBuilder {
+    /// Registers every certificate compression algorithm enabled by a `cert-compression-*`
+    /// feature, with its default settings.
+    ///
+    /// The algorithms are registered in the order of their IANA identifiers: zlib, brotli, then
+    /// zstd. Does nothing if no such feature is enabled.
+    pub fn add_default_certificate_compression_algorithms(&mut self) -> Result<(), ErrorStack> {
+        #[cfg(feature = "cert-compression-zlib")]
+        self.add_certificate_compression_algorithm(ZlibCertificateCompressor::default())?;
+        #[cfg(feature = "cert-compression-brotli")]
+        self.add_certificate_compression_algorithm(BrotliCertificateCompressor::default())?;
+        #[cfg(feature = "cert-compression-zstd")]
+        self.add_certificate_compression_algorithm(ZstdCertificateCompressor::default())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+//! Typed accessors for the fields of a ClientHello.
+//!
+//! BoringSSL only checks the framing of the extensions before the select certificate callback
+//! runs, so the contents of each extension are parsed here with bounds checks, and accessors
+//! return `None` if an extension is absent or malformed.
+use std::slice;
+
+use crate::ssl::{
+    CertificateCompressionAlgorithm, ClientHello, ExtensionType, SslSignatureAlgorithm, SslVersion,
+};
+
+/// Reads the integers and length-prefixed vectors of a TLS message.
+#[derive(Clone)]
+pub(crate) struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader(data)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the data that has not been read yet.
+    pub(crate) fn rest(&self) -> &'a [u8] {
+        self.0
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Option<u16> {
+        self.bytes(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn u8_prefixed(&mut self) -> Option<Reader<'a>> {
+        let len = self.u8()?;
+        self.bytes(len.into()).map(Reader)
+    }
+
+    pub(crate) fn u16_prefixed(&mut self) -> Option<Reader<'a>> {
+        let len = self.u16()?;
+        self.bytes(len.into()).map(Reader)
+    }
+
+    /// Reads the rest of the data as a list of 16-bit values.
+    pub(crate) fn u16_list(mut self) -> Option<Vec<u16>> {
+        if self.0.len() % 2 != 0 {
+            return None;
+        }
+        let mut values = Vec::with_capacity(self.0.len() / 2);
+        while !self.is_empty() {
+            values.push(self.u16()?);
+        }
+        Some(values)
+    }
+}
+
+/// Parses the contents of an extension with `f`, which must consume them entirely.
+fn parse<'a, T>(data: &'a [u8], f: impl FnOnce(&mut Reader<'a>) -> Option<T>) -> Option<T> {
+    let mut reader = Reader::new(data);
+    let value = f(&mut reader)?;
+    reader.is_empty().then_some(value)
+}
+
+impl ClientHello<'_> {
+    /// Returns the extensions of the ClientHello in the order the client sent them, with their
+    /// contents.
+    #[must_use]
+    pub fn extensions(&self) -> Vec<(ExtensionType, &[u8])> {
+        // BoringSSL leaves the pointer null for ClientHellos without extensions.
+        if self.0.extensions_len == 0 {
+            return vec![];
+        }
+        let data = unsafe { slice::from_raw_parts(self.0.extensions, self.0.extensions_len) };
+        let mut reader = Reader::new(data);
+        let mut extensions = vec![];
+        while !reader.is_empty() {
+            // BoringSSL rejects ClientHellos with malformed extension blocks before this is
+            // reachable, but stop rather than panic if that ever changes.
+            let (Some(ty), Some(contents)) = (reader.u16(), reader.u16_prefixed()) else {
+                break;
+            };
+            extensions.push((ExtensionType(ty), contents.0));
+        }
+        extensions
+    }
+
+    /// Returns the protocols offered with ALPN, in the client's order of preference.
+    #[must_use]
+    pub fn alpn_protocols(&self) -> Option<Vec<&[u8]>> {
+        let data = self.get_extension(ExtensionType::APPLICATION_LAYER_PROTOCOL_NEGOTIATION)?;
+        parse(data, |reader| {
+            let mut list = reader.u16_prefixed()?;
+            let mut protocols = vec![];
+            while !list.is_empty() {
+                let protocol = list.u8_prefixed()?.0;
+                if protocol.is_empty() {
+                    return None;
+                }
+                protocols.push(protocol);
+            }
+            Some(protocols)
+        })
+    }
+
+    /// Returns the IANA identifiers of the groups supported by the client.
+    #[must_use]
+    pub fn supported_groups(&self) -> Option<Vec<u16>> {
+        let data = self.get_extension(ExtensionType::SUPPORTED_GROUPS)?;
+        parse(data, |reader| reader.u16_prefixed()?.u16_list())
+    }
+
+    /// Returns the IANA identifiers of the groups the client sent key shares for, in order.
+    #[must_use]
+    pub fn key_share_groups(&self) -> Option<Vec<u16>> {
+        let data = self.get_extension(ExtensionType::KEY_SHARE)?;
+        parse(data, |reader| {
+            let mut shares = reader.u16_prefixed()?;
+            let mut groups = vec![];
+            while !shares.is_empty() {
+                groups.push(shares.u16()?);
+                shares.u16_prefixed()?;
+            }
+            Some(groups)
+        })
+    }
+
+    /// Returns the signature algorithms supported by the client.
+    #[must_use]
+    pub fn signature_algorithms(&self) -> Option<Vec<SslSignatureAlgorithm>> {
+        let data = self.get_extension(ExtensionType::SIGNATURE_ALGORITHMS)?;
+        let algorithms = parse(data, |reader| reader.u16_prefixed()?.u16_list())?;
+        Some(algorithms.into_iter().map(SslSignatureAlgorithm).collect())
+    }
+
+    /// Returns the protocol versions listed in the supported versions extension.
+    #[must_use]
+    pub fn supported_versions(&self) -> Option<Vec<SslVersion>> {
+        let data = self.get_extension(ExtensionType::SUPPORTED_VERSIONS)?;
+        let versions = parse(data, |reader| reader.u8_prefixed()?.u16_list())?;
+        Some(versions.into_iter().map(SslVersion).collect())
+    }
+
+    /// Returns the PSK key exchange modes supported by the client: 0 for `psk_ke` and 1 for
+    /// `psk_dhe_ke`.
+    #[must_use]
+    pub fn psk_key_exchange_modes(&self) -> Option<Vec<u8>> {
+        let data = self.get_extension(ExtensionType::PSK_KEY_EXCHANGE_MODES)?;
+        parse(data, |reader| Some(reader.u8_prefixed()?.0.to_vec()))
+    }
+
+    /// Returns the certificate compression algorithms supported by the client.
+    #[must_use]
+    pub fn cert_compression_algorithms(&self) -> Option<Vec<CertificateCompressionAlgorithm>> {
+        let data = self.get_extension(ExtensionType::CERT_COMPRESSION)?;
+        let algorithms = parse(data, |reader| reader.u8_prefixed()?.u16_list())?;
+        Some(
+            algorithms
+                .into_iter()
+                .map(CertificateCompressionAlgorithm)
+                .collect(),
+        )
+    }
+
+    /// Returns whether the ClientHello has an encrypted ClientHello extension.
+    ///
+    /// This is the case for ClientHelloOuters, including those sent by clients using ECH
+    /// GREASE, and for decrypted ClientHelloInners.
+    #[must_use]
+    pub fn has_ech(&self) -> bool {
+        self.get_extension(ExtensionType::ENCRYPTED_CLIENT_HELLO)
+            .is_some()
+    }
+
+    /// Returns whether `value` is one of the reserved GREASE values of RFC 8701.
+    #[must_use]
+    pub fn is_grease(value: u16) -> bool {
+        value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+    }
+
+    /// Returns whether the client sent a GREASE value as a cipher suite, extension, group, key
+    /// share, signature algorithm or version.
+    #[must_use]
+    pub fn has_grease(&self) -> bool {
+        let ciphers = Reader::new(self.ciphers()).u16_list().unwrap_or_default();
+        let extensions = self.extensions().into_iter().map(|(ty, _)| ty.0);
+        let signature_algorithms = self
+            .signature_algorithms()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|algorithm| algorithm.0);
+        let versions = self
+            .supported_versions()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|version| version.0);
+
+        ciphers
+            .into_iter()
+            .chain(extensions)
+            .chain(self.supported_groups().unwrap_or_default())
+            .chain(self.key_share_groups().unwrap_or_default())
+            .chain(signature_algorithms)
+            .chain(versions)
+            .any(Self::is_grease)
+    }
+}
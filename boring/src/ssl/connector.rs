@@ -1,15 +1,20 @@
 use std::io::{Read, Write};
 use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
 
 use foreign_types::ForeignTypeRef;
 use openssl_macros::corresponds;
 
 use crate::dh::Dh;
 use crate::error::ErrorStack;
+use crate::ssl::pin::{check_pins, PinSet, PIN_SET_INDEX};
+use crate::ssl::profile::CLIENT_HELLO_PROFILE_INDEX;
+use crate::ssl::verify::{chain_alert, peer_chain, verification_alert};
 use crate::ssl::{
-    HandshakeError, Ssl, SslContext, SslContextBuilder, SslContextRef, SslMethod, SslMode,
-    SslOptions, SslRef, SslStream, SslVerifyMode,
+    BoxCustomVerifyFinish, HandshakeError, Ssl, SslContext, SslContextBuilder, SslContextRef,
+    SslMethod, SslMode, SslOptions, SslRef, SslStream, SslVerifyError, SslVerifyMode,
 };
+use crate::x509::aia::AiaFetcher;
 use crate::{cvt, version};
 use std::net::IpAddr;
 
@@ -86,6 +91,23 @@ impl SslConnector {
         Ok(SslConnectorBuilder(ctx))
     }
 
+    /// Creates a new builder for TLS connections trusting the bundled Mozilla root store instead
+    /// of the host's certificates.
+    ///
+    /// See [`X509StoreBuilderRef::add_bundled_roots`]. Requires the `mozilla-roots` feature.
+    ///
+    /// [`X509StoreBuilderRef::add_bundled_roots`]: crate::x509::store::X509StoreBuilderRef::add_bundled_roots
+    #[cfg(feature = "mozilla-roots")]
+    pub fn builder_with_bundled_roots(
+        method: SslMethod,
+    ) -> Result<SslConnectorBuilder, ErrorStack> {
+        let mut builder = SslConnector::no_default_verify_builder(method)?;
+        builder.cert_store_mut().add_bundled_roots()?;
+        setup_verify(&mut builder);
+
+        Ok(builder)
+    }
+
     /// Creates a new builder for TLS connections with no verification.
     ///
     /// This is useful for testing and other purposes where you want to skip verification.
@@ -129,10 +151,15 @@ impl SslConnector {
 
     /// Returns a structure allowing for configuration of a single TLS session before connection.
     pub fn configure(&self) -> Result<ConnectConfiguration, ErrorStack> {
-        Ssl::new(&self.0).map(|ssl| ConnectConfiguration {
+        let mut ssl = Ssl::new(&self.0)?;
+        if let Some(profile) = self.0.ex_data(*CLIENT_HELLO_PROFILE_INDEX) {
+            profile.configure(&mut ssl)?;
+        }
+        Ok(ConnectConfiguration {
             ssl,
             sni: true,
             verify_hostname: true,
+            pins: self.0.ex_data(*PIN_SET_INDEX).cloned(),
         })
     }
 
@@ -153,6 +180,56 @@ impl SslConnector {
 pub struct SslConnectorBuilder(SslContextBuilder);
 
 impl SslConnectorBuilder {
+    /// Verifies server certificates with `fetcher` downloading missing intermediate certificates.
+    ///
+    /// This replaces the built-in certificate verification with a custom verify callback which
+    /// performs the same checks against the context's certificate store, using
+    /// [`X509StoreRef::verify_chain`]. If `fetcher` is asynchronous, the handshake must be driven
+    /// by an async runtime, for instance through `tokio-boring`.
+    ///
+    /// See the [`aia`](crate::x509::aia) module for details.
+    ///
+    /// [`X509StoreRef::verify_chain`]: crate::x509::store::X509StoreRef::verify_chain
+    pub fn set_issuer_fetcher(&mut self, fetcher: Arc<AiaFetcher>) {
+        if fetcher.is_async() {
+            self.set_async_custom_verify_callback(SslVerifyMode::PEER, move |ssl| {
+                let (leaf, intermediates, mut options) = peer_chain(ssl)?;
+                options.set_issuer_fetcher(fetcher.clone());
+                let ctx = ssl.ssl_context().to_owned();
+                Ok(Box::pin(async move {
+                    let result = ctx
+                        .cert_store()
+                        .verify_chain_async(&leaf, &intermediates, &options)
+                        .await;
+                    let result = chain_alert(result)?;
+                    Ok(
+                        Box::new(move |ssl: &mut SslRef| check_pins(ssl, result.chain()))
+                            as BoxCustomVerifyFinish,
+                    )
+                }))
+            });
+        } else {
+            self.set_custom_verify_callback(SslVerifyMode::PEER, move |ssl| {
+                let (leaf, intermediates, mut options) =
+                    peer_chain(ssl).map_err(SslVerifyError::Invalid)?;
+                options.set_issuer_fetcher(fetcher.clone());
+                let result =
+                    ssl.ssl_context()
+                        .cert_store()
+                        .verify_chain(&leaf, &intermediates, &options);
+                verification_alert(ssl, result).map_err(SslVerifyError::Invalid)
+            });
+        }
+    }
+
+    /// Checks the certificate chain of servers against `pins`.
+    ///
+    /// The pins are installed on every connection configured through [`SslConnector::configure`],
+    /// which also backs [`SslConnector::connect`]. See [`ConnectConfiguration::set_pin_set`].
+    pub fn set_pin_set(&mut self, pins: PinSet) {
+        self.replace_ex_data(*PIN_SET_INDEX, pins);
+    }
+
     /// Consumes the builder, returning an `SslConnector`.
     #[must_use]
     pub fn build(self) -> SslConnector {
@@ -179,6 +256,7 @@ pub struct ConnectConfiguration {
     ssl: Ssl,
     sni: bool,
     verify_hostname: bool,
+    pins: Option<PinSet>,
 }
 
 impl ConnectConfiguration {
@@ -216,6 +294,21 @@ impl ConnectConfiguration {
         self.verify_hostname = verify_hostname;
     }
 
+    /// Checks the certificate chain of the server against `pins`, replacing those set with
+    /// [`SslConnectorBuilder::set_pin_set`].
+    ///
+    /// The pins of the domain passed to [`Self::into_ssl`] are checked against the chain built by
+    /// the verification configured on the context, be it the built-in one or a verifier such as
+    /// [`SslConnectorBuilder::set_issuer_fetcher`] or [`SslContextBuilder::set_reloadable_trust`].
+    /// With the built-in verification, the pins are only checked once the verify callback set
+    /// with [`SslContextBuilder::set_verify_callback`] accepted the chain.
+    ///
+    /// [`Self::into_ssl`] fails if peer verification is disabled with [`SslVerifyMode::NONE`],
+    /// under which the pins would never be enforced.
+    pub fn set_pin_set(&mut self, pins: PinSet) {
+        self.pins = Some(pins);
+    }
+
     /// Returns an [`Ssl`] configured to connect to the provided domain.
     ///
     /// The domain is used for SNI (if it is not an IP address) and hostname verification if enabled.
@@ -228,6 +321,10 @@ impl ConnectConfiguration {
             setup_verify_hostname(&mut self.ssl, domain)?;
         }
 
+        if let Some(pins) = self.pins.take() {
+            pins.install(&mut self.ssl, domain)?;
+        }
+
         Ok(self.ssl)
     }
 
@@ -0,0 +1,458 @@
+//! Certificates and keys picked for each connection from the signature algorithms of the peer.
+//!
+//! The bundled BoringSSL predates its `SSL_CREDENTIAL` API, so credentials are kept in ex data
+//! and applied to the connection from a certificate callback, which runs once the peer's
+//! signature algorithms are known.
+use std::ffi::c_void;
+use std::ptr;
+use std::slice;
+use std::sync::{Arc, LazyLock};
+
+use foreign_types::ForeignTypeRef;
+use libc::c_int;
+use openssl_macros::corresponds;
+
+use crate::error::ErrorStack;
+use crate::ex_data::Index;
+use crate::pkey::{PKey, Private};
+use crate::ssl::key_kind::KeyKind;
+use crate::ssl::{
+    PrivateKeyMethod, PrivateKeyMethodError, Ssl, SslContext, SslContextBuilder, SslRef,
+    SslSignatureAlgorithm, SslVersion,
+};
+use crate::x509::{X509Ref, X509};
+use crate::{cvt, ffi};
+
+static CREDENTIALS_INDEX: LazyLock<Index<SslContext, Vec<SslCredential>>> =
+    LazyLock::new(|| SslContext::new_ex_index().unwrap());
+static SSL_CREDENTIALS_INDEX: LazyLock<Index<Ssl, Vec<SslCredential>>> =
+    LazyLock::new(|| Ssl::new_ex_index().unwrap());
+static SELECTED_CREDENTIAL_INDEX: LazyLock<Index<Ssl, SslCredential>> =
+    LazyLock::new(|| Ssl::new_ex_index().unwrap());
+static CERT_CALLBACK_INDEX: LazyLock<Index<SslContext, Box<CertCallback>>> =
+    LazyLock::new(|| SslContext::new_ex_index().unwrap());
+
+type CertCallback = dyn Fn(&mut SslRef) -> Result<(), ErrorStack> + Send + Sync;
+
+static CREDENTIAL_KEY_METHOD: ffi::SSL_PRIVATE_KEY_METHOD = ffi::SSL_PRIVATE_KEY_METHOD {
+    sign: Some(raw_sign),
+    decrypt: Some(raw_decrypt),
+    complete: Some(raw_complete),
+};
+
+enum Key {
+    Private(PKey<Private>),
+    Method(Box<dyn PrivateKeyMethod>),
+}
+
+struct Credential {
+    cert: X509,
+    chain: Vec<X509>,
+    key: Key,
+    kind: KeyKind,
+    ocsp_response: Option<Vec<u8>>,
+    signed_cert_timestamps: Option<Vec<u8>>,
+    signing_algorithm_prefs: Vec<SslSignatureAlgorithm>,
+}
+
+/// A builder for [`SslCredential`]s.
+pub struct SslCredentialBuilder {
+    cert: X509,
+    chain: Vec<X509>,
+    key: Option<Key>,
+    ocsp_response: Option<Vec<u8>>,
+    signed_cert_timestamps: Option<Vec<u8>>,
+    signing_algorithm_prefs: Vec<SslSignatureAlgorithm>,
+}
+
+impl SslCredentialBuilder {
+    /// Sets the private key of the certificate.
+    pub fn set_private_key(&mut self, key: PKey<Private>) {
+        self.key = Some(Key::Private(key));
+    }
+
+    /// Signs with `method` instead of a private key held in memory.
+    ///
+    /// See [`PrivateKeyMethod`] for more details.
+    pub fn set_private_key_method<M>(&mut self, method: M)
+    where
+        M: PrivateKeyMethod,
+    {
+        self.key = Some(Key::Method(Box::new(method)));
+    }
+
+    /// Sets the OCSP response stapled for clients which request it.
+    pub fn set_ocsp_response(&mut self, response: &[u8]) {
+        self.ocsp_response = Some(response.to_vec());
+    }
+
+    /// Sets the serialized list of signed certificate timestamps sent to clients which request
+    /// them.
+    pub fn set_signed_cert_timestamp_list(&mut self, list: &[u8]) {
+        self.signed_cert_timestamps = Some(list.to_vec());
+    }
+
+    /// Restricts the signature algorithms used with the key, in order of preference.
+    pub fn set_signing_algorithm_prefs(&mut self, prefs: &[SslSignatureAlgorithm]) {
+        self.signing_algorithm_prefs = prefs.to_vec();
+    }
+
+    /// Consumes the builder, returning a new `SslCredential`.
+    ///
+    /// Fails if no key was set, if the private key does not match the certificate, or if a
+    /// signing algorithm cannot be used with the key.
+    pub fn build(self) -> Result<SslCredential, ErrorStack> {
+        let public_key = self.cert.public_key()?;
+        let key = self
+            .key
+            .ok_or_else(|| ErrorStack::internal_error_str("no private key set"))?;
+        if let Key::Private(key) = &key {
+            if !public_key.public_eq(key) {
+                return Err(ErrorStack::internal_error_str(
+                    "private key does not match the certificate",
+                ));
+            }
+        }
+        let kind = KeyKind::of(&public_key);
+        if kind == KeyKind::Other {
+            return Err(ErrorStack::internal_error_str("unsupported key type"));
+        }
+        if !self
+            .signing_algorithm_prefs
+            .iter()
+            .all(|&sigalg| kind.supports(sigalg))
+        {
+            return Err(ErrorStack::internal_error_str(
+                "signing algorithm not supported by the key",
+            ));
+        }
+
+        Ok(SslCredential(Arc::new(Credential {
+            cert: self.cert,
+            chain: self.chain,
+            key,
+            kind,
+            ocsp_response: self.ocsp_response,
+            signed_cert_timestamps: self.signed_cert_timestamps,
+            signing_algorithm_prefs: self.signing_algorithm_prefs,
+        })))
+    }
+}
+
+/// A certificate chain with its key, and the data served along with them.
+///
+/// Add credentials with [`SslContextBuilder::add_credential`] or [`SslRef::add_credential`].
+/// Clones share the same credential.
+#[derive(Clone)]
+pub struct SslCredential(Arc<Credential>);
+
+impl SslCredential {
+    /// Creates a new builder for a credential serving `cert` and its `chain` of intermediates.
+    #[must_use]
+    pub fn builder(cert: X509, chain: Vec<X509>) -> SslCredentialBuilder {
+        SslCredentialBuilder {
+            cert,
+            chain,
+            key: None,
+            ocsp_response: None,
+            signed_cert_timestamps: None,
+            signing_algorithm_prefs: vec![],
+        }
+    }
+
+    /// Returns the certificate.
+    #[must_use]
+    pub fn certificate(&self) -> &X509Ref {
+        &self.0.cert
+    }
+
+    /// Returns the intermediates sent with the certificate.
+    #[must_use]
+    pub fn chain(&self) -> &[X509] {
+        &self.0.chain
+    }
+
+    /// Returns whether the credential can sign with `sigalg`.
+    fn can_sign(&self, sigalg: SslSignatureAlgorithm) -> bool {
+        let prefs = &self.0.signing_algorithm_prefs;
+        self.0.kind.supports(sigalg) && (prefs.is_empty() || prefs.contains(&sigalg))
+    }
+
+    fn apply(&self, ssl: &mut SslRef) -> Result<(), ErrorStack> {
+        let credential = &self.0;
+        ssl.set_certificate(&credential.cert)?;
+        unsafe { cvt(ffi::SSL_clear_chain_certs(ssl.as_ptr()))? };
+        for cert in &credential.chain {
+            ssl.add_chain_cert(cert)?;
+        }
+        match &credential.key {
+            Key::Private(key) => {
+                ssl.set_private_key(key)?;
+                // A private key method set on the context would take precedence over the key.
+                unsafe { ffi::SSL_set_private_key_method(ssl.as_ptr(), ptr::null()) };
+            }
+            Key::Method(_) => unsafe {
+                ffi::SSL_set_private_key_method(ssl.as_ptr(), &CREDENTIAL_KEY_METHOD);
+            },
+        }
+        if let Some(response) = &credential.ocsp_response {
+            ssl.set_ocsp_status(response)?;
+        }
+        if let Some(list) = &credential.signed_cert_timestamps {
+            unsafe {
+                cvt(ffi::SSL_set_signed_cert_timestamp_list(
+                    ssl.as_ptr(),
+                    list.as_ptr(),
+                    list.len(),
+                ))?;
+            }
+        }
+        if !credential.signing_algorithm_prefs.is_empty() {
+            let prefs: Vec<u16> = credential
+                .signing_algorithm_prefs
+                .iter()
+                .map(|sigalg| sigalg.0)
+                .collect();
+            unsafe {
+                cvt(ffi::SSL_set_signing_algorithm_prefs(
+                    ssl.as_ptr(),
+                    prefs.as_ptr(),
+                    prefs.len(),
+                ))?;
+            }
+        }
+        ssl.replace_ex_data(*SELECTED_CREDENTIAL_INDEX, self.clone());
+        Ok(())
+    }
+}
+
+/// Returns the signature algorithms the peer can verify, in its order of preference.
+///
+/// Peers which send none are assumed to only verify SHA-1 signatures, as TLS 1.2 specifies.
+fn peer_sigalgs(ssl: &SslRef) -> Vec<SslSignatureAlgorithm> {
+    unsafe {
+        let mut sigalgs = ptr::null();
+        let len = ffi::SSL_get0_peer_verify_algorithms(ssl.as_ptr(), &mut sigalgs);
+        if len == 0 {
+            return vec![
+                SslSignatureAlgorithm::RSA_PKCS1_SHA1,
+                SslSignatureAlgorithm::ECDSA_SHA1,
+            ];
+        }
+        slice::from_raw_parts(sigalgs, len)
+            .iter()
+            .map(|&sigalg| SslSignatureAlgorithm(sigalg))
+            .collect()
+    }
+}
+
+/// Returns the credential to use for the first signature algorithm of the peer one of
+/// `credentials` can sign with.
+fn select_credential<'a>(
+    ssl: &SslRef,
+    credentials: &'a [SslCredential],
+) -> Option<&'a SslCredential> {
+    // Before TLS 1.2, the signature algorithm follows from the key: MD5-SHA1 for RSA keys and
+    // SHA-1 for ECDSA keys.
+    if ssl
+        .version2()
+        .is_some_and(|version| version.0 < SslVersion::TLS1_2.0)
+    {
+        return credentials
+            .iter()
+            .find(|credential| matches!(credential.0.kind, KeyKind::Rsa | KeyKind::Ec(_)));
+    }
+    peer_sigalgs(ssl).into_iter().find_map(|sigalg| {
+        credentials
+            .iter()
+            .find(|credential| credential.can_sign(sigalg))
+    })
+}
+
+/// Runs the callback set with [`SslContextBuilder::set_cert_callback`], then applies the
+/// credential, among those of the context followed by those of the connection, chosen by
+/// [`select_credential`].
+unsafe extern "C" fn raw_cert_cb(ssl: *mut ffi::SSL, _: *mut c_void) -> c_int {
+    // SAFETY: boring provides valid inputs.
+    let ssl = unsafe { SslRef::from_ptr_mut(ssl) };
+
+    let ssl_context = ssl.ssl_context().to_owned();
+    if let Some(callback) = ssl_context.ex_data(*CERT_CALLBACK_INDEX) {
+        if callback(ssl).is_err() {
+            return 0;
+        }
+    }
+
+    let mut credentials = ssl_context
+        .ex_data(*CREDENTIALS_INDEX)
+        .cloned()
+        .unwrap_or_default();
+    credentials.extend(
+        ssl.ex_data(*SSL_CREDENTIALS_INDEX)
+            .into_iter()
+            .flatten()
+            .cloned(),
+    );
+
+    // Fall back to the certificate set on the context or connection, if any.
+    let Some(credential) = select_credential(ssl, &credentials) else {
+        return 1;
+    };
+    match credential.apply(ssl) {
+        Ok(()) => 1,
+        Err(_) => 0,
+    }
+}
+
+unsafe extern "C" fn raw_sign(
+    ssl: *mut ffi::SSL,
+    out: *mut u8,
+    out_len: *mut usize,
+    max_out: usize,
+    signature_algorithm: u16,
+    in_: *const u8,
+    in_len: usize,
+) -> ffi::ssl_private_key_result_t {
+    // SAFETY: boring provides valid inputs.
+    let input = unsafe { slice::from_raw_parts(in_, in_len) };
+    let signature_algorithm = SslSignatureAlgorithm(signature_algorithm);
+
+    // SAFETY: boring provides valid inputs.
+    unsafe {
+        raw_key_method_callback(ssl, out, out_len, max_out, |method, ssl, output| {
+            method.sign(ssl, input, signature_algorithm, output)
+        })
+    }
+}
+
+unsafe extern "C" fn raw_decrypt(
+    ssl: *mut ffi::SSL,
+    out: *mut u8,
+    out_len: *mut usize,
+    max_out: usize,
+    in_: *const u8,
+    in_len: usize,
+) -> ffi::ssl_private_key_result_t {
+    // SAFETY: boring provides valid inputs.
+    let input = unsafe { slice::from_raw_parts(in_, in_len) };
+
+    // SAFETY: boring provides valid inputs.
+    unsafe {
+        raw_key_method_callback(ssl, out, out_len, max_out, |method, ssl, output| {
+            method.decrypt(ssl, input, output)
+        })
+    }
+}
+
+unsafe extern "C" fn raw_complete(
+    ssl: *mut ffi::SSL,
+    out: *mut u8,
+    out_len: *mut usize,
+    max_out: usize,
+) -> ffi::ssl_private_key_result_t {
+    // SAFETY: boring provides valid inputs.
+    unsafe {
+        raw_key_method_callback(ssl, out, out_len, max_out, |method, ssl, output| {
+            method.complete(ssl, output)
+        })
+    }
+}
+
+unsafe fn raw_key_method_callback(
+    ssl: *mut ffi::SSL,
+    out: *mut u8,
+    out_len: *mut usize,
+    max_out: usize,
+    callback: impl FnOnce(
+        &dyn PrivateKeyMethod,
+        &mut SslRef,
+        &mut [u8],
+    ) -> Result<usize, PrivateKeyMethodError>,
+) -> ffi::ssl_private_key_result_t {
+    // SAFETY: boring provides valid inputs.
+    let ssl = unsafe { SslRef::from_ptr_mut(ssl) };
+    let output = unsafe { slice::from_raw_parts_mut(out, max_out) };
+    let out_len = unsafe { &mut *out_len };
+
+    let Some(credential) = ssl.ex_data(*SELECTED_CREDENTIAL_INDEX).cloned() else {
+        return PrivateKeyMethodError::FAILURE.0;
+    };
+    let Key::Method(method) = &credential.0.key else {
+        return PrivateKeyMethodError::FAILURE.0;
+    };
+
+    match callback(&**method, ssl, output) {
+        Ok(written) => {
+            assert!(written <= max_out);
+
+            *out_len = written;
+
+            ffi::ssl_private_key_result_t::ssl_private_key_success
+        }
+        Err(err) => err.0,
+    }
+}
+
+impl SslContextBuilder {
+    /// Adds a credential offered by every connection of the context.
+    ///
+    /// When the peer asks for a certificate, its signature algorithms are walked in its order of
+    /// preference, and the first credential which can sign with one of them is used and reported
+    /// by [`SslRef::selected_credential`]. Credentials of the context come before those added to
+    /// the connection. Peers which send no signature algorithms get a credential usable with the
+    /// TLS defaults. If none can be used, the certificate and key set with
+    /// [`Self::set_certificate`] and [`Self::set_private_key`] are used, if any.
+    ///
+    /// This installs a certificate callback, which also runs the one set with
+    /// [`Self::set_cert_callback`].
+    pub fn add_credential(&mut self, credential: SslCredential) {
+        let mut credentials = self
+            .ctx
+            .ex_data(*CREDENTIALS_INDEX)
+            .cloned()
+            .unwrap_or_default();
+        credentials.push(credential);
+        self.replace_ex_data(*CREDENTIALS_INDEX, credentials);
+        unsafe { ffi::SSL_CTX_set_cert_cb(self.as_ptr(), Some(raw_cert_cb), ptr::null_mut()) }
+    }
+
+    /// Sets a callback called when the certificate of the connection is needed, before a
+    /// credential is chosen.
+    ///
+    /// On servers, this runs once the ClientHello has been processed, and may configure the
+    /// connection, such as adding credentials with [`SslRef::add_credential`]. Returning an error
+    /// fails the handshake.
+    #[corresponds(SSL_CTX_set_cert_cb)]
+    pub fn set_cert_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(&mut SslRef) -> Result<(), ErrorStack> + Send + Sync + 'static,
+    {
+        self.replace_ex_data(
+            *CERT_CALLBACK_INDEX,
+            Box::new(callback) as Box<CertCallback>,
+        );
+        unsafe { ffi::SSL_CTX_set_cert_cb(self.as_ptr(), Some(raw_cert_cb), ptr::null_mut()) }
+    }
+}
+
+impl SslRef {
+    /// Adds a credential offered by this connection, after those of its context.
+    ///
+    /// This installs the same certificate callback as [`SslContextBuilder::add_credential`],
+    /// which keeps running the callback set with [`SslContextBuilder::set_cert_callback`].
+    pub fn add_credential(&mut self, credential: SslCredential) {
+        let mut credentials = self
+            .ex_data(*SSL_CREDENTIALS_INDEX)
+            .cloned()
+            .unwrap_or_default();
+        credentials.push(credential);
+        self.replace_ex_data(*SSL_CREDENTIALS_INDEX, credentials);
+        unsafe { ffi::SSL_set_cert_cb(self.as_ptr(), Some(raw_cert_cb), ptr::null_mut()) }
+    }
+
+    /// Returns the credential chosen for the connection, if one was.
+    #[must_use]
+    pub fn selected_credential(&self) -> Option<&SslCredential> {
+        self.ex_data(*SELECTED_CREDENTIAL_INDEX)
+    }
+}
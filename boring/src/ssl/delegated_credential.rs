@@ -0,0 +1,462 @@
+//! Delegated credentials of RFC 9345.
+//!
+//! A delegated credential lets a server sign handshakes with a short-lived key, itself signed by
+//! the key of a certificate carrying the [`DelegationUsage`] extension, so that the certificate's
+//! key can stay offline. Servers offer one with [`SslRef::set_delegated_credential`] to clients
+//! which advertise support with [`SslContextBuilder::set_delegated_credentials`].
+//!
+//! # Clients
+//!
+//! Accepting delegated credentials as a client is out of scope. The bundled BoringSSL only
+//! serves them: as a client, it advertises them but rejects the Certificate message of a server
+//! which sends one, failing the handshake, and never exposes the credential to verify callbacks,
+//! where it could be checked with [`DelegatedCredential::verify`]. An option to accept them, and
+//! a client-side [`SslRef::delegated_credential_used`], need BoringSSL's handshake to be patched
+//! first, and are left to a separate change.
+//!
+//! [`DelegationUsage`]: crate::x509::extension::DelegationUsage
+//! [`SslContextBuilder::set_delegated_credentials`]: crate::ssl::SslContextBuilder::set_delegated_credentials
+use std::ptr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use foreign_types::{ForeignType, ForeignTypeRef};
+use openssl_macros::corresponds;
+
+use crate::asn1::{Asn1Object, Asn1Time, Asn1TimeRef};
+use crate::error::ErrorStack;
+use crate::hash::MessageDigest;
+use crate::nid::Nid;
+use crate::pkey::{HasPrivate, HasPublic, Id, PKey, PKeyRef, Private, Public};
+use crate::rsa::Padding;
+use crate::sign::{RsaPssSaltlen, Signer, Verifier};
+use crate::ssl::client_hello::Reader;
+use crate::ssl::key_kind::KeyKind;
+use crate::ssl::{SslRef, SslSignatureAlgorithm};
+use crate::x509::extension::DelegationUsage;
+use crate::x509::X509Ref;
+use crate::{cvt, cvt_p, ffi};
+
+/// The longest validity period of a delegated credential.
+const MAX_VALIDITY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Returns the digest of `algorithm`, or `None` for Ed25519, failing for the algorithms TLS 1.3
+/// does not allow.
+fn digest(algorithm: SslSignatureAlgorithm) -> Result<Option<MessageDigest>, ErrorStack> {
+    match algorithm {
+        SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256
+        | SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256 => Ok(Some(MessageDigest::sha256())),
+        SslSignatureAlgorithm::RSA_PSS_RSAE_SHA384
+        | SslSignatureAlgorithm::ECDSA_SECP384R1_SHA384 => Ok(Some(MessageDigest::sha384())),
+        SslSignatureAlgorithm::RSA_PSS_RSAE_SHA512
+        | SslSignatureAlgorithm::ECDSA_SECP521R1_SHA512 => Ok(Some(MessageDigest::sha512())),
+        SslSignatureAlgorithm::ED25519 => Ok(None),
+        _ => Err(ErrorStack::internal_error_str(
+            "signature algorithm cannot be used with delegated credentials",
+        )),
+    }
+}
+
+/// Returns the signature algorithm used by default with keys of `kind`.
+fn default_algorithm(kind: KeyKind) -> Result<SslSignatureAlgorithm, ErrorStack> {
+    match kind {
+        KeyKind::Rsa => Ok(SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256),
+        KeyKind::Ec(Some(Nid::X9_62_PRIME256V1)) => {
+            Ok(SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256)
+        }
+        KeyKind::Ec(Some(Nid::SECP384R1)) => Ok(SslSignatureAlgorithm::ECDSA_SECP384R1_SHA384),
+        KeyKind::Ec(Some(Nid::SECP521R1)) => Ok(SslSignatureAlgorithm::ECDSA_SECP521R1_SHA512),
+        KeyKind::Ed25519 => Ok(SslSignatureAlgorithm::ED25519),
+        _ => Err(ErrorStack::internal_error_str("unsupported key type")),
+    }
+}
+
+/// Checks that `algorithm` can be used with delegated credentials and keys of `kind`.
+fn check_algorithm(kind: KeyKind, algorithm: SslSignatureAlgorithm) -> Result<(), ErrorStack> {
+    digest(algorithm)?;
+    if !kind.supports(algorithm) {
+        return Err(ErrorStack::internal_error_str(
+            "signature algorithm not supported by the key",
+        ));
+    }
+    Ok(())
+}
+
+fn sign(
+    key: &PKeyRef<Private>,
+    algorithm: SslSignatureAlgorithm,
+    message: &[u8],
+) -> Result<Vec<u8>, ErrorStack> {
+    let mut signer = match digest(algorithm)? {
+        Some(digest) => Signer::new(digest, key)?,
+        None => Signer::new_without_digest(key)?,
+    };
+    if key.id() == Id::RSA {
+        signer.set_rsa_padding(Padding::PKCS1_PSS)?;
+        signer.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
+    }
+    signer.sign_oneshot_to_vec(message)
+}
+
+fn verify(
+    key: &PKeyRef<Public>,
+    algorithm: SslSignatureAlgorithm,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<bool, ErrorStack> {
+    let mut verifier = match digest(algorithm)? {
+        Some(digest) => Verifier::new(digest, key)?,
+        None => Verifier::new_without_digest(key)?,
+    };
+    if key.id() == Id::RSA {
+        verifier.set_rsa_padding(Padding::PKCS1_PSS)?;
+        verifier.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
+    }
+    verifier.verify_oneshot(signature, message)
+}
+
+/// Checks that the key of `cert` may sign delegated credentials.
+fn check_certificate(cert: &X509Ref) -> Result<(), ErrorStack> {
+    let oid = Asn1Object::from_str(DelegationUsage::OID)?;
+    let (position, flags, key_usage) = unsafe {
+        (
+            ffi::X509_get_ext_by_OBJ(cert.as_ptr(), oid.as_ptr(), -1),
+            ffi::X509_get_extension_flags(cert.as_ptr()),
+            ffi::X509_get_key_usage(cert.as_ptr()),
+        )
+    };
+    if position < 0 {
+        return Err(ErrorStack::internal_error_str(
+            "certificate lacks the DelegationUsage extension",
+        ));
+    }
+    if flags & ffi::EXFLAG_KUSAGE as _ != 0 && key_usage & ffi::KU_DIGITAL_SIGNATURE as _ == 0 {
+        return Err(ErrorStack::internal_error_str(
+            "certificate key usage does not allow digital signatures",
+        ));
+    }
+    Ok(())
+}
+
+/// Returns `time` as a number of seconds since the Unix epoch.
+fn unix_time(time: &Asn1TimeRef) -> Result<u64, ErrorStack> {
+    let diff = Asn1Time::from_unix(0)?.diff(time)?;
+    u64::try_from(i64::from(diff.days) * 24 * 60 * 60 + i64::from(diff.secs))
+        .map_err(|_| ErrorStack::internal_error_str("certificate time before 1970"))
+}
+
+/// Returns the message signed by the certificate's key.
+fn signed_message(
+    cert: &X509Ref,
+    credential: &[u8],
+    algorithm: SslSignatureAlgorithm,
+) -> Result<Vec<u8>, ErrorStack> {
+    let mut message = vec![0x20; 64];
+    message.extend_from_slice(b"TLS, server delegated credentials\0");
+    message.extend_from_slice(&cert.to_der()?);
+    message.extend_from_slice(credential);
+    message.extend_from_slice(&algorithm.0.to_be_bytes());
+    Ok(message)
+}
+
+/// A builder for [`DelegatedCredential`]s.
+pub struct DelegatedCredentialBuilder<'a> {
+    cert: &'a X509Ref,
+    key: &'a PKeyRef<Private>,
+    public_key: Option<(Vec<u8>, KeyKind)>,
+    valid_for: Option<Duration>,
+    cert_verify_algorithm: Option<SslSignatureAlgorithm>,
+    signature_algorithm: Option<SslSignatureAlgorithm>,
+}
+
+impl DelegatedCredentialBuilder<'_> {
+    /// Sets the public key delegated to.
+    pub fn set_public_key<T>(&mut self, key: &PKeyRef<T>) -> Result<(), ErrorStack>
+    where
+        T: HasPublic,
+    {
+        self.public_key = Some((key.public_key_to_der()?, KeyKind::of(key)));
+        Ok(())
+    }
+
+    /// Sets how long the delegated credential is valid for from now, at most 7 days.
+    pub fn set_valid_for(&mut self, valid_for: Duration) {
+        self.valid_for = Some(valid_for);
+    }
+
+    /// Sets the signature algorithm the delegated key signs handshakes with.
+    ///
+    /// Defaults to the TLS 1.3 algorithm of the delegated key, using SHA-256 for RSA keys.
+    pub fn set_cert_verify_algorithm(&mut self, algorithm: SslSignatureAlgorithm) {
+        self.cert_verify_algorithm = Some(algorithm);
+    }
+
+    /// Sets the signature algorithm the certificate's key signs the delegated credential with.
+    ///
+    /// Defaults to the TLS 1.3 algorithm of the certificate's key, using SHA-256 for RSA keys.
+    pub fn set_signature_algorithm(&mut self, algorithm: SslSignatureAlgorithm) {
+        self.signature_algorithm = Some(algorithm);
+    }
+
+    /// Consumes the builder, signing a new `DelegatedCredential`.
+    ///
+    /// Fails if the certificate cannot sign delegated credentials, if the private key does not
+    /// match the certificate, if the delegated credential would outlive the certificate, or if a
+    /// signature algorithm cannot be used with its key.
+    pub fn build(self) -> Result<DelegatedCredential, ErrorStack> {
+        check_certificate(self.cert)?;
+        let cert_key = self.cert.public_key()?;
+        if !cert_key.public_eq(self.key) {
+            return Err(ErrorStack::internal_error_str(
+                "private key does not match the certificate",
+            ));
+        }
+        let (public_key, kind) = self
+            .public_key
+            .ok_or_else(|| ErrorStack::internal_error_str("no public key set"))?;
+        let cert_verify_algorithm = match self.cert_verify_algorithm {
+            Some(algorithm) => algorithm,
+            None => default_algorithm(kind)?,
+        };
+        check_algorithm(kind, cert_verify_algorithm)?;
+        let signature_algorithm = match self.signature_algorithm {
+            Some(algorithm) => algorithm,
+            None => default_algorithm(KeyKind::of(&cert_key))?,
+        };
+        check_algorithm(KeyKind::of(&cert_key), signature_algorithm)?;
+
+        let valid_for = self
+            .valid_for
+            .ok_or_else(|| ErrorStack::internal_error_str("no validity period set"))?;
+        if valid_for.is_zero() || valid_for > MAX_VALIDITY {
+            return Err(ErrorStack::internal_error_str(
+                "validity period must be at most 7 days",
+            ));
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| ErrorStack::internal_error_str("system time before 1970"))?;
+        let expiry = (now + valid_for).as_secs();
+        if expiry > unix_time(self.cert.not_after())? {
+            return Err(ErrorStack::internal_error_str(
+                "delegated credential would outlive the certificate",
+            ));
+        }
+        let valid_time = expiry
+            .checked_sub(unix_time(self.cert.not_before())?)
+            .and_then(|valid_time| u32::try_from(valid_time).ok())
+            .ok_or_else(|| ErrorStack::internal_error_str("validity time out of range"))?;
+
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&valid_time.to_be_bytes());
+        bytes.extend_from_slice(&cert_verify_algorithm.0.to_be_bytes());
+        bytes.extend_from_slice(&(public_key.len() as u32).to_be_bytes()[1..]);
+        bytes.extend_from_slice(&public_key);
+
+        let message = signed_message(self.cert, &bytes, signature_algorithm)?;
+        let signature = sign(self.key, signature_algorithm, &message)?;
+        bytes.extend_from_slice(&signature_algorithm.0.to_be_bytes());
+        bytes.extend_from_slice(&(signature.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&signature);
+
+        DelegatedCredential::from_bytes(&bytes)
+    }
+}
+
+/// A delegated credential, in its TLS encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DelegatedCredential {
+    bytes: Vec<u8>,
+    credential_len: usize,
+    valid_time: u32,
+    cert_verify_algorithm: SslSignatureAlgorithm,
+    public_key: Vec<u8>,
+    signature_algorithm: SslSignatureAlgorithm,
+    signature: Vec<u8>,
+}
+
+impl DelegatedCredential {
+    /// Creates a new builder for a delegated credential signed by `key`, the private key of
+    /// `cert`.
+    ///
+    /// The certificate must carry the [`DelegationUsage`] extension.
+    ///
+    /// [`DelegationUsage`]: crate::x509::extension::DelegationUsage
+    #[must_use]
+    pub fn builder<'a>(
+        cert: &'a X509Ref,
+        key: &'a PKeyRef<Private>,
+    ) -> DelegatedCredentialBuilder<'a> {
+        DelegatedCredentialBuilder {
+            cert,
+            key,
+            public_key: None,
+            valid_for: None,
+            cert_verify_algorithm: None,
+            signature_algorithm: None,
+        }
+    }
+
+    /// Parses a delegated credential from its TLS encoding.
+    ///
+    /// This only checks the encoding: use [`Self::verify`] to check the delegated credential
+    /// against its certificate.
+    pub fn from_bytes(bytes: &[u8]) -> Result<DelegatedCredential, ErrorStack> {
+        let invalid = || ErrorStack::internal_error_str("invalid delegated credential");
+        let mut reader = Reader::new(bytes);
+        let credential = (|| {
+            let valid_time = reader.bytes(4)?;
+            let valid_time = u32::from_be_bytes(valid_time.try_into().ok()?);
+            let cert_verify_algorithm = SslSignatureAlgorithm(reader.u16()?);
+            // A public key with a 24-bit length.
+            let len = reader.bytes(3)?;
+            let len = usize::from(len[0]) << 16 | usize::from(len[1]) << 8 | usize::from(len[2]);
+            let public_key = reader.bytes(len).filter(|key| !key.is_empty())?;
+            let credential_len = bytes.len() - reader.rest().len();
+            let signature_algorithm = SslSignatureAlgorithm(reader.u16()?);
+            let signature = reader.u16_prefixed()?.rest();
+            if !reader.is_empty() || signature.is_empty() {
+                return None;
+            }
+            Some(DelegatedCredential {
+                bytes: bytes.to_vec(),
+                credential_len,
+                valid_time,
+                cert_verify_algorithm,
+                public_key: public_key.to_vec(),
+                signature_algorithm,
+                signature: signature.to_vec(),
+            })
+        })()
+        .ok_or_else(invalid)?;
+        PKey::public_key_from_der(&credential.public_key).map_err(|_| invalid())?;
+        Ok(credential)
+    }
+
+    /// Returns the TLS encoding of the delegated credential.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the validity time, relative to the start of the validity of the certificate.
+    #[must_use]
+    pub fn valid_time(&self) -> Duration {
+        Duration::from_secs(self.valid_time.into())
+    }
+
+    /// Returns the time the delegated credential for `cert` expires.
+    pub fn expires_at(&self, cert: &X509Ref) -> Result<SystemTime, ErrorStack> {
+        let not_before = Duration::from_secs(unix_time(cert.not_before())?);
+        Ok(UNIX_EPOCH + not_before + self.valid_time())
+    }
+
+    /// Returns the signature algorithm the delegated key signs handshakes with.
+    #[must_use]
+    pub fn cert_verify_algorithm(&self) -> SslSignatureAlgorithm {
+        self.cert_verify_algorithm
+    }
+
+    /// Returns the delegated public key.
+    pub fn public_key(&self) -> Result<PKey<Public>, ErrorStack> {
+        PKey::public_key_from_der(&self.public_key)
+    }
+
+    /// Returns the signature algorithm the certificate's key signed the delegated credential
+    /// with.
+    #[must_use]
+    pub fn signature_algorithm(&self) -> SslSignatureAlgorithm {
+        self.signature_algorithm
+    }
+
+    /// Returns the signature of the certificate's key.
+    #[must_use]
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    /// Verifies that the delegated credential was signed by the key of `cert`, and is valid
+    /// now.
+    pub fn verify(&self, cert: &X509Ref) -> Result<(), ErrorStack> {
+        self.verify_at(cert, SystemTime::now())
+    }
+
+    /// Verifies that the delegated credential was signed by the key of `cert`, and is valid at
+    /// `time`.
+    ///
+    /// This checks the signature, the algorithms and the validity time as described in RFC 9345,
+    /// but not the certificate itself, which must be verified separately.
+    pub fn verify_at(&self, cert: &X509Ref, time: SystemTime) -> Result<(), ErrorStack> {
+        check_certificate(cert)?;
+        let cert_key = cert.public_key()?;
+        check_algorithm(KeyKind::of(&cert_key), self.signature_algorithm)?;
+        check_algorithm(KeyKind::of(&self.public_key()?), self.cert_verify_algorithm)?;
+
+        let message = signed_message(
+            cert,
+            &self.bytes[..self.credential_len],
+            self.signature_algorithm,
+        )?;
+        if !verify(
+            &cert_key,
+            self.signature_algorithm,
+            &message,
+            &self.signature,
+        )? {
+            return Err(ErrorStack::internal_error_str(
+                "invalid delegated credential signature",
+            ));
+        }
+
+        let expiry = self.expires_at(cert)?;
+        match expiry.duration_since(time) {
+            Ok(remaining) if remaining > MAX_VALIDITY => Err(ErrorStack::internal_error_str(
+                "delegated credential is valid for more than 7 days",
+            )),
+            Ok(remaining) if !remaining.is_zero() => Ok(()),
+            _ => Err(ErrorStack::internal_error_str(
+                "delegated credential has expired",
+            )),
+        }
+    }
+}
+
+impl SslRef {
+    /// Sets the delegated credential served to clients which support it, with the private key of
+    /// its delegated public key.
+    ///
+    /// The certificate the delegated credential was issued for must already be set.
+    #[corresponds(SSL_set1_delegated_credential)]
+    pub fn set_delegated_credential<T>(
+        &mut self,
+        credential: &DelegatedCredential,
+        key: &PKeyRef<T>,
+    ) -> Result<(), ErrorStack>
+    where
+        T: HasPrivate,
+    {
+        unsafe {
+            let buffer = cvt_p(ffi::CRYPTO_BUFFER_new(
+                credential.bytes.as_ptr(),
+                credential.bytes.len(),
+                ptr::null_mut(),
+            ))?;
+            let ret = ffi::SSL_set1_delegated_credential(
+                self.as_ptr(),
+                buffer,
+                key.as_ptr(),
+                ptr::null(),
+            );
+            ffi::CRYPTO_BUFFER_free(buffer);
+            cvt(ret).map(|_| ())
+        }
+    }
+
+    /// Returns whether the server signed the handshake with its delegated credential.
+    ///
+    /// Clients do not accept delegated credentials, so this is always `false` for them.
+    #[corresponds(SSL_delegated_credential_used)]
+    #[must_use]
+    pub fn delegated_credential_used(&self) -> bool {
+        unsafe { ffi::SSL_delegated_credential_used(self.as_ptr()) != 0 }
+    }
+}
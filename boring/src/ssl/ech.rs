@@ -1,16 +1,20 @@
 use crate::ffi;
 use foreign_types::ForeignType;
 use libc::c_int;
+use openssl_macros::corresponds;
 
 use crate::error::ErrorStack;
 use crate::hpke::HpkeKey;
 use crate::{cvt_0i, cvt_p};
 
+/// A builder for [`SslEchKeys`].
 pub struct SslEchKeysBuilder {
     keys: SslEchKeys,
 }
 
 impl SslEchKeysBuilder {
+    /// Creates a new, empty set of keys.
+    #[corresponds(SSL_ECH_KEYS_new)]
     pub fn new() -> Result<SslEchKeysBuilder, ErrorStack> {
         unsafe {
             ffi::init();
@@ -20,12 +24,21 @@ impl SslEchKeysBuilder {
         }
     }
 
+    /// Wraps a set of keys.
+    ///
+    /// # Safety
+    ///
+    /// `keys` must be a valid pointer, whose ownership is transferred to the builder.
     pub unsafe fn from_ptr(keys: *mut ffi::SSL_ECH_KEYS) -> Self {
         Self {
             keys: SslEchKeys::from_ptr(keys),
         }
     }
 
+    /// Adds an ECHConfig and its private key.
+    ///
+    /// If `is_retry_config` is set, the ECHConfig is sent to clients whose ECH is rejected.
+    #[corresponds(SSL_ECH_KEYS_add)]
     pub fn add_key(
         &mut self,
         is_retry_config: bool,
@@ -44,6 +57,7 @@ impl SslEchKeysBuilder {
         }
     }
 
+    /// Consumes the builder, returning the keys.
     pub fn build(self) -> SslEchKeys {
         self.keys
     }
@@ -53,10 +67,12 @@ foreign_type_and_impl_send_sync! {
     type CType = ffi::SSL_ECH_KEYS;
     fn drop = ffi::SSL_ECH_KEYS_free;
 
+    /// The ECH keys of a server.
     pub struct SslEchKeys;
 }
 
 impl SslEchKeys {
+    /// Creates a new builder.
     pub fn builder() -> Result<SslEchKeysBuilder, ErrorStack> {
         SslEchKeysBuilder::new()
     }
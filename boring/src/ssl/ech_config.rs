@@ -0,0 +1,413 @@
+//! ECH configurations, as published in DNS and loaded into [`SslEchKeys`].
+//!
+//! An [`EchConfig`] describes one HPKE key of a client-facing server. Clients receive a list of
+//! them, an [`EchConfigList`], in the `ech` SvcParam of the server's HTTPS or SVCB DNS record,
+//! and pass it to [`SslRef::set_ech_config_list`]. Only version `0xfe0d` of the ECHConfig
+//! structure is supported.
+//!
+//! [`SslEchKeys`]: crate::ssl::SslEchKeys
+//! [`SslRef::set_ech_config_list`]: crate::ssl::SslRef::set_ech_config_list
+use crate::base64;
+use crate::error::ErrorStack;
+use crate::hpke::{HpkeAead, HpkeKdf, HpkeKem, HpkeKey, HpkeKeyRef};
+use crate::ssl::client_hello::Reader;
+use crate::ssl::ech::SslEchKeysBuilder;
+
+/// The version of the ECHConfig structure.
+const VERSION: u16 = 0xfe0d;
+
+/// The key of the `ech` SvcParam.
+const SVC_PARAM_KEY: u16 = 5;
+
+/// Returns whether `name` is a DNS name which does not parse as an IPv4 address, as required of
+/// public names.
+fn is_valid_public_name(name: &str) -> bool {
+    if name.is_empty() || name.len() > 255 {
+        return false;
+    }
+    let valid_labels = name.split('.').all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+    });
+    // The last label of an IPv4 address is a decimal or hexadecimal number.
+    let last = name.rsplit('.').next().unwrap_or_default();
+    let hex = last
+        .strip_prefix("0x")
+        .or_else(|| last.strip_prefix("0X"))
+        .is_some_and(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()));
+    valid_labels && !hex && !last.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// An HPKE KDF and AEAD pair.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HpkeSymmetricCipherSuite {
+    /// The KDF.
+    pub kdf: HpkeKdf,
+    /// The AEAD.
+    pub aead: HpkeAead,
+}
+
+/// A builder for [`EchConfig`]s.
+pub struct EchConfigBuilder<'a> {
+    config_id: u8,
+    public_name: String,
+    key: &'a HpkeKeyRef,
+    cipher_suites: Vec<HpkeSymmetricCipherSuite>,
+    max_name_length: u8,
+    extensions: Vec<(u16, Vec<u8>)>,
+}
+
+impl EchConfigBuilder<'_> {
+    /// Sets the cipher suites clients may encrypt their ClientHello with.
+    ///
+    /// Defaults to HKDF-SHA256 with AES-128-GCM or ChaCha20-Poly1305.
+    pub fn set_cipher_suites(&mut self, cipher_suites: &[HpkeSymmetricCipherSuite]) {
+        self.cipher_suites = cipher_suites.to_vec();
+    }
+
+    /// Sets the length of the longest name clients are expected to connect to, which they pad
+    /// their ClientHello to. Defaults to 0.
+    pub fn set_max_name_length(&mut self, len: u8) {
+        self.max_name_length = len;
+    }
+
+    /// Adds an ECHConfig extension.
+    ///
+    /// Extensions whose type has the high bit set are mandatory: clients which do not support
+    /// them ignore the configuration, which BoringSSL does for all of them.
+    pub fn add_extension(&mut self, extension_type: u16, data: &[u8]) {
+        self.extensions.push((extension_type, data.to_vec()));
+    }
+
+    /// Consumes the builder, returning a new `EchConfig`.
+    ///
+    /// Fails if the public name is not a valid DNS name, if no cipher suite is set, or if an
+    /// extension type is repeated.
+    pub fn build(self) -> Result<EchConfig, ErrorStack> {
+        if !is_valid_public_name(&self.public_name) {
+            return Err(ErrorStack::internal_error_str("invalid ECH public name"));
+        }
+        if self.cipher_suites.is_empty() {
+            return Err(ErrorStack::internal_error_str("no HPKE cipher suite set"));
+        }
+        for (i, (extension_type, _)) in self.extensions.iter().enumerate() {
+            if self.extensions[..i]
+                .iter()
+                .any(|(ty, _)| ty == extension_type)
+            {
+                return Err(ErrorStack::internal_error_str(
+                    "duplicate ECHConfig extension",
+                ));
+            }
+        }
+
+        let public_key = self.key.public_key()?;
+        let too_long = || ErrorStack::internal_error_str("ECHConfig too long");
+        let mut contents = vec![self.config_id];
+        contents.extend_from_slice(&self.key.kem().as_raw().to_be_bytes());
+        put_u16_prefixed(&mut contents, &public_key).ok_or_else(too_long)?;
+        let suites = self
+            .cipher_suites
+            .iter()
+            .flat_map(|suite| {
+                [suite.kdf.as_raw(), suite.aead.as_raw()]
+                    .into_iter()
+                    .flat_map(u16::to_be_bytes)
+            })
+            .collect::<Vec<_>>();
+        put_u16_prefixed(&mut contents, &suites).ok_or_else(too_long)?;
+        contents.push(self.max_name_length);
+        contents.push(self.public_name.len() as u8);
+        contents.extend_from_slice(self.public_name.as_bytes());
+        let mut extensions = vec![];
+        for (extension_type, data) in &self.extensions {
+            extensions.extend_from_slice(&extension_type.to_be_bytes());
+            put_u16_prefixed(&mut extensions, data).ok_or_else(too_long)?;
+        }
+        put_u16_prefixed(&mut contents, &extensions).ok_or_else(too_long)?;
+
+        let mut bytes = VERSION.to_be_bytes().to_vec();
+        put_u16_prefixed(&mut bytes, &contents).ok_or_else(too_long)?;
+        EchConfig::from_bytes(&bytes)
+    }
+}
+
+/// Appends `data` to `out` with a 16-bit length, or returns `None` if it is too long.
+fn put_u16_prefixed(out: &mut Vec<u8>, data: &[u8]) -> Option<()> {
+    out.extend_from_slice(&u16::try_from(data.len()).ok()?.to_be_bytes());
+    out.extend_from_slice(data);
+    Some(())
+}
+
+/// An ECHConfig structure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EchConfig {
+    bytes: Vec<u8>,
+    config_id: u8,
+    kem: HpkeKem,
+    public_key: Vec<u8>,
+    cipher_suites: Vec<HpkeSymmetricCipherSuite>,
+    max_name_length: u8,
+    public_name: String,
+    extensions: Vec<(u16, Vec<u8>)>,
+}
+
+impl EchConfig {
+    /// Creates a new builder for a configuration identified by `config_id`, publishing the public
+    /// half of `key`.
+    ///
+    /// Clients send the outer ClientHello to `public_name`, and check the certificate of the
+    /// client-facing server against it if ECH is rejected.
+    #[must_use]
+    pub fn builder<'a>(
+        config_id: u8,
+        public_name: &str,
+        key: &'a HpkeKeyRef,
+    ) -> EchConfigBuilder<'a> {
+        EchConfigBuilder {
+            config_id,
+            public_name: public_name.to_string(),
+            key,
+            cipher_suites: vec![
+                HpkeSymmetricCipherSuite {
+                    kdf: HpkeKdf::HKDF_SHA256,
+                    aead: HpkeAead::AES_128_GCM,
+                },
+                HpkeSymmetricCipherSuite {
+                    kdf: HpkeKdf::HKDF_SHA256,
+                    aead: HpkeAead::CHACHA20_POLY1305,
+                },
+            ],
+            max_name_length: 0,
+            extensions: vec![],
+        }
+    }
+
+    /// Parses a single ECHConfig.
+    pub fn from_bytes(bytes: &[u8]) -> Result<EchConfig, ErrorStack> {
+        let mut reader = Reader::new(bytes);
+        let config = Self::read(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(ErrorStack::internal_error_str("invalid ECHConfig"));
+        }
+        config.ok_or_else(|| ErrorStack::internal_error_str("unsupported ECHConfig version"))
+    }
+
+    /// Reads an ECHConfig, returning `None` if its version is not supported.
+    fn read(reader: &mut Reader<'_>) -> Result<Option<EchConfig>, ErrorStack> {
+        let invalid = || ErrorStack::internal_error_str("invalid ECHConfig");
+        let start = reader.rest();
+        let version = reader.u16().ok_or_else(invalid)?;
+        let mut contents = reader.u16_prefixed().ok_or_else(invalid)?;
+        let bytes = &start[..start.len() - reader.rest().len()];
+        if version != VERSION {
+            return Ok(None);
+        }
+
+        let config = (|| {
+            let config_id = contents.u8()?;
+            let kem = HpkeKem::from_raw(contents.u16()?);
+            let public_key = contents.u16_prefixed()?.rest();
+            let suites = contents.u16_prefixed()?.u16_list()?;
+            let max_name_length = contents.u8()?;
+            let public_name = std::str::from_utf8(contents.u8_prefixed()?.rest()).ok()?;
+            let mut extensions = vec![];
+            let mut list = contents.u16_prefixed()?;
+            while !list.is_empty() {
+                let extension_type = list.u16()?;
+                extensions.push((extension_type, list.u16_prefixed()?.rest().to_vec()));
+            }
+            if !contents.is_empty()
+                || public_key.is_empty()
+                || suites.is_empty()
+                || suites.len() % 2 != 0
+                || !is_valid_public_name(public_name)
+            {
+                return None;
+            }
+            Some(EchConfig {
+                bytes: bytes.to_vec(),
+                config_id,
+                kem,
+                public_key: public_key.to_vec(),
+                cipher_suites: suites
+                    .chunks(2)
+                    .map(|suite| HpkeSymmetricCipherSuite {
+                        kdf: HpkeKdf::from_raw(suite[0]),
+                        aead: HpkeAead::from_raw(suite[1]),
+                    })
+                    .collect(),
+                max_name_length,
+                public_name: public_name.to_string(),
+                extensions,
+            })
+        })()
+        .ok_or_else(invalid)?;
+        Ok(Some(config))
+    }
+
+    /// Returns the encoded ECHConfig, as passed to [`SslEchKeysBuilder::add_key`].
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the configuration identifier.
+    #[must_use]
+    pub fn config_id(&self) -> u8 {
+        self.config_id
+    }
+
+    /// Returns the KEM of the public key.
+    #[must_use]
+    pub fn kem(&self) -> HpkeKem {
+        self.kem
+    }
+
+    /// Returns the encoded public key.
+    #[must_use]
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// Returns the cipher suites clients may encrypt their ClientHello with.
+    #[must_use]
+    pub fn cipher_suites(&self) -> &[HpkeSymmetricCipherSuite] {
+        &self.cipher_suites
+    }
+
+    /// Returns the length of the longest name clients are expected to connect to.
+    #[must_use]
+    pub fn max_name_length(&self) -> u8 {
+        self.max_name_length
+    }
+
+    /// Returns the public name.
+    #[must_use]
+    pub fn public_name(&self) -> &str {
+        &self.public_name
+    }
+
+    /// Returns the type and data of the extensions.
+    #[must_use]
+    pub fn extensions(&self) -> &[(u16, Vec<u8>)] {
+        &self.extensions
+    }
+}
+
+/// An ECHConfigList structure, as published in DNS.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EchConfigList {
+    configs: Vec<EchConfig>,
+}
+
+impl EchConfigList {
+    /// Creates a list of `configs`, in order of preference.
+    #[must_use]
+    pub fn new(configs: Vec<EchConfig>) -> EchConfigList {
+        EchConfigList { configs }
+    }
+
+    /// Parses an encoded ECHConfigList.
+    ///
+    /// Configurations with an unsupported version are skipped, as clients do.
+    pub fn from_bytes(bytes: &[u8]) -> Result<EchConfigList, ErrorStack> {
+        let invalid = || ErrorStack::internal_error_str("invalid ECHConfigList");
+        let mut reader = Reader::new(bytes);
+        let mut list = reader.u16_prefixed().ok_or_else(invalid)?;
+        if !reader.is_empty() || list.is_empty() {
+            return Err(invalid());
+        }
+        let mut configs = vec![];
+        while !list.is_empty() {
+            configs.extend(EchConfig::read(&mut list)?);
+        }
+        Ok(EchConfigList { configs })
+    }
+
+    /// Returns the encoded ECHConfigList, as passed to [`SslRef::set_ech_config_list`].
+    ///
+    /// [`SslRef::set_ech_config_list`]: crate::ssl::SslRef::set_ech_config_list
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ErrorStack> {
+        let configs = self
+            .configs
+            .iter()
+            .flat_map(|config| config.as_bytes().iter().copied())
+            .collect::<Vec<_>>();
+        if configs.is_empty() {
+            return Err(ErrorStack::internal_error_str("empty ECHConfigList"));
+        }
+        let mut bytes = vec![];
+        put_u16_prefixed(&mut bytes, &configs)
+            .ok_or_else(|| ErrorStack::internal_error_str("ECHConfigList too long"))?;
+        Ok(bytes)
+    }
+
+    /// Returns the configurations, in order of preference.
+    #[must_use]
+    pub fn configs(&self) -> &[EchConfig] {
+        &self.configs
+    }
+
+    /// Parses the `ech` SvcParam of an HTTPS or SVCB record, in its wire format: the SvcParamKey,
+    /// the length and the ECHConfigList.
+    pub fn from_svc_param(param: &[u8]) -> Result<EchConfigList, ErrorStack> {
+        let invalid = || ErrorStack::internal_error_str("invalid ech SvcParam");
+        let mut reader = Reader::new(param);
+        if reader.u16() != Some(SVC_PARAM_KEY) {
+            return Err(invalid());
+        }
+        let value = reader.u16_prefixed().ok_or_else(invalid)?;
+        if !reader.is_empty() {
+            return Err(invalid());
+        }
+        Self::from_bytes(value.rest())
+    }
+
+    /// Returns the `ech` SvcParam of an HTTPS or SVCB record, in its wire format.
+    pub fn to_svc_param(&self) -> Result<Vec<u8>, ErrorStack> {
+        let mut param = SVC_PARAM_KEY.to_be_bytes().to_vec();
+        put_u16_prefixed(&mut param, &self.to_bytes()?)
+            .ok_or_else(|| ErrorStack::internal_error_str("ECHConfigList too long"))?;
+        Ok(param)
+    }
+
+    /// Parses the value of the `ech` SvcParam in the presentation format of zone files, the
+    /// base64 encoding of the ECHConfigList.
+    ///
+    /// A leading `ech=` and surrounding quotes are accepted.
+    pub fn from_presentation(value: &str) -> Result<EchConfigList, ErrorStack> {
+        let value = value.trim();
+        let value = value.strip_prefix("ech=").unwrap_or(value);
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        Self::from_bytes(&base64::decode_block(value)?)
+    }
+
+    /// Returns the `ech` SvcParam in the presentation format of zone files, `ech=` followed by
+    /// the base64 encoding of the ECHConfigList.
+    pub fn to_presentation(&self) -> Result<String, ErrorStack> {
+        Ok(format!("ech={}", base64::encode_block(&self.to_bytes()?)))
+    }
+}
+
+impl SslEchKeysBuilder {
+    /// Adds the private key of `config`.
+    ///
+    /// See [`Self::add_key`].
+    pub fn add_config(
+        &mut self,
+        is_retry_config: bool,
+        config: &EchConfig,
+        key: HpkeKey,
+    ) -> Result<(), ErrorStack> {
+        self.add_key(is_retry_config, config.as_bytes(), key)
+    }
+}
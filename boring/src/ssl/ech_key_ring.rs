@@ -0,0 +1,273 @@
+//! ECH keys which are rotated while contexts are in use.
+use std::collections::VecDeque;
+use std::io;
+use std::mem;
+use std::sync::{Arc, LazyLock, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use foreign_types::ForeignTypeRef;
+
+use crate::error::ErrorStack;
+use crate::ex_data::Index;
+use crate::ffi;
+use crate::hpke::{HpkeKey, HpkeKeyRef};
+use crate::rand::rand_bytes;
+use crate::ssl::{
+    EchConfig, EchConfigList, SslContext, SslContextBuilder, SslContextRef, SslEchKeys,
+};
+
+static REGISTRATION_INDEX: LazyLock<Index<SslContext, Registration>> =
+    LazyLock::new(|| SslContext::new_ex_index().unwrap());
+
+/// How long the rotation thread waits before retrying a failed rotation.
+const RETRY_WAIT: Duration = Duration::from_secs(1);
+
+/// A builder for [`EchKeyRing`].
+pub struct EchKeyRingBuilder {
+    public_name: String,
+    rotation_interval: Duration,
+    retired_keys: usize,
+}
+
+impl EchKeyRingBuilder {
+    /// Sets how long a key is current before it is rotated out.
+    ///
+    /// Defaults to one hour.
+    pub fn set_rotation_interval(&mut self, interval: Duration) {
+        self.rotation_interval = interval;
+    }
+
+    /// Sets how many retired keys are kept to decrypt the ClientHellos of clients with cached
+    /// configurations.
+    ///
+    /// The interval times this number should exceed the TTL of the DNS records publishing
+    /// [`EchKeyRing::config_list`]. Defaults to 2.
+    pub fn set_retired_keys(&mut self, count: usize) {
+        self.retired_keys = count;
+    }
+
+    /// Generates the first key of the ring.
+    pub fn build(self) -> Result<EchKeyRing, ErrorStack> {
+        if self.rotation_interval.is_zero() {
+            return Err(ErrorStack::internal_error_str(
+                "rotation interval must not be zero",
+            ));
+        }
+        let mut config_id = [0];
+        rand_bytes(&mut config_id)?;
+        let current = Key::generate(&self.public_name, config_id[0])?;
+        Ok(EchKeyRing(Arc::new(Mutex::new(KeyRing {
+            public_name: self.public_name,
+            rotation_interval: self.rotation_interval,
+            retired_keys: self.retired_keys,
+            current,
+            retired: VecDeque::new(),
+            rotated_at: Instant::now(),
+            contexts: vec![],
+        }))))
+    }
+}
+
+struct Key {
+    config: EchConfig,
+    key: HpkeKey,
+}
+
+impl Key {
+    fn generate(public_name: &str, config_id: u8) -> Result<Key, ErrorStack> {
+        let key = HpkeKey::generate_x25519_hkdf_sha256()?;
+        let config = EchConfig::builder(config_id, public_name, &key).build()?;
+        Ok(Key { config, key })
+    }
+}
+
+struct KeyRing {
+    public_name: String,
+    rotation_interval: Duration,
+    retired_keys: usize,
+    current: Key,
+    /// Newest first.
+    retired: VecDeque<Key>,
+    rotated_at: Instant,
+    contexts: Vec<Weak<RegisteredContext>>,
+}
+
+/// A context a ring is installed on, without a reference to it.
+///
+/// The pointer is cleared, under the lock, when the context is freed.
+struct RegisteredContext(Mutex<Option<ContextPtr>>);
+
+struct ContextPtr(*mut ffi::SSL_CTX);
+
+// SAFETY: `SSL_CTX_set1_ech_keys`, the only function called through the pointer, may be called
+// from any thread.
+unsafe impl Send for ContextPtr {}
+
+/// Stored in the ex data of the context, which drops it when it is freed or another ring is
+/// installed.
+struct Registration(Arc<RegisteredContext>);
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        *self.0 .0.lock().unwrap() = None;
+    }
+}
+
+impl KeyRing {
+    fn ech_keys<'a>(
+        &self,
+        current: &Key,
+        retired: impl IntoIterator<Item = &'a Key>,
+    ) -> Result<SslEchKeys, ErrorStack> {
+        let mut builder = SslEchKeys::builder()?;
+        builder.add_config(true, &current.config, copy_key(&current.key)?)?;
+        for key in retired.into_iter().take(self.retired_keys) {
+            builder.add_config(false, &key.config, copy_key(&key.key)?)?;
+        }
+        Ok(builder.build())
+    }
+
+    fn rotate(&mut self) -> Result<(), ErrorStack> {
+        let config_id = self.current.config.config_id().wrapping_add(1);
+        let next = Key::generate(&self.public_name, config_id)?;
+        let keys = self.ech_keys(&next, [&self.current].into_iter().chain(&self.retired))?;
+        self.contexts.retain(|context| context.strong_count() > 0);
+        for context in &self.contexts {
+            let Some(context) = context.upgrade() else {
+                continue;
+            };
+            let ctx = context.0.lock().unwrap();
+            if let Some(ctx) = &*ctx {
+                // SAFETY: the context is alive as long as the pointer is set.
+                unsafe { SslContextRef::from_ptr(ctx.0) }.set_ech_keys(&keys)?;
+            }
+        }
+
+        let previous = mem::replace(&mut self.current, next);
+        self.retired.push_front(previous);
+        self.retired.truncate(self.retired_keys);
+        self.rotated_at = Instant::now();
+        Ok(())
+    }
+
+    fn time_until_rotation(&self) -> Duration {
+        self.rotation_interval
+            .saturating_sub(self.rotated_at.elapsed())
+    }
+}
+
+fn copy_key(key: &HpkeKeyRef) -> Result<HpkeKey, ErrorStack> {
+    HpkeKey::dhkem_p256_sha256(&key.private_key()?)
+}
+
+/// A set of ECH keys which is rotated on a schedule.
+///
+/// Install it with [`SslContextBuilder::set_ech_key_ring`]. Clones share the same keys. Each
+/// rotation generates a new X25519 key, whose configuration is sent to clients as retry config,
+/// and keeps the previous keys to decrypt, but not advertise, until they are
+/// [retired](EchKeyRingBuilder::set_retired_keys). The contexts the ring is installed on switch
+/// to the new keys immediately. The ring does not keep them alive: a context leaves the ring when
+/// it is freed, or when another ring is installed on it.
+///
+/// Keys are rotated by [`Self::rotate_if_due`], or by the thread started with
+/// [`Self::spawn_rotation`].
+#[derive(Clone)]
+pub struct EchKeyRing(Arc<Mutex<KeyRing>>);
+
+impl EchKeyRing {
+    /// Creates a builder for a ring whose configurations advertise `public_name`.
+    #[must_use]
+    pub fn builder(public_name: &str) -> EchKeyRingBuilder {
+        EchKeyRingBuilder {
+            public_name: public_name.to_string(),
+            rotation_interval: Duration::from_secs(60 * 60),
+            retired_keys: 2,
+        }
+    }
+
+    /// Returns the configuration of the current key, to publish in DNS.
+    #[must_use]
+    pub fn config_list(&self) -> EchConfigList {
+        let ring = self.0.lock().unwrap();
+        EchConfigList::new(vec![ring.current.config.clone()])
+    }
+
+    /// Returns the configurations of the keys which are kept after a rotation, newest first.
+    #[must_use]
+    pub fn retired_configs(&self) -> Vec<EchConfig> {
+        let ring = self.0.lock().unwrap();
+        ring.retired.iter().map(|key| key.config.clone()).collect()
+    }
+
+    /// Replaces the current key with a new one, now.
+    ///
+    /// This only fails if the new key cannot be generated, before any context is updated:
+    /// BoringSSL only rejects keys without a retry config, and the current key always is one.
+    pub fn rotate(&self) -> Result<(), ErrorStack> {
+        self.0.lock().unwrap().rotate()
+    }
+
+    /// Rotates the keys if the current key is older than the rotation interval, returning
+    /// whether they were rotated.
+    pub fn rotate_if_due(&self) -> Result<bool, ErrorStack> {
+        let mut ring = self.0.lock().unwrap();
+        if !ring.time_until_rotation().is_zero() {
+            return Ok(false);
+        }
+        ring.rotate()?;
+        Ok(true)
+    }
+
+    /// Returns the time left until the keys are due to be rotated.
+    #[must_use]
+    pub fn time_until_rotation(&self) -> Duration {
+        self.0.lock().unwrap().time_until_rotation()
+    }
+
+    /// Starts a thread which rotates the keys whenever they are due.
+    ///
+    /// The thread does not keep the ring alive, and exits once it wakes up after the last clone
+    /// was dropped. Failed rotations are retried a second later.
+    pub fn spawn_rotation(&self) -> io::Result<JoinHandle<()>> {
+        let weak = Arc::downgrade(&self.0);
+        thread::Builder::new()
+            .name("ech-key-rotation".to_string())
+            .spawn(move || rotation_loop(&weak))
+    }
+}
+
+fn rotation_loop(weak: &Weak<Mutex<KeyRing>>) {
+    let mut retry_wait = Duration::ZERO;
+    loop {
+        let Some(ring) = weak.upgrade() else {
+            return;
+        };
+        let wait = ring.lock().unwrap().time_until_rotation();
+        drop(ring);
+        thread::sleep(wait.max(retry_wait));
+
+        let Some(ring) = weak.upgrade() else {
+            return;
+        };
+        let mut ring = ring.lock().unwrap();
+        let failed = ring.time_until_rotation().is_zero() && ring.rotate().is_err();
+        retry_wait = if failed { RETRY_WAIT } else { Duration::ZERO };
+    }
+}
+
+impl SslContextBuilder {
+    /// Serves the keys of `ring`, and those it is rotated to later, replacing the keys set with
+    /// [`Self::set_ech_keys`].
+    pub fn set_ech_key_ring(&mut self, ring: &EchKeyRing) -> Result<(), ErrorStack> {
+        let mut ring = ring.0.lock().unwrap();
+        let keys = ring.ech_keys(&ring.current, &ring.retired)?;
+        self.set_ech_keys(&keys)?;
+        let context = Arc::new(RegisteredContext(Mutex::new(Some(ContextPtr(
+            self.as_ptr(),
+        )))));
+        ring.contexts.push(Arc::downgrade(&context));
+        self.replace_ex_data(*REGISTRATION_INDEX, Registration(context));
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+//! Connecting with ECH, retrying once with the configurations sent by a server which rejects it.
+use std::io::{self, Read, Write};
+
+use crate::error::ErrorStack;
+use crate::ffi;
+use crate::ssl::{HandshakeError, SslConnector, SslStream};
+
+/// How ECH was accepted by [`SslConnector::connect_with_ech_retry`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EchOutcome {
+    /// The server accepted the ECHConfigList the connection was started with.
+    Accepted,
+    /// The server rejected the ECHConfigList the connection was started with, and accepted the
+    /// retry configs it sent instead.
+    ///
+    /// The retry configs should replace the rejected list for later connections to the server.
+    AcceptedAfterRetry {
+        /// The encoded ECHConfigList sent by the server.
+        retry_configs: Vec<u8>,
+    },
+}
+
+impl<S> HandshakeError<S> {
+    /// Returns the ECHConfigList sent by the server if the handshake failed because it rejected
+    /// ECH.
+    ///
+    /// By then, the certificate of the server was verified against the public name of the
+    /// rejected configuration, if verification is enabled.
+    #[must_use]
+    pub fn ech_retry_configs(&self) -> Option<&[u8]> {
+        let HandshakeError::Failure(stream) = self else {
+            return None;
+        };
+        let rejected = stream.error().ssl_error().is_some_and(|errors| {
+            errors.errors().iter().any(|error| {
+                error.library_reason(ffi::ERR_LIB_SSL) == Some(ffi::SSL_R_ECH_REJECTED)
+            })
+        });
+        if !rejected {
+            return None;
+        }
+        stream
+            .ssl()
+            .get_ech_retry_configs()
+            .filter(|configs| !configs.is_empty())
+    }
+}
+
+impl SslConnector {
+    /// Connects to `domain`, offering ECH with `ech_config_list`.
+    ///
+    /// If the server rejects ECH and sends retry configs, the connection is retried once with
+    /// them, as allowed by the ECH specification. `stream` is called to open the transport of
+    /// each attempt, and must return blocking streams. A rejection without retry configs fails
+    /// the connection rather than falling back to a handshake without ECH.
+    pub fn connect_with_ech_retry<S, F>(
+        &self,
+        domain: &str,
+        ech_config_list: &[u8],
+        mut stream: F,
+    ) -> Result<(SslStream<S>, EchOutcome), HandshakeError<S>>
+    where
+        S: Read + Write,
+        F: FnMut() -> io::Result<S>,
+    {
+        let err = match self.connect_with_ech(domain, ech_config_list, &mut stream) {
+            Ok(stream) => return Ok((stream, EchOutcome::Accepted)),
+            Err(err) => err,
+        };
+        let Some(retry_configs) = err.ech_retry_configs().map(<[u8]>::to_vec) else {
+            return Err(err);
+        };
+
+        let stream = self.connect_with_ech(domain, &retry_configs, &mut stream)?;
+        Ok((stream, EchOutcome::AcceptedAfterRetry { retry_configs }))
+    }
+
+    fn connect_with_ech<S, F>(
+        &self,
+        domain: &str,
+        ech_config_list: &[u8],
+        stream: &mut F,
+    ) -> Result<SslStream<S>, HandshakeError<S>>
+    where
+        S: Read + Write,
+        F: FnMut() -> io::Result<S>,
+    {
+        let mut config = self.configure().map_err(HandshakeError::SetupFailure)?;
+        config
+            .set_ech_config_list(ech_config_list)
+            .map_err(HandshakeError::SetupFailure)?;
+        let stream = stream()
+            .map_err(|err| HandshakeError::SetupFailure(ErrorStack::internal_error(err)))?;
+        config.connect(domain, stream)
+    }
+}
@@ -0,0 +1,364 @@
+//! JA3 and JA4 fingerprints of ClientHellos.
+//!
+//! Both fingerprints leave out the GREASE values of RFC 8701 wherever they appear. JA3 keeps the
+//! cipher suites and extensions in the order the client sent them, so clients which permute their
+//! extensions, like Chrome, produce a different JA3 fingerprint on every connection. JA4 sorts the
+//! cipher suites and extensions before hashing them, and keeps the signature algorithms in the
+//! order the client sent them.
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::mem::MaybeUninit;
+
+use foreign_types::ForeignTypeRef;
+
+use crate::error::ErrorStack;
+use crate::ffi;
+use crate::sha::sha256;
+use crate::ssl::client_hello::Reader;
+use crate::ssl::{ClientHello, ExtensionType, HandshakeError, MidHandshakeSslStream, SslConnector};
+
+/// The fingerprints of a ClientHello.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Fingerprint {
+    /// The JA3 string.
+    pub ja3: String,
+    /// The MD5 hash of the JA3 string, in hexadecimal.
+    pub ja3_hash: String,
+    /// The JA4 fingerprint.
+    pub ja4: String,
+    /// The JA4 fingerprint with the cipher suites and extensions left unhashed.
+    pub ja4_r: String,
+}
+
+fn without_grease(values: impl IntoIterator<Item = u16>) -> Vec<u16> {
+    values
+        .into_iter()
+        .filter(|&value| !ClientHello::is_grease(value))
+        .collect()
+}
+
+fn join(values: &[u16], f: impl Fn(u16) -> String, separator: &str) -> String {
+    values
+        .iter()
+        .map(|&value| f(value))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{byte:02x}");
+        out
+    })
+}
+
+/// The first 12 hexadecimal characters of the SHA-256 hash of `data`, or zeros if it is empty.
+fn truncated_hash(data: &str) -> String {
+    if data.is_empty() {
+        return "000000000000".to_string();
+    }
+    to_hex(&sha256(data.as_bytes())[..6])
+}
+
+#[allow(deprecated)] // https://github.com/rust-lang/rust/issues/63566
+fn md5(data: &[u8]) -> [u8; 16] {
+    unsafe {
+        let mut hash: MaybeUninit<[u8; 16]> = MaybeUninit::uninit();
+        ffi::MD5(data.as_ptr(), data.len(), hash.as_mut_ptr().cast());
+        hash.assume_init()
+    }
+}
+
+impl ClientHello<'_> {
+    fn cipher_suites(&self) -> Vec<u16> {
+        without_grease(Reader::new(self.ciphers()).u16_list().unwrap_or_default())
+    }
+
+    fn extension_types(&self) -> Vec<u16> {
+        without_grease(self.extensions().into_iter().map(|(ty, _)| ty.0))
+    }
+
+    /// Returns the JA3 string of the ClientHello.
+    ///
+    /// This lists the legacy version, the cipher suites, the extensions, the supported groups and
+    /// the EC point formats, in the order the client sent them.
+    #[must_use]
+    pub fn ja3(&self) -> String {
+        let decimal = |value: u16| value.to_string();
+        let groups = without_grease(self.supported_groups().unwrap_or_default());
+        let point_formats = self
+            .get_extension(ExtensionType::EC_POINT_FORMATS)
+            .and_then(|data| Reader::new(data).u8_prefixed())
+            .map(|formats| {
+                formats
+                    .rest()
+                    .iter()
+                    .map(|&format| u16::from(format))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        format!(
+            "{},{},{},{},{}",
+            self.client_version().0,
+            join(&self.cipher_suites(), decimal, "-"),
+            join(&self.extension_types(), decimal, "-"),
+            join(&groups, decimal, "-"),
+            join(&point_formats, decimal, "-"),
+        )
+    }
+
+    /// Returns the MD5 hash of the JA3 string of the ClientHello, in hexadecimal.
+    #[must_use]
+    pub fn ja3_hash(&self) -> String {
+        to_hex(&md5(self.ja3().as_bytes()))
+    }
+
+    /// Returns the first section of the JA4 fingerprint, which is the same for `ja4` and
+    /// `ja4_r`.
+    fn ja4_a(&self) -> String {
+        // QUIC clients must send transport parameters, and the legacy versions of DTLS start
+        // with 0xfe.
+        let protocol = if self
+            .get_extension(ExtensionType::QUIC_TRANSPORT_PARAMETERS_STANDARD)
+            .or_else(|| self.get_extension(ExtensionType::QUIC_TRANSPORT_PARAMETERS_LEGACY))
+            .is_some()
+        {
+            'q'
+        } else if self.client_version().0 >> 8 == 0xfe {
+            'd'
+        } else {
+            't'
+        };
+
+        let versions = self.supported_versions().unwrap_or_default();
+        let version = without_grease(versions.into_iter().map(|version| version.0))
+            .into_iter()
+            .max()
+            .unwrap_or(self.client_version().0);
+        let version = match version {
+            0x0304 => "13",
+            0x0303 => "12",
+            0x0302 => "11",
+            0x0301 => "10",
+            0x0300 => "s3",
+            0x0200 => "s2",
+            0x0100 => "s1",
+            0xfeff => "d1",
+            0xfefd => "d2",
+            0xfefc => "d3",
+            _ => "00",
+        };
+
+        let sni = if self.get_extension(ExtensionType::SERVER_NAME).is_some() {
+            'd'
+        } else {
+            'i'
+        };
+
+        let alpn = match self
+            .alpn_protocols()
+            .as_deref()
+            .and_then(|protocols| protocols.first().copied())
+        {
+            Some(protocol) => {
+                let (first, last) = (protocol[0], protocol[protocol.len() - 1]);
+                if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() {
+                    format!("{}{}", char::from(first), char::from(last))
+                } else {
+                    // Use the first and last characters of the hexadecimal encoding instead.
+                    let hex = to_hex(protocol);
+                    format!("{}{}", &hex[..1], &hex[hex.len() - 1..])
+                }
+            }
+            None => "00".to_string(),
+        };
+
+        format!(
+            "{protocol}{version}{sni}{:02}{:02}{alpn}",
+            self.cipher_suites().len().min(99),
+            self.extension_types().len().min(99),
+        )
+    }
+
+    /// Returns the sorted cipher suites, and the sorted extensions followed by the signature
+    /// algorithms, as they are hashed into the JA4 fingerprint.
+    fn ja4_lists(&self) -> (String, String) {
+        let hex = |value: u16| format!("{value:04x}");
+
+        let mut ciphers = self.cipher_suites();
+        ciphers.sort_unstable();
+
+        // The server name and ALPN extensions are already described by the first section.
+        let mut extensions = self.extension_types();
+        extensions.retain(|&ty| {
+            ty != ExtensionType::SERVER_NAME.0
+                && ty != ExtensionType::APPLICATION_LAYER_PROTOCOL_NEGOTIATION.0
+        });
+        extensions.sort_unstable();
+        let mut extensions = join(&extensions, hex, ",");
+
+        let signature_algorithms = self.signature_algorithms().unwrap_or_default();
+        let signature_algorithms = without_grease(
+            signature_algorithms
+                .into_iter()
+                .map(|algorithm| algorithm.0),
+        );
+        if !signature_algorithms.is_empty() {
+            extensions.push('_');
+            extensions.push_str(&join(&signature_algorithms, hex, ","));
+        }
+
+        (join(&ciphers, hex, ","), extensions)
+    }
+
+    /// Returns the JA4 fingerprint of the ClientHello.
+    ///
+    /// The cipher suites and extensions are sorted before they are hashed, so the fingerprint
+    /// does not depend on their order.
+    #[must_use]
+    pub fn ja4(&self) -> String {
+        let (ciphers, extensions) = self.ja4_lists();
+        format!(
+            "{}_{}_{}",
+            self.ja4_a(),
+            truncated_hash(&ciphers),
+            truncated_hash(&extensions)
+        )
+    }
+
+    /// Returns the raw JA4 fingerprint of the ClientHello, with the sorted lists of cipher
+    /// suites and extensions in place of their hashes.
+    #[must_use]
+    pub fn ja4_r(&self) -> String {
+        let (ciphers, extensions) = self.ja4_lists();
+        format!("{}_{ciphers}_{extensions}", self.ja4_a())
+    }
+
+    /// Returns the JA3 and JA4 fingerprints of the ClientHello.
+    #[must_use]
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint {
+            ja3: self.ja3(),
+            ja3_hash: self.ja3_hash(),
+            ja4: self.ja4(),
+            ja4_r: self.ja4_r(),
+        }
+    }
+}
+
+/// Records what the client writes, and never has anything to read.
+pub(crate) struct Capture(Vec<u8>);
+
+impl Capture {
+    /// Returns the records written so far.
+    pub(crate) fn records(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Read for Capture {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reassembles the ClientHello message from the handshake records written by a client.
+fn client_hello_message(mut records: Reader<'_>) -> Option<Vec<u8>> {
+    let mut message = vec![];
+    while !records.is_empty() {
+        let content_type = records.u8()?;
+        records.u16()?;
+        let fragment = records.u16_prefixed()?;
+        // Handshake records.
+        if content_type == 22 {
+            message.extend_from_slice(fragment.rest());
+        }
+    }
+    Some(message)
+}
+
+impl SslConnector {
+    /// Starts a new connection from [`Self::configure`] to `domain`, stopped once it has written
+    /// its ClientHello.
+    ///
+    /// The records holding the ClientHello are available from [`Capture::records`].
+    pub(crate) fn client_hello_records(
+        &self,
+        domain: &str,
+    ) -> Result<MidHandshakeSslStream<Capture>, ErrorStack> {
+        let ssl = self.configure()?.into_ssl(domain)?;
+        match ssl.connect(Capture(vec![])) {
+            Err(HandshakeError::WouldBlock(stream)) => Ok(stream),
+            Err(HandshakeError::SetupFailure(e)) => Err(e),
+            _ => Err(ErrorStack::internal_error_str(
+                "handshake did not stop after the ClientHello",
+            )),
+        }
+    }
+
+    /// Returns the fingerprints of the ClientHello this connector sends to `domain`.
+    ///
+    /// The ClientHello is generated by a new connection from [`Self::configure`], which is
+    /// dropped once the ClientHello is written. GREASE values and, if enabled, the order of the
+    /// extensions are chosen at random, so the JA3 fingerprint may differ from one connection to
+    /// the next.
+    pub fn client_hello_fingerprint(&self, domain: &str) -> Result<Fingerprint, ErrorStack> {
+        let stream = self.client_hello_records(domain)?;
+        let records = stream.get_ref().records();
+        let invalid = || ErrorStack::internal_error_str("invalid ClientHello");
+        let message = client_hello_message(Reader::new(records)).ok_or_else(invalid)?;
+
+        let mut reader = Reader::new(&message);
+        // A ClientHello, with a 24-bit length.
+        if reader.u8() != Some(1) {
+            return Err(invalid());
+        }
+        let len = reader.bytes(3).ok_or_else(invalid)?;
+        let len = usize::from(len[0]) << 16 | usize::from(len[1]) << 8 | usize::from(len[2]);
+        let mut body = Reader::new(reader.bytes(len).ok_or_else(invalid)?);
+
+        let hello = (|| {
+            let version = body.u16()?;
+            let random = body.bytes(32)?;
+            let session_id = body.u8_prefixed()?.rest();
+            let cipher_suites = body.u16_prefixed()?.rest();
+            let compression_methods = body.u8_prefixed()?.rest();
+            let extensions = body.u16_prefixed()?.rest();
+            if !body.is_empty() {
+                return None;
+            }
+            Some(ffi::SSL_CLIENT_HELLO {
+                ssl: stream.ssl().as_ptr(),
+                client_hello: message[4..].as_ptr(),
+                client_hello_len: len,
+                version,
+                random: random.as_ptr(),
+                random_len: random.len(),
+                session_id: session_id.as_ptr(),
+                session_id_len: session_id.len(),
+                cipher_suites: cipher_suites.as_ptr(),
+                cipher_suites_len: cipher_suites.len(),
+                compression_methods: compression_methods.as_ptr(),
+                compression_methods_len: compression_methods.len(),
+                extensions: extensions.as_ptr(),
+                extensions_len: extensions.len(),
+            })
+        })()
+        .ok_or_else(invalid)?;
+
+        Ok(ClientHello(&hello).fingerprint())
+    }
+}
@@ -0,0 +1,56 @@
+//! The kinds of keys certificates are served with.
+use crate::nid::Nid;
+use crate::pkey::{Id, PKeyRef};
+use crate::ssl::SslSignatureAlgorithm;
+
+/// The kind of key of a certificate, deciding which signature algorithms it can be used with.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) enum KeyKind {
+    Rsa,
+    Ec(Option<Nid>),
+    Ed25519,
+    Other,
+}
+
+impl KeyKind {
+    pub(crate) fn of<T>(key: &PKeyRef<T>) -> KeyKind {
+        match key.id() {
+            Id::RSA => KeyKind::Rsa,
+            Id::EC => KeyKind::Ec(key.ec_key().ok().and_then(|ec| ec.group().curve_name())),
+            Id::ED25519 => KeyKind::Ed25519,
+            _ => KeyKind::Other,
+        }
+    }
+
+    pub(crate) fn supports(self, sigalg: SslSignatureAlgorithm) -> bool {
+        match self {
+            KeyKind::Rsa => [
+                SslSignatureAlgorithm::RSA_PKCS1_SHA1,
+                SslSignatureAlgorithm::RSA_PKCS1_SHA256,
+                SslSignatureAlgorithm::RSA_PKCS1_SHA384,
+                SslSignatureAlgorithm::RSA_PKCS1_SHA512,
+                SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256,
+                SslSignatureAlgorithm::RSA_PSS_RSAE_SHA384,
+                SslSignatureAlgorithm::RSA_PSS_RSAE_SHA512,
+            ]
+            .contains(&sigalg),
+            KeyKind::Ec(curve) => {
+                sigalg == SslSignatureAlgorithm::ECDSA_SHA1
+                    || match curve {
+                        Some(Nid::X9_62_PRIME256V1) => {
+                            sigalg == SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256
+                        }
+                        Some(Nid::SECP384R1) => {
+                            sigalg == SslSignatureAlgorithm::ECDSA_SECP384R1_SHA384
+                        }
+                        Some(Nid::SECP521R1) => {
+                            sigalg == SslSignatureAlgorithm::ECDSA_SECP521R1_SHA512
+                        }
+                        _ => false,
+                    }
+            }
+            KeyKind::Ed25519 => sigalg == SslSignatureAlgorithm::ED25519,
+            KeyKind::Other => false,
+        }
+    }
+}
@@ -87,11 +87,10 @@ use crate::pkey::{HasPrivate, PKeyRef, Params, Private};
 use crate::srtp::{SrtpProtectionProfile, SrtpProtectionProfileRef};
 use crate::ssl::bio::BioMethod;
 use crate::ssl::callbacks::*;
-#[cfg(not(feature = "fips"))]
-use crate::ssl::ech::SslEchKeys;
 use crate::ssl::error::InnerError;
 use crate::stack::{Stack, StackRef, Stackable};
 use crate::symm::CipherCtxRef;
+use crate::x509::chain::{build_serving_chain, ServingChain};
 use crate::x509::store::{X509Store, X509StoreBuilder, X509StoreBuilderRef, X509StoreRef};
 use crate::x509::verify::X509VerifyParamRef;
 use crate::x509::{
@@ -105,22 +104,58 @@ pub use self::async_callbacks::{
     BoxCustomVerifyFuture, BoxGetSessionFinish, BoxGetSessionFuture, BoxPrivateKeyMethodFinish,
     BoxPrivateKeyMethodFuture, BoxSelectCertFinish, BoxSelectCertFuture, ExDataFuture,
 };
+#[cfg(feature = "cert-compression-brotli")]
+pub use self::cert_compression::BrotliCertificateCompressor;
+#[cfg(feature = "cert-compression-zlib")]
+pub use self::cert_compression::ZlibCertificateCompressor;
+#[cfg(feature = "cert-compression-zstd")]
+pub use self::cert_compression::ZstdCertificateCompressor;
 pub use self::connector::{
     ConnectConfiguration, SslAcceptor, SslAcceptorBuilder, SslConnector, SslConnectorBuilder,
 };
+pub use self::credential::{SslCredential, SslCredentialBuilder};
+pub use self::delegated_credential::{DelegatedCredential, DelegatedCredentialBuilder};
+#[cfg(not(feature = "fips"))]
+pub use self::ech::{SslEchKeys, SslEchKeysBuilder, SslEchKeysRef};
+#[cfg(not(feature = "fips"))]
+pub use self::ech_config::{EchConfig, EchConfigBuilder, EchConfigList, HpkeSymmetricCipherSuite};
 #[cfg(not(feature = "fips"))]
-pub use self::ech::SslEchKeysRef;
+pub use self::ech_key_ring::{EchKeyRing, EchKeyRingBuilder};
+pub use self::ech_retry::EchOutcome;
 pub use self::error::{Error, ErrorCode, HandshakeError};
+pub use self::fingerprint::Fingerprint;
+pub use self::pin::{PinMode, PinReport, PinSet, PinSetBuilder};
+pub use self::profile::{ClientHelloProfile, ExtensionOrder};
+pub use self::reload::{ReloadableIdentity, ReloadableTrust};
+pub use self::sni::{SniResolver, SniResolverBuilder, UnknownNamePolicy};
+pub use self::spiffe::{SpiffeId, SpiffeVerifier, SpiffeVerifierBuilder};
 
 mod async_callbacks;
 mod bio;
 mod callbacks;
+mod cert_compression;
+mod client_hello;
 mod connector;
+mod credential;
+mod delegated_credential;
 mod ech;
+#[cfg(not(feature = "fips"))]
+mod ech_config;
+#[cfg(not(feature = "fips"))]
+mod ech_key_ring;
+mod ech_retry;
 mod error;
+mod fingerprint;
+mod key_kind;
 mod mut_only;
+mod pin;
+mod profile;
+mod reload;
+mod sni;
+mod spiffe;
 #[cfg(test)]
-mod test;
+pub(crate) mod test;
+mod verify;
 
 bitflags! {
     /// Options controlling the behavior of an `SslContext`.
@@ -447,6 +482,8 @@ static SSL_INDEXES: LazyLock<Mutex<HashMap<TypeId, c_int>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
 static SESSION_CTX_INDEX: LazyLock<Index<Ssl, SslContext>> =
     LazyLock::new(|| Ssl::new_ex_index().unwrap());
+static CERT_COMPRESSION_INDEX: LazyLock<Index<SslContext, Vec<CertificateCompressionAlgorithm>>> =
+    LazyLock::new(|| SslContext::new_ex_index().unwrap());
 
 /// An error returned from the SNI callback.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -1316,6 +1353,31 @@ impl SslContextBuilder {
         }
     }
 
+    /// Sets the leaf certificate and the chain built from `pool` by [`build_serving_chain`].
+    ///
+    /// The chain stops at the certificate issued by one of `roots`, which should be the roots
+    /// clients are expected to trust. Certificates previously added with `add_extra_chain_cert`
+    /// are replaced. The chain is installed even if it is incomplete; inspect the returned
+    /// [`ServingChain`] to reject such configurations.
+    ///
+    /// [`build_serving_chain`]: crate::x509::chain::build_serving_chain
+    pub fn set_leaf_and_pool(
+        &mut self,
+        leaf: &X509Ref,
+        pool: &[X509],
+        roots: &[X509],
+    ) -> Result<ServingChain, ErrorStack> {
+        let chain = build_serving_chain(leaf, pool, roots)?;
+        self.set_certificate(leaf)?;
+        unsafe {
+            cvt(ffi::SSL_CTX_clear_extra_chain_certs(self.as_ptr()) as c_int)?;
+        }
+        for cert in chain.intermediates() {
+            self.add_extra_chain_cert(cert.clone())?;
+        }
+        Ok(chain)
+    }
+
     /// Loads the private key from a file.
     #[corresponds(SSL_CTX_use_PrivateKey_file)]
     pub fn set_private_key_file<P: AsRef<Path>>(
@@ -1584,9 +1646,21 @@ impl SslContextBuilder {
             return Err(ErrorStack::get());
         }
         self.replace_ex_data(SslContext::cached_ex_index::<C>(), compressor);
+        if C::CAN_DECOMPRESS {
+            let mut algorithms = self.certificate_compression_algorithms().to_vec();
+            algorithms.push(C::ALGORITHM);
+            self.replace_ex_data(*CERT_COMPRESSION_INDEX, algorithms);
+        }
         Ok(())
     }
 
+    /// Returns the certificate compression algorithms offered to peers, in order.
+    pub(crate) fn certificate_compression_algorithms(&self) -> &[CertificateCompressionAlgorithm] {
+        self.ctx
+            .ex_data(*CERT_COMPRESSION_INDEX)
+            .map_or(&[], Vec::as_slice)
+    }
+
     /// Configures a custom private key method on the context.
     ///
     /// See [`PrivateKeyMethod`] for more details.
@@ -1865,6 +1939,11 @@ impl SslContextBuilder {
     }
 
     /// Sets whether the context should enable delegated credentials.
+    ///
+    /// Clients advertise support for delegated credentials signed with `sigalgs`, a
+    /// colon-separated list of signature algorithm names. The bundled BoringSSL does not accept
+    /// the delegated credentials servers send in return: the handshake fails if the server uses
+    /// one. See [`DelegatedCredential`] for serving them.
     #[corresponds(SSL_CTX_set_delegated_credentials)]
     pub fn set_delegated_credentials(&mut self, sigalgs: &str) -> Result<(), ErrorStack> {
         let sigalgs = CString::new(sigalgs).unwrap();
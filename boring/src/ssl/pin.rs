@@ -0,0 +1,247 @@
+//! Public key pinning of server certificates.
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, LazyLock};
+
+use foreign_types::ForeignTypeRef;
+use libc::c_int;
+
+use crate::error::ErrorStack;
+use crate::ex_data::Index;
+use crate::ffi;
+use crate::ssl::{Ssl, SslAlert, SslContext, SslRef, SslVerifyMode};
+use crate::x509::{X509Ref, X509VerifyError, X509};
+
+pub(crate) static PIN_SET_INDEX: LazyLock<Index<SslContext, PinSet>> =
+    LazyLock::new(|| SslContext::new_ex_index().unwrap());
+
+static PIN_CHECK_INDEX: LazyLock<Index<Ssl, PinCheck>> =
+    LazyLock::new(|| Ssl::new_ex_index().unwrap());
+
+/// What happens when none of the pins of a host match its certificate chain.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum PinMode {
+    /// Fails the handshake.
+    #[default]
+    Enforce,
+    /// Reports the mismatch and lets the connection proceed.
+    SoftFail,
+}
+
+/// A pin mismatch, passed to the callback set with [`PinSetBuilder::set_report_callback`].
+#[derive(Debug)]
+pub struct PinReport<'a> {
+    hostname: &'a str,
+    chain_pins: &'a [[u8; 32]],
+    enforced: bool,
+}
+
+impl PinReport<'_> {
+    /// Returns the host the connection was made to.
+    #[must_use]
+    pub fn hostname(&self) -> &str {
+        self.hostname
+    }
+
+    /// Returns the SHA-256 digests of the SubjectPublicKeyInfo of every certificate in the
+    /// verified chain, starting with the leaf.
+    #[must_use]
+    pub fn chain_pins(&self) -> &[[u8; 32]] {
+        self.chain_pins
+    }
+
+    /// Returns `true` if the handshake is failed because of the mismatch.
+    #[must_use]
+    pub fn enforced(&self) -> bool {
+        self.enforced
+    }
+}
+
+type ReportCallback = dyn Fn(&PinReport<'_>) + Send + Sync;
+
+struct HostPins {
+    pins: Vec<[u8; 32]>,
+    mode: PinMode,
+}
+
+/// A builder for [`PinSet`]s.
+#[derive(Default)]
+pub struct PinSetBuilder {
+    hosts: HashMap<String, HostPins>,
+    report: Option<Box<ReportCallback>>,
+    report_only: bool,
+}
+
+impl PinSetBuilder {
+    /// Pins the certificates of `hostname`.
+    ///
+    /// Each pin is the SHA-256 digest of a SubjectPublicKeyInfo, as returned by
+    /// [`X509Ref::spki_sha256`]. A chain matches if any of its certificates, leaf, intermediates
+    /// or root, has one of the `pins` or `backup_pins`. Backup pins are usually the keys of the
+    /// next certificate, kept offline until it is deployed. Pinning a host again replaces its
+    /// pins.
+    ///
+    /// [`X509Ref::spki_sha256`]: crate::x509::X509Ref::spki_sha256
+    pub fn add_host(
+        &mut self,
+        hostname: &str,
+        pins: &[[u8; 32]],
+        backup_pins: &[[u8; 32]],
+        mode: PinMode,
+    ) -> Result<(), ErrorStack> {
+        if pins.is_empty() {
+            return Err(ErrorStack::internal_error_str("no pins given"));
+        }
+        let pins = pins.iter().chain(backup_pins).copied().collect();
+        self.hosts
+            .insert(normalize(hostname), HostPins { pins, mode });
+        Ok(())
+    }
+
+    /// Sets a callback called for every pin mismatch, whether it is enforced or not.
+    pub fn set_report_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(&PinReport<'_>) + Send + Sync + 'static,
+    {
+        self.report = Some(Box::new(callback));
+    }
+
+    /// Only reports mismatches, as if every host used [`PinMode::SoftFail`].
+    ///
+    /// Defaults to `false`.
+    pub fn set_report_only(&mut self, report_only: bool) {
+        self.report_only = report_only;
+    }
+
+    /// Consumes the builder, returning a new `PinSet`.
+    #[must_use]
+    pub fn build(self) -> PinSet {
+        PinSet(Arc::new(self))
+    }
+}
+
+/// A set of public key pins, checked against the verified certificate chain of servers.
+///
+/// Install it with [`SslConnectorBuilder::set_pin_set`] or
+/// [`ConnectConfiguration::set_pin_set`]. Connections to hosts without pins are only subject to
+/// the usual certificate verification. Clones share the same pins.
+///
+/// [`SslConnectorBuilder::set_pin_set`]: crate::ssl::SslConnectorBuilder::set_pin_set
+/// [`ConnectConfiguration::set_pin_set`]: crate::ssl::ConnectConfiguration::set_pin_set
+#[derive(Clone)]
+pub struct PinSet(Arc<PinSetBuilder>);
+
+impl PinSet {
+    /// Creates a new builder.
+    #[must_use]
+    pub fn builder() -> PinSetBuilder {
+        PinSetBuilder::default()
+    }
+
+    /// Returns `true` if `hostname` is pinned.
+    #[must_use]
+    pub fn contains(&self, hostname: &str) -> bool {
+        self.0.hosts.contains_key(&normalize(hostname))
+    }
+
+    /// Checks the pins of `hostname` against the chain produced by the verification configured
+    /// on `ssl`.
+    ///
+    /// The built-in verification checks them from a verify callback, once the callback already
+    /// set on `ssl` accepted the chain, and the custom verify callbacks of this crate through
+    /// [`check_pins`]. Returns an error if `ssl` does not verify the peer, as the pins would
+    /// never be enforced.
+    pub(crate) fn install(self, ssl: &mut SslRef, hostname: &str) -> Result<(), ErrorStack> {
+        let mode = ssl.verify_mode();
+        if !mode.contains(SslVerifyMode::PEER) {
+            return Err(ErrorStack::internal_error_str(
+                "pins cannot be enforced without peer verification",
+            ));
+        }
+
+        let check = PinCheck {
+            pins: self,
+            hostname: normalize(hostname),
+        };
+        let builtin = check.clone();
+        let previous = unsafe { ffi::SSL_get_verify_callback(ssl.as_ptr()) };
+        ssl.set_verify_callback(mode, move |mut preverify_ok, x509_ctx| {
+            if let Some(previous) = previous {
+                preverify_ok =
+                    unsafe { previous(c_int::from(preverify_ok), x509_ctx.as_ptr()) } != 0;
+            }
+            if !preverify_ok || x509_ctx.error_depth() != 0 {
+                return preverify_ok;
+            }
+            let Some(chain) = x509_ctx.chain() else {
+                return false;
+            };
+            if builtin.check(chain.iter()).is_err() {
+                x509_ctx.set_error(Err(X509VerifyError::APPLICATION_VERIFICATION));
+                return false;
+            }
+            true
+        });
+        ssl.replace_ex_data(*PIN_CHECK_INDEX, check);
+        Ok(())
+    }
+}
+
+/// The pins of the host a connection is made to.
+#[derive(Clone)]
+pub(crate) struct PinCheck {
+    pins: PinSet,
+    hostname: String,
+}
+
+impl PinCheck {
+    fn check<'a>(&self, chain: impl IntoIterator<Item = &'a X509Ref>) -> Result<(), SslAlert> {
+        let Some(host) = self.pins.0.hosts.get(&self.hostname) else {
+            return Ok(());
+        };
+        let chain_pins = chain
+            .into_iter()
+            .map(X509Ref::spki_sha256)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| SslAlert::INTERNAL_ERROR)?;
+        if chain_pins.iter().any(|pin| host.pins.contains(pin)) {
+            return Ok(());
+        }
+
+        let enforced = host.mode == PinMode::Enforce && !self.pins.0.report_only;
+        if let Some(report) = &self.pins.0.report {
+            report(&PinReport {
+                hostname: &self.hostname,
+                chain_pins: &chain_pins,
+                enforced,
+            });
+        }
+        if enforced {
+            return Err(SslAlert::BAD_CERTIFICATE);
+        }
+        Ok(())
+    }
+}
+
+/// Checks the pins installed on `ssl`, if any, against a chain verified by a custom verify
+/// callback, starting with the leaf.
+pub(crate) fn check_pins(ssl: &SslRef, chain: &[X509]) -> Result<(), SslAlert> {
+    match ssl.ex_data(*PIN_CHECK_INDEX) {
+        Some(check) => check.check(chain.iter().map(|cert| &**cert)),
+        None => Ok(()),
+    }
+}
+
+impl fmt::Debug for PinSet {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("PinSet")
+            .field("hosts", &self.0.hosts.keys())
+            .field("report_only", &self.0.report_only)
+            .finish()
+    }
+}
+
+fn normalize(hostname: &str) -> String {
+    hostname.trim_end_matches('.').to_ascii_lowercase()
+}
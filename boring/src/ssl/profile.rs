@@ -0,0 +1,453 @@
+//! Declarative ClientHello profiles.
+//!
+//! A [`ClientHelloProfile`] describes everything a client controls in its ClientHello, so that
+//! it can be applied to an [`SslConnectorBuilder`] in one call and checked for inconsistencies
+//! beforehand. The [`chrome`](ClientHelloProfile::chrome) and
+//! [`firefox`](ClientHelloProfile::firefox) presets mimic the ClientHellos of these browsers.
+use std::sync::LazyLock;
+
+use foreign_types::ForeignTypeRef;
+
+use crate::cvt;
+use crate::error::ErrorStack;
+use crate::ex_data::Index;
+use crate::ffi;
+use crate::ssl::{
+    CertificateCompressionAlgorithm, ExtensionType, SslConnectorBuilder, SslContext, SslRef,
+    SslSignatureAlgorithm, SslVersion,
+};
+
+pub(crate) static CLIENT_HELLO_PROFILE_INDEX: LazyLock<Index<SslContext, ClientHelloProfile>> =
+    LazyLock::new(|| SslContext::new_ex_index().unwrap());
+
+/// The order of the extensions of a ClientHello.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtensionOrder {
+    /// The order in which BoringSSL implements the extensions.
+    Default,
+    /// A random order, chosen for every connection.
+    Permuted,
+    /// The given order.
+    ///
+    /// Every extension the profile sends must be listed, as BoringSSL appends the missing ones in
+    /// a random order. So must the status request and signed certificate timestamp extensions if
+    /// they are enabled on the connector. GREASE extensions always come first and last. The padding extension is
+    /// added by BoringSSL to ClientHellos of 256 to 511 bytes; it may only be listed last.
+    Fixed(Vec<ExtensionType>),
+}
+
+/// The settings of a client which shape its ClientHello.
+///
+/// Start from a preset and adjust its fields, then install it with
+/// [`SslConnectorBuilder::set_client_hello_profile`].
+///
+/// Padding is not part of the profile: BoringSSL has no setting for it, and always adds the
+/// padding extension (RFC 7685) to ClientHellos which would otherwise be 256 to 511 bytes long,
+/// the range some middleboxes fail on. Chrome, which shares that behaviour, and Firefox pad the
+/// same way, so the presets match them without a control.
+///
+/// Neither are OCSP stapling and signed certificate timestamps: BoringSSL can start requesting
+/// them but never stop. Enable them on the connector with
+/// [`SslContextBuilder::enable_ocsp_stapling`] and
+/// [`SslContextBuilder::enable_signed_cert_timestamps`] as the presets describe.
+///
+/// [`SslContextBuilder::enable_ocsp_stapling`]: crate::ssl::SslContextBuilder::enable_ocsp_stapling
+/// [`SslContextBuilder::enable_signed_cert_timestamps`]: crate::ssl::SslContextBuilder::enable_signed_cert_timestamps
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ClientHelloProfile {
+    /// The minimum supported protocol version.
+    pub min_version: SslVersion,
+    /// The maximum supported protocol version.
+    pub max_version: SslVersion,
+    /// The cipher suites, by OpenSSL name, in order of preference.
+    ///
+    /// TLS 1.3 cipher suites such as `TLS_AES_128_GCM_SHA256` are sent in the given order, except
+    /// with the `fips` feature, where BoringSSL orders them itself.
+    pub ciphers: Vec<String>,
+    /// The supported groups, such as `X25519MLKEM768` or `P-256`, in order of preference.
+    pub groups: Vec<String>,
+    /// The groups to send key shares for.
+    ///
+    /// These must be the first one to three `groups`. If empty, BoringSSL sends a key share for
+    /// the first group, and for the first following group that is post-quantum if the first is
+    /// not, or vice versa.
+    pub key_shares: Vec<String>,
+    /// The signature algorithms, in order of preference.
+    pub signature_algorithms: Vec<SslSignatureAlgorithm>,
+    /// The order of the extensions.
+    pub extension_order: ExtensionOrder,
+    /// Whether to send GREASE values, as described in RFC 8701.
+    pub grease: bool,
+    /// The ALPN protocols, in order of preference.
+    pub alpn: Vec<String>,
+    /// The ALPN protocols to offer application-layer protocol settings (ALPS) for.
+    pub alps: Vec<String>,
+    /// Whether to send ALPS with the new codepoint, 17613, instead of 17513.
+    pub alps_new_codepoint: bool,
+    /// The certificate compression algorithms, in order of preference.
+    ///
+    /// Compressors for them must be registered, in this order, with
+    /// [`SslContextBuilder::add_certificate_compression_algorithm`] before the profile is
+    /// installed.
+    ///
+    /// [`SslContextBuilder::add_certificate_compression_algorithm`]: crate::ssl::SslContextBuilder::add_certificate_compression_algorithm
+    pub cert_compression: Vec<CertificateCompressionAlgorithm>,
+    /// Whether to send a GREASE encrypted ClientHello extension.
+    pub ech_grease: bool,
+    /// The record size limit to advertise, as described in RFC 8449.
+    pub record_size_limit: Option<u16>,
+    /// The signature algorithms, by name, to accept delegated credentials for.
+    pub delegated_credentials: Vec<String>,
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+fn invalid(reason: &'static str) -> ErrorStack {
+    ErrorStack::internal_error_str(reason)
+}
+
+impl ClientHelloProfile {
+    /// Returns a profile mimicking Chrome 131 and later.
+    ///
+    /// Installing it requires a Brotli certificate compressor to be registered first. Chrome also
+    /// requests OCSP stapling and signed certificate timestamps, which must be enabled on the
+    /// connector.
+    #[must_use]
+    pub fn chrome() -> ClientHelloProfile {
+        ClientHelloProfile {
+            min_version: SslVersion::TLS1_2,
+            max_version: SslVersion::TLS1_3,
+            ciphers: strings(&[
+                "TLS_AES_128_GCM_SHA256",
+                "TLS_AES_256_GCM_SHA384",
+                "TLS_CHACHA20_POLY1305_SHA256",
+                "ECDHE-ECDSA-AES128-GCM-SHA256",
+                "ECDHE-RSA-AES128-GCM-SHA256",
+                "ECDHE-ECDSA-AES256-GCM-SHA384",
+                "ECDHE-RSA-AES256-GCM-SHA384",
+                "ECDHE-ECDSA-CHACHA20-POLY1305",
+                "ECDHE-RSA-CHACHA20-POLY1305",
+                "ECDHE-RSA-AES128-SHA",
+                "ECDHE-RSA-AES256-SHA",
+                "AES128-GCM-SHA256",
+                "AES256-GCM-SHA384",
+                "AES128-SHA",
+                "AES256-SHA",
+            ]),
+            groups: strings(&["X25519MLKEM768", "X25519", "P-256", "P-384"]),
+            key_shares: strings(&["X25519MLKEM768", "X25519"]),
+            signature_algorithms: vec![
+                SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256,
+                SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256,
+                SslSignatureAlgorithm::RSA_PKCS1_SHA256,
+                SslSignatureAlgorithm::ECDSA_SECP384R1_SHA384,
+                SslSignatureAlgorithm::RSA_PSS_RSAE_SHA384,
+                SslSignatureAlgorithm::RSA_PKCS1_SHA384,
+                SslSignatureAlgorithm::RSA_PSS_RSAE_SHA512,
+                SslSignatureAlgorithm::RSA_PKCS1_SHA512,
+            ],
+            extension_order: ExtensionOrder::Permuted,
+            grease: true,
+            alpn: strings(&["h2", "http/1.1"]),
+            alps: strings(&["h2"]),
+            alps_new_codepoint: true,
+            cert_compression: vec![CertificateCompressionAlgorithm::BROTLI],
+            ech_grease: true,
+            record_size_limit: None,
+            delegated_credentials: vec![],
+        }
+    }
+
+    /// Returns a profile mimicking Firefox 133 and later.
+    ///
+    /// Installing it requires Zlib, Brotli and Zstandard certificate compressors to be
+    /// registered first, in this order. Firefox also requests OCSP stapling, which must be enabled
+    /// on the connector.
+    ///
+    /// Firefox offers to accept delegated credentials, which BoringSSL clients cannot do, so the
+    /// preset leaves them out.
+    #[must_use]
+    pub fn firefox() -> ClientHelloProfile {
+        ClientHelloProfile {
+            min_version: SslVersion::TLS1_2,
+            max_version: SslVersion::TLS1_3,
+            ciphers: strings(&[
+                "TLS_AES_128_GCM_SHA256",
+                "TLS_CHACHA20_POLY1305_SHA256",
+                "TLS_AES_256_GCM_SHA384",
+                "ECDHE-ECDSA-AES128-GCM-SHA256",
+                "ECDHE-RSA-AES128-GCM-SHA256",
+                "ECDHE-ECDSA-CHACHA20-POLY1305",
+                "ECDHE-RSA-CHACHA20-POLY1305",
+                "ECDHE-ECDSA-AES256-GCM-SHA384",
+                "ECDHE-RSA-AES256-GCM-SHA384",
+                "ECDHE-ECDSA-AES256-SHA",
+                "ECDHE-ECDSA-AES128-SHA",
+                "ECDHE-RSA-AES128-SHA",
+                "ECDHE-RSA-AES256-SHA",
+                "AES128-GCM-SHA256",
+                "AES256-GCM-SHA384",
+                "AES128-SHA",
+                "AES256-SHA",
+            ]),
+            groups: strings(&[
+                "X25519MLKEM768",
+                "X25519",
+                "P-256",
+                "P-384",
+                "P-521",
+                "ffdhe2048",
+                "ffdhe3072",
+            ]),
+            key_shares: strings(&["X25519MLKEM768", "X25519", "P-256"]),
+            signature_algorithms: vec![
+                SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256,
+                SslSignatureAlgorithm::ECDSA_SECP384R1_SHA384,
+                SslSignatureAlgorithm::ECDSA_SECP521R1_SHA512,
+                SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256,
+                SslSignatureAlgorithm::RSA_PSS_RSAE_SHA384,
+                SslSignatureAlgorithm::RSA_PSS_RSAE_SHA512,
+                SslSignatureAlgorithm::RSA_PKCS1_SHA256,
+                SslSignatureAlgorithm::RSA_PKCS1_SHA384,
+                SslSignatureAlgorithm::RSA_PKCS1_SHA512,
+                SslSignatureAlgorithm::ECDSA_SHA1,
+                SslSignatureAlgorithm::RSA_PKCS1_SHA1,
+            ],
+            extension_order: ExtensionOrder::Fixed(vec![
+                ExtensionType::SERVER_NAME,
+                ExtensionType::EXTENDED_MASTER_SECRET,
+                ExtensionType::RENEGOTIATE,
+                ExtensionType::SUPPORTED_GROUPS,
+                ExtensionType::EC_POINT_FORMATS,
+                ExtensionType::SESSION_TICKET,
+                ExtensionType::APPLICATION_LAYER_PROTOCOL_NEGOTIATION,
+                ExtensionType::STATUS_REQUEST,
+                ExtensionType::KEY_SHARE,
+                ExtensionType::SUPPORTED_VERSIONS,
+                ExtensionType::SIGNATURE_ALGORITHMS,
+                ExtensionType::PSK_KEY_EXCHANGE_MODES,
+                ExtensionType::RECORD_SIZE_LIMIT,
+                ExtensionType::CERT_COMPRESSION,
+                ExtensionType::ENCRYPTED_CLIENT_HELLO,
+                ExtensionType::PADDING,
+            ]),
+            grease: false,
+            alpn: strings(&["h2", "http/1.1"]),
+            alps: vec![],
+            alps_new_codepoint: false,
+            cert_compression: vec![
+                CertificateCompressionAlgorithm::ZLIB,
+                CertificateCompressionAlgorithm::BROTLI,
+                CertificateCompressionAlgorithm::ZSTD,
+            ],
+            ech_grease: true,
+            record_size_limit: Some(0x4001),
+            delegated_credentials: vec![],
+        }
+    }
+
+    /// Returns the extensions sent with this profile, besides GREASE and padding.
+    fn extensions(&self) -> Vec<ExtensionType> {
+        let mut extensions = vec![ExtensionType::SERVER_NAME, ExtensionType::SUPPORTED_GROUPS];
+        if self.min_version.0 < SslVersion::TLS1_3.0 {
+            extensions.extend([
+                ExtensionType::EXTENDED_MASTER_SECRET,
+                ExtensionType::RENEGOTIATE,
+                ExtensionType::EC_POINT_FORMATS,
+                ExtensionType::SESSION_TICKET,
+            ]);
+        }
+        if self.max_version.0 >= SslVersion::TLS1_2.0 {
+            extensions.push(ExtensionType::SIGNATURE_ALGORITHMS);
+        }
+        if self.max_version.0 >= SslVersion::TLS1_3.0 {
+            extensions.extend([
+                ExtensionType::KEY_SHARE,
+                ExtensionType::PSK_KEY_EXCHANGE_MODES,
+                ExtensionType::SUPPORTED_VERSIONS,
+            ]);
+        }
+        let optional = [
+            (
+                !self.alpn.is_empty(),
+                ExtensionType::APPLICATION_LAYER_PROTOCOL_NEGOTIATION,
+            ),
+            (
+                !self.alps.is_empty() && !self.alps_new_codepoint,
+                ExtensionType::APPLICATION_SETTINGS,
+            ),
+            (
+                !self.alps.is_empty() && self.alps_new_codepoint,
+                ExtensionType::APPLICATION_SETTINGS_NEW,
+            ),
+            (
+                !self.cert_compression.is_empty(),
+                ExtensionType::CERT_COMPRESSION,
+            ),
+            (self.ech_grease, ExtensionType::ENCRYPTED_CLIENT_HELLO),
+            (
+                self.record_size_limit.is_some(),
+                ExtensionType::RECORD_SIZE_LIMIT,
+            ),
+            (
+                !self.delegated_credentials.is_empty(),
+                ExtensionType::DELEGATED_CREDENTIAL,
+            ),
+        ];
+        extensions.extend(
+            optional
+                .into_iter()
+                .filter(|(sent, _)| *sent)
+                .map(|(_, extension)| extension),
+        );
+        extensions
+    }
+
+    /// Checks that the profile is consistent and can be produced by BoringSSL.
+    pub fn validate(&self) -> Result<(), ErrorStack> {
+        if self.min_version.0 < SslVersion::TLS1.0 || self.min_version.0 > self.max_version.0 {
+            return Err(invalid("invalid protocol version range"));
+        }
+        if self.ciphers.is_empty() || self.groups.is_empty() {
+            return Err(invalid("profile must have ciphers and groups"));
+        }
+        if self.max_version.0 >= SslVersion::TLS1_2.0 && self.signature_algorithms.is_empty() {
+            return Err(invalid("profile must have signature algorithms"));
+        }
+        if has_duplicates(&self.ciphers)
+            || has_duplicates(&self.groups)
+            || has_duplicates(&self.signature_algorithms)
+            || has_duplicates(&self.alpn)
+            || has_duplicates(&self.cert_compression)
+        {
+            return Err(invalid("profile has duplicate entries"));
+        }
+        if self.key_shares.len() > 3 || !self.groups.starts_with(&self.key_shares) {
+            return Err(invalid("key shares must be the first one to three groups"));
+        }
+
+        if self
+            .alpn
+            .iter()
+            .any(|protocol| protocol.is_empty() || protocol.len() > 255)
+        {
+            return Err(invalid("ALPN protocols must be 1 to 255 bytes long"));
+        }
+        if !self
+            .alps
+            .iter()
+            .all(|protocol| self.alpn.contains(protocol))
+        {
+            return Err(invalid("ALPS protocols must also be ALPN protocols"));
+        }
+        if !self.alps.is_empty() && self.max_version.0 < SslVersion::TLS1_3.0 {
+            return Err(invalid("ALPS requires TLS 1.3"));
+        }
+        #[cfg(feature = "fips")]
+        if self.ech_grease {
+            return Err(invalid("ECH GREASE is not available with FIPS"));
+        }
+
+        if let ExtensionOrder::Fixed(order) = &self.extension_order {
+            if has_duplicates(order) {
+                return Err(invalid("extension order has duplicate entries"));
+            }
+            if order
+                .iter()
+                .position(|&extension| extension == ExtensionType::PADDING)
+                .is_some_and(|i| i != order.len() - 1)
+            {
+                return Err(invalid("padding can only be the last extension"));
+            }
+            if !self
+                .extensions()
+                .iter()
+                .all(|extension| order.contains(extension))
+            {
+                return Err(invalid("extension order misses a sent extension"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the per-connection settings of the profile.
+    pub(crate) fn configure(&self, ssl: &mut SslRef) -> Result<(), ErrorStack> {
+        unsafe {
+            for protocol in &self.alps {
+                cvt(ffi::SSL_add_application_settings(
+                    ssl.as_ptr(),
+                    protocol.as_ptr(),
+                    protocol.len(),
+                    std::ptr::null(),
+                    0,
+                ))?;
+            }
+            ffi::SSL_set_alps_use_new_codepoint(ssl.as_ptr(), self.alps_new_codepoint as _);
+            #[cfg(not(feature = "fips"))]
+            ffi::SSL_set_enable_ech_grease(ssl.as_ptr(), self.ech_grease as _);
+        }
+        Ok(())
+    }
+}
+
+fn has_duplicates<T: PartialEq>(values: &[T]) -> bool {
+    (1..values.len()).any(|i| values[..i].contains(&values[i]))
+}
+
+impl SslConnectorBuilder {
+    /// Configures the connector to send ClientHellos as described by `profile`.
+    ///
+    /// The profile is validated first. Settings which are not part of the profile, such as
+    /// certificate verification, are left untouched. The certificate compressors of the profile
+    /// must already be registered.
+    pub fn set_client_hello_profile(
+        &mut self,
+        profile: &ClientHelloProfile,
+    ) -> Result<(), ErrorStack> {
+        profile.validate()?;
+        if self.certificate_compression_algorithms() != profile.cert_compression {
+            return Err(invalid(
+                "registered certificate compressors do not match the profile",
+            ));
+        }
+
+        self.set_min_proto_version(Some(profile.min_version))?;
+        self.set_max_proto_version(Some(profile.max_version))?;
+        // This must come before the cipher list is set.
+        #[cfg(not(feature = "fips"))]
+        self.set_preserve_tls13_cipher_list(true);
+        self.set_cipher_list(&profile.ciphers.join(":"))?;
+        self.set_curves_list(&profile.groups.join(":"))?;
+        // Without key shares, BoringSSL picks them itself.
+        if !profile.key_shares.is_empty() {
+            self.set_key_shares_limit(profile.key_shares.len() as u8);
+        }
+        self.set_verify_algorithm_prefs(&profile.signature_algorithms)?;
+        self.set_grease_enabled(profile.grease);
+        match &profile.extension_order {
+            ExtensionOrder::Default => self.set_permute_extensions(false),
+            ExtensionOrder::Permuted => self.set_permute_extensions(true),
+            ExtensionOrder::Fixed(order) => {
+                self.set_permute_extensions(false);
+                self.set_extension_permutation(order)?;
+            }
+        }
+
+        let mut alpn = vec![];
+        for protocol in &profile.alpn {
+            alpn.push(protocol.len() as u8);
+            alpn.extend_from_slice(protocol.as_bytes());
+        }
+        self.set_alpn_protos(&alpn)?;
+        self.set_record_size_limit(profile.record_size_limit.unwrap_or(0));
+        if !profile.delegated_credentials.is_empty() {
+            self.set_delegated_credentials(&profile.delegated_credentials.join(":"))?;
+        }
+
+        self.replace_ex_data(*CLIENT_HELLO_PROFILE_INDEX, profile.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+//! Certificates and trust stores which can be replaced while a context is in use.
+use foreign_types::ForeignTypeRef;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use crate::asn1::Asn1Time;
+use crate::cvt;
+use crate::error::ErrorStack;
+use crate::ffi;
+use crate::pkey::{PKey, Private};
+use crate::ssl::verify::{peer_chain, verification_alert};
+use crate::ssl::{SelectCertError, SslContextBuilder, SslRef, SslVerifyError, SslVerifyMode};
+use crate::x509::chain::signed_by;
+use crate::x509::store::{LoadReport, X509Store, X509StoreBuilder};
+use crate::x509::X509;
+
+/// A certificate with its chain and private key, checked to be consistent.
+pub(crate) struct Identity {
+    pub(crate) cert: X509,
+    pub(crate) chain: Vec<X509>,
+    pub(crate) key: PKey<Private>,
+}
+
+impl Identity {
+    pub(crate) fn new(
+        cert: X509,
+        chain: Vec<X509>,
+        key: PKey<Private>,
+    ) -> Result<Identity, ErrorStack> {
+        if !cert.public_key()?.public_eq(&key) {
+            return Err(ErrorStack::internal_error_str(
+                "private key does not match the certificate",
+            ));
+        }
+        if cert.not_after() < Asn1Time::days_from_now(0)? {
+            return Err(ErrorStack::internal_error_str("certificate has expired"));
+        }
+        let mut child = &cert;
+        for issuer in &chain {
+            if issuer.issued(child).is_err() || !signed_by(child, issuer) {
+                return Err(ErrorStack::internal_error_str(
+                    "certificate chain is not in order",
+                ));
+            }
+            child = issuer;
+        }
+        Ok(Identity { cert, chain, key })
+    }
+
+    fn from_pem(cert_chain: &[u8], key: &[u8]) -> Result<Identity, ErrorStack> {
+        let mut certs = X509::stack_from_pem(cert_chain)?.into_iter();
+        let cert = certs
+            .next()
+            .ok_or_else(|| ErrorStack::internal_error_str("no certificate found"))?;
+        let key = PKey::private_key_from_pem(key)?;
+        Identity::new(cert, certs.collect(), key)
+    }
+
+    fn from_files(cert_chain: &Path, key: &Path) -> Result<Identity, ErrorStack> {
+        let cert_chain = fs::read(cert_chain).map_err(ErrorStack::internal_error)?;
+        let key = fs::read(key).map_err(ErrorStack::internal_error)?;
+        Identity::from_pem(&cert_chain, &key)
+    }
+
+    pub(crate) fn apply(&self, ssl: &mut SslRef) -> Result<(), ErrorStack> {
+        ssl.set_certificate(&self.cert)?;
+        unsafe { cvt(ffi::SSL_clear_chain_certs(ssl.as_ptr()))? };
+        for cert in &self.chain {
+            ssl.add_chain_cert(cert)?;
+        }
+        ssl.set_private_key(&self.key)
+    }
+}
+
+/// A server certificate which can be replaced while connections are being accepted.
+///
+/// Install it with [`SslContextBuilder::set_reloadable_identity`]. Clones share the same
+/// certificate. A reload only affects the connections accepted after it, and is rejected if the
+/// private key does not match the certificate, the certificate has expired or the chain is not
+/// in order.
+#[derive(Clone)]
+pub struct ReloadableIdentity(Arc<RwLock<Arc<Identity>>>);
+
+impl ReloadableIdentity {
+    /// Creates an identity from a certificate, the intermediates to send with it, in order, and
+    /// its private key.
+    pub fn new(
+        cert: X509,
+        chain: Vec<X509>,
+        key: PKey<Private>,
+    ) -> Result<ReloadableIdentity, ErrorStack> {
+        let identity = Identity::new(cert, chain, key)?;
+        Ok(ReloadableIdentity(Arc::new(RwLock::new(Arc::new(
+            identity,
+        )))))
+    }
+
+    /// Creates an identity from a PEM certificate chain, starting with the leaf, and a PEM
+    /// private key.
+    pub fn from_pem(cert_chain: &[u8], key: &[u8]) -> Result<ReloadableIdentity, ErrorStack> {
+        let identity = Identity::from_pem(cert_chain, key)?;
+        Ok(ReloadableIdentity(Arc::new(RwLock::new(Arc::new(
+            identity,
+        )))))
+    }
+
+    /// Creates an identity from the files holding the PEM certificate chain and private key.
+    pub fn from_files<P, Q>(cert_chain: P, key: Q) -> Result<ReloadableIdentity, ErrorStack>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let identity = Identity::from_files(cert_chain.as_ref(), key.as_ref())?;
+        Ok(ReloadableIdentity(Arc::new(RwLock::new(Arc::new(
+            identity,
+        )))))
+    }
+
+    /// Replaces the identity, see [`Self::new`].
+    pub fn reload(
+        &self,
+        cert: X509,
+        chain: Vec<X509>,
+        key: PKey<Private>,
+    ) -> Result<(), ErrorStack> {
+        self.swap(Identity::new(cert, chain, key)?);
+        Ok(())
+    }
+
+    /// Replaces the identity, see [`Self::from_pem`].
+    pub fn reload_pem(&self, cert_chain: &[u8], key: &[u8]) -> Result<(), ErrorStack> {
+        self.swap(Identity::from_pem(cert_chain, key)?);
+        Ok(())
+    }
+
+    /// Replaces the identity, see [`Self::from_files`].
+    pub fn reload_files<P, Q>(&self, cert_chain: P, key: Q) -> Result<(), ErrorStack>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        self.swap(Identity::from_files(cert_chain.as_ref(), key.as_ref())?);
+        Ok(())
+    }
+
+    /// Returns the current certificate.
+    #[must_use]
+    pub fn certificate(&self) -> X509 {
+        self.current().cert.clone()
+    }
+
+    /// Returns the intermediates currently sent with the certificate.
+    #[must_use]
+    pub fn chain(&self) -> Vec<X509> {
+        self.current().chain.clone()
+    }
+
+    fn current(&self) -> Arc<Identity> {
+        self.0.read().unwrap().clone()
+    }
+
+    fn swap(&self, identity: Identity) {
+        *self.0.write().unwrap() = Arc::new(identity);
+    }
+}
+
+/// A trust store which can be replaced while connections are being made.
+///
+/// Install it with [`SslContextBuilder::set_reloadable_trust`]. Clones share the same store. A
+/// reload only affects the certificates verified after it, and is rejected if the new store is
+/// empty or, when loading PEM, if any certificate fails to parse.
+#[derive(Clone)]
+pub struct ReloadableTrust(Arc<RwLock<Arc<X509Store>>>);
+
+impl ReloadableTrust {
+    /// Creates a trust store from `store`.
+    pub fn new(store: X509Store) -> Result<ReloadableTrust, ErrorStack> {
+        let store = checked_store(store)?;
+        Ok(ReloadableTrust(Arc::new(RwLock::new(Arc::new(store)))))
+    }
+
+    /// Creates a trust store from a PEM bundle.
+    pub fn from_pem_bundle(pem: &[u8]) -> Result<ReloadableTrust, ErrorStack> {
+        ReloadableTrust::new(store_from_pem(pem)?)
+    }
+
+    /// Creates a trust store from a file holding a PEM bundle.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ReloadableTrust, ErrorStack> {
+        ReloadableTrust::new(store_from_file(path.as_ref())?)
+    }
+
+    /// Creates a trust store from the certificates in a directory, see
+    /// [`X509StoreBuilderRef::add_directory`].
+    ///
+    /// [`X509StoreBuilderRef::add_directory`]: crate::x509::store::X509StoreBuilderRef::add_directory
+    pub fn from_directory<P: AsRef<Path>>(dir: P) -> Result<ReloadableTrust, ErrorStack> {
+        ReloadableTrust::new(store_from_directory(dir.as_ref())?)
+    }
+
+    /// Replaces the trust store, see [`Self::new`].
+    pub fn reload(&self, store: X509Store) -> Result<(), ErrorStack> {
+        let store = checked_store(store)?;
+        *self.0.write().unwrap() = Arc::new(store);
+        Ok(())
+    }
+
+    /// Replaces the trust store, see [`Self::from_pem_bundle`].
+    pub fn reload_pem_bundle(&self, pem: &[u8]) -> Result<(), ErrorStack> {
+        self.reload(store_from_pem(pem)?)
+    }
+
+    /// Replaces the trust store, see [`Self::from_file`].
+    pub fn reload_file<P: AsRef<Path>>(&self, path: P) -> Result<(), ErrorStack> {
+        self.reload(store_from_file(path.as_ref())?)
+    }
+
+    /// Replaces the trust store, see [`Self::from_directory`].
+    pub fn reload_directory<P: AsRef<Path>>(&self, dir: P) -> Result<(), ErrorStack> {
+        self.reload(store_from_directory(dir.as_ref())?)
+    }
+
+    /// Returns the current trust store.
+    #[must_use]
+    pub fn store(&self) -> Arc<X509Store> {
+        self.0.read().unwrap().clone()
+    }
+}
+
+fn checked_store(store: X509Store) -> Result<X509Store, ErrorStack> {
+    if store.objects_len() == 0 {
+        return Err(ErrorStack::internal_error_str("trust store is empty"));
+    }
+    Ok(store)
+}
+
+fn store_from_pem(pem: &[u8]) -> Result<X509Store, ErrorStack> {
+    let mut builder = X509StoreBuilder::new()?;
+    let report = builder.add_pem_bundle(pem)?;
+    check_report(&report)?;
+    Ok(builder.build())
+}
+
+fn store_from_file(path: &Path) -> Result<X509Store, ErrorStack> {
+    let pem = fs::read(path).map_err(ErrorStack::internal_error)?;
+    store_from_pem(&pem)
+}
+
+fn store_from_directory(dir: &Path) -> Result<X509Store, ErrorStack> {
+    let mut builder = X509StoreBuilder::new()?;
+    let report = builder.add_directory(dir)?;
+    check_report(&report)?;
+    Ok(builder.build())
+}
+
+fn check_report(report: &LoadReport) -> Result<(), ErrorStack> {
+    match report.failures().first() {
+        Some(failure) => Err(ErrorStack::internal_error(failure.error())),
+        None => Ok(()),
+    }
+}
+
+impl SslContextBuilder {
+    /// Serves the certificate of `identity`, picking up reloads for new connections.
+    ///
+    /// This installs a select certificate callback, replacing any set before.
+    pub fn set_reloadable_identity(&mut self, identity: ReloadableIdentity) {
+        self.set_select_certificate_callback(move |mut client_hello| {
+            identity
+                .current()
+                .apply(client_hello.ssl_mut())
+                .map_err(|_| SelectCertError::ERROR)
+        });
+    }
+
+    /// Verifies peer certificates against `trust`, picking up reloads for new connections.
+    ///
+    /// This installs a custom verify callback with `mode`, replacing any verification set
+    /// before. The connection's verification parameters, such as the expected host name, are
+    /// honoured.
+    pub fn set_reloadable_trust(&mut self, mode: SslVerifyMode, trust: ReloadableTrust) {
+        self.set_custom_verify_callback(mode, move |ssl| {
+            let (leaf, intermediates, options) =
+                peer_chain(ssl).map_err(SslVerifyError::Invalid)?;
+            let result = trust.store().verify_chain(&leaf, &intermediates, &options);
+            verification_alert(ssl, result).map_err(SslVerifyError::Invalid)
+        });
+    }
+}
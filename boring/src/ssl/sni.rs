@@ -0,0 +1,197 @@
+//! Selection of the server certificate from the name requested by the client.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::error::ErrorStack;
+use crate::pkey::{PKey, Private};
+use crate::ssl::key_kind::KeyKind;
+use crate::ssl::reload::Identity;
+use crate::ssl::{
+    AsyncSelectCertError, BoxSelectCertFinish, ClientHello, NameType, SelectCertError, SniError,
+    SslContextBuilder, SslSignatureAlgorithm,
+};
+use crate::x509::X509;
+
+/// What [`SniResolver`] does when the client asks for a name it has no certificate for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum UnknownNamePolicy {
+    /// Serves the default certificate, failing if there is none.
+    #[default]
+    UseDefault,
+    /// Fails the handshake.
+    Reject,
+}
+
+/// The certificates served for one name, at most one per kind of key.
+#[derive(Default)]
+struct Certificates(Vec<(KeyKind, Arc<Identity>)>);
+
+impl Certificates {
+    fn insert(&mut self, identity: Identity) {
+        let kind = KeyKind::of(&identity.key);
+        let identity = Arc::new(identity);
+        match self.0.iter_mut().find(|(k, _)| *k == kind) {
+            Some(entry) => entry.1 = identity,
+            None => self.0.push((kind, identity)),
+        }
+    }
+
+    /// Returns the certificate for the first signature algorithm of the client we can use,
+    /// or the first certificate added if there is none.
+    fn choose(&self, sigalgs: &[SslSignatureAlgorithm]) -> Option<&Identity> {
+        sigalgs
+            .iter()
+            .find_map(|&sigalg| self.0.iter().find(|(kind, _)| kind.supports(sigalg)))
+            .or_else(|| self.0.first())
+            .map(|(_, identity)| &**identity)
+    }
+}
+
+/// A builder for [`SniResolver`]s.
+#[derive(Default)]
+pub struct SniResolverBuilder {
+    exact: HashMap<String, Certificates>,
+    wildcard: HashMap<String, Certificates>,
+    default: Certificates,
+    policy: UnknownNamePolicy,
+}
+
+impl SniResolverBuilder {
+    /// Serves `cert` for `hostname`, along with its `chain` of intermediates and its private key.
+    ///
+    /// A hostname of the form `*.example.com` matches every name with exactly one more label,
+    /// such as `www.example.com`, unless that name was added itself. A name can be given one
+    /// RSA, one ECDSA per curve, and one Ed25519 certificate: the one served depends on the
+    /// signature algorithms supported by the client. Adding another certificate with the same
+    /// kind of key replaces the previous one.
+    ///
+    /// The certificate is checked as described in [`ReloadableIdentity::new`].
+    ///
+    /// [`ReloadableIdentity::new`]: crate::ssl::ReloadableIdentity::new
+    pub fn add_certificate(
+        &mut self,
+        hostname: &str,
+        cert: X509,
+        chain: Vec<X509>,
+        key: PKey<Private>,
+    ) -> Result<(), ErrorStack> {
+        let identity = Identity::new(cert, chain, key)?;
+        let hostname = normalize(hostname);
+        let (map, name) = match hostname.strip_prefix("*.") {
+            Some(suffix) => (&mut self.wildcard, suffix.to_string()),
+            None => (&mut self.exact, hostname),
+        };
+        if name.is_empty() || name.contains('*') {
+            return Err(ErrorStack::internal_error_str("invalid hostname pattern"));
+        }
+        map.entry(name).or_default().insert(identity);
+        Ok(())
+    }
+
+    /// Serves `cert` to clients which do not send a name, and to those asking for an unknown
+    /// name under [`UnknownNamePolicy::UseDefault`].
+    ///
+    /// See [`Self::add_certificate`].
+    pub fn add_default_certificate(
+        &mut self,
+        cert: X509,
+        chain: Vec<X509>,
+        key: PKey<Private>,
+    ) -> Result<(), ErrorStack> {
+        self.default.insert(Identity::new(cert, chain, key)?);
+        Ok(())
+    }
+
+    /// Sets what happens when the client asks for an unknown name.
+    ///
+    /// Defaults to [`UnknownNamePolicy::UseDefault`].
+    pub fn set_unknown_name_policy(&mut self, policy: UnknownNamePolicy) {
+        self.policy = policy;
+    }
+
+    /// Consumes the builder, returning a new `SniResolver`.
+    #[must_use]
+    pub fn build(self) -> SniResolver {
+        SniResolver(Arc::new(self))
+    }
+}
+
+/// Picks the server certificate according to the server name indication of the client.
+///
+/// Install it with [`SslContextBuilder::set_sni_resolver`], or call [`Self::select`] from a
+/// select certificate callback. Clones share the same certificates.
+#[derive(Clone)]
+pub struct SniResolver(Arc<SniResolverBuilder>);
+
+impl SniResolver {
+    /// Creates a new builder.
+    #[must_use]
+    pub fn builder() -> SniResolverBuilder {
+        SniResolverBuilder::default()
+    }
+
+    /// Configures the connection with the certificate matching the `client_hello`.
+    ///
+    /// Returns [`SniError::ALERT_FATAL`] if no certificate can be served.
+    pub fn select(&self, client_hello: &mut ClientHello<'_>) -> Result<(), SniError> {
+        let certificates = match client_hello.servername(NameType::HOST_NAME) {
+            Some(name) => self.find(&normalize(name)),
+            None => Some(&self.0.default),
+        };
+        let identity = certificates
+            .and_then(|certificates| {
+                certificates.choose(&client_hello.signature_algorithms().unwrap_or_default())
+            })
+            .ok_or(SniError::ALERT_FATAL)?;
+        identity
+            .apply(client_hello.ssl_mut())
+            .map_err(|_| SniError::ALERT_FATAL)
+    }
+
+    /// Returns a callback selecting the certificate, to be returned from the future of
+    /// [`SslContextBuilder::set_async_select_certificate_callback`].
+    #[must_use]
+    pub fn select_finish(&self) -> BoxSelectCertFinish {
+        let resolver = self.clone();
+        Box::new(move |mut client_hello: ClientHello<'_>| {
+            resolver
+                .select(&mut client_hello)
+                .map_err(|_| AsyncSelectCertError)
+        })
+    }
+
+    fn find(&self, name: &str) -> Option<&Certificates> {
+        let resolver = &self.0;
+        if let Some(certificates) = resolver.exact.get(name) {
+            return Some(certificates);
+        }
+        let wildcard = name
+            .split_once('.')
+            .and_then(|(_, parent)| resolver.wildcard.get(parent));
+        match (wildcard, resolver.policy) {
+            (Some(certificates), _) => Some(certificates),
+            (None, UnknownNamePolicy::UseDefault) => Some(&resolver.default),
+            (None, UnknownNamePolicy::Reject) => None,
+        }
+    }
+}
+
+fn normalize(hostname: &str) -> String {
+    hostname.trim_end_matches('.').to_ascii_lowercase()
+}
+
+impl SslContextBuilder {
+    /// Serves certificates picked by `resolver` from the name requested by the client.
+    ///
+    /// This installs a select certificate callback, replacing any set before. Use
+    /// [`SniResolver::select_finish`] to combine the resolver with
+    /// [`SslContextBuilder::set_async_select_certificate_callback`].
+    pub fn set_sni_resolver(&mut self, resolver: SniResolver) {
+        self.set_select_certificate_callback(move |mut client_hello| {
+            resolver
+                .select(&mut client_hello)
+                .map_err(|_| SelectCertError::ERROR)
+        });
+    }
+}
@@ -0,0 +1,271 @@
+//! Mutual TLS between workloads identified by [SPIFFE] IDs.
+//!
+//! Workloads present X.509-SVIDs: certificates whose only URI subject alternative name is a
+//! `spiffe://` ID, issued by the authorities of a trust domain. A [`SpiffeVerifier`] checks the
+//! peer SVID against the bundle of its trust domain instead of verifying a hostname, then
+//! exposes the ID through [`SslRef::peer_spiffe_id`].
+//!
+//! [SPIFFE]: https://github.com/spiffe/spiffe/blob/main/standards/X509-SVID.md
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, LazyLock};
+
+use foreign_types::ForeignTypeRef;
+
+use crate::error::ErrorStack;
+use crate::ex_data::Index;
+use crate::ffi;
+use crate::ssl::verify::{peer_chain, verification_alert};
+use crate::ssl::{
+    Ssl, SslAcceptorBuilder, SslAlert, SslConnectorBuilder, SslContextBuilder, SslRef,
+    SslVerifyError, SslVerifyMode,
+};
+use crate::x509::store::X509Store;
+use crate::x509::verify::{VerifyOptions, X509Purpose};
+use crate::x509::X509Ref;
+
+static SPIFFE_ID_INDEX: LazyLock<Index<Ssl, SpiffeId>> =
+    LazyLock::new(|| Ssl::new_ex_index().unwrap());
+
+const MAX_ID_LEN: usize = 2048;
+
+/// A SPIFFE ID, such as `spiffe://example.org/ns/default/sa/web`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpiffeId {
+    trust_domain: String,
+    path: String,
+}
+
+impl SpiffeId {
+    /// Parses a SPIFFE ID.
+    ///
+    /// The trust domain may only contain lowercase letters, digits, `.`, `-` and `_`. Path
+    /// segments may also contain uppercase letters, and must not be empty, `.` or `..`.
+    pub fn parse(id: &str) -> Result<SpiffeId, ErrorStack> {
+        if id.len() > MAX_ID_LEN {
+            return Err(ErrorStack::internal_error_str("SPIFFE ID is too long"));
+        }
+        let rest = id
+            .strip_prefix("spiffe://")
+            .ok_or_else(|| ErrorStack::internal_error_str("SPIFFE ID has the wrong scheme"))?;
+        let (trust_domain, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+        validate_trust_domain(trust_domain)?;
+        if !path.is_empty() {
+            for segment in path[1..].split('/') {
+                let valid = !matches!(segment, "" | "." | "..")
+                    && segment
+                        .bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b"-._".contains(&b));
+                if !valid {
+                    return Err(ErrorStack::internal_error_str(
+                        "SPIFFE ID has an invalid path",
+                    ));
+                }
+            }
+        }
+        Ok(SpiffeId {
+            trust_domain: trust_domain.to_string(),
+            path: path.to_string(),
+        })
+    }
+
+    /// Returns the trust domain, such as `example.org`.
+    #[must_use]
+    pub fn trust_domain(&self) -> &str {
+        &self.trust_domain
+    }
+
+    /// Returns the path, such as `/ns/default/sa/web`, which is empty for the ID of a trust
+    /// domain.
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the SPIFFE ID of an X.509-SVID.
+    ///
+    /// The certificate must have exactly one URI subject alternative name, holding a valid ID
+    /// with a path. It must not be a CA certificate, and its key usage must allow digital
+    /// signatures but not certificate or CRL signing.
+    pub fn from_svid(cert: &X509Ref) -> Result<SpiffeId, ErrorStack> {
+        let sans = cert.subject_alt_names();
+        let mut uris = sans.iter().flatten().filter_map(|name| name.uri());
+        let (Some(uri), None) = (uris.next(), uris.next()) else {
+            return Err(ErrorStack::internal_error_str(
+                "SVID must have exactly one URI SAN",
+            ));
+        };
+        let id = SpiffeId::parse(uri)?;
+        if id.path.is_empty() {
+            return Err(ErrorStack::internal_error_str("SVID ID must have a path"));
+        }
+
+        let (flags, key_usage) = unsafe {
+            (
+                ffi::X509_get_extension_flags(cert.as_ptr()),
+                ffi::X509_get_key_usage(cert.as_ptr()),
+            )
+        };
+        if flags & ffi::EXFLAG_CA as _ != 0 {
+            return Err(ErrorStack::internal_error_str("SVID must not be a CA"));
+        }
+        if flags & ffi::EXFLAG_KUSAGE as _ == 0
+            || key_usage & ffi::KU_DIGITAL_SIGNATURE as _ == 0
+            || key_usage & (ffi::KU_KEY_CERT_SIGN | ffi::KU_CRL_SIGN) as _ != 0
+        {
+            return Err(ErrorStack::internal_error_str(
+                "SVID has an invalid key usage",
+            ));
+        }
+        Ok(id)
+    }
+}
+
+impl fmt::Display for SpiffeId {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "spiffe://{}{}", self.trust_domain, self.path)
+    }
+}
+
+fn validate_trust_domain(trust_domain: &str) -> Result<(), ErrorStack> {
+    let valid = !trust_domain.is_empty()
+        && trust_domain
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b"-._".contains(&b));
+    if !valid {
+        return Err(ErrorStack::internal_error_str(
+            "SPIFFE ID has an invalid trust domain",
+        ));
+    }
+    Ok(())
+}
+
+type Authorizer = dyn Fn(&SpiffeId) -> bool + Send + Sync;
+
+/// A builder for [`SpiffeVerifier`]s.
+#[derive(Default)]
+pub struct SpiffeVerifierBuilder {
+    bundles: HashMap<String, X509Store>,
+    authorizer: Option<Box<Authorizer>>,
+}
+
+impl SpiffeVerifierBuilder {
+    /// Trusts the authorities in `bundle` to issue SVIDs for `trust_domain`, replacing any
+    /// bundle set before for it.
+    pub fn add_bundle(&mut self, trust_domain: &str, bundle: X509Store) -> Result<(), ErrorStack> {
+        validate_trust_domain(trust_domain)?;
+        self.bundles.insert(trust_domain.to_string(), bundle);
+        Ok(())
+    }
+
+    /// Sets a callback deciding whether the authenticated peer may connect.
+    ///
+    /// Handshakes with peers for which it returns `false` fail with an `access_denied` alert.
+    /// By default, every peer with a valid SVID from a known trust domain is accepted.
+    pub fn set_authorizer<F>(&mut self, authorizer: F)
+    where
+        F: Fn(&SpiffeId) -> bool + Send + Sync + 'static,
+    {
+        self.authorizer = Some(Box::new(authorizer));
+    }
+
+    /// Consumes the builder, returning a new `SpiffeVerifier`.
+    #[must_use]
+    pub fn build(self) -> SpiffeVerifier {
+        SpiffeVerifier(Arc::new(self))
+    }
+}
+
+/// Verifies peer X.509-SVIDs against the bundles of their trust domains.
+///
+/// Install it with [`SslAcceptorBuilder::set_spiffe_verifier`] or
+/// [`SslConnectorBuilder::set_spiffe_verifier`]. Clones share the same bundles.
+#[derive(Clone)]
+pub struct SpiffeVerifier(Arc<SpiffeVerifierBuilder>);
+
+impl SpiffeVerifier {
+    /// Creates a new builder.
+    #[must_use]
+    pub fn builder() -> SpiffeVerifierBuilder {
+        SpiffeVerifierBuilder::default()
+    }
+
+    fn verify(&self, ssl: &mut SslRef) -> Result<(), SslAlert> {
+        let (leaf, intermediates, _) = peer_chain(ssl)?;
+        let id = SpiffeId::from_svid(&leaf).map_err(|_| SslAlert::BAD_CERTIFICATE)?;
+        let bundle = self
+            .0
+            .bundles
+            .get(id.trust_domain())
+            .ok_or(SslAlert::UNKNOWN_CA)?;
+
+        // SVIDs are not bound to a hostname, so the connection's verification parameters are
+        // not used.
+        let mut options = VerifyOptions::new();
+        options.set_purpose(if ssl.is_server() {
+            X509Purpose::SSL_CLIENT
+        } else {
+            X509Purpose::SSL_SERVER
+        });
+        verification_alert(ssl, bundle.verify_chain(&leaf, &intermediates, &options))?;
+
+        if let Some(authorizer) = &self.0.authorizer {
+            if !authorizer(&id) {
+                return Err(SslAlert::ACCESS_DENIED);
+            }
+        }
+        ssl.replace_ex_data(*SPIFFE_ID_INDEX, id);
+        Ok(())
+    }
+
+    fn install(self, ctx: &mut SslContextBuilder, mode: SslVerifyMode) {
+        ctx.set_custom_verify_callback(mode, move |ssl| {
+            self.verify(ssl).map_err(SslVerifyError::Invalid)
+        });
+    }
+}
+
+impl fmt::Debug for SpiffeVerifier {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("SpiffeVerifier")
+            .field("trust_domains", &self.0.bundles.keys())
+            .finish()
+    }
+}
+
+impl SslAcceptorBuilder {
+    /// Requires clients to present an X.509-SVID accepted by `verifier`.
+    ///
+    /// This replaces the certificate verification of the context.
+    pub fn set_spiffe_verifier(&mut self, verifier: SpiffeVerifier) {
+        verifier.install(
+            self,
+            SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT,
+        );
+    }
+}
+
+impl SslConnectorBuilder {
+    /// Requires servers to present an X.509-SVID accepted by `verifier`.
+    ///
+    /// This replaces the certificate verification of the context, including hostname
+    /// verification.
+    pub fn set_spiffe_verifier(&mut self, verifier: SpiffeVerifier) {
+        verifier.install(self, SslVerifyMode::PEER);
+    }
+}
+
+impl SslRef {
+    /// Returns the SPIFFE ID of the peer, as authenticated by a [`SpiffeVerifier`] during the
+    /// handshake of this connection.
+    ///
+    /// Returns `None` for resumed sessions, whose peer certificate is not verified again. Disable
+    /// session resumption if the ID is needed on every connection.
+    #[must_use]
+    pub fn peer_spiffe_id(&self) -> Option<SpiffeId> {
+        self.ex_data(*SPIFFE_ID_INDEX).cloned()
+    }
+}
@@ -0,0 +1,84 @@
+use std::io::{self, Read};
+use std::sync::Arc;
+
+use super::pki::{server, Chain};
+use crate::ssl::{SslConnector, SslMethod};
+use crate::x509::aia::{AiaFetcher, IssuerFetcher};
+use crate::x509::extension::AuthorityInfoAccess;
+use crate::x509::X509;
+
+const ISSUER_URL: &str = "http://ca.example.com/intermediate.der";
+
+pub(super) struct LocalFetcher(pub(super) Option<Vec<u8>>);
+
+impl IssuerFetcher for LocalFetcher {
+    fn fetch(&self, url: &str) -> io::Result<Vec<u8>> {
+        match &self.0 {
+            Some(body) if url == ISSUER_URL => Ok(body.clone()),
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, url.to_string())),
+        }
+    }
+}
+
+/// Returns a chain whose leaf points to the intermediate through `ISSUER_URL`.
+pub(super) fn pki() -> Chain {
+    Chain::with_leaf_extensions(|| {
+        vec![AuthorityInfoAccess::new()
+            .ca_issuers(ISSUER_URL)
+            .build()
+            .unwrap()]
+    })
+}
+
+fn connector(root: X509, fetched: Option<Vec<u8>>) -> SslConnector {
+    let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+    connector.cert_store_mut().add_cert(root).unwrap();
+    connector.set_issuer_fetcher(Arc::new(AiaFetcher::new(LocalFetcher(fetched))));
+    connector.build()
+}
+
+#[test]
+fn connector_fetches_missing_intermediate() {
+    let Chain {
+        root,
+        intermediate,
+        leaf,
+        key,
+    } = pki();
+    let server = server(&leaf, &key, false);
+
+    let connector = connector(root, Some(intermediate.to_der().unwrap()));
+    let mut s = connector
+        .connect("example.com", server.connect_tcp())
+        .unwrap();
+    s.read_exact(&mut [0]).unwrap();
+}
+
+#[test]
+fn connector_missing_intermediate_not_found() {
+    let Chain {
+        root, leaf, key, ..
+    } = pki();
+    let server = server(&leaf, &key, true);
+
+    let connector = connector(root, None);
+    connector
+        .connect("example.com", server.connect_tcp())
+        .unwrap_err();
+}
+
+#[test]
+fn connector_fetches_and_checks_hostname() {
+    let Chain {
+        root,
+        intermediate,
+        leaf,
+        key,
+    } = pki();
+    let server = server(&leaf, &key, true);
+
+    let connector = connector(root, Some(intermediate.to_der().unwrap()));
+    connector
+        .connect("bogus.com", server.connect_tcp())
+        .unwrap_err();
+}
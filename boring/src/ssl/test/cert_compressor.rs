@@ -1,7 +1,7 @@
 use std::io::Write as _;
 
 use super::server::Server;
-use crate::ssl::CertificateCompressor;
+use crate::ssl::{CertificateCompressionAlgorithm, CertificateCompressor};
 use crate::x509::store::X509StoreBuilder;
 use crate::x509::X509;
 
@@ -100,3 +100,103 @@ fn client_and_server_cert_compression() {
 
     client.connect();
 }
+
+#[cfg(any(
+    feature = "cert-compression-brotli",
+    feature = "cert-compression-zlib",
+    feature = "cert-compression-zstd"
+))]
+fn check_compressor<C>(compressor: C, limited: C)
+where
+    C: CertificateCompressor + Clone,
+{
+    let chain = [super::CERT, super::ROOT_CERT].concat();
+    let mut compressed = vec![];
+    compressor.compress(&chain, &mut compressed).unwrap();
+    assert!(compressed.len() < chain.len());
+    let mut decompressed = vec![];
+    compressor
+        .decompress(&compressed, &mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, chain);
+
+    // A small message which decompresses past the limit.
+    let bomb = vec![0; 1 << 20];
+    let mut compressed = vec![];
+    compressor.compress(&bomb, &mut compressed).unwrap();
+    assert!(compressed.len() < 4096);
+    let mut decompressed = vec![];
+    compressor
+        .decompress(&compressed, &mut decompressed)
+        .unwrap_err();
+    assert!(decompressed.len() <= 100 * 1024 + 1);
+
+    let mut compressed = vec![];
+    compressor.compress(&chain, &mut compressed).unwrap();
+    limited.decompress(&compressed, &mut vec![]).unwrap_err();
+
+    let mut server = Server::builder();
+    server
+        .ctx()
+        .add_certificate_compression_algorithm(compressor.clone())
+        .unwrap();
+    let server = server.build();
+    let mut client = server.client();
+    client
+        .ctx()
+        .add_certificate_compression_algorithm(compressor)
+        .unwrap();
+    client.connect();
+}
+
+#[cfg(feature = "cert-compression-brotli")]
+#[test]
+fn brotli_certificate_compressor() {
+    use crate::ssl::BrotliCertificateCompressor;
+
+    let mut limited = BrotliCertificateCompressor::default();
+    limited.set_max_decompressed_len(100);
+    check_compressor(BrotliCertificateCompressor::default(), limited);
+}
+
+#[cfg(feature = "cert-compression-zlib")]
+#[test]
+fn zlib_certificate_compressor() {
+    use crate::ssl::ZlibCertificateCompressor;
+
+    let mut limited = ZlibCertificateCompressor::default();
+    limited.set_max_decompressed_len(100);
+    check_compressor(ZlibCertificateCompressor::default(), limited);
+}
+
+#[cfg(feature = "cert-compression-zstd")]
+#[test]
+fn zstd_certificate_compressor() {
+    use crate::ssl::ZstdCertificateCompressor;
+
+    let mut limited = ZstdCertificateCompressor::default();
+    limited.set_max_decompressed_len(100);
+    check_compressor(ZstdCertificateCompressor::default(), limited);
+}
+
+#[test]
+fn default_certificate_compression_algorithms() {
+    let server = Server::builder().build();
+    let mut client = server.client();
+    client
+        .ctx()
+        .add_default_certificate_compression_algorithms()
+        .unwrap();
+
+    let expected: &[CertificateCompressionAlgorithm] = &[
+        #[cfg(feature = "cert-compression-zlib")]
+        CertificateCompressionAlgorithm::ZLIB,
+        #[cfg(feature = "cert-compression-brotli")]
+        CertificateCompressionAlgorithm::BROTLI,
+        #[cfg(feature = "cert-compression-zstd")]
+        CertificateCompressionAlgorithm::ZSTD,
+    ];
+    assert_eq!(client.ctx().certificate_compression_algorithms(), expected);
+
+    client.connect();
+}
@@ -0,0 +1,239 @@
+use std::sync::{Arc, Mutex};
+
+use super::profile::Decompressor;
+use super::server::Server;
+use crate::ffi;
+use crate::ssl::{
+    CertificateCompressionAlgorithm, ClientHello, ExtensionType, NameType, SslSignatureAlgorithm,
+    SslVersion,
+};
+
+/// What the server saw in a ClientHello.
+#[derive(Debug, Default)]
+struct Seen {
+    extensions: Vec<ExtensionType>,
+    server_name: Option<String>,
+    alpn: Option<Vec<Vec<u8>>>,
+    groups: Option<Vec<u16>>,
+    key_shares: Option<Vec<u16>>,
+    signature_algorithms: Option<Vec<SslSignatureAlgorithm>>,
+    versions: Option<Vec<SslVersion>>,
+    psk_modes: Option<Vec<u8>>,
+    cert_compression: Option<Vec<CertificateCompressionAlgorithm>>,
+    grease: bool,
+}
+
+fn without_grease(values: Vec<u16>) -> Vec<u16> {
+    values
+        .into_iter()
+        .filter(|&value| !ClientHello::is_grease(value))
+        .collect()
+}
+
+fn handshake(grease: bool) -> Seen {
+    let seen = Arc::new(Mutex::new(Seen::default()));
+    let mut server = Server::builder();
+    server.ctx().set_select_certificate_callback({
+        let seen = seen.clone();
+        move |hello| {
+            *seen.lock().unwrap() = Seen {
+                extensions: hello.extensions().iter().map(|&(ty, _)| ty).collect(),
+                server_name: hello.servername(NameType::HOST_NAME).map(str::to_string),
+                alpn: hello
+                    .alpn_protocols()
+                    .map(|protocols| protocols.iter().map(|p| p.to_vec()).collect()),
+                groups: hello.supported_groups(),
+                key_shares: hello.key_share_groups(),
+                signature_algorithms: hello.signature_algorithms(),
+                versions: hello.supported_versions(),
+                psk_modes: hello.psk_key_exchange_modes(),
+                cert_compression: hello.cert_compression_algorithms(),
+                grease: hello.has_grease(),
+            };
+            Ok(())
+        }
+    });
+    let server = server.build();
+
+    let mut client = server.client();
+    client.ctx().set_grease_enabled(grease);
+    client
+        .ctx()
+        .set_min_proto_version(Some(SslVersion::TLS1_2))
+        .unwrap();
+    client.ctx().set_alpn_protos(b"\x02h2\x08http/1.1").unwrap();
+    client.ctx().set_curves_list("X25519:P-256").unwrap();
+    client
+        .ctx()
+        .set_verify_algorithm_prefs(&[
+            SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256,
+            SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256,
+        ])
+        .unwrap();
+    client
+        .ctx()
+        .add_certificate_compression_algorithm(Decompressor::<2>)
+        .unwrap();
+    let mut client = client.build().builder();
+    client.ssl().set_hostname("foobar.com").unwrap();
+    client.connect();
+
+    std::mem::take(&mut *seen.lock().unwrap())
+}
+
+#[test]
+fn client_hello_accessors() {
+    let seen = handshake(false);
+
+    assert_eq!(seen.server_name.as_deref(), Some("foobar.com"));
+    assert_eq!(seen.alpn.unwrap(), [b"h2".to_vec(), b"http/1.1".to_vec()]);
+    assert_eq!(seen.groups.unwrap(), [0x001d, 0x0017]);
+    assert_eq!(seen.key_shares.unwrap(), [0x001d]);
+    assert_eq!(
+        seen.signature_algorithms.unwrap(),
+        [
+            SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256,
+            SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256,
+        ]
+    );
+    assert_eq!(
+        seen.versions.unwrap(),
+        [SslVersion::TLS1_3, SslVersion::TLS1_2]
+    );
+    assert_eq!(seen.psk_modes.unwrap(), [1]);
+    assert_eq!(
+        seen.cert_compression.unwrap(),
+        [CertificateCompressionAlgorithm::BROTLI]
+    );
+    assert!(!seen.grease);
+
+    assert!(seen.extensions.contains(&ExtensionType::SERVER_NAME));
+    assert!(seen.extensions.contains(&ExtensionType::KEY_SHARE));
+    assert!(!seen
+        .extensions
+        .contains(&ExtensionType::ENCRYPTED_CLIENT_HELLO));
+}
+
+#[test]
+fn client_hello_grease() {
+    let seen = handshake(true);
+
+    assert!(seen.grease);
+    // GREASE extensions come first and last.
+    assert!(ClientHello::is_grease(seen.extensions[0].0));
+    assert!(ClientHello::is_grease(seen.extensions.last().unwrap().0));
+    assert_eq!(without_grease(seen.groups.unwrap()), [0x001d, 0x0017]);
+    assert_eq!(without_grease(seen.key_shares.unwrap()), [0x001d]);
+    let versions: Vec<u16> = seen
+        .versions
+        .unwrap()
+        .into_iter()
+        .map(|version| version.0)
+        .collect();
+    assert_eq!(without_grease(versions), [0x0304, 0x0303]);
+}
+
+/// Calls `f` with a ClientHello with the given version, cipher suites and extensions.
+pub(super) fn with_client_hello(
+    version: u16,
+    ciphers: &[u16],
+    extensions: &[(u16, &[u8])],
+    f: impl FnOnce(ClientHello<'_>),
+) {
+    let ciphers: Vec<u8> = ciphers.iter().flat_map(|c| c.to_be_bytes()).collect();
+    let mut data = vec![];
+    for (ty, contents) in extensions {
+        data.extend_from_slice(&ty.to_be_bytes());
+        data.extend_from_slice(&(contents.len() as u16).to_be_bytes());
+        data.extend_from_slice(contents);
+    }
+    let mut hello: ffi::SSL_CLIENT_HELLO = unsafe { std::mem::zeroed() };
+    hello.version = version;
+    hello.cipher_suites = ciphers.as_ptr();
+    hello.cipher_suites_len = ciphers.len();
+    // Like BoringSSL, leave the pointer null if there are no extensions.
+    if !data.is_empty() {
+        hello.extensions = data.as_ptr();
+    }
+    hello.extensions_len = data.len();
+    f(ClientHello(&hello));
+}
+
+/// Calls `f` with a ClientHello with the given extensions.
+fn with_extensions(extensions: &[(u16, &[u8])], f: impl FnOnce(ClientHello<'_>)) {
+    with_client_hello(0x0303, &[0x1301, 0x3a3a], extensions, f);
+}
+
+#[test]
+fn client_hello_parse() {
+    with_extensions(
+        &[
+            (0x0000, b"\x00\x0e\x00\x00\x0bexample.com"),
+            (0x0010, b"\x00\x03\x02h2"),
+            (0x0033, b"\x00\x0a\x00\x1d\x00\x02\xaa\xbb\x00\x17\x00\x00"),
+            (0x002b, b"\x04\x03\x04\x03\x03"),
+            (0x002d, b"\x02\x00\x01"),
+            (0x001b, b"\x04\x00\x01\x00\x03"),
+            (0xfe0d, b"\x00"),
+        ],
+        |hello| {
+            assert_eq!(hello.extensions().len(), 7);
+            assert_eq!(
+                hello.extensions()[1],
+                (ExtensionType(0x0010), &b"\x00\x03\x02h2"[..])
+            );
+            assert_eq!(hello.alpn_protocols().unwrap(), [b"h2"]);
+            assert_eq!(hello.key_share_groups().unwrap(), [0x001d, 0x0017]);
+            assert_eq!(
+                hello.supported_versions().unwrap(),
+                [SslVersion::TLS1_3, SslVersion::TLS1_2]
+            );
+            assert_eq!(hello.psk_key_exchange_modes().unwrap(), [0, 1]);
+            assert_eq!(
+                hello.cert_compression_algorithms().unwrap(),
+                [
+                    CertificateCompressionAlgorithm::ZLIB,
+                    CertificateCompressionAlgorithm::ZSTD
+                ]
+            );
+            assert_eq!(hello.supported_groups(), None);
+            assert!(hello.has_ech());
+            // The cipher suites include 0x3a3a.
+            assert!(hello.has_grease());
+        },
+    );
+}
+
+#[test]
+fn client_hello_parse_malformed() {
+    with_extensions(
+        &[
+            // Empty protocol names are not allowed.
+            (0x0010, b"\x00\x01\x00"),
+            // The key share is truncated.
+            (0x0033, b"\x00\x06\x00\x1d\x00\x20\xaa\xbb"),
+            // Trailing data after the list.
+            (0x000a, b"\x00\x02\x00\x1d\x00"),
+            // Odd length.
+            (0x000d, b"\x00\x03\x04\x03\x08"),
+            (0x002b, b""),
+        ],
+        |hello| {
+            assert_eq!(hello.alpn_protocols(), None);
+            assert_eq!(hello.key_share_groups(), None);
+            assert_eq!(hello.supported_groups(), None);
+            assert_eq!(hello.signature_algorithms(), None);
+            assert_eq!(hello.supported_versions(), None);
+            assert!(!hello.has_ech());
+        },
+    );
+}
+
+#[test]
+fn client_hello_without_extensions() {
+    with_extensions(&[], |hello| {
+        assert!(hello.extensions().is_empty());
+        assert_eq!(hello.alpn_protocols(), None);
+        assert!(!hello.has_ech());
+    });
+}
@@ -0,0 +1,239 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::pki::{cert, ec_key, ed25519_key, root, rsa_key, self_signed};
+use super::private_key_method::Method;
+use super::server::Server;
+use super::sni::served_name;
+use crate::hash::MessageDigest;
+use crate::nid::Nid;
+use crate::pkey::{PKey, Private};
+use crate::sign::Signer;
+use crate::ssl::{SslCredential, SslSignatureAlgorithm, SslVersion};
+
+fn credential(cn: &str, key: PKey<Private>) -> SslCredential {
+    let mut builder = SslCredential::builder(self_signed(cn, &key), vec![]);
+    builder.set_private_key(key);
+    builder.build().unwrap()
+}
+
+#[test]
+fn credential_selected_by_peer_sigalgs() {
+    let mut server = Server::builder();
+    server.ctx().add_credential(credential("rsa", rsa_key()));
+    server.ctx().add_credential(credential("ecdsa", ec_key()));
+    server.io_cb(|s| {
+        let credential = s.ssl().selected_credential().unwrap();
+        let cert = s.ssl().certificate().unwrap();
+        assert_eq!(
+            credential.certificate().to_der().unwrap(),
+            cert.to_der().unwrap()
+        );
+    });
+    server.expected_connections_count(3);
+    let server = server.build();
+
+    let rsa = [SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256];
+    let ecdsa = [SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256];
+    let both = [
+        SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256,
+        SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256,
+    ];
+    assert_eq!(served_name(&server, None, &rsa), "rsa");
+    assert_eq!(served_name(&server, None, &ecdsa), "ecdsa");
+    // The peer's order of preference wins over the order the credentials were added in.
+    assert_eq!(served_name(&server, None, &both), "ecdsa");
+}
+
+#[test]
+fn credential_signing_algorithm_prefs() {
+    let key = rsa_key();
+    let mut builder = SslCredential::builder(self_signed("pkcs1", &key), vec![]);
+    builder.set_private_key(key);
+    builder.set_signing_algorithm_prefs(&[SslSignatureAlgorithm::RSA_PKCS1_SHA256]);
+    let pkcs1 = builder.build().unwrap();
+
+    let mut server = Server::builder();
+    server.ctx().add_credential(pkcs1);
+    server.ctx().add_credential(credential("ecdsa", ec_key()));
+    server.expected_connections_count(2);
+    let server = server.build();
+
+    let pss = [
+        SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256,
+        SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256,
+    ];
+    let pkcs1 = [
+        SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256,
+        SslSignatureAlgorithm::RSA_PKCS1_SHA256,
+    ];
+    assert_eq!(served_name(&server, None, &pss), "ecdsa");
+    assert_eq!(served_name(&server, None, &pkcs1), "pkcs1");
+}
+
+#[test]
+fn credential_falls_back_to_certificate() {
+    // The server certificate of the test server is an RSA certificate.
+    let mut server = Server::builder();
+    server.ctx().add_credential(credential("ecdsa", ec_key()));
+    server.io_cb(|s| assert!(s.ssl().selected_credential().is_none()));
+    let server = server.build();
+
+    let mut client = server.client();
+    client
+        .ctx()
+        .set_verify_algorithm_prefs(&[SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256])
+        .unwrap();
+    let s = client.connect();
+    let cert = s.ssl().peer_certificate().unwrap();
+    let cn = cert.subject_name().entries_by_nid(Nid::COMMONNAME).next();
+    assert_eq!(
+        cn.unwrap().data().as_utf8().unwrap().to_string(),
+        "foobar.com"
+    );
+}
+
+#[test]
+fn credential_per_connection() {
+    let mut server = Server::builder();
+    server
+        .ctx()
+        .add_credential(credential("context", rsa_key()));
+    let ecdsa = credential("connection", ec_key());
+    server.ssl_cb(move |ssl| ssl.add_credential(ecdsa.clone()));
+    server.expected_connections_count(2);
+    let server = server.build();
+
+    assert_eq!(
+        served_name(&server, None, &[SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256]),
+        "context"
+    );
+    assert_eq!(
+        served_name(
+            &server,
+            None,
+            &[SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256]
+        ),
+        "connection"
+    );
+}
+
+#[test]
+fn credential_legacy_version() {
+    let (root, root_key) = root();
+    let key = ed25519_key();
+    let mut builder = SslCredential::builder(
+        cert("ed25519", &key, Some((&root, &root_key)), |_| vec![]),
+        vec![],
+    );
+    builder.set_private_key(key);
+
+    let mut server = Server::builder();
+    server
+        .ctx()
+        .set_min_proto_version(Some(SslVersion::TLS1_1))
+        .unwrap();
+    server.ctx().add_credential(builder.build().unwrap());
+    server.ctx().add_credential(credential("rsa", rsa_key()));
+    server.expected_connections_count(2);
+    let server = server.build();
+
+    assert_eq!(
+        served_name(&server, None, &[SslSignatureAlgorithm::ED25519]),
+        "ed25519"
+    );
+
+    // Before TLS 1.2, Ed25519 keys cannot sign the handshake.
+    let mut client = server.client();
+    client
+        .ctx()
+        .set_max_proto_version(Some(SslVersion::TLS1_1))
+        .unwrap();
+    let s = client.connect();
+    let cert = s.ssl().peer_certificate().unwrap();
+    let cn = cert.subject_name().entries_by_nid(Nid::COMMONNAME).next();
+    assert_eq!(cn.unwrap().data().as_utf8().unwrap().to_string(), "rsa");
+}
+
+#[test]
+fn credential_cert_callback() {
+    let mut server = Server::builder();
+    server
+        .ctx()
+        .add_credential(credential("context", rsa_key()));
+    let ecdsa = credential("callback", ec_key());
+    server.ctx().set_cert_callback(move |ssl| {
+        ssl.add_credential(ecdsa.clone());
+        Ok(())
+    });
+    server.expected_connections_count(2);
+    let server = server.build();
+
+    assert_eq!(
+        served_name(&server, None, &[SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256]),
+        "context"
+    );
+    assert_eq!(
+        served_name(
+            &server,
+            None,
+            &[SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256]
+        ),
+        "callback"
+    );
+}
+
+#[test]
+fn credential_private_key_method() {
+    let key = ec_key();
+    let cert = self_signed("method", &key);
+    let called_sign = Arc::new(AtomicBool::new(false));
+    let method = Method::new().sign({
+        let called_sign = called_sign.clone();
+        move |_, input, signature_algorithm, output| {
+            assert_eq!(
+                signature_algorithm,
+                SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256
+            );
+            called_sign.store(true, Ordering::SeqCst);
+            let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+            signer.update(input).unwrap();
+            Ok(signer.sign(output).unwrap())
+        }
+    });
+    let mut builder = SslCredential::builder(cert, vec![]);
+    builder.set_private_key_method(method);
+
+    let mut server = Server::builder();
+    server.ctx().add_credential(builder.build().unwrap());
+    let server = server.build();
+
+    assert_eq!(
+        served_name(
+            &server,
+            None,
+            &[SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256]
+        ),
+        "method"
+    );
+    assert!(called_sign.load(Ordering::SeqCst));
+}
+
+#[test]
+fn credential_build_errors() {
+    let key = rsa_key();
+    let cert = self_signed("rsa", &key);
+
+    SslCredential::builder(cert.clone(), vec![])
+        .build()
+        .unwrap_err();
+
+    let mut builder = SslCredential::builder(cert.clone(), vec![]);
+    builder.set_private_key(rsa_key());
+    builder.build().unwrap_err();
+
+    let mut builder = SslCredential::builder(cert, vec![]);
+    builder.set_private_key(key);
+    builder.set_signing_algorithm_prefs(&[SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256]);
+    builder.build().unwrap_err();
+}
@@ -0,0 +1,176 @@
+use std::time::{Duration, SystemTime};
+
+use super::pki::{cert, ec_key, rsa_key, self_signed};
+use super::server::Server;
+use super::sni::served_name;
+use crate::pkey::{PKey, Private};
+use crate::ssl::{DelegatedCredential, HandshakeError, SslSignatureAlgorithm, SslVerifyMode};
+use crate::x509::extension::{DelegationUsage, KeyUsage};
+use crate::x509::X509;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Returns a self-signed certificate whose key may sign delegated credentials.
+fn delegation_cert(key: &PKey<Private>) -> X509 {
+    cert("delegator", key, None, |_| {
+        vec![
+            KeyUsage::new().digital_signature().build().unwrap(),
+            DelegationUsage::new().build().unwrap(),
+        ]
+    })
+}
+
+fn delegated_credential(
+    cert: &X509,
+    key: &PKey<Private>,
+    delegated_key: &PKey<Private>,
+) -> DelegatedCredential {
+    let mut builder = DelegatedCredential::builder(cert, key);
+    builder.set_public_key(delegated_key).unwrap();
+    builder.set_valid_for(DAY);
+    builder.build().unwrap()
+}
+
+#[test]
+fn delegated_credential_round_trip() {
+    let key = rsa_key();
+    let cert = delegation_cert(&key);
+    let delegated_key = ec_key();
+    let dc = delegated_credential(&cert, &key, &delegated_key);
+
+    assert_eq!(
+        dc.cert_verify_algorithm(),
+        SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256
+    );
+    assert_eq!(
+        dc.signature_algorithm(),
+        SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256
+    );
+    assert!(dc.public_key().unwrap().public_eq(&delegated_key));
+    dc.verify(&cert).unwrap();
+
+    let parsed = DelegatedCredential::from_bytes(dc.as_bytes()).unwrap();
+    assert_eq!(parsed, dc);
+    parsed.verify(&cert).unwrap();
+
+    let expiry = dc.expires_at(&cert).unwrap();
+    let now = SystemTime::now();
+    assert!(expiry > now + DAY - Duration::from_secs(60));
+    assert!(expiry <= now + DAY + Duration::from_secs(60));
+    dc.verify_at(&cert, expiry + Duration::from_secs(1))
+        .unwrap_err();
+    // Delegated credentials may not be valid for more than 7 days.
+    dc.verify_at(&cert, expiry - 8 * DAY).unwrap_err();
+}
+
+#[test]
+fn delegated_credential_verify_errors() {
+    let key = ec_key();
+    let cert = delegation_cert(&key);
+    let dc = delegated_credential(&cert, &key, &ec_key());
+
+    let mut bytes = dc.as_bytes().to_vec();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    DelegatedCredential::from_bytes(&bytes)
+        .unwrap()
+        .verify(&cert)
+        .unwrap_err();
+
+    DelegatedCredential::from_bytes(&bytes[..last]).unwrap_err();
+    bytes.push(0);
+    DelegatedCredential::from_bytes(&bytes).unwrap_err();
+
+    let other_key = ec_key();
+    dc.verify(&delegation_cert(&other_key)).unwrap_err();
+}
+
+#[test]
+fn delegated_credential_build_errors() {
+    let key = ec_key();
+    let cert = delegation_cert(&key);
+
+    // The certificate lacks the DelegationUsage extension.
+    let plain = self_signed("plain", &key);
+    let mut builder = DelegatedCredential::builder(&plain, &key);
+    builder.set_public_key(&ec_key()).unwrap();
+    builder.set_valid_for(DAY);
+    builder.build().unwrap_err();
+
+    let other_key = ec_key();
+    let mut builder = DelegatedCredential::builder(&cert, &other_key);
+    builder.set_public_key(&ec_key()).unwrap();
+    builder.set_valid_for(DAY);
+    builder.build().unwrap_err();
+
+    let mut builder = DelegatedCredential::builder(&cert, &key);
+    builder.set_public_key(&ec_key()).unwrap();
+    builder.set_valid_for(8 * DAY);
+    builder.build().unwrap_err();
+
+    // TLS 1.3 does not allow RSA PKCS#1 v1.5 signatures in handshakes.
+    let mut builder = DelegatedCredential::builder(&cert, &key);
+    builder.set_public_key(&rsa_key()).unwrap();
+    builder.set_valid_for(DAY);
+    builder.set_cert_verify_algorithm(SslSignatureAlgorithm::RSA_PKCS1_SHA256);
+    builder.build().unwrap_err();
+
+    let mut builder = DelegatedCredential::builder(&cert, &key);
+    builder.set_public_key(&ec_key()).unwrap();
+    builder.build().unwrap_err();
+}
+
+#[test]
+fn delegated_credential_server() {
+    let key = ec_key();
+    let cert = delegation_cert(&key);
+    let delegated_key = ec_key();
+    let dc = delegated_credential(&cert, &key, &delegated_key);
+
+    let mut server = Server::builder();
+    server.ctx().set_certificate(&cert).unwrap();
+    server.ctx().set_private_key(&key).unwrap();
+    server.ssl_cb(move |ssl| {
+        ssl.set_delegated_credential(&dc, &ec_key()).unwrap_err();
+        ssl.set_delegated_credential(&dc, &delegated_key).unwrap();
+    });
+    // The client does not advertise delegated credentials.
+    server.io_cb(|s| assert!(!s.ssl().delegated_credential_used()));
+    let server = server.build();
+
+    assert_eq!(served_name(&server, None, &[]), "delegator");
+}
+
+#[test]
+fn delegated_credential_used() {
+    let key = ec_key();
+    let cert = delegation_cert(&key);
+    let delegated_key = ec_key();
+    let dc = delegated_credential(&cert, &key, &delegated_key);
+
+    let mut server = Server::builder();
+    server.ctx().set_certificate(&cert).unwrap();
+    server.ctx().set_private_key(&key).unwrap();
+    server.ssl_cb(move |ssl| ssl.set_delegated_credential(&dc, &delegated_key).unwrap());
+    server.should_error();
+    // The server signs with the delegated credential the client advertised support for...
+    server.err_cb(|err| {
+        let HandshakeError::Failure(s) = err else {
+            panic!("unexpected error {err:?}");
+        };
+        assert!(s.ssl().delegated_credential_used());
+    });
+    let server = server.build();
+
+    // ...which the client does not accept.
+    let mut client = server.client();
+    client.ctx().set_verify(SslVerifyMode::NONE);
+    client
+        .ctx()
+        .set_delegated_credentials("ecdsa_secp256r1_sha256")
+        .unwrap();
+    let HandshakeError::Failure(s) = client.connect_err() else {
+        panic!("should fail the handshake");
+    };
+    assert!(!s.ssl().delegated_credential_used());
+}
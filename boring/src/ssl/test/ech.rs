@@ -1,7 +1,14 @@
-use crate::hpke::HpkeKey;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::hpke::{HpkeAead, HpkeKdf, HpkeKey};
 use crate::ssl::ech::SslEchKeys;
 use crate::ssl::test::server::{ClientSslBuilder, Server};
-use crate::ssl::HandshakeError;
+use crate::ssl::{
+    EchConfig, EchConfigList, EchKeyRing, EchOutcome, HandshakeError, HpkeSymmetricCipherSuite,
+    Ssl, SslConnector, SslContext, SslContextBuilder, SslFiletype, SslMethod,
+};
 
 // For future reference, these configs are generated by building the bssl tool (the binary is built
 // alongside boringssl) and running the following command:
@@ -71,3 +78,247 @@ fn ech_grease() {
     let ssl_stream = client.connect();
     assert!(!ssl_stream.ssl().ech_accepted())
 }
+
+#[test]
+fn ech_config_matches_bssl() {
+    let key = HpkeKey::dhkem_p256_sha256(ECH_KEY).unwrap();
+    assert_eq!(key.private_key().unwrap(), ECH_KEY);
+
+    let config = EchConfig::builder(0, "ech.com", &key).build().unwrap();
+    assert_eq!(config.as_bytes(), ECH_CONFIG);
+    assert_eq!(EchConfig::from_bytes(ECH_CONFIG).unwrap(), config);
+
+    let list = EchConfigList::from_bytes(ECH_CONFIG_LIST).unwrap();
+    assert_eq!(list.configs(), [config.clone()]);
+    assert_eq!(list.to_bytes().unwrap(), ECH_CONFIG_LIST);
+    assert_eq!(EchConfigList::new(vec![config]), list);
+}
+
+#[test]
+fn ech_config_fields() {
+    let key = HpkeKey::generate_x25519_hkdf_sha256().unwrap();
+    let mut builder = EchConfig::builder(42, "public.example", &key);
+    builder.set_cipher_suites(&[HpkeSymmetricCipherSuite {
+        kdf: HpkeKdf::HKDF_SHA256,
+        aead: HpkeAead::AES_256_GCM,
+    }]);
+    builder.set_max_name_length(64);
+    builder.add_extension(0x1234, b"data");
+    let config = builder.build().unwrap();
+
+    let config = EchConfig::from_bytes(config.as_bytes()).unwrap();
+    assert_eq!(config.config_id(), 42);
+    assert_eq!(config.kem(), key.kem());
+    assert_eq!(config.public_key(), key.public_key().unwrap());
+    assert_eq!(config.cipher_suites()[0].aead, HpkeAead::AES_256_GCM);
+    assert_eq!(config.max_name_length(), 64);
+    assert_eq!(config.public_name(), "public.example");
+    assert_eq!(config.extensions(), [(0x1234, b"data".to_vec())]);
+}
+
+#[test]
+fn ech_config_invalid() {
+    let key = HpkeKey::generate_x25519_hkdf_sha256().unwrap();
+    for name in [
+        "",
+        "1.2.3.4",
+        "example.0x7f",
+        "ech..com",
+        "-ech.com",
+        "ech_com",
+    ] {
+        EchConfig::builder(0, name, &key).build().unwrap_err();
+    }
+
+    let mut builder = EchConfig::builder(0, "ech.com", &key);
+    builder.set_cipher_suites(&[]);
+    builder.build().unwrap_err();
+
+    let mut builder = EchConfig::builder(0, "ech.com", &key);
+    builder.add_extension(1, b"");
+    builder.add_extension(1, b"");
+    builder.build().unwrap_err();
+
+    EchConfig::from_bytes(&ECH_CONFIG[..ECH_CONFIG.len() - 1]).unwrap_err();
+    EchConfigList::from_bytes(&ECH_CONFIG_LIST[1..]).unwrap_err();
+    EchConfigList::from_bytes(b"\x00\x00").unwrap_err();
+}
+
+#[test]
+fn ech_config_list_skips_unknown_versions() {
+    let mut list = b"\xfe\x0e\x00\x02\xab\xcd".to_vec();
+    list.extend_from_slice(ECH_CONFIG);
+    let mut bytes = (list.len() as u16).to_be_bytes().to_vec();
+    bytes.extend_from_slice(&list);
+
+    let list = EchConfigList::from_bytes(&bytes).unwrap();
+    assert_eq!(list.configs(), [EchConfig::from_bytes(ECH_CONFIG).unwrap()]);
+}
+
+#[test]
+fn ech_svc_param() {
+    let list = EchConfigList::from_bytes(ECH_CONFIG_LIST).unwrap();
+
+    let param = list.to_svc_param().unwrap();
+    assert_eq!(param[..4], [0, 5, 0, ECH_CONFIG_LIST.len() as u8]);
+    assert_eq!(param[4..], *ECH_CONFIG_LIST);
+    assert_eq!(EchConfigList::from_svc_param(&param).unwrap(), list);
+    EchConfigList::from_svc_param(&param[..param.len() - 1]).unwrap_err();
+
+    let presentation = list.to_presentation().unwrap();
+    assert!(presentation.starts_with("ech=AD7+DQA6"));
+    assert_eq!(
+        EchConfigList::from_presentation(&presentation).unwrap(),
+        list
+    );
+    let quoted = format!("\"{}\"", &presentation[4..]);
+    assert_eq!(EchConfigList::from_presentation(&quoted).unwrap(), list);
+}
+
+#[test]
+fn ech_generated_config() {
+    let key = HpkeKey::generate_x25519_hkdf_sha256().unwrap();
+    let config = EchConfig::builder(7, "ech.com", &key).build().unwrap();
+    let list = EchConfigList::new(vec![config.clone()]).to_bytes().unwrap();
+
+    let (_server, client) = bootstrap_ech(config.as_bytes(), &key.private_key().unwrap(), &list);
+
+    let ssl_stream = client.connect();
+    assert!(ssl_stream.ssl().ech_accepted())
+}
+
+fn server_ctx() -> SslContextBuilder {
+    let mut ctx = SslContext::builder(SslMethod::tls()).unwrap();
+    ctx.set_certificate_chain_file("test/cert.pem").unwrap();
+    ctx.set_private_key_file("test/key.pem", SslFiletype::PEM)
+        .unwrap();
+    ctx
+}
+
+/// Accepts `count` connections, returning whether each handshake succeeded with ECH.
+fn serve(ctx: SslContext, count: usize) -> (SocketAddr, JoinHandle<Vec<bool>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        (0..count)
+            .map(|_| {
+                let socket = listener.accept().unwrap().0;
+                let stream = Ssl::new(&ctx).unwrap().accept(socket);
+                stream.is_ok_and(|stream| stream.ssl().ech_accepted())
+            })
+            .collect()
+    });
+    (addr, handle)
+}
+
+fn connector() -> SslConnector {
+    let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+    connector.set_ca_file("test/root-ca.pem").unwrap();
+    connector.build()
+}
+
+#[test]
+fn ech_key_ring_rotation() {
+    let mut ring = EchKeyRing::builder("foobar.com");
+    ring.set_retired_keys(1);
+    let ring = ring.build().unwrap();
+    let first = ring.config_list().to_bytes().unwrap();
+
+    let mut ctx = server_ctx();
+    ctx.set_ech_key_ring(&ring).unwrap();
+    let (addr, server) = serve(ctx.build(), 4);
+    let connector = connector();
+    let connect = |list: &[u8]| {
+        connector
+            .connect_with_ech_retry("foobar.com", list, || TcpStream::connect(addr))
+            .unwrap()
+            .1
+    };
+
+    assert_eq!(connect(&first), EchOutcome::Accepted);
+
+    // The retired key still decrypts ClientHellos, without being advertised.
+    ring.rotate().unwrap();
+    assert_eq!(connect(&first), EchOutcome::Accepted);
+    assert_ne!(ring.config_list().to_bytes().unwrap(), first);
+
+    ring.rotate().unwrap();
+    assert_eq!(ring.retired_configs().len(), 1);
+    assert_eq!(
+        connect(&first),
+        EchOutcome::AcceptedAfterRetry {
+            retry_configs: ring.config_list().to_bytes().unwrap(),
+        }
+    );
+
+    assert_eq!(server.join().unwrap(), [true, true, false, true]);
+}
+
+#[test]
+fn ech_key_ring_detaches_contexts() {
+    let first = EchKeyRing::builder("foobar.com").build().unwrap();
+    let second = EchKeyRing::builder("foobar.com").build().unwrap();
+
+    // Freed contexts leave the ring.
+    server_ctx().set_ech_key_ring(&first).unwrap();
+    first.rotate().unwrap();
+
+    // So do contexts another ring is installed on.
+    let mut ctx = server_ctx();
+    ctx.set_ech_key_ring(&first).unwrap();
+    ctx.set_ech_key_ring(&second).unwrap();
+    let (addr, server) = serve(ctx.build(), 1);
+    first.rotate().unwrap();
+
+    let list = second.config_list().to_bytes().unwrap();
+    let outcome = connector()
+        .connect_with_ech_retry("foobar.com", &list, || TcpStream::connect(addr))
+        .unwrap()
+        .1;
+    assert_eq!(outcome, EchOutcome::Accepted);
+    assert_eq!(server.join().unwrap(), [true]);
+}
+
+#[test]
+fn ech_retry_requires_retry_configs() {
+    // The server does not support ECH, so it sends no retry configs.
+    let (addr, server) = serve(server_ctx().build(), 1);
+
+    let key = HpkeKey::generate_x25519_hkdf_sha256().unwrap();
+    let config = EchConfig::builder(0, "foobar.com", &key).build().unwrap();
+    let list = EchConfigList::new(vec![config]).to_bytes().unwrap();
+
+    let err = connector()
+        .connect_with_ech_retry("foobar.com", &list, || TcpStream::connect(addr))
+        .unwrap_err();
+    assert!(matches!(err, HandshakeError::Failure(_)));
+    assert_eq!(err.ech_retry_configs(), None);
+
+    assert_eq!(server.join().unwrap(), [false]);
+}
+
+#[test]
+fn ech_key_ring_schedule() {
+    let mut ring = EchKeyRing::builder("foobar.com");
+    ring.set_rotation_interval(Duration::ZERO);
+    assert!(ring.build().is_err());
+
+    let ring = EchKeyRing::builder("foobar.com").build().unwrap();
+    assert!(ring.time_until_rotation() > Duration::from_secs(60 * 59));
+    assert!(!ring.rotate_if_due().unwrap());
+    assert!(ring.retired_configs().is_empty());
+
+    let mut ring = EchKeyRing::builder("foobar.com");
+    ring.set_rotation_interval(Duration::from_millis(50));
+    let ring = ring.build().unwrap();
+    let first = ring.config_list();
+    let rotation = ring.spawn_rotation().unwrap();
+
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(ring.retired_configs().len(), 2);
+    assert_ne!(ring.config_list(), first);
+
+    // The thread exits once the ring is dropped.
+    drop(ring);
+    rotation.join().unwrap();
+}
@@ -0,0 +1,146 @@
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+
+use super::client_hello::with_client_hello;
+use super::profile::connector;
+use super::server::Server;
+use crate::ssl::{ClientHelloProfile, SslVerifyMode};
+
+#[test]
+fn ja3_vector() {
+    // The example from the JA3 README, with GREASE values added.
+    with_client_hello(
+        0x0301,
+        &[
+            0x0a0a, 47, 53, 5, 10, 49161, 49162, 49171, 49172, 50, 56, 19, 4,
+        ],
+        &[
+            (0x1a1a, b""),
+            (0, b""),
+            (10, b"\x00\x08\x2a\x2a\x00\x17\x00\x18\x00\x19"),
+            (11, b"\x01\x00"),
+            (0xfafa, b"\x00"),
+        ],
+        |hello| {
+            assert_eq!(
+                hello.ja3(),
+                "769,47-53-5-10-49161-49162-49171-49172-50-56-19-4,0-10-11,23-24-25,0"
+            );
+            assert_eq!(hello.ja3_hash(), "ada70206e40642a3e4461f35503241d5");
+        },
+    );
+}
+
+#[test]
+fn ja4_vector() {
+    // The Chrome example from the JA4 technical details, with GREASE values added.
+    with_client_hello(
+        0x0303,
+        &[
+            0x2a2a, 0x1301, 0x1302, 0x1303, 0xc02b, 0xc02f, 0xc02c, 0xc030, 0xcca9, 0xcca8, 0xc013,
+            0xc014, 0x009c, 0x009d, 0x002f, 0x0035,
+        ],
+        &[
+            (0x0a0a, b""),
+            (0x0000, b"\x00\x0e\x00\x00\x0bexample.com"),
+            (0x0017, b""),
+            (0xff01, b"\x00"),
+            (0x000a, b"\x00\x06\x4a\x4a\x00\x1d\x00\x17"),
+            (0x000b, b"\x01\x00"),
+            (0x0023, b""),
+            (0x0010, b"\x00\x0c\x02h2\x08http/1.1"),
+            (0x0005, b"\x01\x00\x00\x00\x00"),
+            (
+                0x000d,
+                b"\x00\x12\x0a\x0a\x04\x03\x08\x04\x04\x01\x05\x03\x08\x05\x05\x01\x08\x06\x06\x01",
+            ),
+            (0x0012, b""),
+            (0x0033, b"\x00\x05\x4a\x4a\x00\x01\x00"),
+            (0x002d, b"\x01\x01"),
+            (0x002b, b"\x06\x5a\x5a\x03\x04\x03\x03"),
+            (0x001b, b"\x02\x00\x02"),
+            (0x4469, b"\x00\x03\x02h2"),
+            (0xdada, b"\x00"),
+            (0x0015, b"\x00\x00"),
+        ],
+        |hello| {
+            assert_eq!(hello.ja4(), "t13d1516h2_8daaf6152771_e5627efa2ab1");
+            assert_eq!(
+                hello.ja4_r(),
+                "t13d1516h2_002f,0035,009c,009d,1301,1302,1303,c013,c014,c02b,c02c,c02f,c030,\
+                 cca8,cca9_0005,000a,000b,000d,0012,0015,0017,001b,0023,002b,002d,0033,4469,\
+                 ff01_0403,0804,0401,0503,0805,0501,0806,0601"
+            );
+        },
+    );
+}
+
+#[test]
+fn ja4_edge_cases() {
+    // No server name, no supported versions, an ALPN protocol that is not alphanumeric, and no
+    // other extensions or signature algorithms.
+    with_client_hello(
+        0x0303,
+        &[0x1301],
+        &[(0x0010, b"\x00\x03\x02\xab\xcd")],
+        |hello| {
+            assert_eq!(hello.ja4(), "t12i0101ad_0f2cb44170f4_000000000000");
+            assert_eq!(hello.ja4_r(), "t12i0101ad_1301_");
+        },
+    );
+
+    with_client_hello(0x0303, &[], &[], |hello| {
+        assert_eq!(hello.ja3(), "771,,,,");
+        assert_eq!(hello.ja4(), "t12i000000_000000000000_000000000000");
+    });
+}
+
+#[test]
+fn client_hello_fingerprint() {
+    // The extension order of the Firefox profile is fixed and it does not use GREASE, so every
+    // ClientHello has the same fingerprints.
+    let firefox = connector(&ClientHelloProfile::firefox()).build();
+    let fingerprint = firefox.client_hello_fingerprint("example.com").unwrap();
+    assert_eq!(
+        firefox.client_hello_fingerprint("example.com").unwrap(),
+        fingerprint
+    );
+    assert!(fingerprint.ja4.starts_with("t13d"));
+    assert!(fingerprint.ja4.contains("h2_"));
+
+    // Chrome permutes its extensions, which only changes the JA3 fingerprint.
+    let chrome = connector(&ClientHelloProfile::chrome()).build();
+    let first = chrome.client_hello_fingerprint("example.com").unwrap();
+    let second = chrome.client_hello_fingerprint("example.com").unwrap();
+    assert_eq!(first.ja4, second.ja4);
+    assert_eq!(first.ja4_r, second.ja4_r);
+    assert!(first.ja3.starts_with("771,"));
+}
+
+#[test]
+fn client_hello_fingerprint_matches_server() {
+    let seen = Arc::new(Mutex::new(None));
+    let mut server = Server::builder();
+    server.ctx().set_select_certificate_callback({
+        let seen = seen.clone();
+        move |hello| {
+            *seen.lock().unwrap() = Some(hello.fingerprint());
+            Ok(())
+        }
+    });
+    let server = server.build();
+
+    let mut builder = connector(&ClientHelloProfile::firefox());
+    builder.set_verify(SslVerifyMode::NONE);
+    let connector = builder.build();
+    let expected = connector.client_hello_fingerprint("foobar.com").unwrap();
+    let mut stream = connector
+        .configure()
+        .unwrap()
+        .verify_hostname(false)
+        .connect("foobar.com", server.connect_tcp())
+        .unwrap();
+    stream.read_exact(&mut [0]).unwrap();
+
+    assert_eq!(seen.lock().unwrap().take().unwrap(), expected);
+}
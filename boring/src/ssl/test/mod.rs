@@ -23,14 +23,25 @@ use crate::x509::{X509Name, X509};
 
 use super::CompliancePolicy;
 
+mod aia;
 mod cert_compressor;
 mod cert_verify;
+mod client_hello;
+mod credential;
 mod custom_verify;
+mod delegated_credential;
 mod ech;
+mod fingerprint;
+mod pin;
+pub(crate) mod pki;
 mod private_key_method;
+mod profile;
+mod reload;
 mod server;
 mod session;
 mod session_resumption;
+mod sni;
+mod spiffe;
 mod verify;
 
 static ROOT_CERT: &[u8] = include_bytes!("../../../test/root-ca.pem");
@@ -0,0 +1,269 @@
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+
+use super::aia::{self, LocalFetcher};
+use super::pki::{ca, leaf, rsa_key, server, Chain};
+use super::server::Server;
+use crate::pkey::{PKey, Private};
+use crate::ssl::{
+    PinMode, PinSet, PinSetBuilder, ReloadableTrust, SslConnector, SslConnectorBuilder, SslMethod,
+    SslVerifyMode,
+};
+use crate::x509::aia::AiaFetcher;
+use crate::x509::{X509Ref, X509};
+
+struct Pki {
+    root: X509,
+    leaf: X509,
+    key: PKey<Private>,
+}
+
+impl Pki {
+    fn new() -> Pki {
+        let root_key = rsa_key();
+        let root = ca("Test Root", &root_key, None);
+        let key = rsa_key();
+        let leaf = leaf("example.com", &key, (&root, &root_key));
+        Pki { root, leaf, key }
+    }
+
+    fn server(&self, should_error: bool) -> Server {
+        server(&self.leaf, &self.key, should_error)
+    }
+
+    fn connector(&self, pins: PinSet) -> SslConnector {
+        let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+        connector
+            .cert_store_mut()
+            .add_cert(self.root.clone())
+            .unwrap();
+        connector.set_pin_set(pins);
+        connector.build()
+    }
+
+    /// Connects to `example.com`, expecting the handshake to succeed.
+    fn connect(&self, pins: PinSet) {
+        let connector = self.connector(pins);
+        let server = self.server(false);
+        let mut s = connector
+            .connect("example.com", server.connect_tcp())
+            .unwrap();
+        s.read_exact(&mut [0]).unwrap();
+    }
+}
+
+fn pins(hostname: &str, pins: &[[u8; 32]], backup_pins: &[[u8; 32]], mode: PinMode) -> PinSet {
+    let mut builder = PinSet::builder();
+    builder.add_host(hostname, pins, backup_pins, mode).unwrap();
+    builder.build()
+}
+
+/// Returns a builder recording reports as `(hostname, enforced)`.
+fn recording() -> (PinSetBuilder, Arc<Mutex<Vec<(String, bool)>>>) {
+    let reports = Arc::new(Mutex::new(vec![]));
+    let mut builder = PinSet::builder();
+    let recorded = reports.clone();
+    builder.set_report_callback(move |report| {
+        assert!(!report.chain_pins().is_empty());
+        recorded
+            .lock()
+            .unwrap()
+            .push((report.hostname().to_string(), report.enforced()));
+    });
+    (builder, reports)
+}
+
+#[test]
+fn pin_matches_chain() {
+    let pki = Pki::new();
+    let leaf_pin = pki.leaf.spki_sha256().unwrap();
+    let root_pin = pki.root.spki_sha256().unwrap();
+    let other_pin = [0; 32];
+
+    pki.connect(pins("example.com", &[leaf_pin], &[], PinMode::Enforce));
+    pki.connect(pins("Example.com.", &[root_pin], &[], PinMode::Enforce));
+    pki.connect(pins(
+        "example.com",
+        &[other_pin],
+        &[leaf_pin],
+        PinMode::Enforce,
+    ));
+    // Other hosts are not pinned.
+    pki.connect(pins("other.com", &[other_pin], &[], PinMode::Enforce));
+}
+
+#[test]
+fn pin_mismatch() {
+    let pki = Pki::new();
+    let (mut builder, reports) = recording();
+    builder
+        .add_host("example.com", &[[0; 32]], &[], PinMode::Enforce)
+        .unwrap();
+    let connector = pki.connector(builder.build());
+
+    let server = pki.server(true);
+    connector
+        .connect("example.com", server.connect_tcp())
+        .unwrap_err();
+    assert_eq!(
+        *reports.lock().unwrap(),
+        [("example.com".to_string(), true)]
+    );
+}
+
+#[test]
+fn pin_soft_fail() {
+    let pki = Pki::new();
+    let (mut builder, reports) = recording();
+    builder
+        .add_host("example.com", &[[0; 32]], &[], PinMode::SoftFail)
+        .unwrap();
+    pki.connect(builder.build());
+    assert_eq!(
+        *reports.lock().unwrap(),
+        [("example.com".to_string(), false)]
+    );
+
+    let (mut builder, reports) = recording();
+    builder
+        .add_host("example.com", &[[0; 32]], &[], PinMode::Enforce)
+        .unwrap();
+    builder.set_report_only(true);
+    pki.connect(builder.build());
+    assert_eq!(
+        *reports.lock().unwrap(),
+        [("example.com".to_string(), false)]
+    );
+}
+
+#[test]
+fn pin_still_verifies_chain() {
+    let pki = Pki::new();
+    let untrusted = Pki::new();
+    let pin = untrusted.leaf.spki_sha256().unwrap();
+
+    // A matching pin does not make an untrusted chain valid.
+    let connector = pki.connector(pins("example.com", &[pin], &[], PinMode::Enforce));
+    let server = untrusted.server(true);
+    connector
+        .connect("example.com", server.connect_tcp())
+        .unwrap_err();
+}
+
+#[test]
+fn pin_set_per_connection() {
+    let pki = Pki::new();
+    let connector = pki.connector(pins("example.com", &[[0; 32]], &[], PinMode::Enforce));
+
+    let server = pki.server(false);
+    let mut config = connector.configure().unwrap();
+    config.set_pin_set(pins(
+        "example.com",
+        &[pki.leaf.spki_sha256().unwrap()],
+        &[],
+        PinMode::Enforce,
+    ));
+    let mut s = config.connect("example.com", server.connect_tcp()).unwrap();
+    s.read_exact(&mut [0]).unwrap();
+}
+
+#[test]
+fn pin_set_requires_pins() {
+    PinSet::builder()
+        .add_host("example.com", &[], &[[0; 32]], PinMode::Enforce)
+        .unwrap_err();
+}
+
+/// Connects to a server presenting `leaf`, expecting the handshake to fail if `should_error` is
+/// set.
+fn check(connector: SslConnectorBuilder, leaf: &X509Ref, key: &PKey<Private>, should_error: bool) {
+    let server = server(leaf, key, should_error);
+    let result = connector
+        .build()
+        .connect("example.com", server.connect_tcp());
+    if should_error {
+        result.unwrap_err();
+    } else {
+        result.unwrap().read_exact(&mut [0]).unwrap();
+    }
+}
+
+#[test]
+fn pin_with_issuer_fetcher() {
+    let Chain {
+        root,
+        intermediate,
+        leaf,
+        key,
+    } = aia::pki();
+    let fetcher = Arc::new(AiaFetcher::new(LocalFetcher(Some(
+        intermediate.to_der().unwrap(),
+    ))));
+
+    // The server does not send the intermediate, so its pin only matches the fetched chain.
+    let intermediate_pin = intermediate.spki_sha256().unwrap();
+    for (pin, should_error) in [(intermediate_pin, false), ([0; 32], true)] {
+        let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+        connector.cert_store_mut().add_cert(root.clone()).unwrap();
+        connector.set_issuer_fetcher(fetcher.clone());
+        connector.set_pin_set(pins("example.com", &[pin], &[], PinMode::Enforce));
+        check(connector, &leaf, &key, should_error);
+    }
+}
+
+#[test]
+fn pin_with_reloadable_trust() {
+    let pki = Pki::new();
+    let trust = ReloadableTrust::from_pem_bundle(&pki.root.to_pem().unwrap()).unwrap();
+
+    // The context's own store is empty, so the chain is only verified through `trust`.
+    let root_pin = pki.root.spki_sha256().unwrap();
+    for (pin, should_error) in [(root_pin, false), ([0; 32], true)] {
+        let mut connector = SslConnector::no_default_verify_builder(SslMethod::tls()).unwrap();
+        connector.set_reloadable_trust(SslVerifyMode::PEER, trust.clone());
+        connector.set_pin_set(pins("example.com", &[pin], &[], PinMode::Enforce));
+        check(connector, &pki.leaf, &pki.key, should_error);
+    }
+}
+
+#[test]
+fn pin_after_verify_callback() {
+    let pki = Pki::new();
+    let pin = pki.leaf.spki_sha256().unwrap();
+
+    for accept in [true, false] {
+        let calls = Arc::new(Mutex::new(0));
+        let counted = calls.clone();
+        let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+        connector
+            .cert_store_mut()
+            .add_cert(pki.root.clone())
+            .unwrap();
+        connector.set_verify_callback(SslVerifyMode::PEER, move |preverify_ok, _| {
+            *counted.lock().unwrap() += 1;
+            preverify_ok && accept
+        });
+        connector.set_pin_set(pins("example.com", &[pin], &[], PinMode::Enforce));
+        check(connector, &pki.leaf, &pki.key, !accept);
+        assert!(*calls.lock().unwrap() > 0);
+    }
+}
+
+#[test]
+fn pin_requires_peer_verification() {
+    let pki = Pki::new();
+    let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+    connector.set_verify(SslVerifyMode::NONE);
+    connector.set_pin_set(pins(
+        "example.com",
+        &[pki.leaf.spki_sha256().unwrap()],
+        &[],
+        PinMode::Enforce,
+    ));
+    connector
+        .build()
+        .configure()
+        .unwrap()
+        .into_ssl("example.com")
+        .unwrap_err();
+}
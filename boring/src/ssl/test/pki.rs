@@ -0,0 +1,181 @@
+//! Keys and certificates issued on the fly.
+use super::server::Server;
+use crate::asn1::Asn1Time;
+use crate::ec::{EcGroup, EcKey};
+use crate::hash::MessageDigest;
+use crate::nid::Nid;
+use crate::pkey::{PKey, Private};
+use crate::rand::rand_bytes;
+use crate::rsa::Rsa;
+use crate::x509::extension::{
+    BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
+};
+use crate::x509::store::{X509Store, X509StoreBuilder};
+use crate::x509::{X509Builder, X509Extension, X509Name, X509Ref, X509};
+
+pub(crate) fn rsa_key() -> PKey<Private> {
+    PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap()
+}
+
+pub(crate) fn ec_key() -> PKey<Private> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+}
+
+pub(crate) fn ed25519_key() -> PKey<Private> {
+    // A PKCS #8 Ed25519 private key, followed by its seed.
+    let mut der = b"\x30\x2e\x02\x01\x00\x30\x05\x06\x03\x2b\x65\x70\x04\x22\x04\x20".to_vec();
+    let mut seed = [0; 32];
+    rand_bytes(&mut seed).unwrap();
+    der.extend_from_slice(&seed);
+    PKey::private_key_from_der(&der).unwrap()
+}
+
+/// Returns a builder for a certificate for `cn`, valid for 30 days, which is self-issued if
+/// `issuer` is `None`. The certificate is left unsigned.
+pub(crate) fn builder(cn: &str, key: &PKey<Private>, issuer: Option<&X509Ref>) -> X509Builder {
+    let mut name = X509Name::builder().unwrap();
+    name.append_entry_by_nid(Nid::COMMONNAME, cn).unwrap();
+    let name = name.build();
+
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder
+        .set_issuer_name(issuer.map_or(&*name, |cert| cert.subject_name()))
+        .unwrap();
+    builder.set_pubkey(key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(30).unwrap())
+        .unwrap();
+    builder
+}
+
+/// Issues a certificate for `cn`, valid for 30 days, with the extensions returned by
+/// `extensions`. It is self-signed if `issuer` is `None`.
+pub(crate) fn cert(
+    cn: &str,
+    key: &PKey<Private>,
+    issuer: Option<(&X509Ref, &PKey<Private>)>,
+    extensions: impl FnOnce(&X509Builder) -> Vec<X509Extension>,
+) -> X509 {
+    let mut builder = builder(cn, key, issuer.map(|(cert, _)| cert));
+    for extension in extensions(&builder) {
+        builder.append_extension(&extension).unwrap();
+    }
+    let signing_key = issuer.map_or(key, |(_, key)| key);
+    builder.sign(signing_key, MessageDigest::sha256()).unwrap();
+    builder.build()
+}
+
+/// Issues a certificate for `cn` without extensions.
+pub(crate) fn self_signed(cn: &str, key: &PKey<Private>) -> X509 {
+    cert(cn, key, None, |_| vec![])
+}
+
+/// Returns the extensions of a CA certificate.
+fn ca_extensions() -> Vec<X509Extension> {
+    vec![
+        BasicConstraints::new().critical().ca().build().unwrap(),
+        KeyUsage::new().key_cert_sign().build().unwrap(),
+    ]
+}
+
+/// Issues a CA certificate, self-signed if `issuer` is `None`.
+pub(crate) fn ca(
+    cn: &str,
+    key: &PKey<Private>,
+    issuer: Option<(&X509Ref, &PKey<Private>)>,
+) -> X509 {
+    cert(cn, key, issuer, |_| ca_extensions())
+}
+
+/// Issues a self-signed CA certificate for a new RSA key.
+pub(crate) fn root() -> (X509, PKey<Private>) {
+    let key = rsa_key();
+    (ca("Test Root", &key, None), key)
+}
+
+/// Returns a subjectAltName extension holding the DNS name `dns`.
+pub(crate) fn dns_san(builder: &X509Builder, issuer: &X509Ref, dns: &str) -> X509Extension {
+    SubjectAlternativeName::new()
+        .dns(dns)
+        .build(&builder.x509v3_context(Some(issuer), None))
+        .unwrap()
+}
+
+/// Issues a certificate for the DNS name `cn`.
+pub(crate) fn leaf(cn: &str, key: &PKey<Private>, issuer: (&X509Ref, &PKey<Private>)) -> X509 {
+    cert(cn, key, Some(issuer), |builder| {
+        vec![dns_san(builder, issuer.0, cn)]
+    })
+}
+
+/// A root, an intermediate it issued, and a server certificate for `example.com` issued by the
+/// intermediate.
+pub(crate) struct Chain {
+    pub(crate) root: X509,
+    pub(crate) intermediate: X509,
+    pub(crate) leaf: X509,
+    pub(crate) key: PKey<Private>,
+}
+
+impl Chain {
+    pub(crate) fn new() -> Chain {
+        Chain::with_leaf_extensions(Vec::new)
+    }
+
+    /// Issues a chain whose leaf also holds the extensions returned by `extensions`.
+    pub(crate) fn with_leaf_extensions(extensions: impl FnOnce() -> Vec<X509Extension>) -> Chain {
+        let (root, root_key) = root();
+        let intermediate_key = rsa_key();
+        let intermediate = ca(
+            "Test Intermediate",
+            &intermediate_key,
+            Some((&root, &root_key)),
+        );
+        let key = rsa_key();
+        let leaf = cert(
+            "example.com",
+            &key,
+            Some((&intermediate, &intermediate_key)),
+            |builder| {
+                let mut leaf_extensions = vec![
+                    BasicConstraints::new().build().unwrap(),
+                    ExtendedKeyUsage::new().server_auth().build().unwrap(),
+                    dns_san(builder, &intermediate, "example.com"),
+                ];
+                leaf_extensions.extend(extensions());
+                leaf_extensions
+            },
+        );
+        Chain {
+            root,
+            intermediate,
+            leaf,
+            key,
+        }
+    }
+
+    /// Returns a store trusting the root.
+    pub(crate) fn store(&self) -> X509Store {
+        let mut store = X509StoreBuilder::new().unwrap();
+        store.add_cert(self.root.clone()).unwrap();
+        store.build()
+    }
+}
+
+/// Starts a server presenting `leaf`, which expects the handshake to fail if `should_error` is
+/// set.
+pub(super) fn server(leaf: &X509Ref, key: &PKey<Private>, should_error: bool) -> Server {
+    let mut server = Server::builder();
+    server.ctx().set_certificate(leaf).unwrap();
+    server.ctx().set_private_key(key).unwrap();
+    if should_error {
+        server.should_error();
+    }
+    server.build()
+}
@@ -0,0 +1,248 @@
+use std::fmt::Write as _;
+use std::io::Read;
+
+use super::server::Server;
+use crate::ssl::{
+    CertificateCompressionAlgorithm, CertificateCompressor, ClientHelloProfile, ExtensionOrder,
+    ExtensionType, SslConnector, SslConnectorBuilder, SslMethod, SslVerifyMode, SslVersion,
+};
+
+/// Advertises a certificate compression algorithm without implementing it.
+pub(super) struct Decompressor<const ALGORITHM: u16>;
+
+impl<const ALGORITHM: u16> CertificateCompressor for Decompressor<ALGORITHM> {
+    const ALGORITHM: CertificateCompressionAlgorithm = CertificateCompressionAlgorithm(ALGORITHM);
+
+    const CAN_COMPRESS: bool = false;
+
+    const CAN_DECOMPRESS: bool = true;
+}
+
+pub(super) fn connector(profile: &ClientHelloProfile) -> SslConnectorBuilder {
+    let mut connector = SslConnector::no_default_verify_builder(SslMethod::tls()).unwrap();
+    for algorithm in &profile.cert_compression {
+        match *algorithm {
+            CertificateCompressionAlgorithm::ZLIB => {
+                connector.add_certificate_compression_algorithm(Decompressor::<1>)
+            }
+            CertificateCompressionAlgorithm::BROTLI => {
+                connector.add_certificate_compression_algorithm(Decompressor::<2>)
+            }
+            CertificateCompressionAlgorithm::ZSTD => {
+                connector.add_certificate_compression_algorithm(Decompressor::<3>)
+            }
+            _ => unreachable!(),
+        }
+        .unwrap();
+    }
+    connector.set_client_hello_profile(profile).unwrap();
+    connector
+}
+
+fn client_hello(connector: SslConnectorBuilder) -> Vec<u8> {
+    connector
+        .build()
+        .client_hello_records("example.com")
+        .unwrap()
+        .get_ref()
+        .records()
+        .to_vec()
+}
+
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+fn u16_at(bytes: &[u8], i: usize) -> u16 {
+    u16::from_be_bytes([bytes[i], bytes[i + 1]])
+}
+
+fn u16_list(bytes: &[u8]) -> String {
+    bytes
+        .chunks(2)
+        .map(|value| match u16::from_be_bytes([value[0], value[1]]) {
+            value if is_grease(value) => "GREASE".to_string(),
+            value => format!("{value:04x}"),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Describes a ClientHello record one field per line, leaving out the random values: the
+/// client random, the session ID, the key shares, which GREASE values are used, and the
+/// encrypted ClientHello and padding extensions, whose lengths are random.
+fn snapshot(record: &[u8], sort_extensions: bool) -> String {
+    assert_eq!(&record[..3], [0x16, 0x03, 0x01]);
+    assert_eq!(usize::from(u16_at(record, 3)), record.len() - 5);
+    let hello = &record[5..];
+    assert_eq!(hello[0], 1);
+    let body = &hello[4..];
+
+    let mut out = String::new();
+    writeln!(out, "version {:04x}", u16_at(body, 0)).unwrap();
+    let mut i = 2 + 32;
+    let session_id_len = usize::from(body[i]);
+    writeln!(out, "session_id {session_id_len}").unwrap();
+    i += 1 + session_id_len;
+    let ciphers_len = usize::from(u16_at(body, i));
+    writeln!(
+        out,
+        "cipher_suites {}",
+        u16_list(&body[i + 2..i + 2 + ciphers_len])
+    )
+    .unwrap();
+    i += 2 + ciphers_len;
+    let compression_len = usize::from(body[i]);
+    writeln!(
+        out,
+        "compression_methods {}",
+        hex::encode(&body[i + 1..i + 1 + compression_len])
+    )
+    .unwrap();
+    i += 1 + compression_len;
+    assert_eq!(usize::from(u16_at(body, i)), body.len() - i - 2);
+    i += 2;
+
+    let mut extensions = vec![];
+    while i < body.len() {
+        let ty = u16_at(body, i);
+        let len = usize::from(u16_at(body, i + 2));
+        let data = &body[i + 4..i + 4 + len];
+        i += 4 + len;
+
+        let line = match ExtensionType::from(ty) {
+            _ if is_grease(ty) => format!("extension GREASE {}", hex::encode(data)),
+            ExtensionType::SUPPORTED_GROUPS => format!("extension 000a {}", u16_list(&data[2..])),
+            ExtensionType::SUPPORTED_VERSIONS => {
+                format!("extension 002b {}", u16_list(&data[1..]))
+            }
+            ExtensionType::KEY_SHARE => {
+                let mut shares = vec![];
+                let mut j = 2;
+                while j < data.len() {
+                    let group = u16_list(&data[j..j + 2]);
+                    let len = u16_at(data, j + 2);
+                    shares.push(format!("{group}:{len}"));
+                    j += 4 + usize::from(len);
+                }
+                format!("extension 0033 {}", shares.join(" "))
+            }
+            ExtensionType::ENCRYPTED_CLIENT_HELLO | ExtensionType::PADDING => {
+                format!("extension {ty:04x}")
+            }
+            _ => format!("extension {ty:04x} {}", hex::encode(data)),
+        };
+        extensions.push(line.trim_end().to_string());
+    }
+    if sort_extensions {
+        extensions.sort();
+    }
+    for extension in extensions {
+        writeln!(out, "{extension}").unwrap();
+    }
+    out
+}
+
+#[test]
+fn chrome_client_hello() {
+    let mut connector = connector(&ClientHelloProfile::chrome());
+    connector.enable_ocsp_stapling();
+    connector.enable_signed_cert_timestamps();
+    assert_eq!(
+        snapshot(&client_hello(connector), true),
+        include_str!("../../../test/client-hello/chrome.txt")
+    );
+}
+
+#[test]
+fn firefox_client_hello() {
+    let profile = ClientHelloProfile::firefox();
+    let expected = include_str!("../../../test/client-hello/firefox.txt");
+    // The extension order is fixed, so every ClientHello must match exactly.
+    for _ in 0..4 {
+        let mut connector = connector(&profile);
+        connector.enable_ocsp_stapling();
+        assert_eq!(snapshot(&client_hello(connector), false), expected);
+    }
+}
+
+#[test]
+fn client_hello_profile_default_key_shares() {
+    let mut profile = ClientHelloProfile::chrome();
+    profile.groups = vec!["X25519".into(), "P-256".into()];
+    profile.key_shares.clear();
+    let snapshot = snapshot(&client_hello(connector(&profile)), true);
+    assert!(
+        snapshot.contains("extension 0033 GREASE:1 001d:32\n"),
+        "{snapshot}"
+    );
+}
+
+#[test]
+fn client_hello_profile_handshake() {
+    let mut server = Server::builder();
+    server.expected_connections_count(2);
+    let server = server.build();
+
+    for profile in [ClientHelloProfile::chrome(), ClientHelloProfile::firefox()] {
+        let mut connector = connector(&profile);
+        connector.set_verify(SslVerifyMode::NONE);
+        let connector = connector.build();
+        let mut stream = connector
+            .configure()
+            .unwrap()
+            .verify_hostname(false)
+            .connect("foobar.com", server.connect_tcp())
+            .unwrap();
+        stream.read_exact(&mut [0]).unwrap();
+        assert_eq!(stream.ssl().version2(), Some(SslVersion::TLS1_3));
+    }
+}
+
+#[test]
+fn client_hello_profile_missing_compressor() {
+    let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+    connector
+        .set_client_hello_profile(&ClientHelloProfile::chrome())
+        .unwrap_err();
+
+    // Compressors must be registered in the order of the profile.
+    let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+    connector
+        .add_certificate_compression_algorithm(Decompressor::<2>)
+        .unwrap();
+    connector
+        .add_certificate_compression_algorithm(Decompressor::<1>)
+        .unwrap();
+    connector
+        .add_certificate_compression_algorithm(Decompressor::<3>)
+        .unwrap();
+    connector
+        .set_client_hello_profile(&ClientHelloProfile::firefox())
+        .unwrap_err();
+}
+
+#[test]
+fn client_hello_profile_validate() {
+    ClientHelloProfile::chrome().validate().unwrap();
+    ClientHelloProfile::firefox().validate().unwrap();
+
+    let invalid: [fn(&mut ClientHelloProfile); 8] = [
+        |profile| profile.max_version = SslVersion::TLS1_1,
+        |profile| profile.key_shares = vec!["X25519".into()],
+        |profile| profile.groups.push("X25519".into()),
+        |profile| profile.alps = vec!["h3".into()],
+        |profile| profile.alpn.push(String::new()),
+        |profile| profile.signature_algorithms.clear(),
+        |profile| {
+            profile.extension_order =
+                ExtensionOrder::Fixed(vec![ExtensionType::PADDING, ExtensionType::SERVER_NAME])
+        },
+        |profile| profile.extension_order = ExtensionOrder::Fixed(vec![ExtensionType::SERVER_NAME]),
+    ];
+    for update in invalid {
+        let mut profile = ClientHelloProfile::chrome();
+        update(&mut profile);
+        profile.validate().unwrap_err();
+    }
+}
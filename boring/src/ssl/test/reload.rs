@@ -0,0 +1,138 @@
+use std::io::Read;
+
+use super::pki::{ca, leaf, rsa_key, server};
+use super::server::Server;
+use crate::pkey::{PKey, Private};
+use crate::ssl::{ReloadableIdentity, ReloadableTrust, Ssl, SslContext, SslMethod, SslVerifyMode};
+use crate::x509::store::X509StoreBuilder;
+use crate::x509::X509;
+
+/// Returns a root and a leaf it issued with its key.
+fn pki(name: &str) -> (X509, X509, PKey<Private>) {
+    let root_key = rsa_key();
+    let root = ca(&format!("{name} Root"), &root_key, None);
+    let leaf_key = rsa_key();
+    let leaf = leaf(name, &leaf_key, (&root, &root_key));
+    (root, leaf, leaf_key)
+}
+
+fn served_certificate(server: &Server) -> Vec<u8> {
+    let s = server.client().connect();
+    s.ssl().peer_certificate().unwrap().to_der().unwrap()
+}
+
+#[test]
+fn identity_reload() {
+    let (_, leaf_a, key_a) = pki("a.example.com");
+    let (_, leaf_b, key_b) = pki("b.example.com");
+    let identity = ReloadableIdentity::new(leaf_a.clone(), vec![], key_a).unwrap();
+
+    let mut server = Server::builder();
+    server.ctx().set_reloadable_identity(identity.clone());
+    server.expected_connections_count(2);
+    let server = server.build();
+
+    assert_eq!(served_certificate(&server), leaf_a.to_der().unwrap());
+    identity
+        .reload_pem(
+            &leaf_b.to_pem().unwrap(),
+            &key_b.private_key_to_pem_pkcs8().unwrap(),
+        )
+        .unwrap();
+    assert_eq!(
+        identity.certificate().to_der().unwrap(),
+        leaf_b.to_der().unwrap()
+    );
+    assert_eq!(served_certificate(&server), leaf_b.to_der().unwrap());
+}
+
+#[test]
+fn identity_reload_serves_chain() {
+    let (root, leaf, key) = pki("example.com");
+    let identity = ReloadableIdentity::new(leaf, vec![root.clone()], key).unwrap();
+
+    let mut server = Server::builder();
+    server.ctx().set_reloadable_identity(identity);
+    let server = server.build();
+
+    let s = server.client().connect();
+    let chain = s.ssl().peer_cert_chain().unwrap();
+    assert_eq!(chain.len(), 2);
+    assert_eq!(chain[1].to_der().unwrap(), root.to_der().unwrap());
+}
+
+#[test]
+fn identity_reload_rejected() {
+    let (root_a, leaf_a, key_a) = pki("a.example.com");
+    let (_, leaf_b, key_b) = pki("b.example.com");
+    let identity = ReloadableIdentity::new(leaf_a.clone(), vec![root_a.clone()], key_a).unwrap();
+
+    // The key does not match the certificate.
+    identity
+        .reload(leaf_b.clone(), vec![], rsa_key())
+        .unwrap_err();
+    // The chain does not belong to the certificate.
+    identity
+        .reload(leaf_b.clone(), vec![root_a], key_b.clone())
+        .unwrap_err();
+    // No certificate at all.
+    identity
+        .reload_pem(b"", &key_b.private_key_to_pem_pkcs8().unwrap())
+        .unwrap_err();
+    identity
+        .reload_files("/nonexistent/cert.pem", "/nonexistent/key.pem")
+        .unwrap_err();
+
+    assert_eq!(
+        identity.certificate().to_der().unwrap(),
+        leaf_a.to_der().unwrap()
+    );
+    assert_eq!(identity.chain().len(), 1);
+}
+
+#[test]
+fn trust_reload() {
+    let (root_a, _, _) = pki("example.com");
+    let (root_b, leaf_b, key_b) = pki("example.com");
+    let trust = ReloadableTrust::from_pem_bundle(&root_a.to_pem().unwrap()).unwrap();
+
+    let mut ctx = SslContext::builder(SslMethod::tls()).unwrap();
+    ctx.set_reloadable_trust(SslVerifyMode::PEER, trust.clone());
+    let ctx = ctx.build();
+
+    let untrusted = server(&leaf_b, &key_b, true);
+    Ssl::new(&ctx)
+        .unwrap()
+        .connect(untrusted.connect_tcp())
+        .unwrap_err();
+
+    let mut store = X509StoreBuilder::new().unwrap();
+    store.add_cert(root_b).unwrap();
+    trust.reload(store.build()).unwrap();
+
+    let trusted = server(&leaf_b, &key_b, false);
+    let mut s = Ssl::new(&ctx)
+        .unwrap()
+        .connect(trusted.connect_tcp())
+        .unwrap();
+    s.read_exact(&mut [0]).unwrap();
+}
+
+#[test]
+fn trust_reload_rejected() {
+    let (root, _, _) = pki("example.com");
+    let trust = ReloadableTrust::from_pem_bundle(&root.to_pem().unwrap()).unwrap();
+
+    trust
+        .reload(X509StoreBuilder::new().unwrap().build())
+        .unwrap_err();
+    trust.reload_pem_bundle(b"").unwrap_err();
+    trust
+        .reload_pem_bundle(
+            b"-----BEGIN CERTIFICATE-----\nbm90IGEgY2VydGlmaWNhdGU=\n-----END CERTIFICATE-----\n",
+        )
+        .unwrap_err();
+    trust.reload_file("/nonexistent/roots.pem").unwrap_err();
+
+    assert_eq!(trust.store().objects_len(), 1);
+}
@@ -0,0 +1,140 @@
+use std::sync::Arc;
+use std::task::{Wake, Waker};
+
+use super::pki::{ec_key, rsa_key, self_signed};
+use super::server::Server;
+use crate::nid::Nid;
+use crate::ssl::{SniResolver, SslSignatureAlgorithm, UnknownNamePolicy};
+
+/// Returns the common name of the certificate served for `hostname`.
+pub(super) fn served_name(
+    server: &Server,
+    hostname: Option<&str>,
+    sigalgs: &[SslSignatureAlgorithm],
+) -> String {
+    let mut client = server.client();
+    if !sigalgs.is_empty() {
+        client.ctx().set_verify_algorithm_prefs(sigalgs).unwrap();
+    }
+    let client = client.build();
+    let mut client = client.builder();
+    if let Some(hostname) = hostname {
+        client.ssl().set_hostname(hostname).unwrap();
+    }
+    let s = client.connect();
+    let cert = s.ssl().peer_certificate().unwrap();
+    let cn = cert.subject_name().entries_by_nid(Nid::COMMONNAME).next();
+    cn.unwrap().data().as_utf8().unwrap().to_string()
+}
+
+fn resolver(policy: UnknownNamePolicy) -> SniResolver {
+    let mut resolver = SniResolver::builder();
+    for (hostname, cn) in [
+        ("a.example.com", "a rsa"),
+        ("*.example.com", "wildcard rsa"),
+    ] {
+        let key = rsa_key();
+        resolver
+            .add_certificate(hostname, self_signed(cn, &key), vec![], key)
+            .unwrap();
+    }
+    let key = ec_key();
+    resolver
+        .add_certificate("A.Example.com.", self_signed("a ecdsa", &key), vec![], key)
+        .unwrap();
+    let key = rsa_key();
+    resolver
+        .add_default_certificate(self_signed("default", &key), vec![], key)
+        .unwrap();
+    resolver.set_unknown_name_policy(policy);
+    resolver.build()
+}
+
+#[test]
+fn sni_resolver() {
+    let mut server = Server::builder();
+    server
+        .ctx()
+        .set_sni_resolver(resolver(UnknownNamePolicy::UseDefault));
+    server.expected_connections_count(6);
+    let server = server.build();
+
+    let rsa = [SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256];
+    let ecdsa = [SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256];
+    let both = [
+        SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256,
+        SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256,
+    ];
+    assert_eq!(served_name(&server, Some("a.example.com"), &rsa), "a rsa");
+    assert_eq!(
+        served_name(&server, Some("a.example.com"), &ecdsa),
+        "a ecdsa"
+    );
+    assert_eq!(
+        served_name(&server, Some("a.example.com"), &both),
+        "a ecdsa"
+    );
+    assert_eq!(
+        served_name(&server, Some("b.example.com"), &both),
+        "wildcard rsa"
+    );
+    // Wildcards only match a single label.
+    assert_eq!(
+        served_name(&server, Some("c.b.example.com"), &[]),
+        "default"
+    );
+    assert_eq!(served_name(&server, None, &[]), "default");
+}
+
+#[test]
+fn sni_resolver_rejects_unknown_name() {
+    let mut server = Server::builder();
+    server
+        .ctx()
+        .set_sni_resolver(resolver(UnknownNamePolicy::Reject));
+    server.should_error();
+    let server = server.build();
+
+    let client = server.client().build();
+    let mut client = client.builder();
+    client.ssl().set_hostname("unknown.com").unwrap();
+    client.connect_err();
+}
+
+#[test]
+fn sni_resolver_invalid_pattern() {
+    let key = rsa_key();
+    let cert = self_signed("invalid", &key);
+    let mut resolver = SniResolver::builder();
+    resolver
+        .add_certificate("*.*.example.com", cert.clone(), vec![], key.clone())
+        .unwrap_err();
+    resolver
+        .add_certificate("example.com", cert, vec![], rsa_key())
+        .unwrap_err();
+}
+
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+#[test]
+fn sni_resolver_async() {
+    let resolver = resolver(UnknownNamePolicy::Reject);
+    let mut server = Server::builder();
+    server
+        .ctx()
+        .set_async_select_certificate_callback(move |_| {
+            let resolver = resolver.clone();
+            Ok(Box::pin(async move { Ok(resolver.select_finish()) }))
+        });
+    server.ssl_cb(|ssl| ssl.set_task_waker(Some(Waker::from(Arc::new(NoopWaker)))));
+    let server = server.build();
+
+    assert_eq!(
+        served_name(&server, Some("b.example.com"), &[]),
+        "wildcard rsa"
+    );
+}
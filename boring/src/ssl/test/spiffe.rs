@@ -0,0 +1,288 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::pki::{ca, cert, rsa_key};
+use crate::pkey::{PKey, Private};
+use crate::ssl::{
+    SpiffeId, SpiffeVerifier, SslAcceptor, SslConnector, SslConnectorBuilder, SslMethod,
+    SslSession, SslSessionCacheMode,
+};
+use crate::x509::extension::{BasicConstraints, KeyUsage, SubjectAlternativeName};
+use crate::x509::store::{X509Store, X509StoreBuilder};
+use crate::x509::X509;
+
+/// The authority of a trust domain.
+struct TrustDomain {
+    root: X509,
+    key: PKey<Private>,
+}
+
+impl TrustDomain {
+    fn new() -> TrustDomain {
+        let key = rsa_key();
+        let root = ca("Test Root", &key, None);
+        TrustDomain { root, key }
+    }
+
+    fn bundle(&self) -> X509Store {
+        let mut store = X509StoreBuilder::new().unwrap();
+        store.add_cert(self.root.clone()).unwrap();
+        store.build()
+    }
+
+    /// Issues an SVID with the given URIs, returning it with its key.
+    fn svid(&self, uris: &[&str], key_usage: &mut KeyUsage) -> (X509, PKey<Private>) {
+        let key = rsa_key();
+        let svid = cert("SVID", &key, Some((&*self.root, &self.key)), |builder| {
+            let mut san = SubjectAlternativeName::new();
+            for uri in uris {
+                san.uri(uri);
+            }
+            let context = builder.x509v3_context(Some(&*self.root), None);
+            vec![
+                BasicConstraints::new().critical().build().unwrap(),
+                key_usage.build().unwrap(),
+                san.build(&context).unwrap(),
+            ]
+        });
+        (svid, key)
+    }
+}
+
+fn signing() -> KeyUsage {
+    let mut key_usage = KeyUsage::new();
+    key_usage.critical().digital_signature().key_encipherment();
+    key_usage
+}
+
+fn verifier(domain: &TrustDomain, allowed: Option<&'static str>) -> SpiffeVerifier {
+    let mut verifier = SpiffeVerifier::builder();
+    verifier.add_bundle("example.org", domain.bundle()).unwrap();
+    if let Some(allowed) = allowed {
+        verifier.set_authorizer(move |id| id.to_string() == allowed);
+    }
+    verifier.build()
+}
+
+/// Returns an acceptor presenting an SVID for `id` issued by `domain`, and trusting `domain`.
+fn acceptor(domain: &TrustDomain, id: &str, allows: Option<&'static str>) -> SslAcceptor {
+    let (cert, key) = domain.svid(&[id], &mut signing());
+    let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+    acceptor.set_certificate(&cert).unwrap();
+    acceptor.set_private_key(&key).unwrap();
+    acceptor.set_spiffe_verifier(verifier(domain, allows));
+    acceptor.build()
+}
+
+/// Returns a connector trusting `domain`, presenting an SVID issued by `client_domain`.
+fn connector(domain: &TrustDomain, client_domain: &TrustDomain, id: &str) -> SslConnectorBuilder {
+    let (cert, key) = client_domain.svid(&[id], &mut signing());
+    let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+    connector.set_certificate(&cert).unwrap();
+    connector.set_private_key(&key).unwrap();
+    connector.set_spiffe_verifier(verifier(domain, None));
+    connector
+}
+
+/// Runs a mutual TLS handshake with SVIDs issued by `domain`, except for the client SVID which
+/// is issued by `client_domain`, returning the peer IDs seen by the server and the client.
+fn handshake(
+    domain: &TrustDomain,
+    client_domain: &TrustDomain,
+    server_id: &str,
+    client_id: &str,
+    server_allows: Option<&'static str>,
+) -> Option<(SpiffeId, SpiffeId)> {
+    let acceptor = acceptor(domain, server_id, server_allows);
+    let connector = connector(domain, client_domain, client_id).build();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let stream = listener.accept().unwrap().0;
+        let mut stream = acceptor.accept(stream).ok()?;
+        stream.write_all(&[0]).unwrap();
+        stream.ssl().peer_spiffe_id()
+    });
+
+    let client = connector
+        .connect("ignored.example.com", TcpStream::connect(addr).unwrap())
+        .ok()
+        .and_then(|mut stream| {
+            // With TLS 1.3, the server only rejects the client after its handshake completes.
+            stream.read_exact(&mut [0]).ok()?;
+            stream.ssl().peer_spiffe_id()
+        });
+    let server = server.join().unwrap();
+    Some((server?, client?))
+}
+
+#[test]
+fn spiffe_mtls() {
+    let domain = TrustDomain::new();
+    let (seen_by_server, seen_by_client) = handshake(
+        &domain,
+        &domain,
+        "spiffe://example.org/server",
+        "spiffe://example.org/ns/default/client",
+        Some("spiffe://example.org/ns/default/client"),
+    )
+    .unwrap();
+
+    assert_eq!(seen_by_server.trust_domain(), "example.org");
+    assert_eq!(seen_by_server.path(), "/ns/default/client");
+    assert_eq!(seen_by_client.to_string(), "spiffe://example.org/server");
+}
+
+#[test]
+fn spiffe_authorizer_denies() {
+    let domain = TrustDomain::new();
+    assert!(handshake(
+        &domain,
+        &domain,
+        "spiffe://example.org/server",
+        "spiffe://example.org/intruder",
+        Some("spiffe://example.org/client"),
+    )
+    .is_none());
+}
+
+#[test]
+fn spiffe_unknown_trust_domain() {
+    let domain = TrustDomain::new();
+    assert!(handshake(
+        &domain,
+        &domain,
+        "spiffe://example.org/server",
+        "spiffe://other.org/client",
+        None,
+    )
+    .is_none());
+}
+
+#[test]
+fn spiffe_untrusted_issuer() {
+    let domain = TrustDomain::new();
+    let other = TrustDomain::new();
+    assert!(handshake(
+        &domain,
+        &other,
+        "spiffe://example.org/server",
+        "spiffe://example.org/client",
+        None,
+    )
+    .is_none());
+}
+
+#[test]
+fn spiffe_id_not_kept_on_resumption() {
+    let domain = TrustDomain::new();
+    let acceptor = acceptor(&domain, "spiffe://example.org/server", None);
+    let mut connector = connector(&domain, &domain, "spiffe://example.org/client");
+    let session = Arc::new(Mutex::new(None::<SslSession>));
+    let new_session = session.clone();
+    connector.set_session_cache_mode(SslSessionCacheMode::CLIENT);
+    connector.set_new_session_callback(move |_, session| {
+        *new_session.lock().unwrap() = Some(session);
+    });
+    let connector = connector.build();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let mut ids = vec![];
+        for _ in 0..2 {
+            let stream = listener.accept().unwrap().0;
+            let mut stream = acceptor.accept(stream).unwrap();
+            stream.write_all(&[0]).unwrap();
+            ids.push(stream.ssl().peer_spiffe_id());
+        }
+        ids
+    });
+
+    let mut stream = connector
+        .connect("ignored.example.com", TcpStream::connect(addr).unwrap())
+        .unwrap();
+    stream.read_exact(&mut [0]).unwrap();
+    assert!(stream.ssl().peer_spiffe_id().is_some());
+
+    let session = session.lock().unwrap().take().unwrap();
+    let mut ssl = connector
+        .configure()
+        .unwrap()
+        .into_ssl("ignored.example.com")
+        .unwrap();
+    unsafe { ssl.set_session(&session).unwrap() };
+    let mut stream = ssl.connect(TcpStream::connect(addr).unwrap()).unwrap();
+    stream.read_exact(&mut [0]).unwrap();
+    assert!(stream.ssl().session_reused());
+    assert_eq!(stream.ssl().peer_spiffe_id(), None);
+
+    let ids = server.join().unwrap();
+    assert!(ids[0].is_some());
+    assert_eq!(ids[1], None);
+}
+
+#[test]
+fn spiffe_id_from_svid() {
+    let domain = TrustDomain::new();
+
+    let (svid, _) = domain.svid(&["spiffe://example.org/workload"], &mut signing());
+    assert_eq!(
+        SpiffeId::from_svid(&svid).unwrap().to_string(),
+        "spiffe://example.org/workload"
+    );
+
+    let (svid, _) = domain.svid(
+        &["spiffe://example.org/a", "spiffe://example.org/b"],
+        &mut signing(),
+    );
+    SpiffeId::from_svid(&svid).unwrap_err();
+
+    let (svid, _) = domain.svid(&["spiffe://example.org"], &mut signing());
+    SpiffeId::from_svid(&svid).unwrap_err();
+
+    let (svid, _) = domain.svid(
+        &["spiffe://example.org/workload"],
+        KeyUsage::new().key_encipherment(),
+    );
+    SpiffeId::from_svid(&svid).unwrap_err();
+
+    let (svid, _) = domain.svid(
+        &["spiffe://example.org/workload"],
+        signing().key_cert_sign(),
+    );
+    SpiffeId::from_svid(&svid).unwrap_err();
+
+    // Certificate authorities are not SVIDs.
+    SpiffeId::from_svid(&domain.root).unwrap_err();
+}
+
+#[test]
+fn spiffe_id_parse() {
+    let id = SpiffeId::parse("spiffe://example.org/ns/default/sa/Web_1").unwrap();
+    assert_eq!(id.trust_domain(), "example.org");
+    assert_eq!(id.path(), "/ns/default/sa/Web_1");
+    assert_eq!(id.to_string(), "spiffe://example.org/ns/default/sa/Web_1");
+    assert_eq!(SpiffeId::parse("spiffe://example.org").unwrap().path(), "");
+
+    for invalid in [
+        "https://example.org/workload",
+        "spiffe://",
+        "spiffe:///workload",
+        "spiffe://Example.org/workload",
+        "spiffe://example.org:8080/workload",
+        "spiffe://user@example.org/workload",
+        "spiffe://example.org/",
+        "spiffe://example.org//workload",
+        "spiffe://example.org/./workload",
+        "spiffe://example.org/../workload",
+        "spiffe://example.org/workload?query",
+        "spiffe://example.org/workload#fragment",
+        "spiffe://example.org/work%20load",
+    ] {
+        SpiffeId::parse(invalid).unwrap_err();
+    }
+}
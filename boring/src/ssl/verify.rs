@@ -0,0 +1,65 @@
+//! Verification of peer certificates from custom verify callbacks.
+use crate::error::ErrorStack;
+use crate::ssl::pin::check_pins;
+use crate::ssl::{SslAlert, SslRef};
+use crate::x509::verify::{ChainVerification, VerifyOptions, X509Purpose};
+use crate::x509::{X509VerifyError, X509};
+
+/// Returns the certificates sent by the peer and the options to verify them with, matching the
+/// connection's verification parameters.
+pub(crate) fn peer_chain(ssl: &mut SslRef) -> Result<(X509, Vec<X509>, VerifyOptions), SslAlert> {
+    let leaf = ssl.peer_certificate().ok_or(SslAlert::BAD_CERTIFICATE)?;
+    let mut intermediates = ssl
+        .peer_cert_chain()
+        .map(|chain| chain.iter().map(ToOwned::to_owned).collect::<Vec<_>>())
+        .unwrap_or_default();
+    // Only clients see the leaf in the peer chain.
+    if !ssl.is_server() && !intermediates.is_empty() {
+        intermediates.remove(0);
+    }
+
+    let purpose = if ssl.is_server() {
+        X509Purpose::SSL_CLIENT
+    } else {
+        X509Purpose::SSL_SERVER
+    };
+    let mut options = VerifyOptions::new();
+    options
+        .set_param(ssl.verify_param_mut())
+        .map_err(|_| SslAlert::INTERNAL_ERROR)?;
+    options.set_purpose(purpose);
+    Ok((leaf, intermediates, options))
+}
+
+/// Maps the first verification error to the alert sent to the peer, then checks the pins
+/// installed on the connection against the verified chain.
+pub(crate) fn verification_alert(
+    ssl: &SslRef,
+    result: Result<ChainVerification, ErrorStack>,
+) -> Result<(), SslAlert> {
+    let result = chain_alert(result)?;
+    check_pins(ssl, result.chain())
+}
+
+/// Maps the first verification error to the alert sent to the peer, returning the verification
+/// if there was none.
+pub(crate) fn chain_alert(
+    result: Result<ChainVerification, ErrorStack>,
+) -> Result<ChainVerification, SslAlert> {
+    let result = result.map_err(|_| SslAlert::INTERNAL_ERROR)?;
+    let Some(error) = result.errors().first() else {
+        return Ok(result);
+    };
+    Err(match error.error() {
+        X509VerifyError::CERT_HAS_EXPIRED | X509VerifyError::CERT_NOT_YET_VALID => {
+            SslAlert::CERTIFICATE_EXPIRED
+        }
+        X509VerifyError::CERT_REVOKED => SslAlert::CERTIFICATE_REVOKED,
+        X509VerifyError::UNABLE_TO_GET_ISSUER_CERT
+        | X509VerifyError::UNABLE_TO_GET_ISSUER_CERT_LOCALLY
+        | X509VerifyError::DEPTH_ZERO_SELF_SIGNED_CERT
+        | X509VerifyError::SELF_SIGNED_CERT_IN_CHAIN
+        | X509VerifyError::CERT_UNTRUSTED => SslAlert::UNKNOWN_CA,
+        _ => SslAlert::BAD_CERTIFICATE,
+    })
+}
@@ -0,0 +1,229 @@
+//! Fetching missing intermediate certificates through the authority information access
+//! extension.
+//!
+//! Servers sometimes send only their leaf certificate. When the issuer of the last certificate
+//! of a chain is unknown, an [`AiaFetcher`] downloads it from the `caIssuers` URLs listed in
+//! that certificate and verification is retried. The download itself is left to an
+//! [`IssuerFetcher`] or [`AsyncIssuerFetcher`], so any HTTP client can be used.
+//!
+//! Fetching is opt-in, through [`VerifyOptions::set_issuer_fetcher`] for
+//! [`X509StoreRef::verify_chain`] and through [`SslConnectorBuilder::set_issuer_fetcher`] for
+//! TLS connections.
+//!
+//! [`VerifyOptions::set_issuer_fetcher`]: crate::x509::verify::VerifyOptions::set_issuer_fetcher
+//! [`X509StoreRef::verify_chain`]: crate::x509::store::X509StoreRef::verify_chain
+//! [`SslConnectorBuilder::set_issuer_fetcher`]: crate::ssl::SslConnectorBuilder::set_issuer_fetcher
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use crate::nid::Nid;
+use crate::x509::chain::signed_by;
+use crate::x509::{X509Ref, X509};
+
+/// Downloads the certificate published at a `caIssuers` URL.
+pub trait IssuerFetcher: Send + Sync {
+    /// Returns the body of the resource at `url`.
+    ///
+    /// Both a DER-encoded and a PEM-encoded certificate are accepted.
+    fn fetch(&self, url: &str) -> io::Result<Vec<u8>>;
+}
+
+/// The future returned by [`AsyncIssuerFetcher::fetch`].
+pub type BoxIssuerFetchFuture = Pin<Box<dyn Future<Output = io::Result<Vec<u8>>> + Send>>;
+
+/// An asynchronous version of [`IssuerFetcher`].
+pub trait AsyncIssuerFetcher: Send + Sync {
+    /// Resolves to the body of the resource at `url`.
+    ///
+    /// See [`IssuerFetcher::fetch`] for the accepted formats.
+    fn fetch(&self, url: &str) -> BoxIssuerFetchFuture;
+}
+
+enum Fetcher {
+    Sync(Box<dyn IssuerFetcher>),
+    Async(Box<dyn AsyncIssuerFetcher>),
+}
+
+struct Cache {
+    certs: HashMap<String, X509>,
+    order: VecDeque<String>,
+}
+
+/// Fetches missing issuers with an [`IssuerFetcher`], caching the certificates it downloads.
+///
+/// A downloaded certificate is only used if it actually issued the certificate it was fetched
+/// for. Download and parse failures are not reported: verification then fails with the same
+/// error as without fetching.
+pub struct AiaFetcher {
+    fetcher: Fetcher,
+    cache: Mutex<Cache>,
+    cache_capacity: usize,
+    max_fetches: usize,
+}
+
+impl AiaFetcher {
+    /// Creates a fetcher downloading certificates with `fetcher`.
+    pub fn new<F>(fetcher: F) -> AiaFetcher
+    where
+        F: IssuerFetcher + 'static,
+    {
+        AiaFetcher::with_fetcher(Fetcher::Sync(Box::new(fetcher)))
+    }
+
+    /// Creates a fetcher downloading certificates with the asynchronous `fetcher`.
+    ///
+    /// Such a fetcher is only used by asynchronous verification, such as
+    /// [`X509StoreRef::verify_chain_async`] or a TLS connection driven by an async runtime.
+    ///
+    /// [`X509StoreRef::verify_chain_async`]: crate::x509::store::X509StoreRef::verify_chain_async
+    pub fn new_async<F>(fetcher: F) -> AiaFetcher
+    where
+        F: AsyncIssuerFetcher + 'static,
+    {
+        AiaFetcher::with_fetcher(Fetcher::Async(Box::new(fetcher)))
+    }
+
+    fn with_fetcher(fetcher: Fetcher) -> AiaFetcher {
+        AiaFetcher {
+            fetcher,
+            cache: Mutex::new(Cache {
+                certs: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+            cache_capacity: 64,
+            max_fetches: 3,
+        }
+    }
+
+    /// Sets the maximum number of certificates fetched while verifying a single chain.
+    ///
+    /// Defaults to 3.
+    pub fn set_max_fetches(&mut self, max_fetches: usize) -> &mut AiaFetcher {
+        self.max_fetches = max_fetches;
+        self
+    }
+
+    /// Sets the number of downloaded certificates kept in the cache, the oldest being evicted
+    /// first.
+    ///
+    /// Defaults to 64. A capacity of 0 disables caching.
+    pub fn set_cache_capacity(&mut self, capacity: usize) -> &mut AiaFetcher {
+        self.cache_capacity = capacity;
+        self
+    }
+
+    /// Returns `true` if certificates are downloaded with an [`AsyncIssuerFetcher`].
+    #[must_use]
+    pub fn is_async(&self) -> bool {
+        matches!(self.fetcher, Fetcher::Async(_))
+    }
+
+    /// Removes every certificate from the cache.
+    pub fn clear_cache(&self) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.certs.clear();
+        cache.order.clear();
+    }
+
+    pub(crate) fn max_fetches(&self) -> usize {
+        self.max_fetches
+    }
+
+    /// Returns the issuer of `cert`, downloading it if it is not cached.
+    ///
+    /// Always returns `None` for asynchronous fetchers.
+    pub(crate) fn fetch_issuer(&self, cert: &X509Ref) -> Option<X509> {
+        let Fetcher::Sync(fetcher) = &self.fetcher else {
+            return None;
+        };
+        for url in ca_issuers(cert) {
+            if let Some(issuer) = self.cached(&url, cert) {
+                return Some(issuer);
+            }
+            let response = fetcher.fetch(&url);
+            if let Some(issuer) = self.accept(url, cert, response) {
+                return Some(issuer);
+            }
+        }
+        None
+    }
+
+    /// Returns the issuer of `cert`, downloading it if it is not cached.
+    pub(crate) async fn fetch_issuer_async(&self, cert: &X509Ref) -> Option<X509> {
+        for url in ca_issuers(cert) {
+            if let Some(issuer) = self.cached(&url, cert) {
+                return Some(issuer);
+            }
+            let response = match &self.fetcher {
+                Fetcher::Sync(fetcher) => fetcher.fetch(&url),
+                Fetcher::Async(fetcher) => fetcher.fetch(&url).await,
+            };
+            if let Some(issuer) = self.accept(url, cert, response) {
+                return Some(issuer);
+            }
+        }
+        None
+    }
+
+    fn cached(&self, url: &str, cert: &X509Ref) -> Option<X509> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .certs
+            .get(url)
+            .filter(|issuer| is_issuer(issuer, cert))
+            .cloned()
+    }
+
+    fn accept(&self, url: String, cert: &X509Ref, response: io::Result<Vec<u8>>) -> Option<X509> {
+        let body = response.ok()?;
+        let issuer = X509::from_der(&body)
+            .or_else(|_| X509::from_pem(&body))
+            .ok()?;
+        if !is_issuer(&issuer, cert) {
+            return None;
+        }
+
+        if self.cache_capacity > 0 {
+            let mut cache = self.cache.lock().unwrap();
+            if cache.certs.insert(url.clone(), issuer.clone()).is_none() {
+                cache.order.push_back(url);
+            }
+            while cache.order.len() > self.cache_capacity {
+                if let Some(oldest) = cache.order.pop_front() {
+                    cache.certs.remove(&oldest);
+                }
+            }
+        }
+        Some(issuer)
+    }
+}
+
+impl fmt::Debug for AiaFetcher {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("AiaFetcher")
+            .field("async", &self.is_async())
+            .field("cache_capacity", &self.cache_capacity)
+            .field("max_fetches", &self.max_fetches)
+            .finish()
+    }
+}
+
+/// Returns the `caIssuers` URLs of `cert`.
+fn ca_issuers(cert: &X509Ref) -> Vec<String> {
+    let Some(info) = cert.authority_info() else {
+        return vec![];
+    };
+    info.iter()
+        .filter(|desc| desc.method().nid() == Nid::AD_CA_ISSUERS)
+        .filter_map(|desc| desc.location().uri())
+        .map(str::to_string)
+        .collect()
+}
+
+fn is_issuer(issuer: &X509Ref, cert: &X509Ref) -> bool {
+    issuer.issued(cert).is_ok() && signed_by(cert, issuer)
+}
@@ -0,0 +1,377 @@
+//! Issue certificates from certificate signing requests.
+//!
+//! A [`CertificateAuthority`] pairs an issuing certificate with its private key and signs
+//! `X509Req`s according to a [`CertProfile`]. The profile, not the request, decides how long the
+//! certificate is valid for and what it may be used for: only the subject, the public key and
+//! the subject alternative names allowed by the profile are taken from the request.
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Mutex;
+
+use foreign_types::ForeignTypeRef;
+
+use crate::asn1::{Asn1Integer, Asn1Time};
+use crate::bn::{BigNum, MsbOption};
+use crate::error::ErrorStack;
+use crate::ffi;
+use crate::hash::MessageDigest;
+use crate::pkey::{Id, PKey, PKeyRef, Private, Public};
+use crate::x509::extension::{
+    AuthorityKeyIdentifier, BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
+    SubjectKeyIdentifier,
+};
+use crate::x509::{GeneralNameRef, X509Extension, X509Ref, X509ReqRef, X509};
+
+bitflags! {
+    /// The kinds of subject alternative names copied from a request into the issued certificate.
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+    #[repr(transparent)]
+    pub struct SanCopy: u8 {
+        const DNS = 1 << 0;
+        const IP = 1 << 1;
+        const EMAIL = 1 << 2;
+        const URI = 1 << 3;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProfileKind {
+    Server,
+    Client,
+    Intermediate,
+    CodeSigning,
+}
+
+/// The policy applied to every certificate issued by a `CertificateAuthority`.
+#[derive(Debug, Clone)]
+pub struct CertProfile {
+    kind: ProfileKind,
+    validity_days: u32,
+    path_len: Option<u32>,
+    extended_key_usage: Vec<String>,
+    san_copy: SanCopy,
+}
+
+impl CertProfile {
+    /// A TLS server certificate valid for 397 days, copying DNS and IP names from the request.
+    #[must_use]
+    pub fn server() -> CertProfile {
+        CertProfile {
+            kind: ProfileKind::Server,
+            validity_days: 397,
+            path_len: None,
+            extended_key_usage: vec!["serverAuth".to_string()],
+            san_copy: SanCopy::DNS | SanCopy::IP,
+        }
+    }
+
+    /// A TLS client certificate valid for 365 days, copying every supported name from the
+    /// request.
+    #[must_use]
+    pub fn client() -> CertProfile {
+        CertProfile {
+            kind: ProfileKind::Client,
+            validity_days: 365,
+            path_len: None,
+            extended_key_usage: vec!["clientAuth".to_string()],
+            san_copy: SanCopy::all(),
+        }
+    }
+
+    /// An intermediate CA certificate valid for five years which may only issue end-entity
+    /// certificates.
+    #[must_use]
+    pub fn intermediate() -> CertProfile {
+        CertProfile {
+            kind: ProfileKind::Intermediate,
+            validity_days: 5 * 365,
+            path_len: Some(0),
+            extended_key_usage: vec![],
+            san_copy: SanCopy::empty(),
+        }
+    }
+
+    /// A code signing certificate valid for 365 days.
+    #[must_use]
+    pub fn code_signing() -> CertProfile {
+        CertProfile {
+            kind: ProfileKind::CodeSigning,
+            validity_days: 365,
+            path_len: None,
+            extended_key_usage: vec!["codeSigning".to_string()],
+            san_copy: SanCopy::empty(),
+        }
+    }
+
+    /// Sets the number of days issued certificates are valid for.
+    ///
+    /// Certificates never outlive the issuing certificate.
+    pub fn set_validity_days(&mut self, days: u32) -> &mut CertProfile {
+        self.validity_days = days;
+        self
+    }
+
+    /// Sets the maximum number of CAs that may follow an intermediate issued with this profile.
+    ///
+    /// Ignored for end-entity profiles.
+    pub fn set_path_len(&mut self, path_len: Option<u32>) -> &mut CertProfile {
+        self.path_len = path_len;
+        self
+    }
+
+    /// Adds an extended key usage, either by short name such as `serverAuth` or dotted OID.
+    pub fn add_extended_key_usage(&mut self, usage: &str) -> &mut CertProfile {
+        self.extended_key_usage.push(usage.to_string());
+        self
+    }
+
+    /// Sets which subject alternative names are copied from the request.
+    pub fn set_san_copy(&mut self, san_copy: SanCopy) -> &mut CertProfile {
+        self.san_copy = san_copy;
+        self
+    }
+
+    /// Returns the number of days issued certificates are valid for.
+    #[must_use]
+    pub fn validity_days(&self) -> u32 {
+        self.validity_days
+    }
+
+    fn is_ca(&self) -> bool {
+        self.kind == ProfileKind::Intermediate
+    }
+
+    fn basic_constraints(&self) -> Result<X509Extension, ErrorStack> {
+        let mut bc = BasicConstraints::new();
+        bc.critical();
+        if self.is_ca() {
+            bc.ca();
+            if let Some(path_len) = self.path_len {
+                bc.pathlen(path_len);
+            }
+        }
+        bc.build()
+    }
+
+    fn key_usage(&self, key: &PKeyRef<Public>) -> Result<X509Extension, ErrorStack> {
+        let mut ku = KeyUsage::new();
+        ku.critical();
+        match self.kind {
+            ProfileKind::Server => {
+                ku.digital_signature();
+                // Only RSA keys can be used for RSA key exchange.
+                if key.id() == Id::RSA {
+                    ku.key_encipherment();
+                }
+            }
+            ProfileKind::Client | ProfileKind::CodeSigning => {
+                ku.digital_signature();
+            }
+            ProfileKind::Intermediate => {
+                ku.key_cert_sign().crl_sign();
+            }
+        }
+        ku.build()
+    }
+
+    fn extended_key_usage(&self) -> Result<Option<X509Extension>, ErrorStack> {
+        if self.extended_key_usage.is_empty() {
+            return Ok(None);
+        }
+        let mut eku = ExtendedKeyUsage::new();
+        for usage in &self.extended_key_usage {
+            eku.other(usage);
+        }
+        eku.build().map(Some)
+    }
+
+    fn copy_name(&self, name: &GeneralNameRef, san: &mut SubjectAlternativeName) -> bool {
+        if let Some(dns) = name.dnsname() {
+            if self.san_copy.contains(SanCopy::DNS) {
+                san.dns(dns);
+                return true;
+            }
+        } else if let Some(ip) = name.ipaddress() {
+            let ip = match *ip {
+                [a, b, c, d] => IpAddr::V4(Ipv4Addr::new(a, b, c, d)),
+                _ => match <[u8; 16]>::try_from(ip) {
+                    Ok(octets) => IpAddr::V6(Ipv6Addr::from(octets)),
+                    Err(_) => return false,
+                },
+            };
+            if self.san_copy.contains(SanCopy::IP) {
+                san.ip(&ip.to_string());
+                return true;
+            }
+        } else if let Some(email) = name.email() {
+            if self.san_copy.contains(SanCopy::EMAIL) {
+                san.email(email);
+                return true;
+            }
+        } else if let Some(uri) = name.uri() {
+            if self.san_copy.contains(SanCopy::URI) {
+                san.uri(uri);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// A certificate authority issuing certificates from certificate signing requests.
+pub struct CertificateAuthority {
+    cert: X509,
+    key: PKey<Private>,
+    chain: Vec<X509>,
+    digest: MessageDigest,
+    serials: Mutex<HashSet<Vec<u8>>>,
+}
+
+impl CertificateAuthority {
+    /// Creates an authority issuing certificates as `cert`, signed with `key`.
+    ///
+    /// Returns an error if `cert` is not a CA certificate allowed to sign certificates, that is
+    /// without a basicConstraints extension marking it as a CA and a keyUsage extension with
+    /// `keyCertSign`, or if `key` does not match its public key.
+    pub fn new(cert: X509, key: PKey<Private>) -> Result<CertificateAuthority, ErrorStack> {
+        let (flags, key_usage) = unsafe {
+            (
+                ffi::X509_get_extension_flags(cert.as_ptr()),
+                ffi::X509_get_key_usage(cert.as_ptr()),
+            )
+        };
+        if flags & ffi::EXFLAG_CA as _ == 0
+            || flags & ffi::EXFLAG_KUSAGE as _ == 0
+            || key_usage & ffi::KU_KEY_CERT_SIGN as _ == 0
+        {
+            return Err(ErrorStack::internal_error_str(
+                "issuer certificate is not allowed to sign certificates",
+            ));
+        }
+        if !cert.public_key()?.public_eq(&key) {
+            return Err(ErrorStack::internal_error_str(
+                "private key does not match the issuer certificate",
+            ));
+        }
+
+        // Ed25519 signs the certificate itself rather than a digest of it.
+        let digest = if key.id() == Id::ED25519 {
+            MessageDigest::null()
+        } else {
+            MessageDigest::sha256()
+        };
+        Ok(CertificateAuthority {
+            cert,
+            key,
+            chain: vec![],
+            digest,
+            serials: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Sets the certificates between the issuer certificate and the root, in order.
+    ///
+    /// They are appended to the chain returned by [`Self::issue`].
+    pub fn set_chain(&mut self, chain: Vec<X509>) -> &mut CertificateAuthority {
+        self.chain = chain;
+        self
+    }
+
+    /// Sets the digest used to sign certificates.
+    ///
+    /// Defaults to SHA-256, or to no digest if the issuer key is an Ed25519 key, which cannot be
+    /// used with any other.
+    pub fn set_digest(&mut self, digest: MessageDigest) -> &mut CertificateAuthority {
+        self.digest = digest;
+        self
+    }
+
+    /// Returns the issuer certificate.
+    #[must_use]
+    pub fn certificate(&self) -> &X509Ref {
+        &self.cert
+    }
+
+    /// Issues a certificate for `req` following `profile`.
+    ///
+    /// The request's signature is checked before anything else. On success, returns the new
+    /// certificate followed by the issuer certificate and the rest of the configured chain.
+    pub fn issue(&self, req: &X509ReqRef, profile: &CertProfile) -> Result<Vec<X509>, ErrorStack> {
+        let pubkey = req.public_key()?;
+        if !req.verify(&pubkey)? {
+            return Err(ErrorStack::internal_error_str(
+                "certificate request signature is invalid",
+            ));
+        }
+
+        let mut builder = X509::builder()?;
+        builder.set_version(2)?;
+        builder.set_serial_number(&self.next_serial()?)?;
+        builder.set_subject_name(req.subject_name())?;
+        builder.set_issuer_name(self.cert.subject_name())?;
+        builder.set_pubkey(&pubkey)?;
+
+        builder.set_not_before(&Asn1Time::days_from_now(0)?)?;
+        let not_after = Asn1Time::days_from_now(profile.validity_days)?;
+        if self.cert.not_after() < not_after {
+            builder.set_not_after(self.cert.not_after())?;
+        } else {
+            builder.set_not_after(&not_after)?;
+        }
+
+        builder.append_extension(&profile.basic_constraints()?)?;
+        builder.append_extension(&profile.key_usage(&pubkey)?)?;
+        if let Some(eku) = profile.extended_key_usage()? {
+            builder.append_extension(&eku)?;
+        }
+
+        let ski =
+            SubjectKeyIdentifier::new().build(&builder.x509v3_context(Some(&self.cert), None))?;
+        builder.append_extension(&ski)?;
+        let aki = AuthorityKeyIdentifier::new()
+            .keyid(false)
+            .issuer(false)
+            .build(&builder.x509v3_context(Some(&self.cert), None))?;
+        builder.append_extension(&aki)?;
+
+        // Without a subject, the names are only found in the subjectAltName extension, which
+        // must then be critical (RFC 5280, section 4.2.1.6).
+        let empty_subject = req.subject_name().entries().next().is_none();
+        let mut san = SubjectAlternativeName::new();
+        if empty_subject {
+            san.critical();
+        }
+        let mut copied = false;
+        for name in req.subject_alt_names().iter().flatten() {
+            copied |= profile.copy_name(name, &mut san);
+        }
+        if copied {
+            let san = san.build(&builder.x509v3_context(Some(&self.cert), None))?;
+            builder.append_extension(&san)?;
+        } else if empty_subject {
+            return Err(ErrorStack::internal_error_str(
+                "certificate would have neither a subject nor subject alternative names",
+            ));
+        }
+
+        builder.sign(&self.key, self.digest)?;
+
+        let mut chain = Vec::with_capacity(self.chain.len() + 2);
+        chain.push(builder.build());
+        chain.push(self.cert.clone());
+        chain.extend(self.chain.iter().cloned());
+        Ok(chain)
+    }
+
+    /// Returns a random positive serial number that was not handed out before by this authority.
+    fn next_serial(&self) -> Result<Asn1Integer, ErrorStack> {
+        let mut serials = self.serials.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            let mut serial = BigNum::new()?;
+            serial.rand(127, MsbOption::MAYBE_ZERO, false)?;
+            if serial.num_bits() > 0 && serials.insert(serial.to_vec()) {
+                return serial.to_asn1_integer();
+            }
+        }
+    }
+}
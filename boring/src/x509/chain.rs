@@ -0,0 +1,180 @@
+//! Assemble the certificate chain a server should send.
+//!
+//! Certificate bundles are often concatenated by hand: intermediates come in the wrong order,
+//! some are missing and others belong to unrelated chains. [`build_serving_chain`] picks the
+//! certificates that actually link the leaf to a root, in order, and reports what could not be
+//! used.
+use std::cmp::Ordering;
+
+use crate::asn1::Asn1Time;
+use crate::error::ErrorStack;
+use crate::x509::{X509NameRef, X509Ref, X509};
+
+/// The result of [`build_serving_chain`].
+#[derive(Debug, Clone)]
+pub struct ServingChain {
+    chain: Vec<X509>,
+    root: Option<X509>,
+    trusted: bool,
+    unrelated: Vec<X509>,
+}
+
+impl ServingChain {
+    /// Returns the chain to serve, starting with the leaf and excluding the root.
+    #[must_use]
+    pub fn chain(&self) -> &[X509] {
+        &self.chain
+    }
+
+    /// Returns the leaf certificate.
+    #[must_use]
+    pub fn leaf(&self) -> &X509 {
+        &self.chain[0]
+    }
+
+    /// Returns the certificates between the leaf and the root, in order.
+    #[must_use]
+    pub fn intermediates(&self) -> &[X509] {
+        &self.chain[1..]
+    }
+
+    /// Returns the self-signed certificate the chain ends at, if one was found.
+    ///
+    /// The root is never part of [`Self::chain`].
+    #[must_use]
+    pub fn root(&self) -> Option<&X509> {
+        self.root.as_ref()
+    }
+
+    /// Returns `true` if the chain ends at one of the given roots.
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.trusted
+    }
+
+    /// Returns the issuer name of the last certificate of the chain if its issuer could not be
+    /// found.
+    #[must_use]
+    pub fn missing_issuer(&self) -> Option<&X509NameRef> {
+        if self.root.is_some() {
+            return None;
+        }
+        let last = self.chain.last()?;
+        if is_self_signed(last) {
+            return None;
+        }
+        Some(last.issuer_name())
+    }
+
+    /// Returns the certificates of the pool that are not part of the chain.
+    #[must_use]
+    pub fn unrelated(&self) -> &[X509] {
+        &self.unrelated
+    }
+}
+
+/// Builds the chain a server should send for `leaf`.
+///
+/// Issuers are looked up in `roots` first, then in `pool`. The chain stops at the first
+/// certificate issued by a root, or by a self-signed certificate of the pool, and that root is
+/// left out. Among several candidate issuers, currently valid certificates expiring last are
+/// preferred. Duplicates of the certificates in the chain and of the root are ignored; every
+/// other certificate of the pool is reported as unrelated.
+pub fn build_serving_chain(
+    leaf: &X509Ref,
+    pool: &[X509],
+    roots: &[X509],
+) -> Result<ServingChain, ErrorStack> {
+    let now = Asn1Time::days_from_now(0)?;
+    let pool = pool
+        .iter()
+        .map(|cert| Ok((cert.to_der()?, cert)))
+        .collect::<Result<Vec<_>, ErrorStack>>()?;
+    let mut used = vec![leaf.to_der()?];
+    let mut chain = vec![leaf.to_owned()];
+    let mut root = None;
+    let mut trusted = false;
+
+    loop {
+        let current = chain.last().unwrap();
+        if is_self_signed(current) {
+            break;
+        }
+
+        if let Some(anchor) = best_issuer(current, roots.iter(), &now)? {
+            root = Some(anchor.clone());
+            trusted = true;
+            break;
+        }
+
+        let candidates = pool
+            .iter()
+            .filter(|(der, _)| !used.contains(der))
+            .map(|(_, cert)| *cert);
+        let Some(issuer) = best_issuer(current, candidates, &now)? else {
+            break;
+        };
+        used.push(issuer.to_der()?);
+        if is_self_signed(issuer) {
+            root = Some(issuer.clone());
+            break;
+        }
+        chain.push(issuer.clone());
+    }
+
+    if let Some(root) = &root {
+        used.push(root.to_der()?);
+    }
+    let mut unrelated = vec![];
+    for (der, cert) in pool {
+        if !used.contains(&der) {
+            unrelated.push(cert.clone());
+            used.push(der);
+        }
+    }
+
+    Ok(ServingChain {
+        chain,
+        root,
+        trusted,
+        unrelated,
+    })
+}
+
+fn is_self_signed(cert: &X509Ref) -> bool {
+    cert.issued(cert).is_ok() && signed_by(cert, cert)
+}
+
+pub(crate) fn signed_by(cert: &X509Ref, issuer: &X509Ref) -> bool {
+    issuer
+        .public_key()
+        .and_then(|key| cert.verify(&key))
+        .unwrap_or(false)
+}
+
+/// Returns the candidate that issued `cert`, preferring valid certificates which expire last.
+fn best_issuer<'a>(
+    cert: &X509Ref,
+    candidates: impl Iterator<Item = &'a X509>,
+    now: &Asn1Time,
+) -> Result<Option<&'a X509>, ErrorStack> {
+    let mut best: Option<(&X509, bool)> = None;
+    for candidate in candidates {
+        if candidate.issued(cert).is_err() || !signed_by(cert, candidate) {
+            continue;
+        }
+        let valid = candidate.not_before() <= *now && candidate.not_after() >= *now;
+        let better = match best {
+            None => true,
+            Some((current, current_valid)) => {
+                valid > current_valid
+                    || (valid == current_valid
+                        && candidate.not_after().compare(current.not_after())? == Ordering::Greater)
+            }
+        };
+        if better {
+            best = Some((candidate, valid));
+        }
+    }
+    Ok(best.map(|(cert, _)| cert))
+}
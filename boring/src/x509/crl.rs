@@ -0,0 +1,289 @@
+//! Certificate revocation lists.
+use crate::ffi;
+use foreign_types::{ForeignType, ForeignTypeRef};
+use libc::c_long;
+use openssl_macros::corresponds;
+
+use crate::asn1::{Asn1IntegerRef, Asn1TimeRef};
+use crate::error::ErrorStack;
+use crate::hash::MessageDigest;
+use crate::pkey::{HasPrivate, HasPublic, PKeyRef};
+use crate::stack::{StackRef, Stackable};
+use crate::util::ForeignTypeRefExt;
+use crate::x509::signer::{AsyncCertSigner, CertSigner, SignFns};
+use crate::x509::{print_to_string, X509ExtensionRef, X509NameRef};
+use crate::{cvt, cvt_n, cvt_p};
+
+const CRL_SIGN: SignFns<ffi::X509_CRL> = SignFns {
+    set_algorithm: ffi::X509_CRL_set1_signature_algo,
+    tbs: ffi::i2d_re_X509_CRL_tbs,
+    set_signature: ffi::X509_CRL_set1_signature_value,
+};
+
+/// A builder used to construct an `X509Crl`.
+pub struct X509CrlBuilder(X509Crl);
+
+impl X509CrlBuilder {
+    /// Creates a new builder for a version 2 CRL.
+    #[corresponds(X509_CRL_new)]
+    pub fn new() -> Result<X509CrlBuilder, ErrorStack> {
+        unsafe {
+            ffi::init();
+            let crl = X509Crl::from_ptr(cvt_p(ffi::X509_CRL_new())?);
+            cvt(ffi::X509_CRL_set_version(
+                crl.as_ptr(),
+                ffi::X509_CRL_VERSION_2 as c_long,
+            ))?;
+            Ok(X509CrlBuilder(crl))
+        }
+    }
+
+    /// Sets the issuer name of the CRL.
+    #[corresponds(X509_CRL_set_issuer_name)]
+    pub fn set_issuer_name(&mut self, issuer_name: &X509NameRef) -> Result<(), ErrorStack> {
+        unsafe {
+            cvt(ffi::X509_CRL_set_issuer_name(
+                self.0.as_ptr(),
+                issuer_name.as_ptr(),
+            ))
+            .map(|_| ())
+        }
+    }
+
+    /// Sets the time this CRL was issued.
+    #[corresponds(X509_CRL_set1_lastUpdate)]
+    pub fn set_last_update(&mut self, last_update: &Asn1TimeRef) -> Result<(), ErrorStack> {
+        unsafe {
+            cvt(ffi::X509_CRL_set1_lastUpdate(
+                self.0.as_ptr(),
+                last_update.as_ptr(),
+            ))
+            .map(|_| ())
+        }
+    }
+
+    /// Sets the time by which the next CRL will be issued.
+    #[corresponds(X509_CRL_set1_nextUpdate)]
+    pub fn set_next_update(&mut self, next_update: &Asn1TimeRef) -> Result<(), ErrorStack> {
+        unsafe {
+            cvt(ffi::X509_CRL_set1_nextUpdate(
+                self.0.as_ptr(),
+                next_update.as_ptr(),
+            ))
+            .map(|_| ())
+        }
+    }
+
+    /// Adds the certificate with serial number `serial_number` to the list of revoked
+    /// certificates.
+    #[corresponds(X509_CRL_add0_revoked)]
+    pub fn add_revoked(
+        &mut self,
+        serial_number: &Asn1IntegerRef,
+        revocation_date: &Asn1TimeRef,
+    ) -> Result<(), ErrorStack> {
+        unsafe {
+            let revoked = X509Revoked::from_ptr(cvt_p(ffi::X509_REVOKED_new())?);
+            cvt(ffi::X509_REVOKED_set_serialNumber(
+                revoked.as_ptr(),
+                serial_number.as_ptr(),
+            ))?;
+            cvt(ffi::X509_REVOKED_set_revocationDate(
+                revoked.as_ptr(),
+                revocation_date.as_ptr(),
+            ))?;
+            cvt(ffi::X509_CRL_add0_revoked(
+                self.0.as_ptr(),
+                revoked.as_ptr(),
+            ))?;
+            std::mem::forget(revoked);
+            Ok(())
+        }
+    }
+
+    /// Adds an X509 extension value to the CRL.
+    #[corresponds(X509_CRL_add_ext)]
+    pub fn append_extension(&mut self, extension: &X509ExtensionRef) -> Result<(), ErrorStack> {
+        unsafe {
+            cvt(ffi::X509_CRL_add_ext(
+                self.0.as_ptr(),
+                extension.as_ptr(),
+                -1,
+            ))
+            .map(|_| ())
+        }
+    }
+
+    /// Signs the CRL with a private key.
+    #[corresponds(X509_CRL_sign)]
+    pub fn sign<T>(&mut self, key: &PKeyRef<T>, hash: MessageDigest) -> Result<(), ErrorStack>
+    where
+        T: HasPrivate,
+    {
+        unsafe {
+            cvt(ffi::X509_CRL_sort(self.0.as_ptr()))?;
+            cvt(ffi::X509_CRL_sign(
+                self.0.as_ptr(),
+                key.as_ptr(),
+                hash.as_ptr(),
+            ))
+            .map(|_| ())
+        }
+    }
+
+    /// Signs the CRL with an external signer.
+    ///
+    /// See [`CertSigner`] for details.
+    pub fn sign_with(&mut self, signer: &dyn CertSigner) -> Result<(), ErrorStack> {
+        unsafe {
+            cvt(ffi::X509_CRL_sort(self.0.as_ptr()))?;
+            CRL_SIGN.sign(self.0.as_ptr(), signer)
+        }
+    }
+
+    /// Signs the CRL with an external asynchronous signer.
+    ///
+    /// See [`AsyncCertSigner`] for details.
+    pub async fn sign_with_async(
+        &mut self,
+        signer: &dyn AsyncCertSigner,
+    ) -> Result<(), ErrorStack> {
+        let tbs = unsafe {
+            cvt(ffi::X509_CRL_sort(self.0.as_ptr()))?;
+            CRL_SIGN.prepare(self.0.as_ptr(), signer.algorithm())?
+        };
+        let signature = signer.sign(tbs).await.map_err(ErrorStack::internal_error)?;
+        unsafe { CRL_SIGN.finish(self.0.as_ptr(), &signature) }
+    }
+
+    /// Consumes the builder, returning the CRL.
+    #[must_use]
+    pub fn build(self) -> X509Crl {
+        self.0
+    }
+}
+
+foreign_type_and_impl_send_sync! {
+    type CType = ffi::X509_CRL;
+    fn drop = ffi::X509_CRL_free;
+
+    /// A certificate revocation list.
+    pub struct X509Crl;
+}
+
+impl X509Crl {
+    /// A builder for `X509Crl`.
+    pub fn builder() -> Result<X509CrlBuilder, ErrorStack> {
+        X509CrlBuilder::new()
+    }
+
+    from_pem! {
+        /// Deserializes a PEM-encoded certificate revocation list.
+        ///
+        /// The input should have a header of `-----BEGIN X509 CRL-----`.
+        #[corresponds(PEM_read_bio_X509_CRL)]
+        from_pem,
+        X509Crl,
+        ffi::PEM_read_bio_X509_CRL
+    }
+
+    from_der! {
+        /// Deserializes a DER-encoded certificate revocation list.
+        #[corresponds(d2i_X509_CRL)]
+        from_der,
+        X509Crl,
+        ffi::d2i_X509_CRL,
+        ::libc::c_long
+    }
+}
+
+impl X509CrlRef {
+    to_pem! {
+        /// Serializes the CRL into a PEM-encoded structure.
+        ///
+        /// The output will have a header of `-----BEGIN X509 CRL-----`.
+        #[corresponds(PEM_write_bio_X509_CRL)]
+        to_pem,
+        ffi::PEM_write_bio_X509_CRL
+    }
+
+    to_der! {
+        /// Serializes the CRL into a DER-encoded structure.
+        #[corresponds(i2d_X509_CRL)]
+        to_der,
+        ffi::i2d_X509_CRL
+    }
+
+    /// Returns a human-readable description of the CRL, in the format of `openssl crl -text`.
+    #[corresponds(X509_CRL_print)]
+    pub fn to_text(&self) -> Result<String, ErrorStack> {
+        print_to_string(|bio| unsafe { ffi::X509_CRL_print(bio, self.as_ptr()) })
+    }
+
+    /// Returns the issuer name of the CRL.
+    #[corresponds(X509_CRL_get_issuer)]
+    #[must_use]
+    pub fn issuer_name(&self) -> &X509NameRef {
+        unsafe { X509NameRef::from_const_ptr(ffi::X509_CRL_get_issuer(self.as_ptr())) }
+    }
+
+    /// Returns the time this CRL was issued.
+    #[corresponds(X509_CRL_get0_lastUpdate)]
+    #[must_use]
+    pub fn last_update(&self) -> &Asn1TimeRef {
+        unsafe { Asn1TimeRef::from_const_ptr(ffi::X509_CRL_get0_lastUpdate(self.as_ptr())) }
+    }
+
+    /// Returns the time by which the next CRL will be issued, if set.
+    #[corresponds(X509_CRL_get0_nextUpdate)]
+    #[must_use]
+    pub fn next_update(&self) -> Option<&Asn1TimeRef> {
+        unsafe { Asn1TimeRef::from_const_ptr_opt(ffi::X509_CRL_get0_nextUpdate(self.as_ptr())) }
+    }
+
+    /// Returns the revoked certificates listed in the CRL, if any.
+    #[corresponds(X509_CRL_get_REVOKED)]
+    #[must_use]
+    pub fn revoked(&self) -> Option<&StackRef<X509Revoked>> {
+        unsafe { StackRef::from_const_ptr_opt(ffi::X509_CRL_get_REVOKED(self.as_ptr())) }
+    }
+
+    /// Checks that the CRL is signed using the given public key.
+    #[corresponds(X509_CRL_verify)]
+    pub fn verify<T>(&self, key: &PKeyRef<T>) -> Result<bool, ErrorStack>
+    where
+        T: HasPublic,
+    {
+        unsafe { cvt_n(ffi::X509_CRL_verify(self.as_ptr(), key.as_ptr())).map(|n| n != 0) }
+    }
+}
+
+foreign_type_and_impl_send_sync! {
+    type CType = ffi::X509_REVOKED;
+    fn drop = ffi::X509_REVOKED_free;
+
+    /// An entry in a certificate revocation list.
+    pub struct X509Revoked;
+}
+
+impl Stackable for X509Revoked {
+    type StackType = ffi::stack_st_X509_REVOKED;
+}
+
+impl X509RevokedRef {
+    /// Returns the serial number of the revoked certificate.
+    #[corresponds(X509_REVOKED_get0_serialNumber)]
+    #[must_use]
+    pub fn serial_number(&self) -> &Asn1IntegerRef {
+        unsafe {
+            Asn1IntegerRef::from_const_ptr(ffi::X509_REVOKED_get0_serialNumber(self.as_ptr()))
+        }
+    }
+
+    /// Returns the time the certificate was revoked.
+    #[corresponds(X509_REVOKED_get0_revocationDate)]
+    #[must_use]
+    pub fn revocation_date(&self) -> &Asn1TimeRef {
+        unsafe { Asn1TimeRef::from_const_ptr(ffi::X509_REVOKED_get0_revocationDate(self.as_ptr())) }
+    }
+}
@@ -16,12 +16,17 @@
 //! let extension: X509Extension = bc.build().unwrap();
 //! ```
 use std::fmt::Write;
+use std::mem;
+use std::net::IpAddr;
 
-use crate::asn1::Asn1Object;
+use crate::asn1::{Asn1Object, Asn1Type};
+use crate::der;
 use crate::error::ErrorStack;
 use crate::nid::Nid;
 use crate::x509::{GeneralName, Stack, X509Extension, X509v3Context};
+use crate::{cvt, cvt_0, cvt_p, ffi};
 use foreign_types::ForeignType;
+use libc::c_int;
 
 /// An extension which indicates whether a certificate is a CA certificate.
 pub struct BasicConstraints {
@@ -524,6 +529,506 @@ impl SubjectAlternativeName {
     }
 }
 
+enum Subtree {
+    Dns(String),
+    Email(String),
+    Uri(String),
+    IpRange(IpAddr, u8),
+}
+
+impl Subtree {
+    fn general_name(&self) -> Result<GeneralName, ErrorStack> {
+        match self {
+            Subtree::Dns(s) => GeneralName::new_dns(s.as_bytes()),
+            Subtree::Email(s) => GeneralName::new_email(s.as_bytes()),
+            Subtree::Uri(s) => GeneralName::new_uri(s.as_bytes()),
+            Subtree::IpRange(ip, prefix) => GeneralName::new_ip_range(*ip, *prefix),
+        }
+    }
+}
+
+/// An extension restricting the names a CA certificate may issue certificates for.
+///
+/// RFC 5280 requires this extension to be marked critical, so it is critical unless
+/// [`NameConstraints::non_critical`] is called.
+pub struct NameConstraints {
+    critical: bool,
+    permitted: Vec<Subtree>,
+    excluded: Vec<Subtree>,
+}
+
+impl Default for NameConstraints {
+    fn default() -> NameConstraints {
+        NameConstraints::new()
+    }
+}
+
+impl NameConstraints {
+    /// Construct a new `NameConstraints` extension.
+    #[must_use]
+    pub fn new() -> NameConstraints {
+        NameConstraints {
+            critical: true,
+            permitted: vec![],
+            excluded: vec![],
+        }
+    }
+
+    /// Sets the `critical` flag to `true`. The extension will be critical.
+    ///
+    /// This is the default.
+    pub fn critical(&mut self) -> &mut NameConstraints {
+        self.critical = true;
+        self
+    }
+
+    /// Sets the `critical` flag to `false`, for relying parties which do not support the
+    /// extension.
+    pub fn non_critical(&mut self) -> &mut NameConstraints {
+        self.critical = false;
+        self
+    }
+
+    /// Permits DNS names within `dns`, e.g. `example.com` or `.example.com`.
+    pub fn permitted_dns(&mut self, dns: &str) -> &mut NameConstraints {
+        self.permitted.push(Subtree::Dns(dns.to_string()));
+        self
+    }
+
+    /// Permits email addresses matching `email`, which may be a mailbox, host or domain.
+    pub fn permitted_email(&mut self, email: &str) -> &mut NameConstraints {
+        self.permitted.push(Subtree::Email(email.to_string()));
+        self
+    }
+
+    /// Permits URIs whose host is within `uri`.
+    pub fn permitted_uri(&mut self, uri: &str) -> &mut NameConstraints {
+        self.permitted.push(Subtree::Uri(uri.to_string()));
+        self
+    }
+
+    /// Permits IP addresses within the network `ip/prefix`.
+    pub fn permitted_ip_range(&mut self, ip: IpAddr, prefix: u8) -> &mut NameConstraints {
+        self.permitted.push(Subtree::IpRange(ip, prefix));
+        self
+    }
+
+    /// Excludes DNS names within `dns`.
+    pub fn excluded_dns(&mut self, dns: &str) -> &mut NameConstraints {
+        self.excluded.push(Subtree::Dns(dns.to_string()));
+        self
+    }
+
+    /// Excludes email addresses matching `email`.
+    pub fn excluded_email(&mut self, email: &str) -> &mut NameConstraints {
+        self.excluded.push(Subtree::Email(email.to_string()));
+        self
+    }
+
+    /// Excludes URIs whose host is within `uri`.
+    pub fn excluded_uri(&mut self, uri: &str) -> &mut NameConstraints {
+        self.excluded.push(Subtree::Uri(uri.to_string()));
+        self
+    }
+
+    /// Excludes IP addresses within the network `ip/prefix`.
+    pub fn excluded_ip_range(&mut self, ip: IpAddr, prefix: u8) -> &mut NameConstraints {
+        self.excluded.push(Subtree::IpRange(ip, prefix));
+        self
+    }
+
+    /// Return the `NameConstraints` extension as an `X509Extension`.
+    pub fn build(&self) -> Result<X509Extension, ErrorStack> {
+        unsafe fn subtrees(
+            field: &mut *mut ffi::stack_st_GENERAL_SUBTREE,
+            items: &[Subtree],
+        ) -> Result<(), ErrorStack> {
+            if items.is_empty() {
+                return Ok(());
+            }
+            *field = cvt_p(ffi::sk_new_null())?.cast();
+            for item in items {
+                let base = item.general_name()?;
+                let subtree = Owned::new(ffi::GENERAL_SUBTREE_new(), ffi::GENERAL_SUBTREE_free)?;
+                ffi::GENERAL_NAME_free((*subtree.as_ptr()).base);
+                (*subtree.as_ptr()).base = base.into_ptr();
+                push(*field, subtree)?;
+            }
+            Ok(())
+        }
+
+        unsafe {
+            ffi::init();
+            let nc = Owned::new(ffi::NAME_CONSTRAINTS_new(), ffi::NAME_CONSTRAINTS_free)?;
+            subtrees(&mut (*nc.as_ptr()).permittedSubtrees, &self.permitted)?;
+            subtrees(&mut (*nc.as_ptr()).excludedSubtrees, &self.excluded)?;
+            X509Extension::new_internal(Nid::NAME_CONSTRAINTS, self.critical, nc.as_ptr().cast())
+        }
+    }
+}
+
+/// A qualifier attached to a policy in a `CertificatePolicies` extension.
+pub enum PolicyQualifier {
+    /// A pointer to the certification practice statement published by the CA.
+    Cps(String),
+    /// A user notice carrying explicit text to be displayed to relying parties.
+    UserNotice(String),
+}
+
+/// An extension listing the policies under which a certificate was issued.
+pub struct CertificatePolicies {
+    critical: bool,
+    policies: Vec<(String, Vec<PolicyQualifier>)>,
+}
+
+impl Default for CertificatePolicies {
+    fn default() -> CertificatePolicies {
+        CertificatePolicies::new()
+    }
+}
+
+impl CertificatePolicies {
+    /// Construct a new `CertificatePolicies` extension.
+    #[must_use]
+    pub fn new() -> CertificatePolicies {
+        CertificatePolicies {
+            critical: false,
+            policies: vec![],
+        }
+    }
+
+    /// Sets the `critical` flag to `true`. The extension will be critical.
+    pub fn critical(&mut self) -> &mut CertificatePolicies {
+        self.critical = true;
+        self
+    }
+
+    /// Adds the policy identified by the dotted `oid`.
+    pub fn policy(&mut self, oid: &str) -> &mut CertificatePolicies {
+        self.policy_with_qualifiers(oid, vec![])
+    }
+
+    /// Adds the policy identified by the dotted `oid` along with its qualifiers.
+    pub fn policy_with_qualifiers(
+        &mut self,
+        oid: &str,
+        qualifiers: Vec<PolicyQualifier>,
+    ) -> &mut CertificatePolicies {
+        self.policies.push((oid.to_string(), qualifiers));
+        self
+    }
+
+    /// Adds the special `anyPolicy` identifier.
+    pub fn any_policy(&mut self) -> &mut CertificatePolicies {
+        self.policy("2.5.29.32.0")
+    }
+
+    /// Return the `CertificatePolicies` extension as an `X509Extension`.
+    pub fn build(&self) -> Result<X509Extension, ErrorStack> {
+        unsafe fn qualifier(
+            qualifier: &PolicyQualifier,
+        ) -> Result<Owned<ffi::POLICYQUALINFO>, ErrorStack> {
+            let info = Owned::new(ffi::POLICYQUALINFO_new(), ffi::POLICYQUALINFO_free)?;
+            match qualifier {
+                PolicyQualifier::Cps(uri) => {
+                    (*info.as_ptr()).pqualid = ffi::OBJ_nid2obj(Nid::ID_QT_CPS.as_raw());
+                    (*info.as_ptr()).d.cpsuri = string(Asn1Type::IA5STRING, uri)?;
+                }
+                PolicyQualifier::UserNotice(text) => {
+                    (*info.as_ptr()).pqualid = ffi::OBJ_nid2obj(Nid::ID_QT_UNOTICE.as_raw());
+                    let notice = cvt_p(ffi::USERNOTICE_new())?;
+                    (*info.as_ptr()).d.usernotice = notice;
+                    (*notice).exptext = string(Asn1Type::UTF8STRING, text)?;
+                }
+            }
+            Ok(info)
+        }
+
+        unsafe {
+            ffi::init();
+            let policies = Owned::new(
+                ffi::CERTIFICATEPOLICIES_new(),
+                ffi::CERTIFICATEPOLICIES_free,
+            )?;
+            for (oid, qualifiers) in &self.policies {
+                let info = Owned::new(ffi::POLICYINFO_new(), ffi::POLICYINFO_free)?;
+                (*info.as_ptr()).policyid = Asn1Object::from_str(oid)?.into_ptr();
+                if !qualifiers.is_empty() {
+                    (*info.as_ptr()).qualifiers = cvt_p(ffi::sk_new_null())?.cast();
+                    for q in qualifiers {
+                        push((*info.as_ptr()).qualifiers, qualifier(q)?)?;
+                    }
+                }
+                push(policies.as_ptr(), info)?;
+            }
+            X509Extension::new_internal(
+                Nid::CERTIFICATE_POLICIES,
+                self.critical,
+                policies.as_ptr().cast(),
+            )
+        }
+    }
+}
+
+/// An extension listing where the CRLs covering a certificate can be retrieved.
+pub struct CrlDistributionPoints {
+    critical: bool,
+    points: Vec<Vec<String>>,
+}
+
+impl Default for CrlDistributionPoints {
+    fn default() -> CrlDistributionPoints {
+        CrlDistributionPoints::new()
+    }
+}
+
+impl CrlDistributionPoints {
+    /// Construct a new `CrlDistributionPoints` extension.
+    #[must_use]
+    pub fn new() -> CrlDistributionPoints {
+        CrlDistributionPoints {
+            critical: false,
+            points: vec![],
+        }
+    }
+
+    /// Sets the `critical` flag to `true`. The extension will be critical.
+    pub fn critical(&mut self) -> &mut CrlDistributionPoints {
+        self.critical = true;
+        self
+    }
+
+    /// Adds a distribution point whose full name is `uri`.
+    pub fn uri(&mut self, uri: &str) -> &mut CrlDistributionPoints {
+        self.distribution_point(&[uri])
+    }
+
+    /// Adds a single distribution point reachable through any of `uris`.
+    pub fn distribution_point(&mut self, uris: &[&str]) -> &mut CrlDistributionPoints {
+        self.points
+            .push(uris.iter().map(|uri| uri.to_string()).collect());
+        self
+    }
+
+    /// Return the `CrlDistributionPoints` extension as an `X509Extension`.
+    pub fn build(&self) -> Result<X509Extension, ErrorStack> {
+        unsafe {
+            ffi::init();
+            let points = Owned::new(ffi::CRL_DIST_POINTS_new(), ffi::CRL_DIST_POINTS_free)?;
+            for uris in &self.points {
+                let point = Owned::new(ffi::DIST_POINT_new(), ffi::DIST_POINT_free)?;
+                let name = cvt_p(ffi::DIST_POINT_NAME_new())?;
+                (*point.as_ptr()).distpoint = name;
+
+                let mut full_name = Stack::<GeneralName>::new()?;
+                for uri in uris {
+                    full_name.push(GeneralName::new_uri(uri.as_bytes())?)?;
+                }
+                (*name).type_ = DIST_POINT_FULL_NAME;
+                (*name).name.fullname = full_name.as_ptr().cast();
+                mem::forget(full_name);
+
+                push(points.as_ptr(), point)?;
+            }
+            X509Extension::new_internal(
+                Nid::CRL_DISTRIBUTION_POINTS,
+                self.critical,
+                points.as_ptr().cast(),
+            )
+        }
+    }
+}
+
+/// An extension describing how to access information and services of the certificate's issuer,
+/// such as its OCSP responder or its own certificate.
+pub struct AuthorityInfoAccess {
+    critical: bool,
+    items: Vec<(Nid, String)>,
+}
+
+impl Default for AuthorityInfoAccess {
+    fn default() -> AuthorityInfoAccess {
+        AuthorityInfoAccess::new()
+    }
+}
+
+impl AuthorityInfoAccess {
+    /// Construct a new `AuthorityInfoAccess` extension.
+    #[must_use]
+    pub fn new() -> AuthorityInfoAccess {
+        AuthorityInfoAccess {
+            critical: false,
+            items: vec![],
+        }
+    }
+
+    /// Sets the `critical` flag to `true`. The extension will be critical.
+    pub fn critical(&mut self) -> &mut AuthorityInfoAccess {
+        self.critical = true;
+        self
+    }
+
+    /// Adds the URI of an OCSP responder for the certificate.
+    pub fn ocsp(&mut self, uri: &str) -> &mut AuthorityInfoAccess {
+        self.items.push((Nid::AD_OCSP, uri.to_string()));
+        self
+    }
+
+    /// Adds the URI from which the issuer's certificate can be downloaded.
+    pub fn ca_issuers(&mut self, uri: &str) -> &mut AuthorityInfoAccess {
+        self.items.push((Nid::AD_CA_ISSUERS, uri.to_string()));
+        self
+    }
+
+    /// Return the `AuthorityInfoAccess` extension as an `X509Extension`.
+    pub fn build(&self) -> Result<X509Extension, ErrorStack> {
+        unsafe {
+            ffi::init();
+            let info = Owned::new(
+                ffi::AUTHORITY_INFO_ACCESS_new(),
+                ffi::AUTHORITY_INFO_ACCESS_free,
+            )?;
+            for (method, uri) in &self.items {
+                let location = GeneralName::new_uri(uri.as_bytes())?;
+                let desc = Owned::new(ffi::ACCESS_DESCRIPTION_new(), ffi::ACCESS_DESCRIPTION_free)?;
+                (*desc.as_ptr()).method = ffi::OBJ_nid2obj(method.as_raw());
+                ffi::GENERAL_NAME_free((*desc.as_ptr()).location);
+                (*desc.as_ptr()).location = location.into_ptr();
+                push(info.as_ptr(), desc)?;
+            }
+            X509Extension::new_internal(Nid::INFO_ACCESS, self.critical, info.as_ptr().cast())
+        }
+    }
+}
+
+/// An extension listing TLS features the certificate's holder promises to support (RFC 7633).
+pub struct TlsFeature {
+    critical: bool,
+    features: Vec<u8>,
+}
+
+impl Default for TlsFeature {
+    fn default() -> TlsFeature {
+        TlsFeature::new()
+    }
+}
+
+impl TlsFeature {
+    /// Construct a new `TlsFeature` extension.
+    #[must_use]
+    pub fn new() -> TlsFeature {
+        TlsFeature {
+            critical: false,
+            features: vec![],
+        }
+    }
+
+    /// Sets the `critical` flag to `true`. The extension will be critical.
+    pub fn critical(&mut self) -> &mut TlsFeature {
+        self.critical = true;
+        self
+    }
+
+    /// Requires the `status_request` feature, also known as OCSP must-staple.
+    pub fn status_request(&mut self) -> &mut TlsFeature {
+        self.feature(5)
+    }
+
+    /// Requires the `status_request_v2` feature.
+    pub fn status_request_v2(&mut self) -> &mut TlsFeature {
+        self.feature(17)
+    }
+
+    fn feature(&mut self, extension_type: u8) -> &mut TlsFeature {
+        if !self.features.contains(&extension_type) {
+            self.features.push(extension_type);
+        }
+        self
+    }
+
+    /// Return the `TlsFeature` extension as an `X509Extension`.
+    pub fn build(&self) -> Result<X509Extension, ErrorStack> {
+        let value = der::encode(|der| {
+            der.write_nested(der::SEQUENCE, |der| {
+                for &feature in &self.features {
+                    der.write_u32(feature.into());
+                }
+            });
+        });
+        let oid = Asn1Object::from_str(Nid::TLSFEATURE.short_name()?)?;
+        X509Extension::new_from_der(&oid, self.critical, &value)
+    }
+}
+
+/// An extension allowing the certificate's key to sign delegated credentials (RFC 9345).
+#[derive(Default)]
+pub struct DelegationUsage {
+    _priv: (),
+}
+
+impl DelegationUsage {
+    /// The OID of the extension.
+    pub const OID: &'static str = "1.3.6.1.4.1.44363.44";
+
+    /// Construct a new `DelegationUsage` extension.
+    #[must_use]
+    pub fn new() -> DelegationUsage {
+        DelegationUsage { _priv: () }
+    }
+
+    /// Return the `DelegationUsage` extension as an `X509Extension`.
+    pub fn build(&self) -> Result<X509Extension, ErrorStack> {
+        // The value is an ASN.1 NULL, and the extension is never critical.
+        let oid = Asn1Object::from_str(Self::OID)?;
+        X509Extension::new_from_der(&oid, false, b"\x05\x00")
+    }
+}
+
+/// The `fullName` choice of a `DistributionPointName`, which BoringSSL has no constant for.
+const DIST_POINT_FULL_NAME: c_int = 0;
+
+/// A freshly allocated ASN.1 structure, freed unless handed over to its parent.
+struct Owned<T>(*mut T, unsafe extern "C" fn(*mut T));
+
+impl<T> Owned<T> {
+    unsafe fn new(ptr: *mut T, free: unsafe extern "C" fn(*mut T)) -> Result<Owned<T>, ErrorStack> {
+        cvt_p(ptr).map(|ptr| Owned(ptr, free))
+    }
+
+    fn as_ptr(&self) -> *mut T {
+        self.0
+    }
+
+    fn into_ptr(self) -> *mut T {
+        let ptr = self.0;
+        mem::forget(self);
+        ptr
+    }
+}
+
+impl<T> Drop for Owned<T> {
+    fn drop(&mut self) {
+        unsafe { (self.1)(self.0) }
+    }
+}
+
+/// Appends `item` to a raw OpenSSL stack, which takes ownership of it.
+unsafe fn push<S, T>(stack: *mut S, item: Owned<T>) -> Result<(), ErrorStack> {
+    cvt_0(ffi::sk_push(stack.cast(), item.as_ptr().cast()))?;
+    item.into_ptr();
+    Ok(())
+}
+
+unsafe fn string(ty: Asn1Type, value: &str) -> Result<*mut ffi::ASN1_STRING, ErrorStack> {
+    let s = Owned::new(
+        ffi::ASN1_STRING_type_new(ty.as_raw()),
+        ffi::ASN1_STRING_free,
+    )?;
+    let len = value.len().try_into().map_err(ErrorStack::internal_error)?;
+    cvt(ffi::ASN1_STRING_set(s.as_ptr(), value.as_ptr().cast(), len))?;
+    Ok(s.into_ptr())
+}
+
 fn append(value: &mut String, first: &mut bool, should: bool, element: &str) {
     if !should {
         return;
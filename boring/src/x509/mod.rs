@@ -10,6 +10,7 @@
 use foreign_types::{ForeignType, ForeignTypeRef};
 use libc::{c_int, c_long, c_void};
 use openssl_macros::corresponds;
+use std::cmp::Ordering;
 use std::convert::TryInto;
 use std::error::Error;
 use std::ffi::{CStr, CString};
@@ -23,8 +24,8 @@ use std::str;
 use std::sync::{LazyLock, Once};
 
 use crate::asn1::{
-    Asn1BitStringRef, Asn1IntegerRef, Asn1Object, Asn1ObjectRef, Asn1StringRef, Asn1TimeRef,
-    Asn1Type,
+    Asn1BitStringRef, Asn1IntegerRef, Asn1Object, Asn1ObjectRef, Asn1String, Asn1StringRef,
+    Asn1TimeRef, Asn1Type,
 };
 use crate::bio::{MemBio, MemBioSlice};
 use crate::conf::ConfRef;
@@ -33,15 +34,23 @@ use crate::ex_data::Index;
 use crate::hash::{DigestBytes, MessageDigest};
 use crate::nid::Nid;
 use crate::pkey::{HasPrivate, HasPublic, PKey, PKeyRef, Public};
+use crate::sha::sha256;
 use crate::ssl::SslRef;
 use crate::stack::{Stack, StackRef, Stackable};
 use crate::string::OpensslString;
 use crate::util::ForeignTypeRefExt;
+use crate::x509::signer::{AsyncCertSigner, CertSigner, SignFns};
 use crate::x509::verify::{X509VerifyParam, X509VerifyParamRef};
 use crate::{cvt, cvt_n, cvt_p};
 use crate::{ffi, free_data_box};
 
+pub mod aia;
+pub mod ca;
+pub mod chain;
+pub mod crl;
 pub mod extension;
+mod rfc4514;
+pub mod signer;
 pub mod store;
 pub mod verify;
 
@@ -502,6 +511,25 @@ impl X509Builder {
         unsafe { cvt(ffi::X509_sign(self.0.as_ptr(), key.as_ptr(), hash.as_ptr())).map(|_| ()) }
     }
 
+    /// Signs the certificate with an external signer.
+    ///
+    /// See [`CertSigner`] for details.
+    pub fn sign_with(&mut self, signer: &dyn CertSigner) -> Result<(), ErrorStack> {
+        unsafe { X509_SIGN.sign(self.0.as_ptr(), signer) }
+    }
+
+    /// Signs the certificate with an external asynchronous signer.
+    ///
+    /// See [`AsyncCertSigner`] for details.
+    pub async fn sign_with_async(
+        &mut self,
+        signer: &dyn AsyncCertSigner,
+    ) -> Result<(), ErrorStack> {
+        let tbs = unsafe { X509_SIGN.prepare(self.0.as_ptr(), signer.algorithm())? };
+        let signature = signer.sign(tbs).await.map_err(ErrorStack::internal_error)?;
+        unsafe { X509_SIGN.finish(self.0.as_ptr(), &signature) }
+    }
+
     /// Consumes the builder, returning the certificate.
     #[must_use]
     pub fn build(self) -> X509 {
@@ -583,6 +611,25 @@ impl X509Ref {
         }
     }
 
+    /// Returns this certificate's authority information access entries, if they exist.
+    #[corresponds(X509_get_ext_d2i)]
+    #[must_use]
+    pub fn authority_info(&self) -> Option<Stack<AccessDescription>> {
+        unsafe {
+            let stack = ffi::X509_get_ext_d2i(
+                self.as_ptr(),
+                ffi::NID_info_access,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            );
+            if stack.is_null() {
+                None
+            } else {
+                Some(Stack::from_ptr(stack as *mut _))
+            }
+        }
+    }
+
     /// Returns this certificate's subject key id, if it exists.
     #[corresponds(X509_get0_subject_key_id)]
     #[must_use]
@@ -611,6 +658,24 @@ impl X509Ref {
         }
     }
 
+    /// Returns the DER encoding of this certificate's SubjectPublicKeyInfo, as it appears in the
+    /// certificate.
+    #[corresponds(X509_get_X509_PUBKEY)]
+    pub fn spki_der(&self) -> Result<Vec<u8>, ErrorStack> {
+        unsafe {
+            let spki = ffi::X509_get_X509_PUBKEY(self.as_ptr());
+            let len = cvt(ffi::i2d_X509_PUBKEY(spki, ptr::null_mut()))?;
+            let mut buf = vec![0; len as usize];
+            cvt(ffi::i2d_X509_PUBKEY(spki, &mut buf.as_mut_ptr()))?;
+            Ok(buf)
+        }
+    }
+
+    /// Returns the SHA-256 digest of [`Self::spki_der`], the value used for public key pinning.
+    pub fn spki_sha256(&self) -> Result<[u8; 32], ErrorStack> {
+        Ok(sha256(&self.spki_der()?))
+    }
+
     /// Returns a digest of the DER representation of the certificate.
     #[corresponds(X509_digest)]
     pub fn digest(&self, hash_type: MessageDigest) -> Result<DigestBytes, ErrorStack> {
@@ -759,6 +824,15 @@ impl X509Ref {
         to_der,
         ffi::i2d_X509
     }
+
+    /// Returns a human-readable description of the certificate, in the format of
+    /// `openssl x509 -text`.
+    ///
+    /// The output only depends on the certificate, so it is suitable for logs and snapshot tests.
+    #[corresponds(X509_print)]
+    pub fn to_text(&self) -> Result<String, ErrorStack> {
+        print_to_string(|bio| unsafe { ffi::X509_print(bio, self.as_ptr()) })
+    }
 }
 
 impl ToOwned for X509Ref {
@@ -988,6 +1062,31 @@ impl X509Extension {
         }
     }
 
+    /// Constructs an extension identified by `oid` from the DER encoding of its value.
+    pub(crate) fn new_from_der(
+        oid: &Asn1ObjectRef,
+        critical: bool,
+        der: &[u8],
+    ) -> Result<X509Extension, ErrorStack> {
+        let len = der.len().try_into().map_err(ErrorStack::internal_error)?;
+        unsafe {
+            ffi::init();
+            let value = Asn1String::from_ptr(cvt_p(ffi::ASN1_OCTET_STRING_new())?);
+            cvt(ffi::ASN1_OCTET_STRING_set(
+                value.as_ptr(),
+                der.as_ptr(),
+                len,
+            ))?;
+            cvt_p(ffi::X509_EXTENSION_create_by_OBJ(
+                ptr::null_mut(),
+                oid.as_ptr(),
+                critical as _,
+                value.as_ptr(),
+            ))
+            .map(|p| X509Extension::from_ptr(p))
+        }
+    }
+
     pub(crate) unsafe fn new_internal(
         nid: Nid,
         critical: bool,
@@ -1149,6 +1248,47 @@ impl X509Name {
         ffi::d2i_X509_NAME,
         ::libc::c_long
     }
+
+    /// Parses a name from its [RFC 4514] string representation, such as
+    /// `CN=foo,O=Bar\, Inc,C=US`.
+    ///
+    /// As in the RFC, the most significant RDN comes last, and the attributes of a multi-valued
+    /// RDN are joined with `+`. Attribute types may be RFC 4514 keywords (in any case), OpenSSL
+    /// short or long names, or dotted OIDs. Hex-encoded (`#...`) values are not supported.
+    ///
+    /// [RFC 4514]: https://www.rfc-editor.org/rfc/rfc4514
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use boring2::x509::X509Name;
+    ///
+    /// let name = X509Name::from_rfc4514(r"CN=foo,O=Bar\, Inc,C=US").unwrap();
+    /// assert_eq!(name.to_rfc4514().unwrap(), r"CN=foo,O=Bar\, Inc,C=US");
+    /// ```
+    pub fn from_rfc4514(name: &str) -> Result<X509Name, ErrorStack> {
+        let rdns = rfc4514::parse(name).map_err(ErrorStack::internal_error_str)?;
+        let builder = X509NameBuilder::new()?;
+        for rdn in rdns.iter().rev() {
+            for (i, (ty, value)) in rdn.iter().enumerate() {
+                let object = rfc4514::attribute_object(ty)?;
+                assert!(value.len() <= ValueLen::MAX as usize);
+                unsafe {
+                    // A set of -1 adds the attribute to the previous RDN.
+                    cvt(ffi::X509_NAME_add_entry_by_OBJ(
+                        builder.0.as_ptr(),
+                        object.as_ptr(),
+                        ffi::MBSTRING_UTF8,
+                        value.as_ptr(),
+                        value.len() as ValueLen,
+                        -1,
+                        if i == 0 { 0 } else { -1 },
+                    ))?;
+                }
+            }
+        }
+        Ok(builder.build())
+    }
 }
 
 impl Stackable for X509Name {
@@ -1197,6 +1337,125 @@ impl X509NameRef {
         to_der,
         ffi::i2d_X509_NAME
     }
+
+    /// Returns the [RFC 4514] string representation of the name.
+    ///
+    /// Attribute types are written as RFC 4514 keywords where one exists, then as OpenSSL short
+    /// names, then as dotted OIDs. The output only depends on the encoded name and can be parsed
+    /// back with [`X509Name::from_rfc4514`].
+    ///
+    /// [RFC 4514]: https://www.rfc-editor.org/rfc/rfc4514
+    pub fn to_rfc4514(&self) -> Result<String, ErrorStack> {
+        let rdns = self.rdns(|entry| {
+            let mut attribute = rfc4514::attribute_type(entry.object())?;
+            attribute.push('=');
+            rfc4514::escape(&entry.data().as_utf8()?, &mut attribute);
+            Ok(attribute)
+        })?;
+        Ok(rdns
+            .iter()
+            .map(|rdn| rdn.join("+"))
+            .collect::<Vec<_>>()
+            .join(","))
+    }
+
+    /// Returns a string identifying the name up to the differences ignored by
+    /// [`X509NameRef::compare`].
+    ///
+    /// Values are trimmed, have their inner whitespace collapsed and are lowercased, attribute
+    /// types are dotted OIDs, and the attributes of multi-valued RDNs are sorted. This is
+    /// suitable as a map key for names.
+    pub fn to_canonical_string(&self) -> Result<String, ErrorStack> {
+        let mut rdns = self.rdns(|entry| {
+            let mut attribute = rfc4514::oid(entry.object())?;
+            attribute.push('=');
+            let value = rfc4514::canonicalize(&entry.data().as_utf8()?);
+            rfc4514::escape(&value, &mut attribute);
+            Ok(attribute)
+        })?;
+        Ok(rdns
+            .iter_mut()
+            .map(|rdn| {
+                rdn.sort_unstable();
+                rdn.join("+")
+            })
+            .collect::<Vec<_>>()
+            .join(","))
+    }
+
+    /// Returns the formatted attributes of each RDN, most significant RDN last.
+    fn rdns(
+        &self,
+        mut attribute: impl FnMut(&X509NameEntryRef) -> Result<String, ErrorStack>,
+    ) -> Result<Vec<Vec<String>>, ErrorStack> {
+        let mut rdns = Vec::<Vec<String>>::new();
+        let mut last_set = -1;
+        for entry in self.entries() {
+            let set = unsafe { ffi::X509_NAME_ENTRY_set(entry.as_ptr()) };
+            if set != last_set || rdns.is_empty() {
+                rdns.push(vec![]);
+                last_set = set;
+            }
+            rdns.last_mut().unwrap().push(attribute(entry)?);
+        }
+        rdns.reverse();
+        Ok(rdns)
+    }
+
+    /// Compares two names after normalizing their string values.
+    ///
+    /// Names differing only in the string types of their values, in letter case or in
+    /// whitespace compare equal.
+    #[corresponds(X509_NAME_cmp)]
+    pub fn compare(&self, other: &X509NameRef) -> Result<Ordering, ErrorStack> {
+        unsafe {
+            ErrorStack::clear();
+            let cmp = ffi::X509_NAME_cmp(self.as_ptr(), other.as_ptr());
+            if cmp == -2 {
+                let errors = ErrorStack::get();
+                if !errors.errors().is_empty() {
+                    return Err(errors);
+                }
+            }
+            Ok(cmp.cmp(&0))
+        }
+    }
+}
+
+impl PartialEq for X509NameRef {
+    fn eq(&self, other: &X509NameRef) -> bool {
+        matches!(self.compare(other), Ok(Ordering::Equal))
+    }
+}
+
+impl PartialEq<X509Name> for X509NameRef {
+    fn eq(&self, other: &X509Name) -> bool {
+        matches!(self.compare(other), Ok(Ordering::Equal))
+    }
+}
+
+impl PartialEq for X509Name {
+    fn eq(&self, other: &X509Name) -> bool {
+        matches!(self.compare(other), Ok(Ordering::Equal))
+    }
+}
+
+impl PartialEq<X509NameRef> for X509Name {
+    fn eq(&self, other: &X509NameRef) -> bool {
+        matches!(self.compare(other), Ok(Ordering::Equal))
+    }
+}
+
+impl PartialOrd for X509NameRef {
+    fn partial_cmp(&self, other: &X509NameRef) -> Option<Ordering> {
+        self.compare(other).ok()
+    }
+}
+
+impl PartialOrd for X509Name {
+    fn partial_cmp(&self, other: &X509Name) -> Option<Ordering> {
+        self.compare(other).ok()
+    }
 }
 
 impl fmt::Debug for X509NameRef {
@@ -1375,6 +1634,25 @@ impl X509ReqBuilder {
         }
     }
 
+    /// Signs the request with an external signer.
+    ///
+    /// See [`CertSigner`] for details.
+    pub fn sign_with(&mut self, signer: &dyn CertSigner) -> Result<(), ErrorStack> {
+        unsafe { X509_REQ_SIGN.sign(self.0.as_ptr(), signer) }
+    }
+
+    /// Signs the request with an external asynchronous signer.
+    ///
+    /// See [`AsyncCertSigner`] for details.
+    pub async fn sign_with_async(
+        &mut self,
+        signer: &dyn AsyncCertSigner,
+    ) -> Result<(), ErrorStack> {
+        let tbs = unsafe { X509_REQ_SIGN.prepare(self.0.as_ptr(), signer.algorithm())? };
+        let signature = signer.sign(tbs).await.map_err(ErrorStack::internal_error)?;
+        unsafe { X509_REQ_SIGN.finish(self.0.as_ptr(), &signature) }
+    }
+
     /// Returns the `X509Req`.
     #[must_use]
     pub fn build(self) -> X509Req {
@@ -1433,6 +1711,13 @@ impl X509ReqRef {
         ffi::i2d_X509_REQ
     }
 
+    /// Returns a human-readable description of the certificate request, in the format of
+    /// `openssl req -text`.
+    #[corresponds(X509_REQ_print)]
+    pub fn to_text(&self) -> Result<String, ErrorStack> {
+        print_to_string(|bio| unsafe { ffi::X509_REQ_print(bio, self.as_ptr()) })
+    }
+
     /// Returns the numerical value of the version field of the certificate request.
     #[corresponds(X509_REQ_get_version)]
     #[must_use]
@@ -1479,6 +1764,26 @@ impl X509ReqRef {
             Ok(Stack::from_ptr(extensions))
         }
     }
+
+    /// Returns the subject alternative name entries requested, if they exist.
+    #[corresponds(X509V3_get_d2i)]
+    #[must_use]
+    pub fn subject_alt_names(&self) -> Option<Stack<GeneralName>> {
+        let extensions = self.extensions().ok()?;
+        unsafe {
+            let stack = ffi::X509V3_get_d2i(
+                extensions.as_ptr(),
+                ffi::NID_subject_alt_name,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            );
+            if stack.is_null() {
+                None
+            } else {
+                Some(Stack::from_ptr(stack as *mut _))
+            }
+        }
+    }
 }
 
 /// The result of peer certificate verification.
@@ -1670,6 +1975,30 @@ impl GeneralName {
         }
     }
 
+    /// Creates an `iPAddress` name covering `prefix` leading bits of `ip`, as used in name
+    /// constraints.
+    pub(crate) fn new_ip_range(ip: IpAddr, prefix: u8) -> Result<GeneralName, ErrorStack> {
+        fn range<const N: usize>(octets: [u8; N], prefix: u8) -> Result<Vec<u8>, ErrorStack> {
+            if usize::from(prefix) > N * 8 {
+                return Err(ErrorStack::internal_error_str("invalid IP prefix length"));
+            }
+            let mut mask = [0u8; N];
+            for (i, byte) in mask.iter_mut().enumerate() {
+                let bits = usize::from(prefix).saturating_sub(i * 8).min(8);
+                *byte = !(0xffu16 >> bits) as u8;
+            }
+            let mut value: Vec<u8> = octets.iter().zip(&mask).map(|(a, m)| a & m).collect();
+            value.extend_from_slice(&mask);
+            Ok(value)
+        }
+
+        let value = match ip {
+            IpAddr::V4(addr) => range(addr.octets(), prefix)?,
+            IpAddr::V6(addr) => range(addr.octets(), prefix)?,
+        };
+        unsafe { GeneralName::new(ffi::GEN_IPADD, Asn1Type::OCTET_STRING, &value) }
+    }
+
     pub(crate) fn new_rid(oid: Asn1Object) -> Result<GeneralName, ErrorStack> {
         unsafe {
             ffi::init();
@@ -1750,6 +2079,32 @@ impl Stackable for GeneralName {
     type StackType = ffi::stack_st_GENERAL_NAME;
 }
 
+foreign_type_and_impl_send_sync! {
+    type CType = ffi::ACCESS_DESCRIPTION;
+    fn drop = ffi::ACCESS_DESCRIPTION_free;
+
+    /// An entry of the `X509` authority information access extension.
+    pub struct AccessDescription;
+}
+
+impl AccessDescriptionRef {
+    /// Returns the access method, such as `Nid::AD_CA_ISSUERS` or `Nid::AD_OCSP`.
+    #[must_use]
+    pub fn method(&self) -> &Asn1ObjectRef {
+        unsafe { Asn1ObjectRef::from_ptr((*self.as_ptr()).method) }
+    }
+
+    /// Returns the location at which the information can be accessed.
+    #[must_use]
+    pub fn location(&self) -> &GeneralNameRef {
+        unsafe { GeneralNameRef::from_ptr((*self.as_ptr()).location) }
+    }
+}
+
+impl Stackable for AccessDescription {
+    type StackType = ffi::stack_st_ACCESS_DESCRIPTION;
+}
+
 foreign_type_and_impl_send_sync! {
     type CType = ffi::X509_ALGOR;
     fn drop = ffi::X509_ALGOR_free;
@@ -1812,6 +2167,27 @@ unsafe fn X509_OBJECT_free(x: *mut ffi::X509_OBJECT) {
     ffi::OPENSSL_free(x as *mut libc::c_void);
 }
 
+const X509_SIGN: SignFns<ffi::X509> = SignFns {
+    set_algorithm: ffi::X509_set1_signature_algo,
+    tbs: ffi::i2d_re_X509_tbs,
+    set_signature: ffi::X509_set1_signature_value,
+};
+
+const X509_REQ_SIGN: SignFns<ffi::X509_REQ> = SignFns {
+    set_algorithm: ffi::X509_REQ_set1_signature_algo,
+    tbs: ffi::i2d_re_X509_REQ_tbs,
+    set_signature: ffi::X509_REQ_set1_signature_value,
+};
+
+/// Collects the output of an `X509_print`-style function.
+pub(crate) fn print_to_string(
+    print: impl FnOnce(*mut ffi::BIO) -> c_int,
+) -> Result<String, ErrorStack> {
+    let bio = MemBio::new()?;
+    cvt(print(bio.as_ptr()))?;
+    String::from_utf8(bio.get_buf().to_vec()).map_err(ErrorStack::internal_error)
+}
+
 unsafe fn get_new_x509_store_ctx_idx(f: ffi::CRYPTO_EX_free) -> c_int {
     // hack around https://rt.openssl.org/Ticket/Display.html?id=3710&user=guest&pass=guest
     static ONCE: Once = Once::new();
@@ -0,0 +1,241 @@
+//! The RFC 4514 string representation of distinguished names.
+use crate::ffi;
+use foreign_types::ForeignTypeRef;
+use libc::c_int;
+use std::fmt::Write;
+use std::str;
+
+use crate::asn1::{Asn1Object, Asn1ObjectRef};
+use crate::error::ErrorStack;
+use crate::nid::Nid;
+use crate::{cvt_n, cvt_p};
+
+/// The attribute type keywords defined by RFC 4514.
+const KEYWORDS: &[(&str, Nid)] = &[
+    ("CN", Nid::COMMONNAME),
+    ("L", Nid::LOCALITYNAME),
+    ("ST", Nid::STATEORPROVINCENAME),
+    ("O", Nid::ORGANIZATIONNAME),
+    ("OU", Nid::ORGANIZATIONALUNITNAME),
+    ("C", Nid::COUNTRYNAME),
+    ("STREET", Nid::STREETADDRESS),
+    ("DC", Nid::DOMAINCOMPONENT),
+    ("UID", Nid::USERID),
+];
+
+/// A relative distinguished name as `(type, value)` pairs.
+pub(crate) type Rdn = Vec<(String, String)>;
+
+/// Parses `s` into its RDNs, in the order they appear in the string.
+pub(crate) fn parse(s: &str) -> Result<Vec<Rdn>, &'static str> {
+    let mut parser = Parser {
+        input: s.as_bytes(),
+        pos: 0,
+    };
+    let mut rdns = vec![];
+    parser.skip_spaces();
+    if parser.peek().is_none() {
+        return Ok(rdns);
+    }
+
+    let mut rdn = vec![];
+    loop {
+        parser.skip_spaces();
+        let ty = parser.attribute_type()?;
+        parser.skip_spaces();
+        if parser.next() != Some(b'=') {
+            return Err("expected '=' after attribute type");
+        }
+        parser.skip_spaces();
+        let value = parser.attribute_value()?;
+        rdn.push((ty, value));
+
+        match parser.next() {
+            Some(b'+') => {}
+            Some(b',') => rdns.push(std::mem::take(&mut rdn)),
+            _ => {
+                rdns.push(rdn);
+                return Ok(rdns);
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek() == Some(b' ') {
+            self.pos += 1;
+        }
+    }
+
+    fn attribute_type(&mut self) -> Result<String, &'static str> {
+        let start = self.pos;
+        let numeric = self.peek().is_some_and(|b| b.is_ascii_digit());
+        while let Some(b) = self.peek() {
+            let valid = if numeric {
+                b.is_ascii_digit() || b == b'.'
+            } else {
+                b.is_ascii_alphanumeric() || b == b'-'
+            };
+            if !valid {
+                break;
+            }
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err("missing attribute type");
+        }
+        // Only ASCII bytes were consumed.
+        Ok(str::from_utf8(&self.input[start..self.pos])
+            .unwrap()
+            .to_string())
+    }
+
+    fn attribute_value(&mut self) -> Result<String, &'static str> {
+        if self.peek() == Some(b'#') {
+            return Err("hex-encoded attribute values are not supported");
+        }
+
+        let mut value = vec![];
+        // Escaped trailing spaces are part of the value.
+        let mut escaped_len = 0;
+        while let Some(b) = self.peek() {
+            match b {
+                b',' | b'+' => break,
+                b'\\' => {
+                    self.pos += 1;
+                    match self.next() {
+                        Some(
+                            c @ (b' ' | b'"' | b'#' | b'+' | b',' | b';' | b'<' | b'=' | b'>'
+                            | b'\\'),
+                        ) => value.push(c),
+                        Some(hi) if hi.is_ascii_hexdigit() => {
+                            let lo = self
+                                .next()
+                                .filter(u8::is_ascii_hexdigit)
+                                .ok_or("invalid hex escape")?;
+                            value.push((hex_value(hi) << 4) | hex_value(lo));
+                        }
+                        _ => return Err("invalid escape sequence"),
+                    }
+                    escaped_len = value.len();
+                }
+                b'"' | b';' | b'<' | b'>' | 0 => return Err("unescaped special character"),
+                _ => {
+                    self.pos += 1;
+                    value.push(b);
+                }
+            }
+        }
+
+        while value.len() > escaped_len && value.last() == Some(&b' ') {
+            value.pop();
+        }
+        String::from_utf8(value).map_err(|_| "attribute value is not valid UTF-8")
+    }
+}
+
+fn hex_value(b: u8) -> u8 {
+    match b {
+        b'0'..=b'9' => b - b'0',
+        b'a'..=b'f' => b - b'a' + 10,
+        _ => b - b'A' + 10,
+    }
+}
+
+/// Resolves an attribute type parsed by [`parse`].
+pub(crate) fn attribute_object(ty: &str) -> Result<Asn1Object, ErrorStack> {
+    match KEYWORDS
+        .iter()
+        .find(|(keyword, _)| keyword.eq_ignore_ascii_case(ty))
+    {
+        Some((_, nid)) => unsafe {
+            cvt_p(ffi::OBJ_nid2obj(nid.as_raw())).map(|p| Asn1Object::from_ptr(p))
+        },
+        None => Asn1Object::from_str(ty),
+    }
+}
+
+/// Returns the string used for `object` as an attribute type.
+///
+/// This is the RFC 4514 keyword if there is one, then the short name, then the dotted OID.
+pub(crate) fn attribute_type(object: &Asn1ObjectRef) -> Result<String, ErrorStack> {
+    let nid = object.nid();
+    if let Some((keyword, _)) = KEYWORDS.iter().find(|(_, n)| *n == nid) {
+        return Ok(keyword.to_string());
+    }
+    if nid != Nid::UNDEF {
+        if let Ok(name) = nid.short_name() {
+            return Ok(name.to_string());
+        }
+    }
+    oid(object)
+}
+
+/// Returns the dotted decimal form of `object`.
+pub(crate) fn oid(object: &Asn1ObjectRef) -> Result<String, ErrorStack> {
+    unsafe {
+        let len = cvt_n(ffi::OBJ_obj2txt(
+            std::ptr::null_mut(),
+            0,
+            object.as_ptr(),
+            1,
+        ))?;
+        let mut buf = vec![0u8; len as usize + 1];
+        ffi::OBJ_obj2txt(
+            buf.as_mut_ptr().cast(),
+            buf.len() as c_int,
+            object.as_ptr(),
+            1,
+        );
+        buf.truncate(len as usize);
+        String::from_utf8(buf).map_err(ErrorStack::internal_error)
+    }
+}
+
+/// Appends `value` to `out`, escaped as an RFC 4514 attribute value.
+pub(crate) fn escape(value: &str, out: &mut String) {
+    let last = value.len().saturating_sub(1);
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' | '+' | ',' | ';' | '<' | '>' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' ' if i == 0 || i == last => out.push_str("\\ "),
+            '#' if i == 0 => out.push_str("\\#"),
+            c if c.is_ascii_control() => {
+                let _ = write!(out, "\\{:02X}", c as u8);
+            }
+            c => out.push(c),
+        }
+    }
+}
+
+/// Returns `value` with surrounding whitespace removed, inner runs of whitespace collapsed and
+/// ASCII letters lowercased, the normalization applied by `X509_NAME_cmp`.
+pub(crate) fn canonicalize(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for word in value.split_ascii_whitespace() {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.extend(word.chars().map(|c| c.to_ascii_lowercase()));
+    }
+    out
+}
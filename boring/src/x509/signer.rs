@@ -0,0 +1,159 @@
+//! Signing certificates, requests and CRLs with keys held outside of BoringSSL.
+//!
+//! The `sign_with` methods of [`X509Builder`], [`X509ReqBuilder`] and [`X509CrlBuilder`] fill in
+//! the signature algorithm, serialize the to-be-signed portion of the structure, hand it to a
+//! [`CertSigner`] and assemble the final DER from the raw signature it returns. This is useful
+//! when the issuing key lives in an HSM or a remote key management service.
+//!
+//! [`X509Builder`]: crate::x509::X509Builder
+//! [`X509ReqBuilder`]: crate::x509::X509ReqBuilder
+//! [`X509CrlBuilder`]: crate::x509::crl::X509CrlBuilder
+use crate::ffi;
+use foreign_types::ForeignType;
+use libc::{c_int, c_long};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::ptr;
+
+use crate::error::ErrorStack;
+use crate::ssl::SslSignatureAlgorithm;
+use crate::x509::X509Algorithm;
+use crate::{cvt, cvt_p};
+
+/// A signer producing signatures over the to-be-signed portion of X.509 structures.
+pub trait CertSigner {
+    /// Returns the algorithm used by [`Self::sign`].
+    ///
+    /// Only RSA PKCS#1 v1.5, RSA-PSS, ECDSA and Ed25519 algorithms can be used.
+    fn algorithm(&self) -> SslSignatureAlgorithm;
+
+    /// Signs `tbs` with [`Self::algorithm`], returning the raw signature.
+    ///
+    /// `tbs` is the message itself, not its digest. ECDSA signatures must be DER-encoded
+    /// `ECDSA-Sig-Value` structures, as produced by `EVP_DigestSign`.
+    fn sign(&self, tbs: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+/// The future returned by [`AsyncCertSigner::sign`].
+pub type BoxCertSignFuture = Pin<Box<dyn Future<Output = io::Result<Vec<u8>>> + Send>>;
+
+/// An asynchronous version of [`CertSigner`].
+pub trait AsyncCertSigner: Send + Sync {
+    /// Returns the algorithm used by [`Self::sign`].
+    fn algorithm(&self) -> SslSignatureAlgorithm;
+
+    /// Signs `tbs` with [`Self::algorithm`], resolving to the raw signature.
+    ///
+    /// See [`CertSigner::sign`] for the expected signature format.
+    fn sign(&self, tbs: Vec<u8>) -> BoxCertSignFuture;
+}
+
+/// The functions used to sign a structure whose to-be-signed portion is encoded separately.
+pub(crate) struct SignFns<T> {
+    pub(crate) set_algorithm: unsafe extern "C" fn(*mut T, *const ffi::X509_ALGOR) -> c_int,
+    pub(crate) tbs: unsafe extern "C" fn(*mut T, *mut *mut u8) -> c_int,
+    pub(crate) set_signature: unsafe extern "C" fn(*mut T, *const u8, usize) -> c_int,
+}
+
+impl<T> SignFns<T> {
+    /// Sets the signature algorithm of `ptr` and returns the encoding of its to-be-signed
+    /// portion.
+    pub(crate) unsafe fn prepare(
+        &self,
+        ptr: *mut T,
+        algorithm: SslSignatureAlgorithm,
+    ) -> Result<Vec<u8>, ErrorStack> {
+        let algorithm = algorithm_identifier(algorithm)?;
+        cvt((self.set_algorithm)(ptr, algorithm.as_ptr()))?;
+
+        let len = cvt((self.tbs)(ptr, ptr::null_mut()))?;
+        let mut tbs = vec![0; len as usize];
+        cvt((self.tbs)(ptr, &mut tbs.as_mut_ptr()))?;
+        Ok(tbs)
+    }
+
+    /// Stores `signature` as the signature of `ptr`.
+    pub(crate) unsafe fn finish(&self, ptr: *mut T, signature: &[u8]) -> Result<(), ErrorStack> {
+        cvt((self.set_signature)(
+            ptr,
+            signature.as_ptr(),
+            signature.len(),
+        ))
+        .map(|_| ())
+    }
+
+    pub(crate) unsafe fn sign(
+        &self,
+        ptr: *mut T,
+        signer: &dyn CertSigner,
+    ) -> Result<(), ErrorStack> {
+        let tbs = self.prepare(ptr, signer.algorithm())?;
+        let signature = signer.sign(&tbs).map_err(ErrorStack::internal_error)?;
+        self.finish(ptr, &signature)
+    }
+}
+
+/// Returns the `AlgorithmIdentifier` X.509 uses for `algorithm`.
+fn algorithm_identifier(algorithm: SslSignatureAlgorithm) -> Result<X509Algorithm, ErrorStack> {
+    let der: &[u8] = match algorithm {
+        SslSignatureAlgorithm::RSA_PKCS1_SHA1 => {
+            b"\x30\x0d\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x05\x05\x00"
+        }
+        SslSignatureAlgorithm::RSA_PKCS1_SHA256 => {
+            b"\x30\x0d\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0b\x05\x00"
+        }
+        SslSignatureAlgorithm::RSA_PKCS1_SHA384 => {
+            b"\x30\x0d\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0c\x05\x00"
+        }
+        SslSignatureAlgorithm::RSA_PKCS1_SHA512 => {
+            b"\x30\x0d\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0d\x05\x00"
+        }
+        SslSignatureAlgorithm::ECDSA_SHA1 => b"\x30\x09\x06\x07\x2a\x86\x48\xce\x3d\x04\x01",
+        SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256 => {
+            b"\x30\x0a\x06\x08\x2a\x86\x48\xce\x3d\x04\x03\x02"
+        }
+        SslSignatureAlgorithm::ECDSA_SECP384R1_SHA384 => {
+            b"\x30\x0a\x06\x08\x2a\x86\x48\xce\x3d\x04\x03\x03"
+        }
+        SslSignatureAlgorithm::ECDSA_SECP521R1_SHA512 => {
+            b"\x30\x0a\x06\x08\x2a\x86\x48\xce\x3d\x04\x03\x04"
+        }
+        // RSASSA-PSS with MGF1 over the same digest and a salt as long as the digest, the only
+        // parameters BoringSSL accepts.
+        SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256 => {
+            b"\x30\x41\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0a\x30\x34\xa0\x0f\x30\x0d\x06\x09\
+              \x60\x86\x48\x01\x65\x03\x04\x02\x01\x05\x00\xa1\x1c\x30\x1a\x06\x09\x2a\x86\x48\x86\
+              \xf7\x0d\x01\x01\x08\x30\x0d\x06\x09\x60\x86\x48\x01\x65\x03\x04\x02\x01\x05\x00\xa2\
+              \x03\x02\x01\x20"
+        }
+        SslSignatureAlgorithm::RSA_PSS_RSAE_SHA384 => {
+            b"\x30\x41\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0a\x30\x34\xa0\x0f\x30\x0d\x06\x09\
+              \x60\x86\x48\x01\x65\x03\x04\x02\x02\x05\x00\xa1\x1c\x30\x1a\x06\x09\x2a\x86\x48\x86\
+              \xf7\x0d\x01\x01\x08\x30\x0d\x06\x09\x60\x86\x48\x01\x65\x03\x04\x02\x02\x05\x00\xa2\
+              \x03\x02\x01\x30"
+        }
+        SslSignatureAlgorithm::RSA_PSS_RSAE_SHA512 => {
+            b"\x30\x41\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0a\x30\x34\xa0\x0f\x30\x0d\x06\x09\
+              \x60\x86\x48\x01\x65\x03\x04\x02\x03\x05\x00\xa1\x1c\x30\x1a\x06\x09\x2a\x86\x48\x86\
+              \xf7\x0d\x01\x01\x08\x30\x0d\x06\x09\x60\x86\x48\x01\x65\x03\x04\x02\x03\x05\x00\xa2\
+              \x03\x02\x01\x40"
+        }
+        SslSignatureAlgorithm::ED25519 => b"\x30\x05\x06\x03\x2b\x65\x70",
+        _ => {
+            return Err(ErrorStack::internal_error_str(
+                "signature algorithm cannot be used for X.509 structures",
+            ))
+        }
+    };
+
+    unsafe {
+        ffi::init();
+        cvt_p(ffi::d2i_X509_ALGOR(
+            ptr::null_mut(),
+            &mut der.as_ptr(),
+            der.len() as c_long,
+        ))
+        .map(|p| X509Algorithm::from_ptr(p))
+    }
+}
@@ -41,14 +41,62 @@
 //! ```
 
 use crate::error::ErrorStack;
+use crate::ex_data::Index;
 use crate::ffi;
-use crate::stack::StackRef;
-use crate::x509::verify::{X509VerifyFlags, X509VerifyParamRef};
-use crate::x509::{X509Object, X509};
+use crate::stack::{Stack, StackRef};
+use crate::x509::verify::{
+    ChainError, ChainVerification, VerifyOptions, X509VerifyFlags, X509VerifyParamRef,
+};
+use crate::x509::{X509Object, X509Ref, X509StoreContext, X509StoreContextRef, X509};
 use crate::{cvt, cvt_p};
 use foreign_types::{ForeignType, ForeignTypeRef};
+use libc::c_int;
 use openssl_macros::corresponds;
-use std::mem;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::{env, fs, io, mem};
+
+/// The release of the bundled Mozilla root store, as published by certifi.
+#[cfg(feature = "mozilla-roots")]
+pub const BUNDLED_ROOTS_VERSION: &str = "2026.05.20";
+
+/// The date the bundled Mozilla root store was taken from Mozilla's `certdata.txt`.
+#[cfg(feature = "mozilla-roots")]
+pub const BUNDLED_ROOTS_DATE: &str = "2026-05-20";
+
+/// certifi's `cacert.pem` for [`BUNDLED_ROOTS_VERSION`], unmodified. Replace it and update both
+/// constants and the digest in `test_bundled_roots_integrity` together.
+#[cfg(feature = "mozilla-roots")]
+static BUNDLED_ROOTS: &[u8] = include_bytes!("../../roots/mozilla.pem");
+
+/// Bundles probed by [`X509StoreBuilderRef::add_system_roots`], the first one found being used.
+const SYSTEM_CERT_FILES: &[&str] = &[
+    // Debian, Ubuntu, Arch, Gentoo
+    "/etc/ssl/certs/ca-certificates.crt",
+    // Fedora, RHEL 7 and later
+    "/etc/pki/ca-trust/extracted/pem/tls-ca-bundle.pem",
+    // RHEL 6
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    // openSUSE
+    "/etc/ssl/ca-bundle.pem",
+    // OpenELEC
+    "/etc/pki/tls/cacert.pem",
+    // Alpine, FreeBSD, macOS
+    "/etc/ssl/cert.pem",
+];
+
+/// Directories probed by [`X509StoreBuilderRef::add_system_roots`] if no bundle was found.
+const SYSTEM_CERT_DIRS: &[&str] = &[
+    "/etc/ssl/certs",
+    "/etc/pki/tls/certs",
+    // Android
+    "/system/etc/security/cacerts",
+];
+
+static CHAIN_ERRORS_INDEX: LazyLock<Index<X509StoreContext, Vec<ChainError>>> =
+    LazyLock::new(|| X509StoreContext::new_ex_index().unwrap());
 
 foreign_type_and_impl_send_sync! {
     type CType = ffi::X509_STORE;
@@ -97,6 +145,96 @@ impl X509StoreBuilderRef {
         unsafe { cvt(ffi::X509_STORE_set_default_paths(self.as_ptr())).map(|_| ()) }
     }
 
+    /// Adds the roots of the bundled Mozilla CA store, trusted for server authentication.
+    ///
+    /// Unlike [`Self::set_default_paths`], this does not depend on the certificates installed on
+    /// the host. See [`BUNDLED_ROOTS_VERSION`] and [`BUNDLED_ROOTS_DATE`] for the bundled
+    /// release.
+    ///
+    /// Requires the `mozilla-roots` feature.
+    #[cfg(feature = "mozilla-roots")]
+    pub fn add_bundled_roots(&mut self) -> Result<(), ErrorStack> {
+        for cert in X509::stack_from_pem(BUNDLED_ROOTS)? {
+            self.add_cert(cert)?;
+        }
+        Ok(())
+    }
+
+    /// Adds every certificate of a PEM bundle.
+    ///
+    /// Other PEM blocks, such as private keys, are ignored. Certificates which cannot be parsed
+    /// are reported in the returned [`LoadReport`] instead of aborting the load, and
+    /// certificates already in the store are only counted. An `Err` is only returned if a
+    /// certificate could not be added to the store.
+    pub fn add_pem_bundle(&mut self, pem: &[u8]) -> Result<LoadReport, ErrorStack> {
+        let mut loader = Loader::new(self)?;
+        loader.add_bytes(None, pem)?;
+        Ok(loader.report)
+    }
+
+    /// Adds the certificates of every file in `dir`, such as an OpenSSL hashed `certs/`
+    /// directory.
+    ///
+    /// Files may be PEM bundles or DER-encoded certificates. Subdirectories and CRL files named
+    /// `<hash>.r<n>` are skipped. Files which cannot be read or parsed are reported in the
+    /// returned [`LoadReport`], see [`Self::add_pem_bundle`].
+    pub fn add_directory<P: AsRef<Path>>(&mut self, dir: P) -> Result<LoadReport, ErrorStack> {
+        let mut loader = Loader::new(self)?;
+        loader.add_directory(dir.as_ref())?;
+        Ok(loader.report)
+    }
+
+    /// Adds the certificates trusted by the system.
+    ///
+    /// The file named by the `SSL_CERT_FILE` environment variable is loaded if set, otherwise
+    /// the first bundle found at the usual Linux locations. The directories listed in
+    /// `SSL_CERT_DIR` are loaded if set, otherwise the usual certificate directories are probed
+    /// when no bundle was found. Failures are reported in the returned [`LoadReport`], see
+    /// [`Self::add_pem_bundle`].
+    pub fn add_system_roots(&mut self) -> Result<LoadReport, ErrorStack> {
+        self.add_system_roots_from(env::var_os("SSL_CERT_FILE"), env::var_os("SSL_CERT_DIR"))
+    }
+
+    pub(crate) fn add_system_roots_from(
+        &mut self,
+        cert_file: Option<OsString>,
+        cert_dir: Option<OsString>,
+    ) -> Result<LoadReport, ErrorStack> {
+        let mut loader = Loader::new(self)?;
+
+        let mut found = 0;
+        match cert_file {
+            Some(file) => found += loader.add_file(Path::new(&file))?,
+            None => {
+                for file in SYSTEM_CERT_FILES.iter().map(Path::new) {
+                    if file.is_file() {
+                        found += loader.add_file(file)?;
+                        if found > 0 {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        match cert_dir {
+            Some(dirs) => {
+                for dir in env::split_paths(&dirs) {
+                    loader.add_directory(&dir)?;
+                }
+            }
+            None if found == 0 => {
+                for dir in SYSTEM_CERT_DIRS.iter().map(Path::new) {
+                    if dir.is_dir() && loader.add_directory(dir)? > 0 {
+                        break;
+                    }
+                }
+            }
+            None => {}
+        }
+
+        Ok(loader.report)
+    }
+
     /// Sets certificate chain validation related flags.
     #[corresponds(X509_STORE_set_flags)]
     pub fn set_flags(&mut self, flags: X509VerifyFlags) {
@@ -126,6 +264,216 @@ impl X509StoreBuilderRef {
     }
 }
 
+/// The outcome of loading certificates into an [`X509StoreBuilder`].
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    added: usize,
+    duplicates: usize,
+    failures: Vec<LoadFailure>,
+}
+
+impl LoadReport {
+    /// Returns the number of certificates added to the store.
+    #[must_use]
+    pub fn added(&self) -> usize {
+        self.added
+    }
+
+    /// Returns the number of certificates skipped because the store already had them.
+    #[must_use]
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+
+    /// Returns the files and certificates which could not be loaded.
+    #[must_use]
+    pub fn failures(&self) -> &[LoadFailure] {
+        &self.failures
+    }
+}
+
+/// A file or certificate which could not be loaded.
+#[derive(Debug)]
+pub struct LoadFailure {
+    path: Option<PathBuf>,
+    index: Option<usize>,
+    error: io::Error,
+}
+
+impl LoadFailure {
+    /// Returns the file the failure occurred in, or `None` for in-memory bundles.
+    #[must_use]
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Returns the position of the offending PEM block, or `None` if the whole file failed.
+    #[must_use]
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    /// Returns the error.
+    #[must_use]
+    pub fn error(&self) -> &io::Error {
+        &self.error
+    }
+}
+
+/// Adds certificates to a store, skipping the ones it already has.
+struct Loader<'a> {
+    store: &'a mut X509StoreBuilderRef,
+    known: HashSet<Vec<u8>>,
+    report: LoadReport,
+}
+
+impl<'a> Loader<'a> {
+    fn new(store: &'a mut X509StoreBuilderRef) -> Result<Loader<'a>, ErrorStack> {
+        let objects = unsafe {
+            StackRef::<X509Object>::from_ptr(ffi::X509_STORE_get0_objects(store.as_ptr()))
+        };
+        let known = objects
+            .iter()
+            .filter_map(|object| object.x509())
+            .map(|cert| cert.to_der())
+            .collect::<Result<_, _>>()?;
+        Ok(Loader {
+            store,
+            known,
+            report: LoadReport::default(),
+        })
+    }
+
+    fn fail(&mut self, path: Option<&Path>, index: Option<usize>, error: io::Error) {
+        self.report.failures.push(LoadFailure {
+            path: path.map(Path::to_path_buf),
+            index,
+            error,
+        });
+    }
+
+    fn add(&mut self, cert: X509) -> Result<(), ErrorStack> {
+        if self.known.insert(cert.to_der()?) {
+            self.store.add_cert(cert)?;
+            self.report.added += 1;
+        } else {
+            self.report.duplicates += 1;
+        }
+        Ok(())
+    }
+
+    /// Adds the certificates of `data`, returning how many were found.
+    fn add_bytes(&mut self, path: Option<&Path>, data: &[u8]) -> Result<usize, ErrorStack> {
+        let blocks = pem_blocks(data);
+        if blocks.is_empty() {
+            return match X509::from_der(data) {
+                Ok(cert) => self.add(cert).map(|_| 1),
+                Err(_) => {
+                    let error = io::Error::new(io::ErrorKind::InvalidData, "no certificate found");
+                    self.fail(path, None, error);
+                    Ok(0)
+                }
+            };
+        }
+
+        let mut found = 0;
+        for (index, (label, block)) in blocks.into_iter().enumerate() {
+            if !label.ends_with(b"CERTIFICATE") {
+                continue;
+            }
+            match X509::from_pem(block) {
+                Ok(cert) => {
+                    self.add(cert)?;
+                    found += 1;
+                }
+                Err(e) => self.fail(path, Some(index), e.into()),
+            }
+        }
+        Ok(found)
+    }
+
+    fn add_file(&mut self, path: &Path) -> Result<usize, ErrorStack> {
+        match fs::read(path) {
+            Ok(data) => self.add_bytes(Some(path), &data),
+            Err(e) => {
+                self.fail(Some(path), None, e);
+                Ok(0)
+            }
+        }
+    }
+
+    fn add_directory(&mut self, dir: &Path) -> Result<usize, ErrorStack> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.fail(Some(dir), None, e);
+                return Ok(0);
+            }
+        };
+        let mut paths = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| !path.is_dir() && !is_crl_link(path))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let mut found = 0;
+        for path in paths {
+            found += self.add_file(&path)?;
+        }
+        Ok(found)
+    }
+}
+
+/// Returns the label and the full text of every PEM block of `data`.
+fn pem_blocks(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    const BEGIN: &[u8] = b"-----BEGIN ";
+    const DASHES: &[u8] = b"-----";
+
+    let mut blocks = vec![];
+    let mut pos = 0;
+    while let Some(start) = find(&data[pos..], BEGIN).map(|i| pos + i) {
+        let label_start = start + BEGIN.len();
+        let Some(label_len) = find(&data[label_start..], DASHES) else {
+            break;
+        };
+        let label = &data[label_start..label_start + label_len];
+        let end_marker = [&b"-----END "[..], label, DASHES].concat();
+        match find(&data[label_start..], &end_marker) {
+            Some(end) => {
+                pos = label_start + end + end_marker.len();
+                blocks.push((label, &data[start..pos]));
+            }
+            None => {
+                // Keep the truncated block so that it is reported.
+                blocks.push((label, &data[start..]));
+                break;
+            }
+        }
+    }
+    blocks
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Returns `true` for the `<hash>.r<n>` links to CRLs found in hashed directories.
+fn is_crl_link(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let Some((hash, suffix)) = name.split_once('.') else {
+        return false;
+    };
+    hash.len() == 8
+        && hash.bytes().all(|b| b.is_ascii_hexdigit())
+        && suffix
+            .strip_prefix('r')
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
 foreign_type_and_impl_send_sync! {
     type CType = ffi::X509_STORE;
     fn drop = ffi::X509_STORE_free;
@@ -135,6 +483,109 @@ foreign_type_and_impl_send_sync! {
 }
 
 impl X509StoreRef {
+    /// Verifies `leaf` against the trusted certificates in the store, using `intermediates` to
+    /// build the chain.
+    ///
+    /// Unlike `X509StoreContextRef::verify_cert`, verification carries on past errors so that
+    /// every problem with the chain is reported. An `Err` is only returned if verification could
+    /// not be attempted.
+    ///
+    /// If an issuer fetcher is set on `options`, missing intermediate certificates are
+    /// downloaded and verification is retried. Asynchronous fetchers are ignored, use
+    /// [`Self::verify_chain_async`] instead.
+    #[corresponds(X509_verify_cert)]
+    pub fn verify_chain(
+        &self,
+        leaf: &X509Ref,
+        intermediates: &[X509],
+        options: &VerifyOptions,
+    ) -> Result<ChainVerification, ErrorStack> {
+        let mut intermediates = intermediates.to_vec();
+        let mut result = self.verify_chain_once(leaf, &intermediates, options)?;
+        if let Some(fetcher) = options.issuer_fetcher() {
+            for _ in 0..fetcher.max_fetches() {
+                let Some(issuer) = result
+                    .missing_issuer()
+                    .and_then(|cert| fetcher.fetch_issuer(cert))
+                else {
+                    break;
+                };
+                if !add_intermediate(&mut intermediates, issuer)? {
+                    break;
+                }
+                result = self.verify_chain_once(leaf, &intermediates, options)?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Like [`Self::verify_chain`], but also uses asynchronous issuer fetchers.
+    pub async fn verify_chain_async(
+        &self,
+        leaf: &X509Ref,
+        intermediates: &[X509],
+        options: &VerifyOptions,
+    ) -> Result<ChainVerification, ErrorStack> {
+        let mut intermediates = intermediates.to_vec();
+        let mut result = self.verify_chain_once(leaf, &intermediates, options)?;
+        if let Some(fetcher) = options.issuer_fetcher() {
+            for _ in 0..fetcher.max_fetches() {
+                let Some(cert) = result.missing_issuer().cloned() else {
+                    break;
+                };
+                let Some(issuer) = fetcher.fetch_issuer_async(&cert).await else {
+                    break;
+                };
+                if !add_intermediate(&mut intermediates, issuer)? {
+                    break;
+                }
+                result = self.verify_chain_once(leaf, &intermediates, options)?;
+            }
+        }
+        Ok(result)
+    }
+
+    fn verify_chain_once(
+        &self,
+        leaf: &X509Ref,
+        intermediates: &[X509],
+        options: &VerifyOptions,
+    ) -> Result<ChainVerification, ErrorStack> {
+        let mut untrusted = Stack::new()?;
+        for cert in intermediates {
+            untrusted.push(cert.clone())?;
+        }
+
+        let mut context = X509StoreContext::new()?;
+        context.init(self, leaf, &untrusted, |ctx| {
+            options.apply(ctx.verify_param_mut())?;
+            ctx.set_ex_data(*CHAIN_ERRORS_INDEX, vec![]);
+            unsafe { ffi::X509_STORE_CTX_set_verify_cb(ctx.as_ptr(), Some(record_chain_error)) };
+
+            let verified = ctx.verify_cert()?;
+
+            let chain = ctx
+                .chain()
+                .map(|chain| chain.iter().map(ToOwned::to_owned).collect())
+                .unwrap_or_default();
+            let mut errors = ctx
+                .ex_data_mut(*CHAIN_ERRORS_INDEX)
+                .map(mem::take)
+                .unwrap_or_default();
+            if !verified && errors.is_empty() {
+                if let Err(error) = ctx.verify_result() {
+                    errors.push(ChainError {
+                        depth: ctx.error_depth(),
+                        cert: ctx.current_cert().map(ToOwned::to_owned),
+                        error,
+                    });
+                }
+            }
+
+            Ok(ChainVerification { chain, errors })
+        })
+    }
+
     /// **Warning: this method is unsound**
     ///
     /// Get a reference to the cache of certificates in this store.
@@ -159,6 +610,36 @@ impl X509StoreRef {
     }
 }
 
+/// Adds `cert` to `intermediates`, returning `false` if it was already there.
+fn add_intermediate(intermediates: &mut Vec<X509>, cert: X509) -> Result<bool, ErrorStack> {
+    let der = cert.to_der()?;
+    for existing in intermediates.iter() {
+        if existing.to_der()? == der {
+            return Ok(false);
+        }
+    }
+    intermediates.push(cert);
+    Ok(true)
+}
+
+/// Records the error and lets verification carry on.
+unsafe extern "C" fn record_chain_error(ok: c_int, ctx: *mut ffi::X509_STORE_CTX) -> c_int {
+    if ok == 0 {
+        let ctx = X509StoreContextRef::from_ptr_mut(ctx);
+        if let Err(error) = ctx.verify_result() {
+            let depth = ctx.error_depth();
+            let cert = ctx.current_cert().map(ToOwned::to_owned);
+            if let Some(errors) = ctx.ex_data_mut(*CHAIN_ERRORS_INDEX) {
+                // Some checks run more than once for the same certificate.
+                if !errors.iter().any(|e| e.depth == depth && e.error == error) {
+                    errors.push(ChainError { depth, cert, error });
+                }
+            }
+        }
+    }
+    1
+}
+
 #[test]
 #[allow(dead_code)]
 // X509Store must not implement Clone because `SslContextBuilder::cert_store_mut` lets
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::nid::Nid;
+use crate::ssl::test::pki::{root, Chain};
+use crate::x509::aia::{AiaFetcher, AsyncIssuerFetcher, BoxIssuerFetchFuture, IssuerFetcher};
+use crate::x509::extension::AuthorityInfoAccess;
+use crate::x509::verify::VerifyOptions;
+use crate::x509::X509VerifyError;
+
+use super::now_or_never;
+
+const ISSUER_URL: &str = "http://ca.example.com/intermediate.der";
+
+/// Serves certificates from memory, counting requests.
+#[derive(Clone)]
+struct LocalFetcher {
+    responses: Arc<HashMap<String, Vec<u8>>>,
+    requests: Arc<AtomicUsize>,
+}
+
+impl LocalFetcher {
+    fn new(responses: &[(&str, Vec<u8>)]) -> LocalFetcher {
+        LocalFetcher {
+            responses: Arc::new(
+                responses
+                    .iter()
+                    .map(|(url, body)| (url.to_string(), body.clone()))
+                    .collect(),
+            ),
+            requests: Arc::default(),
+        }
+    }
+
+    fn get(&self, url: &str) -> io::Result<Vec<u8>> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        self.responses
+            .get(url)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, url.to_string()))
+    }
+
+    fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+}
+
+impl IssuerFetcher for LocalFetcher {
+    fn fetch(&self, url: &str) -> io::Result<Vec<u8>> {
+        self.get(url)
+    }
+}
+
+impl AsyncIssuerFetcher for LocalFetcher {
+    fn fetch(&self, url: &str) -> BoxIssuerFetchFuture {
+        let response = self.get(url);
+        Box::pin(async move { response })
+    }
+}
+
+/// Returns a chain whose leaf points to the intermediate through `ISSUER_URL`.
+fn pki() -> Chain {
+    Chain::with_leaf_extensions(|| {
+        vec![AuthorityInfoAccess::new()
+            .ocsp("http://ocsp.example.com")
+            .ca_issuers(ISSUER_URL)
+            .build()
+            .unwrap()]
+    })
+}
+
+#[test]
+fn authority_info() {
+    let Chain {
+        intermediate, leaf, ..
+    } = pki();
+    assert!(intermediate.authority_info().is_none());
+
+    let info = leaf.authority_info().unwrap();
+    let entries = info
+        .iter()
+        .map(|desc| (desc.method().nid(), desc.location().uri().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        entries,
+        [
+            (Nid::AD_OCSP, "http://ocsp.example.com"),
+            (Nid::AD_CA_ISSUERS, ISSUER_URL)
+        ]
+    );
+}
+
+#[test]
+fn fetch_missing_intermediate() {
+    let chain = pki();
+    let (store, intermediate, leaf) = (chain.store(), chain.intermediate, chain.leaf);
+
+    let result = store
+        .verify_chain(&leaf, &[], &VerifyOptions::new())
+        .unwrap();
+    assert!(result
+        .errors_at(0)
+        .any(|e| e == X509VerifyError::UNABLE_TO_GET_ISSUER_CERT_LOCALLY));
+
+    let fetcher = LocalFetcher::new(&[(ISSUER_URL, intermediate.to_der().unwrap())]);
+    let mut options = VerifyOptions::new();
+    options.set_issuer_fetcher(Arc::new(AiaFetcher::new(fetcher.clone())));
+    let result = store.verify_chain(&leaf, &[], &options).unwrap();
+    assert!(result.is_valid(), "{:?}", result.errors());
+    assert_eq!(result.chain().len(), 3);
+    assert_eq!(
+        result.chain()[1].to_der().unwrap(),
+        intermediate.to_der().unwrap()
+    );
+    assert_eq!(fetcher.requests(), 1);
+
+    // The second verification is served from the cache.
+    let result = store.verify_chain(&leaf, &[], &options).unwrap();
+    assert!(result.is_valid());
+    assert_eq!(fetcher.requests(), 1);
+}
+
+#[test]
+fn fetch_missing_intermediate_async() {
+    let chain = pki();
+    let (store, intermediate, leaf) = (chain.store(), chain.intermediate, chain.leaf);
+
+    let fetcher = LocalFetcher::new(&[(ISSUER_URL, intermediate.to_pem().unwrap())]);
+    let mut options = VerifyOptions::new();
+    options.set_issuer_fetcher(Arc::new(AiaFetcher::new_async(fetcher.clone())));
+
+    // Synchronous verification cannot use an asynchronous fetcher.
+    let result = store.verify_chain(&leaf, &[], &options).unwrap();
+    assert!(!result.is_valid());
+    assert_eq!(fetcher.requests(), 0);
+
+    let result = now_or_never(store.verify_chain_async(&leaf, &[], &options)).unwrap();
+    assert!(result.is_valid(), "{:?}", result.errors());
+    assert_eq!(fetcher.requests(), 1);
+}
+
+#[test]
+fn fetch_rejects_wrong_issuer() {
+    let chain = pki();
+    let (store, leaf) = (chain.store(), chain.leaf);
+    let (unrelated, _) = root();
+
+    let fetcher = LocalFetcher::new(&[(ISSUER_URL, unrelated.to_der().unwrap())]);
+    let mut options = VerifyOptions::new();
+    options.set_issuer_fetcher(Arc::new(AiaFetcher::new(fetcher.clone())));
+
+    for _ in 0..2 {
+        let result = store.verify_chain(&leaf, &[], &options).unwrap();
+        assert!(result
+            .errors_at(0)
+            .any(|e| e == X509VerifyError::UNABLE_TO_GET_ISSUER_CERT_LOCALLY));
+    }
+    // Rejected certificates are not cached.
+    assert_eq!(fetcher.requests(), 2);
+}
+
+#[test]
+fn fetch_failure() {
+    let chain = pki();
+    let (store, leaf) = (chain.store(), chain.leaf);
+
+    let fetcher = LocalFetcher::new(&[]);
+    let mut aia = AiaFetcher::new(fetcher.clone());
+    aia.set_cache_capacity(0);
+    let mut options = VerifyOptions::new();
+    options.set_issuer_fetcher(Arc::new(aia));
+
+    let result = store.verify_chain(&leaf, &[], &options).unwrap();
+    assert!(!result.is_valid());
+    assert_eq!(fetcher.requests(), 1);
+}
@@ -0,0 +1,184 @@
+use crate::hash::MessageDigest;
+use crate::nid::Nid;
+use crate::pkey::{PKey, Private};
+use crate::ssl::test::pki::{ca, ed25519_key, root, self_signed};
+use crate::stack::Stack;
+use crate::x509::ca::{CertProfile, CertificateAuthority, SanCopy};
+use crate::x509::extension::SubjectAlternativeName;
+use crate::x509::store::X509StoreBuilder;
+use crate::x509::{X509Name, X509Req, X509StoreContext, X509};
+
+use super::pkey;
+
+fn name(cn: &str) -> X509Name {
+    let mut name = X509Name::builder().unwrap();
+    name.append_entry_by_nid(Nid::COMMONNAME, cn).unwrap();
+    name.build()
+}
+
+pub(super) fn request(cn: &str, key: &PKey<Private>) -> X509Req {
+    let mut builder = X509Req::builder().unwrap();
+    builder.set_subject_name(&name(cn)).unwrap();
+    builder.set_pubkey(key).unwrap();
+
+    let mut extensions = Stack::new().unwrap();
+    let san = SubjectAlternativeName::new()
+        .dns("example.com")
+        .ip("127.0.0.1")
+        .email("test@example.com")
+        .build(&builder.x509v3_context(None))
+        .unwrap();
+    extensions.push(san).unwrap();
+    builder.add_extensions(&extensions).unwrap();
+
+    builder.sign(key, MessageDigest::sha256()).unwrap();
+    builder.build()
+}
+
+fn verify(chain: &[X509], root: &X509) -> bool {
+    let mut store = X509StoreBuilder::new().unwrap();
+    store.add_cert(root.clone()).unwrap();
+    let store = store.build();
+
+    let mut untrusted = Stack::new().unwrap();
+    for cert in &chain[1..] {
+        untrusted.push(cert.clone()).unwrap();
+    }
+
+    let mut context = X509StoreContext::new().unwrap();
+    context
+        .init(&store, &chain[0], &untrusted, |c| c.verify_cert())
+        .unwrap()
+}
+
+#[test]
+fn issue_server() {
+    let (root, root_key) = root();
+    let ca = CertificateAuthority::new(root.clone(), root_key).unwrap();
+
+    let key = pkey();
+    let req = request("example.com", &key);
+    let chain = ca.issue(&req, &CertProfile::server()).unwrap();
+
+    assert_eq!(chain.len(), 2);
+    let cert = &chain[0];
+    assert!(cert.public_key().unwrap().public_eq(&key));
+    assert!(cert.verify(&root.public_key().unwrap()).unwrap());
+    assert!(cert.check_host("example.com").unwrap());
+    assert!(verify(&chain, &root));
+
+    // Email addresses are not copied into server certificates.
+    let names = cert.subject_alt_names().unwrap();
+    assert_eq!(names.len(), 2);
+    assert!(names.iter().all(|name| name.email().is_none()));
+}
+
+#[test]
+fn issue_client_copies_configured_names() {
+    let (root, root_key) = root();
+    let ca = CertificateAuthority::new(root, root_key).unwrap();
+
+    let mut profile = CertProfile::client();
+    profile.set_san_copy(SanCopy::EMAIL);
+    let chain = ca.issue(&request("client", &pkey()), &profile).unwrap();
+
+    let names = chain[0].subject_alt_names().unwrap();
+    assert_eq!(names.len(), 1);
+    assert_eq!(names[0].email(), Some("test@example.com"));
+}
+
+#[test]
+fn issue_through_intermediate() {
+    let (root, root_key) = root();
+    let root_ca = CertificateAuthority::new(root.clone(), root_key).unwrap();
+
+    let intermediate_key = pkey();
+    let intermediate = root_ca
+        .issue(
+            &request("Test Intermediate", &intermediate_key),
+            &CertProfile::intermediate(),
+        )
+        .unwrap();
+    assert!(intermediate[0].subject_alt_names().is_none());
+
+    let mut intermediate_ca =
+        CertificateAuthority::new(intermediate[0].clone(), intermediate_key).unwrap();
+    intermediate_ca.set_chain(vec![root.clone()]);
+
+    let chain = intermediate_ca
+        .issue(&request("example.com", &pkey()), &CertProfile::server())
+        .unwrap();
+    assert_eq!(chain.len(), 3);
+    assert!(verify(&chain, &root));
+}
+
+#[test]
+fn unique_serials() {
+    let (root, root_key) = root();
+    let ca = CertificateAuthority::new(root, root_key).unwrap();
+    let req = request("example.com", &pkey());
+
+    let first = ca.issue(&req, &CertProfile::server()).unwrap();
+    let second = ca.issue(&req, &CertProfile::server()).unwrap();
+    assert_ne!(
+        first[0].serial_number().to_bn().unwrap(),
+        second[0].serial_number().to_bn().unwrap()
+    );
+}
+
+#[test]
+fn mismatched_key() {
+    let (root, _) = root();
+    assert!(CertificateAuthority::new(root, pkey()).is_err());
+}
+
+#[test]
+fn non_ca_issuer() {
+    let key = pkey();
+    let cert = self_signed("Not a CA", &key);
+    assert!(CertificateAuthority::new(cert, key).is_err());
+}
+
+#[test]
+fn issue_with_ed25519_issuer() {
+    let (root, root_key) = root();
+    let key = ed25519_key();
+    let issuer = ca("Ed25519 Intermediate", &key, Some((&root, &root_key)));
+    let mut ca = CertificateAuthority::new(issuer.clone(), key).unwrap();
+    ca.set_chain(vec![root.clone()]);
+
+    let chain = ca
+        .issue(&request("example.com", &pkey()), &CertProfile::server())
+        .unwrap();
+    assert!(chain[0].verify(&issuer.public_key().unwrap()).unwrap());
+    assert!(verify(&chain, &root));
+}
+
+#[test]
+fn empty_subject_has_critical_san() {
+    let (root, root_key) = root();
+    let ca = CertificateAuthority::new(root, root_key).unwrap();
+
+    let key = pkey();
+    let mut builder = X509Req::builder().unwrap();
+    builder.set_pubkey(&key).unwrap();
+    let mut extensions = Stack::new().unwrap();
+    let san = SubjectAlternativeName::new()
+        .dns("example.com")
+        .build(&builder.x509v3_context(None))
+        .unwrap();
+    extensions.push(san).unwrap();
+    builder.add_extensions(&extensions).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+    let req = builder.build();
+
+    let chain = ca.issue(&req, &CertProfile::server()).unwrap();
+    let text = chain[0].to_text().unwrap();
+    assert!(
+        text.contains("X509v3 Subject Alternative Name: critical"),
+        "{text}"
+    );
+
+    // Nothing would identify the subject of a code signing certificate.
+    assert!(ca.issue(&req, &CertProfile::code_signing()).is_err());
+}
@@ -0,0 +1,232 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::asn1::Asn1Object;
+use crate::ssl::test::pki::{root, Chain};
+use crate::ssl::{SslContext, SslMethod};
+use crate::x509::ca::{CertProfile, CertificateAuthority};
+use crate::x509::chain::build_serving_chain;
+use crate::x509::verify::{VerifyOptions, X509Purpose, X509VerifyFlags};
+use crate::x509::{X509VerifyError, X509};
+
+use super::ca::request;
+use super::pkey;
+
+#[test]
+fn verify_chain() {
+    let chain = Chain::new();
+    let (store, intermediate, leaf) = (chain.store(), chain.intermediate, chain.leaf);
+
+    let mut options = VerifyOptions::new();
+    options
+        .set_host("example.com")
+        .set_purpose(X509Purpose::SSL_SERVER);
+    let result = store
+        .verify_chain(&leaf, &[intermediate.clone()], &options)
+        .unwrap();
+
+    assert!(result.is_valid(), "{:?}", result.errors());
+    let chain = result.chain();
+    assert_eq!(chain.len(), 3);
+    assert_eq!(chain[0].to_der().unwrap(), leaf.to_der().unwrap());
+    assert_eq!(chain[1].to_der().unwrap(), intermediate.to_der().unwrap());
+    assert_eq!(
+        chain[2].subject_name().to_rfc4514().unwrap(),
+        "CN=Test Root"
+    );
+}
+
+#[test]
+fn verify_chain_reports_every_error() {
+    let chain = Chain::new();
+    let (store, intermediate, leaf) = (chain.store(), chain.intermediate, chain.leaf);
+
+    let in_twenty_years = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 20 * 365 * 24 * 60 * 60;
+    let mut options = VerifyOptions::new();
+    options
+        .set_time(in_twenty_years as _)
+        .set_host("example.org")
+        .set_purpose(X509Purpose::SSL_CLIENT);
+    let result = store
+        .verify_chain(&leaf, &[intermediate], &options)
+        .unwrap();
+
+    assert!(!result.is_valid());
+    assert_eq!(result.chain().len(), 3);
+    let leaf_errors = result.errors_at(0).collect::<Vec<_>>();
+    assert!(leaf_errors.contains(&X509VerifyError::CERT_HAS_EXPIRED));
+    assert!(leaf_errors.contains(&X509VerifyError::HOSTNAME_MISMATCH));
+    assert!(leaf_errors.contains(&X509VerifyError::INVALID_PURPOSE));
+    assert!(result
+        .errors_at(1)
+        .any(|e| e == X509VerifyError::CERT_HAS_EXPIRED));
+    assert!(result
+        .errors_at(2)
+        .any(|e| e == X509VerifyError::CERT_HAS_EXPIRED));
+
+    let error = &result.errors()[0];
+    assert_eq!(
+        error.certificate().unwrap().to_der().unwrap(),
+        result.chain()[error.depth() as usize].to_der().unwrap()
+    );
+}
+
+#[test]
+fn verify_chain_missing_intermediate() {
+    let chain = Chain::new();
+    let (store, leaf) = (chain.store(), chain.leaf);
+
+    let result = store
+        .verify_chain(&leaf, &[], &VerifyOptions::new())
+        .unwrap();
+    assert!(!result.is_valid());
+    assert_eq!(result.chain().len(), 1);
+    assert_eq!(
+        result.errors_at(0).collect::<Vec<_>>(),
+        [X509VerifyError::UNABLE_TO_GET_ISSUER_CERT_LOCALLY]
+    );
+}
+
+#[test]
+fn verify_chain_depth_and_policy() {
+    let chain = Chain::new();
+    let (store, intermediate, leaf) = (chain.store(), chain.intermediate, chain.leaf);
+
+    let mut options = VerifyOptions::new();
+    options.set_depth(0);
+    let result = store
+        .verify_chain(&leaf, &[intermediate.clone()], &options)
+        .unwrap();
+    assert!(result
+        .errors()
+        .iter()
+        .any(|e| e.error() == X509VerifyError::CERT_CHAIN_TOO_LONG));
+
+    let mut options = VerifyOptions::new();
+    options
+        .add_policy(Asn1Object::from_str("2.23.140.1.2.1").unwrap())
+        .set_flags(X509VerifyFlags::EXPLICIT_POLICY);
+    let result = store
+        .verify_chain(&leaf, &[intermediate], &options)
+        .unwrap();
+    assert!(result
+        .errors()
+        .iter()
+        .any(|e| e.error() == X509VerifyError::NO_EXPLICIT_POLICY));
+}
+
+/// Returns a root, two intermediates and a leaf issued by the second intermediate.
+fn long_chain() -> [X509; 4] {
+    let (root, root_key) = root();
+    let root_ca = CertificateAuthority::new(root.clone(), root_key).unwrap();
+
+    let mut profile = CertProfile::intermediate();
+    profile.set_path_len(None);
+    let first_key = pkey();
+    let first = root_ca
+        .issue(&request("First Intermediate", &first_key), &profile)
+        .unwrap()
+        .remove(0);
+    let first_ca = CertificateAuthority::new(first.clone(), first_key).unwrap();
+
+    let second_key = pkey();
+    let second = first_ca
+        .issue(&request("Second Intermediate", &second_key), &profile)
+        .unwrap()
+        .remove(0);
+    let second_ca = CertificateAuthority::new(second.clone(), second_key).unwrap();
+
+    let leaf = second_ca
+        .issue(&request("example.com", &pkey()), &CertProfile::server())
+        .unwrap()
+        .remove(0);
+    [root, first, second, leaf]
+}
+
+fn ders(certs: &[X509]) -> Vec<Vec<u8>> {
+    certs.iter().map(|c| c.to_der().unwrap()).collect()
+}
+
+#[test]
+fn serving_chain_orders_pool() {
+    let [root, first, second, leaf] = long_chain();
+    let (unrelated, _) = root();
+
+    let pool = [
+        unrelated.clone(),
+        root.clone(),
+        second.clone(),
+        leaf.clone(),
+        first.clone(),
+        second.clone(),
+    ];
+    let chain = build_serving_chain(&leaf, &pool, &[root.clone()]).unwrap();
+
+    assert_eq!(ders(chain.chain()), ders(&[leaf, second, first]));
+    assert_eq!(chain.intermediates().len(), 2);
+    assert_eq!(
+        chain.root().unwrap().to_der().unwrap(),
+        root.to_der().unwrap()
+    );
+    assert!(chain.is_complete());
+    assert!(chain.missing_issuer().is_none());
+    assert_eq!(ders(chain.unrelated()), ders(&[unrelated]));
+}
+
+#[test]
+fn serving_chain_missing_link() {
+    let [root, first, _, leaf] = long_chain();
+
+    let chain = build_serving_chain(&leaf, &[first.clone()], &[root]).unwrap();
+    assert_eq!(ders(chain.chain()), ders(&[leaf]));
+    assert!(!chain.is_complete());
+    assert!(chain.root().is_none());
+    assert_eq!(
+        chain.missing_issuer().unwrap().to_rfc4514().unwrap(),
+        "CN=Second Intermediate"
+    );
+    assert_eq!(ders(chain.unrelated()), ders(&[first]));
+}
+
+#[test]
+fn serving_chain_root_from_pool() {
+    let [root, first, second, leaf] = long_chain();
+
+    let chain = build_serving_chain(&leaf, &[root.clone(), first, second], &[]).unwrap();
+    assert_eq!(chain.chain().len(), 3);
+    assert!(!chain.is_complete());
+    assert!(chain.missing_issuer().is_none());
+    assert_eq!(
+        chain.root().unwrap().to_der().unwrap(),
+        root.to_der().unwrap()
+    );
+    assert!(chain.unrelated().is_empty());
+}
+
+#[test]
+fn set_leaf_and_pool() {
+    let [root, first, second, leaf] = long_chain();
+
+    let mut ctx = SslContext::builder(SslMethod::tls()).unwrap();
+    ctx.add_extra_chain_cert(leaf.clone()).unwrap();
+    let chain = ctx
+        .set_leaf_and_pool(&leaf, &[first.clone(), second.clone()], &[root])
+        .unwrap();
+    assert_eq!(chain.intermediates().len(), 2);
+    assert!(chain.is_complete());
+
+    let ctx = ctx.build();
+    assert_eq!(
+        ctx.certificate().unwrap().to_der().unwrap(),
+        leaf.to_der().unwrap()
+    );
+    let extra = ctx
+        .extra_chain_certs()
+        .iter()
+        .map(|c| c.to_der().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(extra, ders(&[second, first]));
+}
@@ -1,19 +1,31 @@
 use hex::{self, FromHex};
+use std::cmp::Ordering;
+use std::future::Future;
+use std::pin::pin;
+use std::ptr;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
 
-use crate::asn1::Asn1Time;
+use crate::asn1::{Asn1Time, Asn1Type};
 use crate::bn::{BigNum, MsbOption};
 use crate::hash::MessageDigest;
 use crate::nid::Nid;
 use crate::pkey::{PKey, Private};
 use crate::rsa::Rsa;
 use crate::stack::Stack;
+use crate::x509::crl::X509Crl;
 use crate::x509::extension::{
-    AuthorityKeyIdentifier, BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
-    SubjectKeyIdentifier,
+    AuthorityInfoAccess, AuthorityKeyIdentifier, BasicConstraints, CertificatePolicies,
+    CrlDistributionPoints, ExtendedKeyUsage, KeyUsage, NameConstraints, PolicyQualifier,
+    SubjectAlternativeName, SubjectKeyIdentifier, TlsFeature,
 };
 use crate::x509::store::X509StoreBuilder;
 use crate::x509::{X509Extension, X509Name, X509Req, X509StoreContext, X509};
 
+mod aia;
+mod ca;
+mod chain;
+mod signer;
+mod store;
 mod trusted_first;
 
 fn pkey() -> PKey<Private> {
@@ -21,6 +33,21 @@ fn pkey() -> PKey<Private> {
     PKey::from_rsa(rsa).unwrap()
 }
 
+/// Polls a future that is expected to complete without waiting.
+fn now_or_never<F: Future>(future: F) -> F::Output {
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(
+        |_| RawWaker::new(ptr::null(), &VTABLE),
+        |_| {},
+        |_| {},
+        |_| {},
+    );
+    let waker = unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) };
+    match pin!(future).poll(&mut Context::from_waker(&waker)) {
+        Poll::Ready(output) => output,
+        Poll::Pending => panic!("future did not complete"),
+    }
+}
+
 #[test]
 fn test_cert_loading() {
     let cert = include_bytes!("../../../test/cert.pem");
@@ -198,6 +225,22 @@ fn test_subject_key_id() {
     assert!(aki.is_none());
 }
 
+#[test]
+fn test_spki() {
+    let cert = include_bytes!("../../../test/cert.pem");
+    let cert = X509::from_pem(cert).unwrap();
+
+    let spki = cert.spki_der().unwrap();
+    assert_eq!(
+        spki,
+        cert.public_key().unwrap().public_key_to_der().unwrap()
+    );
+    assert_eq!(
+        hex::encode(cert.spki_sha256().unwrap()),
+        "5474006cd97aee79a464934449e4cabd3c5be5b42677599a5a730a1bfb637006"
+    );
+}
+
 #[test]
 fn test_x509_name_print_ex() {
     let cert = include_bytes!("../../../test/cert.pem");
@@ -222,6 +265,114 @@ fn test_x509_name_print_ex() {
     );
 }
 
+#[test]
+fn test_x509_name_rfc4514() {
+    let cert = include_bytes!("../../../test/cert.pem");
+    let cert = X509::from_pem(cert).unwrap();
+    let subject = cert.subject_name().to_rfc4514().unwrap();
+    assert_eq!(
+        subject,
+        "CN=foobar.com,O=Internet Widgits Pty Ltd,ST=Some-State,C=AU"
+    );
+    let name = X509Name::from_rfc4514(&subject).unwrap();
+    assert_eq!(
+        name.to_der().unwrap(),
+        cert.subject_name().to_der().unwrap()
+    );
+
+    let name = X509Name::from_rfc4514(r"cn=foo\, bar\2B\C3\A9 , O=\ Bar\ +OU=x\\y,C=US").unwrap();
+    let entries = name
+        .entries()
+        .map(|e| (e.object().nid(), e.data().as_utf8().unwrap().to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        entries,
+        [
+            (Nid::COUNTRYNAME, "US".to_string()),
+            // Attributes of multi-valued RDNs are kept in DER order.
+            (Nid::ORGANIZATIONALUNITNAME, r"x\y".to_string()),
+            (Nid::ORGANIZATIONNAME, " Bar ".to_string()),
+            (Nid::COMMONNAME, "foo, bar+é".to_string()),
+        ]
+    );
+    assert_eq!(
+        name.to_rfc4514().unwrap(),
+        r"CN=foo\, bar\+é,OU=x\\y+O=\ Bar\ ,C=US"
+    );
+
+    let name = X509Name::from_rfc4514("1.2.3.4=\\#x\\01,emailAddress=a@example.com").unwrap();
+    assert_eq!(
+        name.to_rfc4514().unwrap(),
+        "1.2.3.4=\\#x\\01,emailAddress=a@example.com"
+    );
+
+    assert_eq!(
+        X509Name::from_rfc4514("").unwrap().to_rfc4514().unwrap(),
+        ""
+    );
+}
+
+#[test]
+fn test_x509_name_rfc4514_invalid() {
+    for name in [
+        "CN",
+        "=foo",
+        "CN=foo,",
+        "CN=a;O=b",
+        r"CN=\q",
+        r"CN=\4",
+        r"CN=\FF",
+        "CN=#0403666f6f",
+        "C=USA",
+        "unknownAttribute=foo",
+    ] {
+        assert!(X509Name::from_rfc4514(name).is_err(), "{name}");
+    }
+}
+
+#[test]
+fn test_x509_name_compare() {
+    let a = X509Name::from_rfc4514("CN=Foo  Bar,O=Example+OU=Ops").unwrap();
+    let mut b = X509Name::builder().unwrap();
+    b.append_entry_by_nid_with_type(
+        Nid::ORGANIZATIONALUNITNAME,
+        "ops",
+        Asn1Type::PRINTABLESTRING,
+    )
+    .unwrap();
+    b.append_entry_by_nid(Nid::ORGANIZATIONNAME, "EXAMPLE")
+        .unwrap();
+    b.append_entry_by_nid(Nid::COMMONNAME, " foo bar").unwrap();
+    let b = b.build();
+    let c = X509Name::from_rfc4514("CN=Foo Baz,O=Example+OU=Ops").unwrap();
+
+    // The multi-valued RDN of `b` was built as two RDNs.
+    assert_ne!(a, b);
+    assert_ne!(
+        a.to_canonical_string().unwrap(),
+        b.to_canonical_string().unwrap()
+    );
+
+    let b = X509Name::from_rfc4514("CN= foo bar,OU=ops+O=EXAMPLE").unwrap();
+    assert_eq!(a, b);
+    assert_eq!(a.compare(&b).unwrap(), Ordering::Equal);
+    assert_eq!(
+        a.to_canonical_string().unwrap(),
+        "2.5.4.3=foo bar,2.5.4.10=example+2.5.4.11=ops"
+    );
+    assert_eq!(
+        a.to_canonical_string().unwrap(),
+        b.to_canonical_string().unwrap()
+    );
+
+    assert_ne!(a, c);
+    assert_eq!(a.partial_cmp(&c), Some(a.compare(&c).unwrap()));
+    assert_ne!(
+        a.to_canonical_string().unwrap(),
+        c.to_canonical_string().unwrap()
+    );
+}
+
 #[test]
 fn x509_builder() {
     let pkey = pkey();
@@ -343,6 +494,89 @@ fn x509_extension_to_der() {
     }
 }
 
+#[test]
+fn x509_extension_builders_to_der() {
+    for (ext, expected) in [
+        (
+            NameConstraints::new()
+                .critical()
+                .permitted_dns("example.com")
+                .permitted_ip_range("192.168.12.0".parse().unwrap(), 16)
+                .excluded_email("example.org")
+                .build()
+                .unwrap(),
+            "303a0603551d1e0101ff0430302ea01b300d820b6578616d706c652e636f6d300a8708c0a80000ffff0000\
+             a10f300d810b6578616d706c652e6f7267",
+        ),
+        (
+            CertificatePolicies::new()
+                .policy_with_qualifiers(
+                    "2.23.140.1.2.1",
+                    vec![
+                        PolicyQualifier::Cps("https://example.com/cps".to_string()),
+                        PolicyQualifier::UserNotice("hi".to_string()),
+                    ],
+                )
+                .any_policy()
+                .build()
+                .unwrap(),
+            "30540603551d20044d304b3041060667810c0102013037302306082b06010505070201161768747470733a\
+             2f2f6578616d706c652e636f6d2f637073301006082b0601050507020230040c02686930060604551d2000",
+        ),
+        (
+            CrlDistributionPoints::new()
+                .uri("http://example.com/crl")
+                .build()
+                .unwrap(),
+            "30270603551d1f0420301e301ca01aa0188616687474703a2f2f6578616d706c652e636f6d2f63726c",
+        ),
+        (
+            AuthorityInfoAccess::new()
+                .ocsp("http://ocsp.example.com")
+                .ca_issuers("http://example.com/ca.der")
+                .build()
+                .unwrap(),
+            "305a06082b06010505070101044e304c302306082b060105050730018617687474703a2f2f6f6373702e65\
+             78616d706c652e636f6d302506082b060105050730028619687474703a2f2f6578616d706c652e636f6d2f\
+             63612e646572",
+        ),
+        (
+            TlsFeature::new().status_request().build().unwrap(),
+            "301106082b0601050507011804053003020105",
+        ),
+    ] {
+        assert_eq!(hex::encode(ext.to_der().unwrap()), expected);
+    }
+}
+
+#[test]
+fn name_constraints_critical_by_default() {
+    let der = |ext: &mut NameConstraints| {
+        hex::encode(
+            ext.permitted_dns("example.com")
+                .build()
+                .unwrap()
+                .to_der()
+                .unwrap(),
+        )
+    };
+    let default = der(&mut NameConstraints::new());
+    assert_eq!(default, der(NameConstraints::new().critical()));
+    assert!(default.starts_with("301d0603551d1e0101ff"));
+    assert_eq!(
+        der(NameConstraints::new().non_critical()),
+        "301a0603551d1e04133011a00f300d820b6578616d706c652e636f6d"
+    );
+}
+
+#[test]
+fn name_constraints_invalid_prefix() {
+    assert!(NameConstraints::new()
+        .permitted_ip_range("10.0.0.0".parse().unwrap(), 33)
+        .build()
+        .is_err());
+}
+
 #[test]
 fn eku_invalid_other() {
     assert!(ExtendedKeyUsage::new()
@@ -528,3 +762,107 @@ fn test_check_ip_asc() {
     assert!(cert.check_ip_asc("0:0:0:0:0:0:0:1").unwrap());
     assert!(!cert.check_ip_asc("0:0:0:0:0:0:0:2").unwrap());
 }
+
+#[test]
+fn test_x509_to_text() {
+    let cert = include_bytes!("../../../test/alt_name_cert.pem");
+    let cert = X509::from_pem(cert).unwrap();
+    let text = cert.to_text().unwrap();
+
+    for line in [
+        "Certificate:",
+        "Version: 3 (0x2)",
+        "Serial Number: 1 (0x1)",
+        "Signature Algorithm: sha256WithRSAEncryption",
+        "Issuer: C=AU, ST=Some-State, O=Internet Widgits Pty Ltd",
+        "Not Before: Jan 15 11:07:03 2018 GMT",
+        "Not After : Jan 13 11:07:03 2028 GMT",
+        "Public Key Algorithm: rsaEncryption",
+        "Public-Key: (2048 bit)",
+        "X509v3 Basic Constraints:",
+        "CA:FALSE",
+        "Digital Signature, Key Encipherment",
+        "DNS:example.com, IP Address:127.0.0.1",
+    ] {
+        assert!(text.contains(line), "missing {line:?} in\n{text}");
+    }
+    assert_eq!(text, cert.to_text().unwrap());
+}
+
+#[test]
+fn test_x509_req_to_text() {
+    let key = pkey();
+    let mut builder = X509Req::builder().unwrap();
+    builder
+        .set_subject_name(&X509Name::from_rfc4514("CN=foobar.com").unwrap())
+        .unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+    let text = builder.build().to_text().unwrap();
+
+    assert!(text.starts_with("Certificate Request:"), "{text}");
+    assert!(text.contains("Subject: CN=foobar.com"), "{text}");
+    assert!(text.contains("Public-Key: (2048 bit)"), "{text}");
+}
+
+#[test]
+fn test_x509_crl_to_text() {
+    let key = pkey();
+    let mut builder = X509Crl::builder().unwrap();
+    builder
+        .set_issuer_name(&X509Name::from_rfc4514("CN=Test CA").unwrap())
+        .unwrap();
+    builder
+        .set_last_update(&Asn1Time::from_unix(1_700_000_000).unwrap())
+        .unwrap();
+    builder
+        .add_revoked(
+            &BigNum::from_u32(42).unwrap().to_asn1_integer().unwrap(),
+            &Asn1Time::from_unix(1_700_000_000).unwrap(),
+        )
+        .unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+    let text = builder.build().to_text().unwrap();
+
+    for line in [
+        "Certificate Revocation List (CRL):",
+        "Issuer: /CN=Test CA",
+        "Last Update: Nov 14 22:13:20 2023 GMT",
+        "Revoked Certificates:",
+        "Serial Number: 2A",
+    ] {
+        assert!(text.contains(line), "missing {line:?} in\n{text}");
+    }
+}
+
+#[test]
+#[cfg(feature = "mozilla-roots")]
+fn test_bundled_roots() {
+    let mut builder = X509StoreBuilder::new().unwrap();
+    builder.add_bundled_roots().unwrap();
+    assert!(builder.objects_len() > 100);
+
+    // Adding the roots again does not duplicate them.
+    let len = builder.objects_len();
+    builder.add_bundled_roots().unwrap();
+    assert_eq!(builder.objects_len(), len);
+}
+
+/// The bundle must be certifi's `cacert.pem` for `BUNDLED_ROOTS_VERSION`, byte for byte.
+///
+/// The digest is the one listed for `certifi/cacert.pem` in the `RECORD` of the certifi 2026.5.20
+/// wheel, so that any certificate added to or removed from the upstream file stands out.
+#[test]
+#[cfg(feature = "mozilla-roots")]
+fn test_bundled_roots_integrity() {
+    use crate::hash::hash;
+    use crate::x509::store::BUNDLED_ROOTS_VERSION;
+
+    const CERTIFI_CACERT_SHA256: &str =
+        "7cef540c6af3bf0a3c7d0070bd3fd7aae34f8aa805b408d1c27a01c8aeb305df";
+
+    assert_eq!(BUNDLED_ROOTS_VERSION, "2026.05.20");
+    let bundle = include_bytes!("../../../roots/mozilla.pem");
+    let digest = hash(MessageDigest::sha256(), bundle).unwrap();
+    assert_eq!(hex::encode(digest), CERTIFI_CACERT_SHA256);
+}
@@ -0,0 +1,175 @@
+use std::io;
+
+use crate::asn1::Asn1Time;
+use crate::bn::BigNum;
+use crate::hash::MessageDigest;
+use crate::nid::Nid;
+use crate::pkey::{PKey, Private};
+use crate::rsa::Padding;
+use crate::sign::{RsaPssSaltlen, Signer};
+use crate::ssl::test::pki::{builder, ec_key};
+use crate::ssl::SslSignatureAlgorithm;
+use crate::x509::crl::X509Crl;
+use crate::x509::signer::{AsyncCertSigner, BoxCertSignFuture, CertSigner};
+use crate::x509::{X509Req, X509};
+
+use super::{now_or_never, pkey};
+
+struct KeySigner {
+    key: PKey<Private>,
+    algorithm: SslSignatureAlgorithm,
+}
+
+impl KeySigner {
+    fn sign_bytes(&self, tbs: &[u8]) -> io::Result<Vec<u8>> {
+        let digest = match self.algorithm {
+            SslSignatureAlgorithm::RSA_PKCS1_SHA256
+            | SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256
+            | SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256 => MessageDigest::sha256(),
+            _ => unreachable!(),
+        };
+        let mut signer = Signer::new(digest, &self.key)?;
+        if self.algorithm == SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256 {
+            signer.set_rsa_padding(Padding::PKCS1_PSS)?;
+            signer.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
+        }
+        signer.update(tbs)?;
+        Ok(signer.sign_to_vec()?)
+    }
+}
+
+impl CertSigner for KeySigner {
+    fn algorithm(&self) -> SslSignatureAlgorithm {
+        self.algorithm
+    }
+
+    fn sign(&self, tbs: &[u8]) -> io::Result<Vec<u8>> {
+        self.sign_bytes(tbs)
+    }
+}
+
+impl AsyncCertSigner for KeySigner {
+    fn algorithm(&self) -> SslSignatureAlgorithm {
+        self.algorithm
+    }
+
+    fn sign(&self, tbs: Vec<u8>) -> BoxCertSignFuture {
+        let signature = self.sign_bytes(&tbs);
+        Box::pin(async move { signature })
+    }
+}
+
+struct FailingSigner;
+
+impl CertSigner for FailingSigner {
+    fn algorithm(&self) -> SslSignatureAlgorithm {
+        SslSignatureAlgorithm::RSA_PKCS1_SHA256
+    }
+
+    fn sign(&self, _: &[u8]) -> io::Result<Vec<u8>> {
+        Err(io::Error::other("key unavailable"))
+    }
+}
+
+#[test]
+fn sign_certificate_with() {
+    for (key, algorithm) in [
+        (pkey(), SslSignatureAlgorithm::RSA_PKCS1_SHA256),
+        (pkey(), SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256),
+        (ec_key(), SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256),
+    ] {
+        let mut builder = builder("signer", &key, None);
+        builder
+            .sign_with(&KeySigner {
+                key: key.clone(),
+                algorithm,
+            })
+            .unwrap();
+        let cert = X509::from_der(&builder.build().to_der().unwrap()).unwrap();
+        assert!(cert.verify(&key).unwrap());
+    }
+}
+
+#[test]
+fn sign_certificate_with_async() {
+    let key = pkey();
+    let signer = KeySigner {
+        key: key.clone(),
+        algorithm: SslSignatureAlgorithm::RSA_PKCS1_SHA256,
+    };
+
+    let mut builder = builder("signer", &key, None);
+    now_or_never(builder.sign_with_async(&signer)).unwrap();
+    let cert = builder.build();
+    assert!(cert.verify(&key).unwrap());
+    assert_eq!(
+        cert.signature_algorithm().object().nid(),
+        Nid::SHA256WITHRSAENCRYPTION
+    );
+}
+
+#[test]
+fn sign_request_with() {
+    let key = ec_key();
+    let mut builder = X509Req::builder().unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder
+        .sign_with(&KeySigner {
+            key: key.clone(),
+            algorithm: SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256,
+        })
+        .unwrap();
+
+    let req = X509Req::from_der(&builder.build().to_der().unwrap()).unwrap();
+    assert!(req.verify(&key).unwrap());
+}
+
+#[test]
+fn sign_crl_with() {
+    let key = pkey();
+    let issuer = builder("signer", &key, None).build();
+
+    let mut builder = X509Crl::builder().unwrap();
+    builder.set_issuer_name(issuer.subject_name()).unwrap();
+    builder
+        .set_last_update(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .add_revoked(
+            &BigNum::from_u32(42).unwrap().to_asn1_integer().unwrap(),
+            &Asn1Time::days_from_now(0).unwrap(),
+        )
+        .unwrap();
+    let signer = KeySigner {
+        key: key.clone(),
+        algorithm: SslSignatureAlgorithm::RSA_PKCS1_SHA256,
+    };
+    now_or_never(builder.sign_with_async(&signer)).unwrap();
+
+    let crl = X509Crl::from_pem(&builder.build().to_pem().unwrap()).unwrap();
+    assert!(crl.verify(&key).unwrap());
+    let revoked = crl.revoked().unwrap();
+    assert_eq!(revoked.len(), 1);
+    assert_eq!(
+        revoked[0].serial_number().to_bn().unwrap(),
+        BigNum::from_u32(42).unwrap()
+    );
+}
+
+#[test]
+fn sign_with_failing_signer() {
+    let key = pkey();
+    let mut builder = builder("signer", &key, None);
+    assert!(builder.sign_with(&FailingSigner).is_err());
+}
+
+#[test]
+fn sign_with_unsupported_algorithm() {
+    let key = pkey();
+    let mut builder = builder("signer", &key, None);
+    let signer = KeySigner {
+        key: key.clone(),
+        algorithm: SslSignatureAlgorithm::RSA_PKCS1_MD5_SHA1,
+    };
+    assert!(builder.sign_with(&signer).is_err());
+}
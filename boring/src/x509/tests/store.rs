@@ -0,0 +1,136 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::x509::store::X509StoreBuilder;
+use crate::x509::X509;
+
+const ROOT: &[u8] = include_bytes!("../../../test/root-ca.pem");
+const ROOT_2: &[u8] = include_bytes!("../../../test/root-ca-2.pem");
+const CERT: &[u8] = include_bytes!("../../../test/cert.pem");
+const KEY: &[u8] = include_bytes!("../../../test/key.pem");
+const INVALID: &[u8] =
+    b"-----BEGIN CERTIFICATE-----\nbm90IGEgY2VydGlmaWNhdGU=\n-----END CERTIFICATE-----\n";
+
+/// A directory removed when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let path =
+            std::env::temp_dir().join(format!("boring-store-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    fn write(&self, name: &str, contents: &[u8]) -> PathBuf {
+        let path = self.0.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn add_pem_bundle() {
+    let bundle = [ROOT, KEY, INVALID, ROOT, CERT].concat();
+
+    let mut builder = X509StoreBuilder::new().unwrap();
+    let report = builder.add_pem_bundle(&bundle).unwrap();
+    assert_eq!(report.added(), 2);
+    assert_eq!(report.duplicates(), 1);
+    assert_eq!(report.failures().len(), 1);
+    assert_eq!(report.failures()[0].path(), None);
+    assert_eq!(report.failures()[0].index(), Some(2));
+    assert_eq!(builder.objects_len(), 2);
+
+    // Certificates already in the store are not added again.
+    let report = builder.add_pem_bundle(ROOT_2).unwrap();
+    assert_eq!(report.added(), 1);
+    let report = builder.add_pem_bundle(&[ROOT, ROOT_2].concat()).unwrap();
+    assert_eq!(report.added(), 0);
+    assert_eq!(report.duplicates(), 2);
+    assert_eq!(builder.objects_len(), 3);
+}
+
+#[test]
+fn add_pem_bundle_truncated() {
+    let mut builder = X509StoreBuilder::new().unwrap();
+    let report = builder
+        .add_pem_bundle(&[ROOT, &CERT[..CERT.len() / 2]].concat())
+        .unwrap();
+    assert_eq!(report.added(), 1);
+    assert_eq!(report.failures().len(), 1);
+    assert_eq!(report.failures()[0].index(), Some(1));
+}
+
+#[test]
+fn add_directory() {
+    let dir = TempDir::new("directory");
+    dir.write("root.pem", ROOT);
+    dir.write("deadbeef.0", ROOT);
+    dir.write("cert.der", &X509::from_pem(CERT).unwrap().to_der().unwrap());
+    dir.write("bundle.crt", &[ROOT_2, CERT].concat());
+    dir.write("deadbeef.r0", b"not a certificate");
+    let junk = dir.write("junk.txt", b"not a certificate");
+    fs::create_dir(dir.0.join("nested")).unwrap();
+
+    let mut builder = X509StoreBuilder::new().unwrap();
+    let report = builder.add_directory(&dir.0).unwrap();
+    assert_eq!(report.added(), 3);
+    assert_eq!(report.duplicates(), 2);
+    assert_eq!(report.failures().len(), 1);
+    assert_eq!(report.failures()[0].path(), Some(junk.as_path()));
+    assert_eq!(report.failures()[0].index(), None);
+    assert_eq!(builder.objects_len(), 3);
+}
+
+#[test]
+fn add_directory_missing() {
+    let dir = TempDir::new("missing");
+    let missing = dir.0.join("missing");
+
+    let mut builder = X509StoreBuilder::new().unwrap();
+    let report = builder.add_directory(&missing).unwrap();
+    assert_eq!(report.added(), 0);
+    assert_eq!(report.failures()[0].path(), Some(missing.as_path()));
+    assert_eq!(
+        report.failures()[0].error().kind(),
+        std::io::ErrorKind::NotFound
+    );
+}
+
+#[test]
+fn add_system_roots_from_environment() {
+    let dir = TempDir::new("system");
+    let file = dir.write("bundle.pem", ROOT);
+    let certs = TempDir::new("system-certs");
+    certs.write("a.pem", ROOT_2);
+    let other = TempDir::new("system-other");
+    other.write("b.pem", CERT);
+
+    let cert_dir = std::env::join_paths([&certs.0, &other.0]).unwrap();
+    let mut builder = X509StoreBuilder::new().unwrap();
+    let report = builder
+        .add_system_roots_from(Some(file.into_os_string()), Some(cert_dir))
+        .unwrap();
+    assert_eq!(report.added(), 3);
+    assert!(report.failures().is_empty());
+
+    let empty = TempDir::new("system-empty");
+    let mut builder = X509StoreBuilder::new().unwrap();
+    let report = builder
+        .add_system_roots_from(
+            Some(OsString::from("/nonexistent/bundle.pem")),
+            Some(empty.0.clone().into_os_string()),
+        )
+        .unwrap();
+    assert_eq!(report.added(), 0);
+    assert_eq!(report.failures().len(), 1);
+}
@@ -3,8 +3,13 @@ use foreign_types::{ForeignType, ForeignTypeRef};
 use libc::{c_int, c_uint, c_ulong, time_t};
 use openssl_macros::corresponds;
 use std::net::IpAddr;
+use std::sync::Arc;
 
+use crate::asn1::Asn1Object;
 use crate::error::ErrorStack;
+use crate::stack::{Stack, StackRef};
+use crate::x509::aia::AiaFetcher;
+use crate::x509::{X509VerifyError, X509};
 use crate::{cvt, cvt_p};
 
 bitflags! {
@@ -56,6 +61,37 @@ bitflags! {
     }
 }
 
+/// The purpose a certificate is verified for.
+///
+/// The purpose determines the extended key usage and trust settings required of the chain.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct X509Purpose(c_int);
+
+impl X509Purpose {
+    pub const SSL_CLIENT: Self = Self(ffi::X509_PURPOSE_SSL_CLIENT as c_int);
+    pub const SSL_SERVER: Self = Self(ffi::X509_PURPOSE_SSL_SERVER as c_int);
+    pub const NS_SSL_SERVER: Self = Self(ffi::X509_PURPOSE_NS_SSL_SERVER as c_int);
+    pub const SMIME_SIGN: Self = Self(ffi::X509_PURPOSE_SMIME_SIGN as c_int);
+    pub const SMIME_ENCRYPT: Self = Self(ffi::X509_PURPOSE_SMIME_ENCRYPT as c_int);
+    pub const CRL_SIGN: Self = Self(ffi::X509_PURPOSE_CRL_SIGN as c_int);
+    pub const ANY: Self = Self(ffi::X509_PURPOSE_ANY as c_int);
+    pub const OCSP_HELPER: Self = Self(ffi::X509_PURPOSE_OCSP_HELPER as c_int);
+    pub const TIMESTAMP_SIGN: Self = Self(ffi::X509_PURPOSE_TIMESTAMP_SIGN as c_int);
+
+    /// Constructs an `X509Purpose` from a raw `X509_PURPOSE_*` value.
+    #[must_use]
+    pub fn from_raw(raw: c_int) -> Self {
+        Self(raw)
+    }
+
+    /// Returns the raw `X509_PURPOSE_*` value.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    #[must_use]
+    pub fn as_raw(&self) -> c_int {
+        self.0
+    }
+}
+
 foreign_type_and_impl_send_sync! {
     type CType = ffi::X509_VERIFY_PARAM;
     fn drop = ffi::X509_VERIFY_PARAM_free;
@@ -184,6 +220,32 @@ impl X509VerifyParamRef {
         unsafe { ffi::X509_VERIFY_PARAM_set_depth(self.as_ptr(), depth) }
     }
 
+    /// Set the purpose the certificate is verified for.
+    #[corresponds(X509_VERIFY_PARAM_set_purpose)]
+    pub fn set_purpose(&mut self, purpose: X509Purpose) -> Result<(), ErrorStack> {
+        unsafe {
+            cvt(ffi::X509_VERIFY_PARAM_set_purpose(
+                self.as_ptr(),
+                purpose.as_raw(),
+            ))
+            .map(|_| ())
+        }
+    }
+
+    /// Set the acceptable certificate policies.
+    ///
+    /// This also enables policy checking.
+    #[corresponds(X509_VERIFY_PARAM_set1_policies)]
+    pub fn set_policies(&mut self, policies: &StackRef<Asn1Object>) -> Result<(), ErrorStack> {
+        unsafe {
+            cvt(ffi::X509_VERIFY_PARAM_set1_policies(
+                self.as_ptr(),
+                policies.as_ptr(),
+            ))
+            .map(|_| ())
+        }
+    }
+
     /// Copies parameters from `src`.
     ///
     /// If a parameter is unset in `src`, the existing value in `self`` is preserved.
@@ -192,3 +254,232 @@ impl X509VerifyParamRef {
         unsafe { cvt(ffi::X509_VERIFY_PARAM_set1(self.as_ptr(), src.as_ptr())).map(|_| ()) }
     }
 }
+
+/// Options for [`X509StoreRef::verify_chain`].
+///
+/// Options left unset use the values configured on the store.
+///
+/// [`X509StoreRef::verify_chain`]: crate::x509::store::X509StoreRef::verify_chain
+pub struct VerifyOptions {
+    time: Option<time_t>,
+    purpose: Option<X509Purpose>,
+    host: Option<String>,
+    ip: Option<IpAddr>,
+    email: Option<String>,
+    depth: Option<u32>,
+    policies: Vec<Asn1Object>,
+    flags: X509VerifyFlags,
+    param: Option<X509VerifyParam>,
+    issuer_fetcher: Option<Arc<AiaFetcher>>,
+}
+
+impl VerifyOptions {
+    /// Creates options which verify with the store's settings.
+    #[must_use]
+    pub fn new() -> VerifyOptions {
+        VerifyOptions {
+            time: None,
+            purpose: None,
+            host: None,
+            ip: None,
+            email: None,
+            depth: None,
+            policies: vec![],
+            flags: X509VerifyFlags::empty(),
+            param: None,
+            issuer_fetcher: None,
+        }
+    }
+
+    /// Verifies the chain as of `time`, in seconds since the epoch, instead of now.
+    pub fn set_time(&mut self, time: time_t) -> &mut VerifyOptions {
+        self.time = Some(time);
+        self
+    }
+
+    /// Requires the chain to be valid for `purpose`.
+    pub fn set_purpose(&mut self, purpose: X509Purpose) -> &mut VerifyOptions {
+        self.purpose = Some(purpose);
+        self
+    }
+
+    /// Requires the leaf certificate to be valid for the DNS name `host`.
+    pub fn set_host(&mut self, host: &str) -> &mut VerifyOptions {
+        self.host = Some(host.to_string());
+        self
+    }
+
+    /// Requires the leaf certificate to be valid for the address `ip`.
+    pub fn set_ip(&mut self, ip: IpAddr) -> &mut VerifyOptions {
+        self.ip = Some(ip);
+        self
+    }
+
+    /// Requires the leaf certificate to be valid for the email address `email`.
+    pub fn set_email(&mut self, email: &str) -> &mut VerifyOptions {
+        self.email = Some(email.to_string());
+        self
+    }
+
+    /// Sets the maximum number of intermediate certificates in the chain.
+    pub fn set_depth(&mut self, depth: u32) -> &mut VerifyOptions {
+        self.depth = Some(depth);
+        self
+    }
+
+    /// Adds an acceptable certificate policy, enabling policy checking.
+    pub fn add_policy(&mut self, policy: Asn1Object) -> &mut VerifyOptions {
+        self.policies.push(policy);
+        self
+    }
+
+    /// Sets additional verification flags, such as [`X509VerifyFlags::EXPLICIT_POLICY`].
+    pub fn set_flags(&mut self, flags: X509VerifyFlags) -> &mut VerifyOptions {
+        self.flags = flags;
+        self
+    }
+
+    /// Fetches missing intermediate certificates with `fetcher`.
+    ///
+    /// See the [`aia`](crate::x509::aia) module for details.
+    pub fn set_issuer_fetcher(&mut self, fetcher: Arc<AiaFetcher>) -> &mut VerifyOptions {
+        self.issuer_fetcher = Some(fetcher);
+        self
+    }
+
+    pub(crate) fn issuer_fetcher(&self) -> Option<&AiaFetcher> {
+        self.issuer_fetcher.as_deref()
+    }
+
+    /// Starts from a copy of `param` instead of the store's parameters.
+    pub(crate) fn set_param(&mut self, param: &X509VerifyParamRef) -> Result<(), ErrorStack> {
+        let mut copy = X509VerifyParam::new()?;
+        copy.copy_from(param)?;
+        self.param = Some(copy);
+        Ok(())
+    }
+
+    pub(crate) fn apply(&self, param: &mut X509VerifyParamRef) -> Result<(), ErrorStack> {
+        if let Some(src) = &self.param {
+            param.copy_from(src)?;
+        }
+        if let Some(time) = self.time {
+            param.set_time(time);
+        }
+        if let Some(purpose) = self.purpose {
+            param.set_purpose(purpose)?;
+        }
+        if let Some(host) = &self.host {
+            param.set_host(host)?;
+        }
+        if let Some(ip) = self.ip {
+            param.set_ip(ip)?;
+        }
+        if let Some(email) = &self.email {
+            param.set_email(email)?;
+        }
+        if let Some(depth) = self.depth {
+            param.set_depth(depth.try_into().unwrap_or(c_int::MAX));
+        }
+        if !self.policies.is_empty() {
+            let mut policies = Stack::new()?;
+            for policy in &self.policies {
+                let policy = unsafe { Asn1Object::from_ptr(cvt_p(ffi::OBJ_dup(policy.as_ptr()))?) };
+                policies.push(policy)?;
+            }
+            param.set_policies(&policies)?;
+        }
+        if !self.flags.is_empty() {
+            param.set_flags(self.flags);
+        }
+        Ok(())
+    }
+}
+
+impl Default for VerifyOptions {
+    fn default() -> VerifyOptions {
+        VerifyOptions::new()
+    }
+}
+
+/// An error found while verifying a chain.
+#[derive(Debug, Clone)]
+pub struct ChainError {
+    pub(crate) depth: u32,
+    pub(crate) cert: Option<X509>,
+    pub(crate) error: X509VerifyError,
+}
+
+impl ChainError {
+    /// Returns the position in the chain of the certificate the error applies to, the leaf
+    /// being at depth 0.
+    #[must_use]
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Returns the certificate the error applies to, if any.
+    #[must_use]
+    pub fn certificate(&self) -> Option<&X509> {
+        self.cert.as_ref()
+    }
+
+    /// Returns the error.
+    #[must_use]
+    pub fn error(&self) -> X509VerifyError {
+        self.error
+    }
+}
+
+/// The outcome of [`X509StoreRef::verify_chain`].
+///
+/// [`X509StoreRef::verify_chain`]: crate::x509::store::X509StoreRef::verify_chain
+#[derive(Debug, Clone)]
+pub struct ChainVerification {
+    pub(crate) chain: Vec<X509>,
+    pub(crate) errors: Vec<ChainError>,
+}
+
+impl ChainVerification {
+    /// Returns `true` if the chain was verified without any error.
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Returns the chain that was built, starting with the leaf.
+    ///
+    /// If no trusted root could be found, this is the longest chain built before giving up.
+    #[must_use]
+    pub fn chain(&self) -> &[X509] {
+        &self.chain
+    }
+
+    /// Returns every error found, in the order they were reported.
+    #[must_use]
+    pub fn errors(&self) -> &[ChainError] {
+        &self.errors
+    }
+
+    /// Returns the last certificate of the chain if verification failed because its issuer
+    /// could not be found.
+    pub(crate) fn missing_issuer(&self) -> Option<&X509> {
+        let missing = self.errors.iter().any(|e| {
+            e.error == X509VerifyError::UNABLE_TO_GET_ISSUER_CERT_LOCALLY
+                || e.error == X509VerifyError::UNABLE_TO_GET_ISSUER_CERT
+        });
+        if missing {
+            self.chain.last()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the errors found for the certificate at `depth`.
+    pub fn errors_at(&self, depth: u32) -> impl Iterator<Item = X509VerifyError> + '_ {
+        self.errors
+            .iter()
+            .filter(move |e| e.depth == depth)
+            .map(|e| e.error)
+    }
+}
@@ -13,12 +13,14 @@
 #![warn(missing_docs)]
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
+use boring::error::ErrorStack;
 use boring::ssl::{
     self, ConnectConfiguration, ErrorCode, MidHandshakeSslStream, ShutdownResult, SslAcceptor,
-    SslRef,
+    SslConnector, SslRef,
 };
 use boring_sys as ffi;
 use std::error::Error;
+use std::ffi::{c_int, CString};
 use std::fmt;
 use std::future::Future;
 use std::io::{self, Write};
@@ -35,7 +37,7 @@ pub use crate::async_callbacks::SslContextBuilderExt;
 pub use boring::ssl::{
     AsyncPrivateKeyMethod, AsyncPrivateKeyMethodError, AsyncSelectCertError, BoxGetSessionFinish,
     BoxGetSessionFuture, BoxPrivateKeyMethodFinish, BoxPrivateKeyMethodFuture, BoxSelectCertFinish,
-    BoxSelectCertFuture, ExDataFuture,
+    BoxSelectCertFuture, EchOutcome, ExDataFuture,
 };
 
 /// Asynchronously performs a client-side TLS handshake over the provided stream.
@@ -57,6 +59,74 @@ where
     HandshakeFuture(Some(mid_handshake)).await
 }
 
+/// Asynchronously connects to `domain`, offering ECH with `ech_config_list`.
+///
+/// If the server rejects ECH and sends retry configs, the connection is retried once with them.
+/// `stream` is called to open the transport of each attempt. See
+/// [`SslConnector::connect_with_ech_retry`].
+pub async fn connect_with_ech_retry<S, F, Fut>(
+    connector: &SslConnector,
+    domain: &str,
+    ech_config_list: &[u8],
+    mut stream: F,
+) -> Result<(SslStream<S>, EchOutcome), HandshakeError<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = io::Result<S>>,
+{
+    let err = match connect_with_ech(connector, domain, ech_config_list, &mut stream).await {
+        Ok(stream) => return Ok((stream, EchOutcome::Accepted)),
+        Err(err) => err,
+    };
+    let Some(retry_configs) = err.0.ech_retry_configs().map(<[u8]>::to_vec) else {
+        return Err(err);
+    };
+
+    let stream = connect_with_ech(connector, domain, &retry_configs, &mut stream).await?;
+    Ok((stream, EchOutcome::AcceptedAfterRetry { retry_configs }))
+}
+
+async fn connect_with_ech<S, F, Fut>(
+    connector: &SslConnector,
+    domain: &str,
+    ech_config_list: &[u8],
+    stream: &mut F,
+) -> Result<SslStream<S>, HandshakeError<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = io::Result<S>>,
+{
+    let setup_failure = |err| HandshakeError(ssl::HandshakeError::SetupFailure(err));
+    let mut config = connector.configure().map_err(setup_failure)?;
+    config
+        .set_ech_config_list(ech_config_list)
+        .map_err(setup_failure)?;
+    let stream = stream()
+        .await
+        .map_err(|err| setup_failure(transport_error(&err)))?;
+
+    connect(config, domain, stream).await
+}
+
+/// Reports a failure to open the transport as a system error, through the error queue as
+/// `ErrorStack` has no public constructor.
+fn transport_error(err: &io::Error) -> ErrorStack {
+    let data = CString::new(err.to_string()).unwrap_or_default();
+    unsafe {
+        ffi::ERR_put_error(
+            ffi::ERR_LIB_SYS.0 as c_int,
+            0,
+            err.raw_os_error().unwrap_or(0),
+            c"tokio-boring".as_ptr(),
+            0,
+        );
+        ffi::ERR_set_error_data(data.as_ptr().cast_mut(), ffi::ERR_FLAG_STRING);
+    }
+    ErrorStack::get()
+}
+
 /// Asynchronously performs a server-side TLS handshake over the provided stream.
 ///
 /// This function automatically sets the task waker on the `Ssl` from `config` to
@@ -0,0 +1,54 @@
+#![cfg(not(feature = "fips"))]
+
+use tokio_boring2 as tokio_boring;
+
+use boring::hpke::HpkeKey;
+use boring::ssl::{EchConfig, EchConfigList, EchKeyRing};
+use futures::future;
+use tokio::net::TcpStream;
+use tokio_boring::EchOutcome;
+
+mod common;
+
+use self::common::{create_acceptor, create_connector, create_listener};
+
+#[tokio::test]
+async fn ech_retry() {
+    let ring = EchKeyRing::builder("localhost").build().unwrap();
+    let (listener, addr) = create_listener();
+    let acceptor = create_acceptor(|builder| builder.set_ech_key_ring(&ring).unwrap());
+
+    let server = async move {
+        let mut accepted = vec![];
+        for _ in 0..2 {
+            let stream = listener.accept().await.unwrap().0;
+            let stream = tokio_boring::accept(&acceptor, stream).await;
+            accepted.push(stream.is_ok_and(|stream| stream.ssl().ech_accepted()));
+        }
+        accepted
+    };
+
+    // A configuration the server has no key for, as if it was cached before a rotation.
+    let key = HpkeKey::generate_x25519_hkdf_sha256().unwrap();
+    let config = EchConfig::builder(0, "localhost", &key).build().unwrap();
+    let stale = EchConfigList::new(vec![config]).to_bytes().unwrap();
+
+    let client = async {
+        let connector = create_connector(|builder| builder.set_ca_file("tests/cert.pem"));
+        tokio_boring::connect_with_ech_retry(&connector, "localhost", &stale, || {
+            TcpStream::connect(addr)
+        })
+        .await
+        .unwrap()
+        .1
+    };
+
+    let (accepted, outcome) = future::join(server, client).await;
+    assert_eq!(accepted, [false, true]);
+    assert_eq!(
+        outcome,
+        EchOutcome::AcceptedAfterRetry {
+            retry_configs: ring.config_list().to_bytes().unwrap(),
+        }
+    );
+}